@@ -1,11 +1,13 @@
 use std::time::Duration;
 
-use common::{ClientConnectionConfig, QuicPolicy, TransportMode, YamuxConfig};
+use common::{
+    ClientConnectionConfig, DirectAccessConfig, GeoIpConfig, PreProxy, QuicPolicy, RoutingConfig,
+    TransportMode, YamuxConfig,
+};
 use protocol::CompressionMode;
 use serde::{Deserialize, Serialize};
 use socket2::Socket;
 
-use crate::direct_access::DirectAccessConfig;
 use crate::error::{AndroidAgentError, Result};
 
 pub const ANDROID_SOCKET_BUFFER_SIZE: usize = 1024 * 1024;
@@ -17,6 +19,10 @@ pub struct AndroidAgentConfig {
     pub username: String,
     pub private_key_pem: String,
 
+    /// 可选的企业 HTTP/SOCKS5 前置代理。配置后 UDP 目标改走 TCP/Yamux。
+    #[serde(default)]
+    pub pre_proxy: Option<PreProxy>,
+
     #[serde(default)]
     pub transport_mode: TransportMode,
 
@@ -43,13 +49,37 @@ pub struct AndroidAgentConfig {
     #[serde(default)]
     pub yamux: YamuxConfig,
 
+    /// 旧版直连访问配置；未配置 `routing` 时转换为等价的分流规则。
     #[serde(default)]
     pub direct_access: DirectAccessConfig,
 
+    /// 额外的 proxy 分组，`routing` 规则按 name 引用。顶层 proxy_addrs、
+    /// username 与 private_key_pem 组成默认分组 `proxy`。
+    #[serde(default)]
+    pub proxy_groups: Vec<AndroidProxyGroupConfig>,
+
+    /// 多出口分流策略：规则按顺序把目标映射到 direct、block 或某个 proxy 分组。
+    #[serde(default)]
+    pub routing: Option<RoutingConfig>,
+
+    /// 离线 GeoIP 数据库，供 `geoip:CN` 这类分流规则使用。
+    #[serde(default)]
+    pub geoip: Option<GeoIpConfig>,
+
     #[serde(default)]
     pub tun: AndroidTunConfig,
 }
 
+/// 一个命名 proxy 分组：拥有独立的 proxy 地址和认证身份。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AndroidProxyGroupConfig {
+    pub name: String,
+    pub proxy_addrs: Vec<String>,
+    pub username: String,
+    pub private_key_pem: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AndroidTunConfig {
     #[serde(default = "default_tun_ipv4")]
@@ -105,9 +135,40 @@ impl AndroidAgentConfig {
                 "private_key_pem must not be empty".to_string(),
             ));
         }
+        for group in &self.proxy_groups {
+            if group.proxy_addrs.is_empty() {
+                return Err(AndroidAgentError::Connection(format!(
+                    "proxy group {} must contain at least one proxy endpoint",
+                    group.name
+                )));
+            }
+            if group.username.trim().is_empty() || group.private_key_pem.trim().is_empty() {
+                return Err(AndroidAgentError::Connection(format!(
+                    "proxy group {} must set username and private_key_pem",
+                    group.name
+                )));
+            }
+        }
         Ok(())
     }
 
+    /// 生成某个 proxy 分组使用的配置：替换 proxy 地址与认证身份，其余传输参数沿用顶层配置。
+    pub fn for_proxy_group(&self, group: &AndroidProxyGroupConfig) -> AndroidAgentConfig {
+        AndroidAgentConfig {
+            proxy_addrs: group.proxy_addrs.clone(),
+            username: group.username.clone(),
+            private_key_pem: group.private_key_pem.clone(),
+            proxy_groups: Vec::new(),
+            ..self.clone()
+        }
+    }
+
+    /// 返回实际生效的 UDP 外层传输；前置代理无法转发 UDP，配置后固定为 TCP。
+    pub fn effective_transport_mode(&self) -> TransportMode {
+        self.transport_mode
+            .effective_with_pre_proxy(self.pre_proxy.is_some())
+    }
+
     /// 限制 UDP 会话池的 socket/内存开销，同时避免错误配置 0。
     pub fn effective_udp_session_pool_size(&self) -> usize {
         self.udp_session_pool_size.clamp(1, 8)
@@ -139,6 +200,10 @@ impl ClientConnectionConfig for AndroidAgentConfig {
         Some(ANDROID_SOCKET_BUFFER_SIZE)
     }
 
+    fn pre_proxy(&self) -> Option<PreProxy> {
+        self.pre_proxy.clone()
+    }
+
     #[cfg(unix)]
     fn protect_socket(&self, socket: &Socket, _dst: std::net::SocketAddr) -> std::io::Result<()> {
         use std::os::fd::AsRawFd;
@@ -244,6 +309,19 @@ mod tests {
         assert_eq!(config.transport_mode, TransportMode::Auto);
     }
 
+    #[test]
+    fn pre_proxy_forces_tcp_transport() {
+        let config: AndroidAgentConfig = serde_json::from_str(
+            r#"{"proxy_addrs":["127.0.0.1:8080"],"username":"u","private_key_pem":"key","pre_proxy":"socks5://10.0.0.1:1080"}"#,
+        )
+        .unwrap();
+        assert_eq!(config.effective_transport_mode(), TransportMode::Tcp);
+        assert_eq!(
+            config.pre_proxy().map(|pre_proxy| pre_proxy.server_addr()),
+            Some("10.0.0.1:1080".to_string())
+        );
+    }
+
     #[test]
     fn udp_session_pool_defaults_to_four_and_is_bounded() {
         let default_config: AndroidAgentConfig = serde_json::from_str(
@@ -283,6 +361,31 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn proxy_groups_and_routing_are_parsed() {
+        let config: AndroidAgentConfig = serde_json::from_str(
+            r#"{"proxy_addrs":["127.0.0.1:8080"],"username":"u","private_key_pem":"key",
+                "proxy_groups":[{"name":"us","proxy_addrs":["198.51.100.10:8080"],"username":"u2","private_key_pem":"key2"}],
+                "routing":{"final":"direct","rules":[{"match":["*.example.com"],"outbound":"us"}]},
+                "geoip":{"database":"/data/geoip/cn.list"}}"#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+
+        let routing = config.routing.as_ref().unwrap();
+        assert_eq!(routing.final_outbound, "direct");
+        assert_eq!(routing.rules[0].outbound, "us");
+        assert_eq!(
+            config.geoip.as_ref().unwrap().database,
+            "/data/geoip/cn.list"
+        );
+
+        let group = config.for_proxy_group(&config.proxy_groups[0]);
+        assert_eq!(group.remote_addr(), "198.51.100.10:8080");
+        assert_eq!(group.username, "u2");
+        assert_eq!(group.transport_mode, config.transport_mode);
+    }
+
     #[test]
     fn explicit_quic_policy_blocks_quic() {
         let config: AndroidTunConfig = serde_json::from_str(r#"{"quic_policy":"block"}"#).unwrap();
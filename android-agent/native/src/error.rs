@@ -10,6 +10,9 @@ pub enum AndroidAgentError {
     #[error("json error: {0}")]
     Json(#[from] serde_json::Error),
 
+    #[error("config error: {0}")]
+    Config(#[from] common::CommonError),
+
     #[error("connection error: {0}")]
     Connection(String),
 
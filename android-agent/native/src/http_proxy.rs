@@ -16,16 +16,16 @@ use tracing::{debug, error, info};
 
 use crate::android_log;
 use crate::config::AndroidAgentConfig;
-use crate::direct_access::{DirectAccessChecker, address_to_string};
 use crate::error::{AndroidAgentError, Result};
 use crate::http_proxy_body::{AgentBody, boxed, empty, text_response};
 use crate::http_proxy_clients::{
     HttpProxyClientLease, is_http_proxy_client_blocked, register_http_proxy_client,
 };
 use crate::http_proxy_io::connect_direct_tcp;
+use crate::routing::{AndroidOutboundRouter, Route, address_to_string, build_router};
 use crate::socks5_proxy::handle_socks5_connection;
 use crate::tcp_relay::{TcpRelayOptions, relay_tcp_bidirectional};
-use crate::yamux_session::{AndroidYamuxSessionManager, AndroidYamuxTargetStream};
+use crate::yamux_session::AndroidYamuxTargetStream;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum ProxyProtocol {
@@ -43,8 +43,7 @@ pub async fn run_android_http_proxy(
     let bind_addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, listen_port));
     let listener = bind_tcp_listener_with_backlog(bind_addr, DEFAULT_TCP_LISTEN_BACKLOG)?;
     let config = Arc::new(config);
-    let direct_checker = Arc::new(DirectAccessChecker::new(&config.direct_access));
-    let tcp_sessions = AndroidYamuxSessionManager::new_tcp_direct(config, shutdown.clone());
+    let router = Arc::new(build_router(config, &shutdown)?);
 
     info!(
         "Android HTTP / SOCKS5 proxy listening on {bind_addr}; tcp_transport=direct-framed-tcp (transport_mode only applies to UDP)"
@@ -65,10 +64,9 @@ pub async fn run_android_http_proxy(
                 if let Err(err) = stream.set_nodelay(true) {
                     debug!("Android HTTP proxy failed to set TCP_NODELAY for {peer_addr}: {err}");
                 }
-                let sessions = tcp_sessions.clone();
-                let checker = direct_checker.clone();
+                let router = router.clone();
                 spawn_guarded("android explicit proxy client", async move {
-                    if let Err(err) = handle_proxy_connection(stream, peer_addr, sessions, checker).await {
+                    if let Err(err) = handle_proxy_connection(stream, peer_addr, router).await {
                         debug!("Android explicit proxy client {peer_addr} ended: {err}");
                     }
                 });
@@ -84,8 +82,7 @@ pub async fn run_android_http_proxy(
 async fn handle_proxy_connection(
     stream: TcpStream,
     peer_addr: SocketAddr,
-    sessions: Arc<AndroidYamuxSessionManager>,
-    direct_checker: Arc<DirectAccessChecker>,
+    router: Arc<AndroidOutboundRouter>,
 ) -> Result<()> {
     let mut buffer = [0u8; 1];
     if stream.peek(&mut buffer).await? == 0 {
@@ -95,11 +92,11 @@ async fn handle_proxy_connection(
     match detect_proxy_protocol(buffer[0]) {
         Some(ProxyProtocol::Socks5) => {
             let client = register_http_proxy_client(peer_addr);
-            handle_socks5_connection(stream, sessions, direct_checker, client).await
+            handle_socks5_connection(stream, router, client).await
         }
         Some(ProxyProtocol::Http) => {
             let client = register_http_proxy_client(peer_addr);
-            handle_http_connection(stream, sessions, direct_checker, client).await
+            handle_http_connection(stream, router, client).await
         }
         None => {
             debug!(
@@ -153,19 +150,15 @@ fn extract_host_port(req: &Request<Incoming>, uri: &Uri) -> (String, u16) {
 
 async fn handle_http_connection(
     stream: TcpStream,
-    sessions: Arc<AndroidYamuxSessionManager>,
-    direct_checker: Arc<DirectAccessChecker>,
+    router: Arc<AndroidOutboundRouter>,
     client: HttpProxyClientLease,
 ) -> Result<()> {
     let io = TokioIo::new(stream);
-    let sessions_clone = sessions.clone();
-    let checker_clone = direct_checker.clone();
     let request_client = client.clone_lease();
     let service = service_fn(move |req| {
-        let sessions = sessions_clone.clone();
-        let checker = checker_clone.clone();
+        let router = router.clone();
         let client = request_client.clone_lease();
-        async move { handle_http_request(req, sessions, checker, client).await }
+        async move { handle_http_request(req, router, client).await }
     });
 
     let conn = http1::Builder::new()
@@ -192,23 +185,21 @@ async fn handle_http_connection(
 
 async fn handle_http_request(
     req: Request<Incoming>,
-    sessions: Arc<AndroidYamuxSessionManager>,
-    direct_checker: Arc<DirectAccessChecker>,
+    router: Arc<AndroidOutboundRouter>,
     client: HttpProxyClientLease,
 ) -> std::result::Result<Response<AgentBody>, hyper::Error> {
     debug!("Android HTTP proxy request: {} {}", req.method(), req.uri());
 
     if req.method() == Method::CONNECT {
-        handle_connect(req, sessions, direct_checker, client).await
+        handle_connect(req, router, client).await
     } else {
-        handle_regular_request(req, sessions, direct_checker, client).await
+        handle_regular_request(req, router, client).await
     }
 }
 
 async fn handle_connect(
     mut req: Request<Incoming>,
-    sessions: Arc<AndroidYamuxSessionManager>,
-    direct_checker: Arc<DirectAccessChecker>,
+    router: Arc<AndroidOutboundRouter>,
     client: HttpProxyClientLease,
 ) -> std::result::Result<Response<AgentBody>, hyper::Error> {
     let uri = req.uri().clone();
@@ -227,56 +218,65 @@ async fn handle_connect(
         port,
     };
     let target = format!("{host}:{port}");
-    let use_direct = direct_checker.is_direct(&address);
-
-    if use_direct {
-        let target_stream = match connect_direct_tcp(&target).await {
-            Ok(stream) => stream,
-            Err(err) => {
-                error!("Android HTTP CONNECT direct failed {target}: {err}");
-                android_log::warn(format!(
-                    "Android HTTP CONNECT direct failed {target}: {err}"
-                ));
-                return Ok(text_response(
-                    StatusCode::BAD_GATEWAY,
-                    "Failed to connect to target",
-                ));
-            }
-        };
+    let proxy_group = match router.route(&address, TransportProtocol::Tcp) {
+        Route::Proxy(group) => group,
+        Route::Block => {
+            debug!("Android HTTP CONNECT blocked by routing {host}:{port}");
+            android_log::info(format!("Android HTTP CONNECT BLOCK {host}:{port}"));
+            return Ok(text_response(
+                StatusCode::FORBIDDEN,
+                "Blocked by routing policy",
+            ));
+        }
+        Route::Direct => {
+            let target_stream = match connect_direct_tcp(&target).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    error!("Android HTTP CONNECT direct failed {target}: {err}");
+                    android_log::warn(format!(
+                        "Android HTTP CONNECT direct failed {target}: {err}"
+                    ));
+                    return Ok(text_response(
+                        StatusCode::BAD_GATEWAY,
+                        "Failed to connect to target",
+                    ));
+                }
+            };
 
-        let tunnel_client = client.clone_lease();
-        tokio::spawn(async move {
-            let cancel = tunnel_client.cancel_token();
-            tokio::select! {
-                upgraded = hyper::upgrade::on(&mut req) => match upgraded {
-                Ok(upgraded) => {
-                    tokio::select! {
-                        result = tunnel_direct(upgraded, target_stream, &target) => {
-                            if let Err(err) = result {
-                                error!("Android HTTP CONNECT direct tunnel error: {err}");
+            let tunnel_client = client.clone_lease();
+            tokio::spawn(async move {
+                let cancel = tunnel_client.cancel_token();
+                tokio::select! {
+                    upgraded = hyper::upgrade::on(&mut req) => match upgraded {
+                    Ok(upgraded) => {
+                        tokio::select! {
+                            result = tunnel_direct(upgraded, target_stream, &target) => {
+                                if let Err(err) = result {
+                                    error!("Android HTTP CONNECT direct tunnel error: {err}");
+                                }
+                            }
+                            _ = cancel.cancelled() => {
+                                debug!("Android HTTP CONNECT direct tunnel cancelled {target}");
                             }
-                        }
-                        _ = cancel.cancelled() => {
-                            debug!("Android HTTP CONNECT direct tunnel cancelled {target}");
                         }
                     }
+                    Err(err) => error!("Android HTTP CONNECT upgrade failed: {err}"),
+                    },
+                    _ = cancel.cancelled() => {
+                        debug!("Android HTTP CONNECT direct upgrade cancelled {target}");
+                    }
                 }
-                Err(err) => error!("Android HTTP CONNECT upgrade failed: {err}"),
-                },
-                _ = cancel.cancelled() => {
-                    debug!("Android HTTP CONNECT direct upgrade cancelled {target}");
-                }
-            }
-        });
+            });
 
-        return Ok(Response::builder()
-            .status(StatusCode::OK)
-            .body(empty())
-            .unwrap());
-    }
+            return Ok(Response::builder()
+                .status(StatusCode::OK)
+                .body(empty())
+                .unwrap());
+        }
+    };
 
-    let connected_stream = match sessions
-        .as_ref()
+    let connected_stream = match proxy_group
+        .tcp_sessions()
         .connect_to_target(address, TransportProtocol::Tcp)
         .await
     {
@@ -329,8 +329,7 @@ async fn handle_connect(
 
 async fn handle_regular_request(
     mut req: Request<Incoming>,
-    sessions: Arc<AndroidYamuxSessionManager>,
-    direct_checker: Arc<DirectAccessChecker>,
+    router: Arc<AndroidOutboundRouter>,
     _client: HttpProxyClientLease,
 ) -> std::result::Result<Response<AgentBody>, hyper::Error> {
     let uri = req.uri().clone();
@@ -348,34 +347,45 @@ async fn handle_regular_request(
         *req.uri_mut() = new_uri;
     }
 
-    if direct_checker.is_direct(&address) {
-        let target = address_to_string(&address);
-        let target_stream = match connect_direct_tcp(&target).await {
-            Ok(stream) => stream,
-            Err(err) => {
-                error!("Android HTTP direct request failed {target}: {err}");
-                return Ok(text_response(
-                    StatusCode::BAD_GATEWAY,
-                    "Failed to connect to target",
-                ));
-            }
-        };
+    let proxy_group = match router.route(&address, TransportProtocol::Tcp) {
+        Route::Proxy(group) => group,
+        Route::Block => {
+            debug!("Android HTTP request blocked by routing {host}:{port}");
+            android_log::info(format!("Android HTTP request BLOCK {host}:{port}"));
+            return Ok(text_response(
+                StatusCode::FORBIDDEN,
+                "Blocked by routing policy",
+            ));
+        }
+        Route::Direct => {
+            let target = address_to_string(&address);
+            let target_stream = match connect_direct_tcp(&target).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    error!("Android HTTP direct request failed {target}: {err}");
+                    return Ok(text_response(
+                        StatusCode::BAD_GATEWAY,
+                        "Failed to connect to target",
+                    ));
+                }
+            };
 
-        let (mut sender, conn) =
-            hyper::client::conn::http1::handshake(TokioIo::new(target_stream)).await?;
-        tokio::spawn(async move {
-            if let Err(err) = conn.await {
-                error!("Android HTTP direct client connection failed: {err:?}");
-            }
-        });
+            let (mut sender, conn) =
+                hyper::client::conn::http1::handshake(TokioIo::new(target_stream)).await?;
+            tokio::spawn(async move {
+                if let Err(err) = conn.await {
+                    error!("Android HTTP direct client connection failed: {err:?}");
+                }
+            });
 
-        let response = sender.send_request(req).await?;
-        let (parts, body) = response.into_parts();
-        return Ok(Response::from_parts(parts, boxed(body)));
-    }
+            let response = sender.send_request(req).await?;
+            let (parts, body) = response.into_parts();
+            return Ok(Response::from_parts(parts, boxed(body)));
+        }
+    };
 
-    let connected_stream = match sessions
-        .as_ref()
+    let connected_stream = match proxy_group
+        .tcp_sessions()
         .connect_to_target(address, TransportProtocol::Tcp)
         .await
     {
@@ -456,14 +466,13 @@ mod tests {
     use super::*;
     use std::time::Duration;
 
-    use common::YamuxConfig;
+    use common::{DirectAccessConfig, DirectAccessMode, YamuxConfig};
     use tokio::io::{AsyncReadExt, AsyncWriteExt};
     use tokio::net::TcpListener;
     use tokio::sync::oneshot;
     use tokio::time::{sleep, timeout};
 
     use crate::config::{AndroidAgentConfig, AndroidTunConfig};
-    use crate::direct_access::{DirectAccessConfig, DirectAccessMode};
 
     #[test]
     fn proxy_protocol_detection_rejects_non_proxy_probe_bytes() {
@@ -495,6 +504,7 @@ mod tests {
             proxy_addrs: vec!["127.0.0.1:9".to_string()],
             username: "test".to_string(),
             private_key_pem: "test".to_string(),
+            pre_proxy: None,
             transport_mode: common::TransportMode::Udp,
             udp_session_pool_size: 4,
             async_runtime_stack_size_mb: 4,
@@ -506,7 +516,11 @@ mod tests {
             direct_access: DirectAccessConfig {
                 mode: DirectAccessMode::DirectAll,
                 rules: Vec::new(),
+                force_proxy_rule_set: None,
             },
+            proxy_groups: Vec::new(),
+            routing: None,
+            geoip: None,
             tun: AndroidTunConfig::default(),
         };
 
@@ -758,6 +772,7 @@ mod tests {
             proxy_addrs: vec!["127.0.0.1:9".to_string()],
             username: "test".to_string(),
             private_key_pem: "test".to_string(),
+            pre_proxy: None,
             transport_mode: common::TransportMode::Udp,
             udp_session_pool_size: 4,
             async_runtime_stack_size_mb: 4,
@@ -769,7 +784,11 @@ mod tests {
             direct_access: DirectAccessConfig {
                 mode: DirectAccessMode::DirectAll,
                 rules: Vec::new(),
+                force_proxy_rule_set: None,
             },
+            proxy_groups: Vec::new(),
+            routing: None,
+            geoip: None,
             tun: AndroidTunConfig::default(),
         }
     }
@@ -16,10 +16,9 @@ use tracing::{info, warn};
 use protocol::udp_transport::UDP_NATIVE_MAX_TUN_MTU;
 
 use crate::config::AndroidAgentConfig;
-use crate::direct_access::DirectAccessChecker;
 use crate::error::Result;
 use crate::fd_device::{AndroidTunDevice, RawFd};
-use crate::yamux_session::AndroidYamuxSessionManager;
+use crate::routing::{AndroidOutboundRouter, build_router};
 
 use direct_domain_cache::DirectDomainCache;
 use network::{TunNetworks, parse_cidr_v4, parse_cidr_v6};
@@ -27,9 +26,7 @@ use supervisor::spawn_netstack_supervisor;
 
 #[derive(Clone)]
 struct ForwardContext {
-    tcp_sessions: Arc<AndroidYamuxSessionManager>,
-    udp_sessions: Arc<AndroidYamuxSessionManager>,
-    direct_checker: Arc<DirectAccessChecker>,
+    router: Arc<AndroidOutboundRouter>,
     direct_domain_cache: Arc<DirectDomainCache>,
     tun_networks: TunNetworks,
     proxy_dns: bool,
@@ -55,7 +52,7 @@ pub async fn run_android_agent(
     let proxy_dns = config.tun.proxy_dns;
     let quic_policy = config.tun.effective_quic_policy();
     let native_udp = config
-        .transport_mode
+        .effective_transport_mode()
         .uses_native_udp_for(protocol::TransportProtocol::Udp);
     let configured_mtu = config.tun.mtu;
     let effective_mtu = if native_udp {
@@ -84,7 +81,7 @@ pub async fn run_android_agent(
         mtu,
         proxy_dns,
         quic_policy,
-        config.transport_mode.as_str(),
+        config.effective_transport_mode().as_str(),
         udp_transport,
         config.yamux.udp_session_count()
     );
@@ -95,13 +92,9 @@ pub async fn run_android_agent(
 
     let device = Arc::new(AndroidTunDevice::from_raw_fd(raw_fd)?);
     let config = Arc::new(config);
-    let direct_checker = Arc::new(DirectAccessChecker::new(&config.direct_access));
-    let tcp_sessions = AndroidYamuxSessionManager::new_tcp_direct(config.clone(), shutdown.clone());
-    let udp_sessions = AndroidYamuxSessionManager::new_udp(config.clone(), shutdown.clone());
+    let router = Arc::new(build_router(config.clone(), &shutdown)?);
     let context = ForwardContext {
-        tcp_sessions,
-        udp_sessions,
-        direct_checker,
+        router,
         direct_domain_cache: Arc::new(DirectDomainCache::new(Duration::from_secs(300))),
         tun_networks,
         proxy_dns,
@@ -21,6 +21,7 @@ use super::direct_domain_cache::DirectDomainCache;
 use super::udp::UdpWriter;
 use crate::android_log;
 use crate::error::Result;
+use crate::routing::Route;
 use crate::traffic_stats::{self, DnsResolutionRecord};
 
 const DNS_PENDING_TTL: Duration = Duration::from_secs(10);
@@ -367,7 +368,9 @@ async fn connect_dns_stream(
     context: &ForwardContext,
 ) -> Result<impl AsyncRead + AsyncWrite + Unpin + Send + 'static> {
     context
-        .udp_sessions
+        .router
+        .default_group()
+        .udp_sessions()
         .connect_to_target(Address::ProxyDns { port: 53 }, TransportProtocol::Udp)
         .await
 }
@@ -431,7 +434,14 @@ async fn try_send_direct_dns_response(
         ));
         return false;
     };
-    if !context.direct_checker.is_direct_domain(&question.query) {
+    let domain_target = Address::Domain {
+        host: question.query.clone(),
+        port: request.target.port(),
+    };
+    if !matches!(
+        context.router.route(&domain_target, TransportProtocol::Udp),
+        Route::Direct
+    ) {
         android_log::info(format!(
             "Android TUN DNS PROXY_CANDIDATE {} {}",
             question.query, question.record_type
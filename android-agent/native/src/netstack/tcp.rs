@@ -15,7 +15,9 @@ use super::ForwardContext;
 use super::network::{address_for_tun_target, reject_tun_target};
 use crate::android_log;
 use crate::error::{AndroidAgentError, Result};
+use crate::routing::Route;
 use crate::tcp_relay::{TcpRelayOptions, relay_tcp_bidirectional};
+use crate::yamux_session::AndroidYamuxSessionManager;
 
 const TUN_TCP_PREFETCH_LIMIT: usize = 64 * 1024;
 const TUN_TCP_PREFETCH_CHUNK: usize = 16 * 1024;
@@ -64,25 +66,40 @@ async fn handle_tcp(
     let mut direct_reason = None;
     let proxy_address = address.clone();
     let mut proxy_reason = None;
-    if !proxy_dns_request && context.direct_checker.is_direct(&address) {
-        direct_target = Some(target);
-    }
-
-    if direct_target.is_none()
-        && !proxy_dns_request
-        && context.direct_checker.has_domain_direct_rules()
-        && let Some(domain) = context
-            .direct_domain_cache
-            .matching_domain_for_ip(target.ip(), |domain| {
-                context.direct_checker.is_direct_domain(domain)
-            })
-    {
-        debug!(
-            "Android TUN TCP cached direct domain matched: {} ({})",
-            target, domain
-        );
-        direct_reason = Some(format!("cached domain {domain}"));
-        direct_target = Some(target);
+    // proxy DNS 等内部目标固定走默认分组。
+    let mut proxy_group = context.router.default_group();
+    if !proxy_dns_request {
+        let policy = context.router.policy();
+        let cached_domain = if policy.has_domain_rules() {
+            context
+                .direct_domain_cache
+                .matching_domain_for_ip(target.ip(), |domain| policy.matches_domain_rule(domain))
+        } else {
+            None
+        };
+        match context.router.route_with_domain(
+            &address,
+            TransportProtocol::Tcp,
+            cached_domain.as_deref(),
+        ) {
+            Route::Direct => {
+                if let Some(domain) = cached_domain {
+                    debug!(
+                        "Android TUN TCP cached direct domain matched: {} ({})",
+                        target, domain
+                    );
+                    direct_reason = Some(format!("cached domain {domain}"));
+                }
+                direct_target = Some(target);
+            }
+            Route::Block => {
+                debug!("Android TUN TCP blocked by routing -> {}", target_label);
+                android_log::info(format!("Android TUN TCP BLOCK {target_label}"));
+                let _ = client.shutdown().await;
+                return Ok(());
+            }
+            Route::Proxy(group) => proxy_group = group,
+        }
     }
 
     if direct_target.is_none()
@@ -130,12 +147,19 @@ async fn handle_tcp(
     if proxy_dns_request {
         debug!("Android TUN TCP DNS -> proxy -> {}", target_label);
     } else {
-        debug!("Android TUN TCP proxy -> {}", proxy_label);
-        android_log::info(format!("Android TUN TCP PROXY {proxy_label}"));
+        debug!(
+            "Android TUN TCP proxy {} -> {}",
+            proxy_group.name(),
+            proxy_label
+        );
+        android_log::info(format!(
+            "Android TUN TCP PROXY {} {proxy_label}",
+            proxy_group.name()
+        ));
     }
     let (mut proxy_io, prefetched) = match connect_proxy_stream_with_tun_prefetch(
         &mut client,
-        &context,
+        proxy_group.tcp_sessions(),
         proxy_address,
         &proxy_label,
     )
@@ -179,15 +203,12 @@ fn proxy_target_label(target_label: &str, reason: Option<&str>) -> String {
 
 async fn connect_proxy_stream_with_tun_prefetch(
     client: &mut netstack_smoltcp::TcpStream,
-    context: &ForwardContext,
+    tcp_sessions: &AndroidYamuxSessionManager,
     proxy_address: protocol::Address,
     label: &str,
 ) -> Result<(crate::yamux_session::AndroidYamuxTargetStream, Vec<u8>)> {
-    let mut connect = Box::pin(
-        context
-            .tcp_sessions
-            .connect_to_target(proxy_address, TransportProtocol::Tcp),
-    );
+    let mut connect =
+        Box::pin(tcp_sessions.connect_to_target(proxy_address, TransportProtocol::Tcp));
     let mut prefetched = Vec::with_capacity(TUN_TCP_PREFETCH_CHUNK);
 
     loop {
@@ -1,7 +1,7 @@
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::sync::Arc;
 
-use common::{QuicPolicy, QuicUdpStats, dns::is_dns_query_packet, spawn_guarded};
+use common::{Outbound, QuicPolicy, QuicUdpStats, dns::is_dns_query_packet, spawn_guarded};
 use futures::{SinkExt, StreamExt};
 use protocol::TransportProtocol;
 use socket2::{Domain, Protocol, SockAddr, Socket, Type};
@@ -20,9 +20,8 @@ use super::network::{
 };
 use super::udp_relay::UdpRelay;
 use crate::android_log;
-use crate::direct_access::{DirectAccessChecker, address_to_string};
 use crate::error::Result;
-use crate::yamux_session::AndroidYamuxSessionManager;
+use crate::routing::{AndroidOutboundRouter, address_to_string};
 
 pub(super) type UdpWriter = Arc<tokio::sync::Mutex<netstack_smoltcp::udp::WriteHalf>>;
 
@@ -35,7 +34,8 @@ const UDP_SESSION_IDLE: Duration = Duration::from_secs(60);
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum UdpRoute {
     Direct,
-    Proxy,
+    // 值为 AndroidOutboundRouter 中 proxy 分组的下标。
+    Proxy(usize),
     Block,
 }
 
@@ -45,8 +45,7 @@ pub(super) struct UdpSessionContext {
     pub(super) proxy_dns: bool,
     pub(super) quic_policy: QuicPolicy,
     pub(super) netstack_tx: UdpWriter,
-    pub(super) udp_sessions: Arc<AndroidYamuxSessionManager>,
-    pub(super) direct_checker: Arc<DirectAccessChecker>,
+    pub(super) router: Arc<AndroidOutboundRouter>,
     pub(super) direct_domain_cache: Arc<DirectDomainCache>,
     pub(super) shutdown: CancellationToken,
 }
@@ -64,7 +63,9 @@ pub(super) fn spawn_udp_sessions(
         let dns_proxy = context
             .proxy_dns
             .then(|| DnsProxy::spawn(context.clone(), udp_tx.clone(), shutdown.clone()));
-        let udp_relay = UdpRelay::spawn(context.clone(), udp_tx.clone(), shutdown.clone());
+        // 每个 proxy 分组各自一个共享 relay，首次有流量分到该分组时再启动。
+        let mut udp_relays: Vec<Option<Arc<UdpRelay>>> =
+            context.router.groups().iter().map(|_| None).collect();
         let quic_stats = Arc::new(QuicUdpStats::default());
         spawn_quic_udp_stats_logger(quic_stats.clone(), shutdown.clone());
 
@@ -112,42 +113,46 @@ pub(super) fn spawn_udp_sessions(
                         continue;
                     }
 
-                    let mut direct_match = context.direct_checker.is_direct(&address);
+                    // UDP/QUIC 代理目标保持原始 IP；只有存在域名规则时才需要查 DNS cache。
+                    // 这样可以避免 proxy 端重新 DNS 到不同 CDN 边缘节点，减少 HTTP/3
+                    // 视频播放抖动。
+                    let policy = context.router.policy();
                     let proxy_address = address.clone();
-                    if !direct_match {
-                        // UDP/QUIC 代理目标保持原始 IP；只有域名规则可能改判直连时，
-                        // 才需要查 DNS cache。这样可以避免 proxy 端重新 DNS 到不同
-                        // CDN 边缘节点，减少 HTTP/3 视频播放抖动。
-                        if context.direct_checker.has_domain_direct_rules()
-                            && context
+                    let cached_domain = policy
+                        .has_domain_rules()
+                        .then(|| {
+                            context
                                 .direct_domain_cache
                                 .matching_domain_for_ip(target.ip(), |domain| {
-                                context.direct_checker.is_direct_domain(domain)
-                            })
-                            .is_some()
-                        {
-                            direct_match = true;
-                        }
-                    }
+                                    policy.matches_domain_rule(domain)
+                                })
+                        })
+                        .flatten();
+                    let outbound = policy.route_with_domain(&address, TransportProtocol::Udp, cached_domain.as_deref());
 
-                    match classify_udp_route(
-                        target.port(),
-                        quic_policy,
-                        direct_match,
-                    ) {
+                    match classify_udp_route(target.port(), quic_policy, outbound) {
                         UdpRoute::Block => {
-                            quic_stats.record_blocked();
+                            if target.port() == 443 {
+                                quic_stats.record_blocked();
+                            }
                             debug!(
-                                "Android TUN UDP/443 QUIC blocked by explicit policy {:?} -> {}; waiting for TCP/TLS fallback",
+                                "Android TUN UDP blocked by QUIC policy {:?} or routing -> {}",
                                 quic_policy,
                                 target
                             );
                             continue;
                         }
-                        UdpRoute::Proxy => {
+                        UdpRoute::Proxy(index) => {
                             if target.port() == 443 {
                                 quic_stats.record_proxied();
                             }
+                            let udp_relay = udp_relays[index].get_or_insert_with(|| {
+                                UdpRelay::spawn(
+                                    context.router.groups()[index].udp_sessions().clone(),
+                                    udp_tx.clone(),
+                                    shutdown.clone(),
+                                )
+                            });
                             udp_relay.send(source, target, proxy_address, data);
                             continue;
                         }
@@ -168,8 +173,7 @@ pub(super) fn spawn_udp_sessions(
                         proxy_dns: false,
                         quic_policy,
                         netstack_tx: udp_tx.clone(),
-                        udp_sessions: context.udp_sessions.clone(),
-                        direct_checker: context.direct_checker.clone(),
+                        router: context.router.clone(),
                         direct_domain_cache: context.direct_domain_cache.clone(),
                         shutdown: shutdown.clone(),
                     };
@@ -222,8 +226,7 @@ pub(super) async fn handle_tun_udp(
         proxy_dns,
         quic_policy,
         netstack_tx,
-        udp_sessions,
-        direct_checker,
+        router,
         direct_domain_cache,
         shutdown,
     } = context;
@@ -255,20 +258,26 @@ pub(super) async fn handle_tun_udp(
     let mut direct_label = target_label.clone();
     let proxy_address = address.clone();
     let mut proxy_reason = None;
+    // proxy DNS 等内部目标固定走默认分组。
+    let mut outbound = Outbound::Proxy(router.default_group().index());
     if !proxy_dns_request {
-        if direct_checker.is_direct(&address) {
-            direct_target = Some(target);
-        } else if direct_checker.has_domain_direct_rules()
-            && let Some(domain) = direct_domain_cache
-                .matching_domain_for_ip(target.ip(), |domain| {
-                    direct_checker.is_direct_domain(domain)
-                })
-        {
-            debug!(
-                "Android TUN UDP cached direct domain matched: {} ({})",
-                target, domain
-            );
-            direct_label = format!("{} ({})", target_label, domain);
+        let policy = router.policy();
+        let cached_domain = if policy.has_domain_rules() {
+            direct_domain_cache
+                .matching_domain_for_ip(target.ip(), |domain| policy.matches_domain_rule(domain))
+        } else {
+            None
+        };
+        outbound =
+            policy.route_with_domain(&address, TransportProtocol::Udp, cached_domain.as_deref());
+        if outbound == Outbound::Direct {
+            if let Some(domain) = cached_domain {
+                debug!(
+                    "Android TUN UDP cached direct domain matched: {} ({})",
+                    target, domain
+                );
+                direct_label = format!("{} ({})", target_label, domain);
+            }
             direct_target = Some(target);
         }
     }
@@ -284,10 +293,10 @@ pub(super) async fn handle_tun_udp(
         proxy_reason = Some(format!("cached domain {domain}"));
     }
 
-    let route = classify_udp_route(target.port(), quic_policy, direct_target.is_some());
+    let route = classify_udp_route(target.port(), quic_policy, outbound);
     if route == UdpRoute::Block {
         debug!(
-            "Android TUN UDP/443 QUIC blocked by explicit policy {:?} -> {}; waiting for TCP/TLS fallback",
+            "Android TUN UDP blocked by QUIC policy {:?} or routing -> {}",
             quic_policy, target_label
         );
         drain_dropped_udp(rx, shutdown).await;
@@ -316,13 +325,25 @@ pub(super) async fn handle_tun_udp(
     }
 
     let proxy_label = proxy_target_label(&target_label, proxy_reason.as_deref());
+    let proxy_group = match route {
+        UdpRoute::Proxy(index) => &router.groups()[index],
+        _ => router.default_group(),
+    };
     if proxy_dns_request {
         debug!("Android TUN UDP DNS -> proxy -> {}", target_label);
     } else {
-        debug!("Android TUN UDP fallback proxy -> {}", proxy_label);
-        android_log::info(format!("Android TUN UDP PROXY {proxy_label}"));
+        debug!(
+            "Android TUN UDP fallback proxy {} -> {}",
+            proxy_group.name(),
+            proxy_label
+        );
+        android_log::info(format!(
+            "Android TUN UDP PROXY {} {proxy_label}",
+            proxy_group.name()
+        ));
     }
-    let proxy_io = match udp_sessions
+    let proxy_io = match proxy_group
+        .udp_sessions()
         .connect_to_target(proxy_address, TransportProtocol::Udp)
         .await
     {
@@ -393,23 +414,14 @@ pub(super) async fn handle_tun_udp(
     Ok(())
 }
 
-fn classify_udp_route(
-    target_port: u16,
-    quic_policy: QuicPolicy,
-    direct_access_match: bool,
-) -> UdpRoute {
-    if target_port == 443 {
-        if quic_policy.should_block_udp443() {
-            UdpRoute::Block
-        } else if direct_access_match {
-            UdpRoute::Direct
-        } else {
-            UdpRoute::Proxy
-        }
-    } else if direct_access_match {
-        UdpRoute::Direct
-    } else {
-        UdpRoute::Proxy
+fn classify_udp_route(target_port: u16, quic_policy: QuicPolicy, outbound: Outbound) -> UdpRoute {
+    if target_port == 443 && quic_policy.should_block_udp443() {
+        return UdpRoute::Block;
+    }
+    match outbound {
+        Outbound::Direct => UdpRoute::Direct,
+        Outbound::Block => UdpRoute::Block,
+        Outbound::Proxy(index) => UdpRoute::Proxy(index),
     }
 }
 
@@ -538,37 +550,47 @@ fn proxy_target_label(target_label: &str, reason: Option<&str>) -> String {
 #[cfg(test)]
 mod route_tests {
     use super::{UdpRoute, classify_udp_route};
-    use common::QuicPolicy;
+    use common::{Outbound, QuicPolicy};
+
+    const PROXY: Outbound = Outbound::Proxy(0);
 
     #[test]
     fn ordinary_udp_preserves_direct_and_proxy_routing() {
         assert_eq!(
-            classify_udp_route(3478, QuicPolicy::Allow, false),
-            UdpRoute::Proxy
+            classify_udp_route(3478, QuicPolicy::Allow, PROXY),
+            UdpRoute::Proxy(0)
         );
         assert_eq!(
-            classify_udp_route(3478, QuicPolicy::Allow, true),
+            classify_udp_route(3478, QuicPolicy::Allow, Outbound::Direct),
             UdpRoute::Direct
         );
+        assert_eq!(
+            classify_udp_route(3478, QuicPolicy::Allow, Outbound::Proxy(2)),
+            UdpRoute::Proxy(2)
+        );
+        assert_eq!(
+            classify_udp_route(3478, QuicPolicy::Allow, Outbound::Block),
+            UdpRoute::Block
+        );
     }
 
     #[test]
-    fn quic_allow_routes_udp443_by_direct_access_rules() {
+    fn quic_allow_routes_udp443_by_routing_rules() {
         assert_eq!(
-            classify_udp_route(443, QuicPolicy::Allow, false),
-            UdpRoute::Proxy
+            classify_udp_route(443, QuicPolicy::Allow, PROXY),
+            UdpRoute::Proxy(0)
         );
         assert_eq!(
-            classify_udp_route(443, QuicPolicy::Allow, true),
+            classify_udp_route(443, QuicPolicy::Allow, Outbound::Direct),
             UdpRoute::Direct
         );
     }
 
     #[test]
-    fn explicit_quic_block_overrides_direct_access_routing() {
-        for direct_access_match in [false, true] {
+    fn explicit_quic_block_overrides_routing() {
+        for outbound in [Outbound::Direct, PROXY, Outbound::Block] {
             assert_eq!(
-                classify_udp_route(443, QuicPolicy::Block, direct_access_match),
+                classify_udp_route(443, QuicPolicy::Block, outbound),
                 UdpRoute::Block
             );
         }
@@ -14,9 +14,9 @@ use tokio::sync::mpsc::{self, error::TrySendError};
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, info, warn};
 
-use super::ForwardContext;
 use super::udp::UdpWriter;
 use crate::error::Result;
+use crate::yamux_session::AndroidYamuxSessionManager;
 
 const UDP_FLOW_TTL: Duration = Duration::from_secs(300);
 const UDP_RELAY_CHANNEL_SIZE: usize = 4096;
@@ -175,7 +175,7 @@ impl UdpRelayState {
 
 impl UdpRelay {
     pub(super) fn spawn(
-        context: ForwardContext,
+        sessions: Arc<AndroidYamuxSessionManager>,
         netstack_tx: UdpWriter,
         shutdown: CancellationToken,
     ) -> Arc<Self> {
@@ -188,7 +188,7 @@ impl UdpRelay {
             spawn_guarded(
                 "android tun udp relay",
                 run_udp_relay(
-                    context.clone(),
+                    sessions.clone(),
                     netstack_tx.clone(),
                     rx,
                     shutdown.clone(),
@@ -235,7 +235,7 @@ fn udp_relay_shard_index(client: SocketAddr, target: SocketAddr, shard_count: us
 }
 
 async fn run_udp_relay(
-    context: ForwardContext,
+    sessions: Arc<AndroidYamuxSessionManager>,
     netstack_tx: UdpWriter,
     mut rx: mpsc::Receiver<UdpRelayRequest>,
     shutdown: CancellationToken,
@@ -260,7 +260,7 @@ async fn run_udp_relay(
             }
         };
 
-        let connected = connect_udp_relay_stream(&context).await;
+        let connected = connect_udp_relay_stream(&sessions).await;
         let proxy_io = match connected {
             Ok(proxy_io) => {
                 reconnect_delay = Duration::from_millis(200);
@@ -371,10 +371,9 @@ async fn run_udp_relay(
 }
 
 async fn connect_udp_relay_stream(
-    context: &ForwardContext,
+    sessions: &AndroidYamuxSessionManager,
 ) -> Result<impl AsyncRead + AsyncWrite + Unpin + Send + 'static> {
-    context
-        .udp_sessions
+    sessions
         .connect_to_target(Address::UdpRelay, TransportProtocol::Udp)
         .await
 }
@@ -0,0 +1,78 @@
+//! 分流出口管理。
+//!
+//! HTTP/SOCKS 入口与 TUN TCP/UDP 都先把目标转换成 `protocol::Address`，再由
+//! [`AndroidOutboundRouter`] 按 `routing` 规则选择出口：本机直连、拒绝，或某个
+//! proxy 分组的会话管理器。规则引擎与桌面端共享 `common::routing`。
+
+use std::sync::Arc;
+
+use common::routing::{DEFAULT_PROXY_GROUP, OUTBOUND_PROXY, OutboundRouter, RoutingSource};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::android_log;
+use crate::config::AndroidAgentConfig;
+use crate::error::Result;
+use crate::yamux_session::AndroidYamuxSessionManager;
+
+pub use common::routing::address_to_string;
+
+/// 一个 proxy 分组的会话管理器。
+pub type AndroidProxyGroup = common::routing::ProxyGroup<AndroidYamuxSessionManager>;
+/// 一次分流的结果。
+pub type Route<'a> = common::routing::Route<'a, AndroidYamuxSessionManager>;
+/// 分流与分组选择与桌面端共享 `common::routing::OutboundRouter`。
+pub type AndroidOutboundRouter = OutboundRouter<AndroidYamuxSessionManager>;
+
+/// 按配置创建各 proxy 分组的会话管理器，并生成分流策略。
+pub fn build_router(
+    config: Arc<AndroidAgentConfig>,
+    shutdown: &CancellationToken,
+) -> Result<AndroidOutboundRouter> {
+    let mut groups = vec![new_group(
+        DEFAULT_PROXY_GROUP,
+        OUTBOUND_PROXY,
+        config.clone(),
+        shutdown,
+    )];
+    for group in &config.proxy_groups {
+        info!(
+            "Android proxy group {}: addrs=[{}] username={}",
+            group.name,
+            group.proxy_addrs.join(", "),
+            group.username
+        );
+        android_log::info(format!(
+            "Android proxy group initialized: name={}, addrs={}",
+            group.name,
+            group.proxy_addrs.len()
+        ));
+        groups.push(new_group(
+            groups.len(),
+            &group.name,
+            Arc::new(config.for_proxy_group(group)),
+            shutdown,
+        ));
+    }
+
+    let source = RoutingSource {
+        routing: config.routing.clone(),
+        direct_access: config.direct_access.clone(),
+        geoip: config.geoip.clone(),
+    };
+    Ok(OutboundRouter::new(source, groups)?)
+}
+
+fn new_group(
+    index: usize,
+    name: &str,
+    config: Arc<AndroidAgentConfig>,
+    shutdown: &CancellationToken,
+) -> AndroidProxyGroup {
+    AndroidProxyGroup::new(
+        index,
+        name,
+        AndroidYamuxSessionManager::new_tcp_direct(config.clone(), shutdown.clone()),
+        AndroidYamuxSessionManager::new_udp(config, shutdown.clone()),
+    )
+}
@@ -12,17 +12,16 @@ use tokio::net::TcpStream;
 use tracing::{debug, error, info};
 
 use crate::android_log;
-use crate::direct_access::{DirectAccessChecker, address_to_string};
 use crate::error::{AndroidAgentError, Result};
 use crate::http_proxy_clients::HttpProxyClientLease;
 use crate::http_proxy_io::connect_direct_tcp;
+use crate::routing::{AndroidOutboundRouter, Route, address_to_string};
 use crate::tcp_relay::{TcpRelayOptions, relay_tcp_bidirectional};
-use crate::yamux_session::{AndroidYamuxSessionManager, AndroidYamuxTargetStream};
+use crate::yamux_session::AndroidYamuxTargetStream;
 
 pub async fn handle_socks5_connection(
     stream: TcpStream,
-    sessions: Arc<AndroidYamuxSessionManager>,
-    direct_checker: Arc<DirectAccessChecker>,
+    router: Arc<AndroidOutboundRouter>,
     client: HttpProxyClientLease,
 ) -> Result<()> {
     info!("Android SOCKS5 proxy connection");
@@ -38,7 +37,7 @@ pub async fn handle_socks5_connection(
 
     match command {
         Socks5Command::TCPConnect => {
-            handle_tcp_connect(protocol, target_addr, sessions, direct_checker, client).await
+            handle_tcp_connect(protocol, target_addr, router, client).await
         }
         Socks5Command::TCPBind | Socks5Command::UDPAssociate => {
             let _ = protocol.reply_error(&ReplyError::CommandNotSupported).await;
@@ -50,57 +49,65 @@ pub async fn handle_socks5_connection(
 async fn handle_tcp_connect(
     protocol: Socks5ServerProtocol<TcpStream, CommandRead>,
     target_addr: TargetAddr,
-    sessions: Arc<AndroidYamuxSessionManager>,
-    direct_checker: Arc<DirectAccessChecker>,
+    router: Arc<AndroidOutboundRouter>,
     client: HttpProxyClientLease,
 ) -> Result<()> {
     let target_label = format_target_addr(&target_addr);
     let address = convert_target_addr(&target_addr);
-    let use_direct = direct_checker.is_direct(&address);
-
-    if use_direct {
-        let target = address_to_string(&address);
-        let mut target_stream = match connect_direct_tcp(&target).await {
-            Ok(stream) => stream,
-            Err(err) => {
-                error!("Android SOCKS5 direct connect failed {target}: {err}");
-                android_log::warn(format!(
-                    "Android SOCKS5 direct connect failed {target}: {err}"
-                ));
-                let _ = protocol.reply_error(&ReplyError::HostUnreachable).await;
-                return Err(AndroidAgentError::Connection(format!(
-                    "SOCKS5 direct connect failed: {err}"
-                )));
-            }
-        };
+    let proxy_group = match router.route(&address, TransportProtocol::Tcp) {
+        Route::Proxy(group) => group,
+        Route::Block => {
+            debug!("Android SOCKS5 target blocked by routing {target_label}");
+            android_log::info(format!("Android SOCKS5 BLOCK {target_label}"));
+            let _ = protocol
+                .reply_error(&ReplyError::ConnectionNotAllowed)
+                .await;
+            return Ok(());
+        }
+        Route::Direct => {
+            let target = address_to_string(&address);
+            let mut target_stream = match connect_direct_tcp(&target).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    error!("Android SOCKS5 direct connect failed {target}: {err}");
+                    android_log::warn(format!(
+                        "Android SOCKS5 direct connect failed {target}: {err}"
+                    ));
+                    let _ = protocol.reply_error(&ReplyError::HostUnreachable).await;
+                    return Err(AndroidAgentError::Connection(format!(
+                        "SOCKS5 direct connect failed: {err}"
+                    )));
+                }
+            };
 
-        let bind_addr: SocketAddr = "0.0.0.0:0".parse().unwrap();
-        let mut client_stream = protocol
-            .reply_success(bind_addr)
-            .await
-            .map_err(socks_error)?;
-        let cancel = client.cancel_token();
-        tokio::select! {
-            result = relay_tcp_bidirectional(
-                &mut client_stream,
-                &mut target_stream,
-                TcpRelayOptions::http_proxy(&target_label),
-            ) => match result {
-                Ok(stats) => debug!(
-                    "Android SOCKS5 direct tunnel closed {target_label}: up={} down={}",
-                    stats.client_to_remote, stats.remote_to_client
-                ),
-                Err(err) => debug!("Android SOCKS5 direct tunnel ended {target_label}: {err}"),
-            },
-            _ = cancel.cancelled() => {
-                debug!("Android SOCKS5 direct tunnel cancelled {target_label}");
+            let bind_addr: SocketAddr = "0.0.0.0:0".parse().unwrap();
+            let mut client_stream = protocol
+                .reply_success(bind_addr)
+                .await
+                .map_err(socks_error)?;
+            let cancel = client.cancel_token();
+            tokio::select! {
+                result = relay_tcp_bidirectional(
+                    &mut client_stream,
+                    &mut target_stream,
+                    TcpRelayOptions::http_proxy(&target_label),
+                ) => match result {
+                    Ok(stats) => debug!(
+                        "Android SOCKS5 direct tunnel closed {target_label}: up={} down={}",
+                        stats.client_to_remote, stats.remote_to_client
+                    ),
+                    Err(err) => debug!("Android SOCKS5 direct tunnel ended {target_label}: {err}"),
+                },
+                _ = cancel.cancelled() => {
+                    debug!("Android SOCKS5 direct tunnel cancelled {target_label}");
+                }
             }
+            return Ok(());
         }
-        return Ok(());
-    }
+    };
 
-    let mut connected_stream = match sessions
-        .as_ref()
+    let mut connected_stream = match proxy_group
+        .tcp_sessions()
         .connect_to_target(address, TransportProtocol::Tcp)
         .await
     {
@@ -101,7 +101,10 @@ impl AndroidYamuxSessionManager {
             .clamp(1, MAX_CONFIGURED_DIRECT_TCP_CONNECTS);
         // transport_mode=udp 只控制 UDP 的外层传输。TCP manager 始终使用
         // direct framed TCP，因此不应分配也不可能误用原生 UDP 会话池。
-        let udp_pool_size = if config.transport_mode.uses_native_udp_for(yamux_transport) {
+        let udp_pool_size = if config
+            .effective_transport_mode()
+            .uses_native_udp_for(yamux_transport)
+        {
             config.effective_udp_session_pool_size()
         } else {
             0
@@ -130,13 +133,17 @@ impl AndroidYamuxSessionManager {
         address: Address,
         transport: TransportProtocol,
     ) -> Result<AndroidYamuxTargetStream> {
-        let route = proxy_stream_route(self.config.transport_mode, self.yamux_transport, transport)
-            .ok_or_else(|| {
-                AndroidAgentError::Connection(format!(
-                    "Android {} only supports {:?} proxy streams",
-                    self.manager_name, self.yamux_transport
-                ))
-            })?;
+        let route = proxy_stream_route(
+            self.config.effective_transport_mode(),
+            self.yamux_transport,
+            transport,
+        )
+        .ok_or_else(|| {
+            AndroidAgentError::Connection(format!(
+                "Android {} only supports {:?} proxy streams",
+                self.manager_name, self.yamux_transport
+            ))
+        })?;
 
         match route {
             ProxyStreamRoute::DirectTcp => self.open_direct_tcp_stream(address).await,
@@ -199,7 +206,10 @@ impl AndroidYamuxSessionManager {
         for attempt in 0..2 {
             let handle = {
                 let mut current = self.udp_sessions[slot_index].lock().await;
-                if self.config.transport_mode.automatically_falls_back_to_tcp()
+                if self
+                    .config
+                    .effective_transport_mode()
+                    .automatically_falls_back_to_tcp()
                     && current
                         .as_ref()
                         .is_some_and(|handle| handle.connection.timed_out())
@@ -221,19 +221,51 @@ where
 {
     let remote_addr = config.remote_addr();
     let timeout = config.timeout_duration();
+    let pre_proxy = config.pre_proxy();
 
-    debug!("正在连接远端代理: {}", remote_addr);
+    // 配置了前置代理时，物理连接拨向前置代理，再在其上建立到远端代理的隧道。
+    let dial_addr = match &pre_proxy {
+        Some(pre_proxy) => {
+            debug!("正在通过前置代理 {pre_proxy:?} 连接远端代理: {remote_addr}");
+            pre_proxy.server_addr()
+        }
+        None => {
+            debug!("正在连接远端代理: {}", remote_addr);
+            remote_addr.clone()
+        }
+    };
 
     // TCP 连接 — 可选绑定到指定本地地址，以绕过可能存在的 TUN 默认路由。
-    let stream = if let Some(bind) = config.bind_addr() {
-        connect_bound(config, &remote_addr, bind, config.bind_interface(), timeout).await?
+    let mut stream = if let Some(bind) = config.bind_addr() {
+        connect_bound(config, &dial_addr, bind, config.bind_interface(), timeout).await?
     } else {
-        connect_unbound(config, &remote_addr, timeout).await?
+        connect_unbound(config, &dial_addr, timeout).await?
     };
     if let Err(err) = stream.set_nodelay(true) {
         warn!("设置代理连接 TCP_NODELAY 失败，将继续使用默认 TCP 行为: {err}");
     }
 
+    if let Some(pre_proxy) = pre_proxy {
+        match tokio::time::timeout(
+            timeout,
+            pre_proxy.establish_tunnel(&mut stream, &remote_addr),
+        )
+        .await
+        {
+            Ok(Ok(())) => debug!("已通过前置代理建立到 {remote_addr} 的隧道"),
+            Ok(Err(e)) => {
+                warn!("通过前置代理 {pre_proxy:?} 连接 {remote_addr} 失败: {e}");
+                return Err(e);
+            }
+            Err(_) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("通过前置代理连接 {remote_addr} 超时"),
+                ));
+            }
+        }
+    }
+
     Ok(stream)
 }
 
@@ -1,3 +1,4 @@
+use super::pre_proxy::PreProxy;
 use protocol::CompressionMode;
 use serde::{Deserialize, Serialize};
 use socket2::Socket;
@@ -61,6 +62,15 @@ pub trait ClientConnectionConfig: Debug {
         None
     }
 
+    /// 可选的前置代理（企业 HTTP/SOCKS5 代理）。
+    ///
+    /// 返回 `Some` 时，外层 TCP 连接先拨向前置代理，再通过 CONNECT 隧道到达
+    /// `remote_addr`；`bind_addr`/`bind_interface`/`protect_socket` 作用于到前置
+    /// 代理的物理连接。原生 UDP 会话无法穿过前置代理。
+    fn pre_proxy(&self) -> Option<PreProxy> {
+        None
+    }
+
     /// Give platform VPN implementations a chance to keep the control socket
     /// outside of the VPN before it connects.
     fn protect_socket(&self, _socket: &Socket, _dst: SocketAddr) -> io::Result<()> {
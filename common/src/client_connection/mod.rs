@@ -2,6 +2,7 @@
 
 pub mod authenticated;
 pub mod config;
+pub mod pre_proxy;
 pub mod socket_bind;
 pub mod stream;
 pub mod udp;
@@ -10,6 +11,7 @@ pub mod yamux;
 // 重新导出公共项
 pub use authenticated::AuthenticatedConnection;
 pub use config::{BindInterface, ClientConnectionConfig};
+pub use pre_proxy::{PreProxy, PreProxyScheme};
 pub use socket_bind::bind_socket_to_interface;
 pub use stream::ClientStream;
 pub use udp::{UdpClientConnection, UdpClientStream};
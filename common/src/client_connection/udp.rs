@@ -20,7 +20,7 @@ use std::task::{Context, Poll};
 use std::time::Duration;
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::net::UdpSocket;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 use tokio::time::{Instant, MissedTickBehavior};
 use tokio_util::sync::PollSender;
 use tracing::{debug, info, trace, warn};
@@ -64,6 +64,9 @@ enum ClientCommand {
     Close {
         flow_id: u64,
     },
+    Ping {
+        reply: oneshot::Sender<()>,
+    },
 }
 
 impl UdpClientConnection {
@@ -71,9 +74,28 @@ impl UdpClientConnection {
     where
         C: ClientConnectionConfig,
     {
+        if config.pre_proxy().is_some() {
+            // 企业 HTTP/SOCKS5 代理只转发 TCP，原生 UDP 会绕过它直连 proxy。
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "配置前置代理时不能使用原生 UDP 会话",
+            ));
+        }
         let timeout = config.timeout_duration();
         let socket = connect_udp_socket(config).await?;
         let (session_id, codec) = authenticate_udp_session(&socket, config, timeout).await?;
+        let connection = Self::start(socket, session_id, codec, timeout);
+        info!(session = %hex::encode(session_id), "已建立原生加密 UDP proxy 会话");
+        Ok(connection)
+    }
+
+    /// 在已认证的 socket 上启动会话 driver，返回与之相连的客户端句柄。
+    fn start(
+        socket: UdpSocket,
+        session_id: [u8; 16],
+        codec: UdpSessionCodec,
+        timeout: Duration,
+    ) -> Self {
         let (command_tx, command_rx) = mpsc::channel(SESSION_COMMAND_CAPACITY);
         let closed = Arc::new(AtomicBool::new(false));
         let driver_closed = closed.clone();
@@ -94,15 +116,14 @@ impl UdpClientConnection {
         if first_flow_id == 0 {
             first_flow_id = 1;
         }
-        info!(session = %hex::encode(session_id), "已建立原生加密 UDP proxy 会话");
-        Ok(Self {
+        Self {
             inner: Arc::new(UdpClientConnectionInner {
                 command_tx,
                 closed,
                 timed_out,
                 next_flow_id: AtomicU64::new(first_flow_id),
             }),
-        })
+        }
     }
 
     pub fn is_closed(&self) -> bool {
@@ -117,6 +138,31 @@ impl UdpClientConnection {
         self.inner.timed_out.load(Ordering::Acquire)
     }
 
+    /// Sends one authenticated `Ping` over this session and waits for the
+    /// matching `Pong`, returning the round-trip time. Auto mode uses it to
+    /// confirm that a fresh session really carries traffic both ways before
+    /// moving a slot back from TCP/Yamux.
+    pub async fn ping(&self, timeout: Duration) -> io::Result<Duration> {
+        let started = Instant::now();
+        let (reply, pong) = oneshot::channel();
+        self.inner
+            .command_tx
+            .send(ClientCommand::Ping { reply })
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::NotConnected, "原生 UDP 会话已关闭"))?;
+        match tokio::time::timeout(timeout, pong).await {
+            Ok(Ok(())) => Ok(started.elapsed()),
+            Ok(Err(_)) => Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "原生 UDP 会话已关闭",
+            )),
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "原生 UDP Ping 响应超时",
+            )),
+        }
+    }
+
     pub async fn connect_to_target(
         &self,
         address: Address,
@@ -335,6 +381,8 @@ async fn run_session_driver(
     configured_timeout: Duration,
 ) -> io::Result<()> {
     let mut streams = HashMap::<u64, mpsc::Sender<Vec<u8>>>::new();
+    // 主动 ping 等待的 pong，按 token 匹配。
+    let mut pending_pings = HashMap::<u64, oneshot::Sender<()>>::new();
     let mut receive_buffer = vec![0_u8; UDP_MAX_DATAGRAM_SIZE + 1];
     let mut keepalive = tokio::time::interval(SESSION_KEEPALIVE_INTERVAL);
     keepalive.set_missed_tick_behavior(MissedTickBehavior::Delay);
@@ -368,6 +416,12 @@ async fn run_session_driver(
                         streams.remove(&flow_id);
                         Some(UdpSessionMessage::Close { flow_id, reason: None })
                     }
+                    ClientCommand::Ping { reply } => {
+                        let token = ping_token;
+                        ping_token = ping_token.wrapping_add(1);
+                        pending_pings.insert(token, reply);
+                        Some(UdpSessionMessage::Ping { token })
+                    }
                 };
                 if let Some(message) = message {
                     send_message(&socket, &mut codec, &message).await?;
@@ -418,7 +472,11 @@ async fn run_session_driver(
                     UdpSessionMessage::Ping { token } => {
                         send_message(&socket, &mut codec, &UdpSessionMessage::Pong { token }).await?;
                     }
-                    UdpSessionMessage::Pong { .. } => {}
+                    UdpSessionMessage::Pong { token } => {
+                        if let Some(reply) = pending_pings.remove(&token) {
+                            let _ = reply.send(());
+                        }
+                    }
                     UdpSessionMessage::OpenData { .. } => {
                         trace!("忽略 proxy 发来的意外 UDP OpenData");
                     }
@@ -431,6 +489,8 @@ async fn run_session_driver(
                         "原生 UDP 会话保活响应超时",
                     ));
                 }
+                // 调用方已放弃等待的 ping 不再保留。
+                pending_pings.retain(|_, reply| !reply.is_closed());
                 send_message(&socket, &mut codec, &UdpSessionMessage::Ping { token: ping_token }).await?;
                 ping_token = ping_token.wrapping_add(1);
             }
@@ -693,4 +753,58 @@ mod tests {
         assert_eq!(stream.read(&mut exact).await.unwrap(), 4);
         assert_eq!(exact, [1, 2, 3, 4]);
     }
+
+    #[tokio::test]
+    async fn ping_completes_only_on_matching_pong_from_proxy() {
+        let session_id = [7_u8; 16];
+        let codec = |role| UdpSessionCodec::new(role, session_id, [1; 32], [2; 32], [3; 32]);
+        let proxy = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let agent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        agent.connect(proxy.local_addr().unwrap()).await.unwrap();
+        proxy.connect(agent.local_addr().unwrap()).await.unwrap();
+        let connection = UdpClientConnection::start(
+            agent,
+            session_id,
+            codec(UdpSessionRole::Agent).unwrap(),
+            Duration::from_secs(5),
+        );
+        let mut proxy_codec = codec(UdpSessionRole::Proxy).unwrap();
+        let mut buffer = vec![0_u8; UDP_MAX_DATAGRAM_SIZE + 1];
+
+        let mut ping = tokio::spawn({
+            let connection = connection.clone();
+            async move { connection.ping(Duration::from_secs(2)).await }
+        });
+        let size = proxy.recv(&mut buffer).await.unwrap();
+        let Some(UdpSessionMessage::Ping { token }) =
+            proxy_codec.decode_datagram(&buffer[..size]).unwrap()
+        else {
+            panic!("ping did not reach the proxy");
+        };
+
+        // token 不匹配的 pong 不能完成等待中的 ping。
+        let wrong = UdpSessionMessage::Pong {
+            token: token.wrapping_add(1),
+        };
+        send_message(&proxy, &mut proxy_codec, &wrong)
+            .await
+            .unwrap();
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), &mut ping)
+                .await
+                .is_err()
+        );
+        send_message(&proxy, &mut proxy_codec, &UdpSessionMessage::Pong { token })
+            .await
+            .unwrap();
+        ping.await.unwrap().unwrap();
+
+        // proxy 不回应时按调用方给的超时失败，会话本身保持可用。
+        let error = connection
+            .ping(Duration::from_millis(20))
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert!(!connection.is_closed());
+    }
 }
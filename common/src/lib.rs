@@ -2,6 +2,8 @@ pub mod client_connection;
 pub mod dns;
 pub mod error;
 pub mod quic;
+pub mod routing;
+pub mod sniff;
 pub mod task_guard;
 pub mod tcp_keepalive;
 pub mod tcp_listener;
@@ -10,13 +12,17 @@ pub mod tun_control;
 pub mod yamux_settings;
 
 pub use client_connection::{
-    AuthenticatedConnection, BindInterface, ClientConnectionConfig, ClientStream,
-    UdpClientConnection, UdpClientStream, YAMUX_OPEN_STREAM_TIMEOUT_MESSAGE,
+    AuthenticatedConnection, BindInterface, ClientConnectionConfig, ClientStream, PreProxy,
+    PreProxyScheme, UdpClientConnection, UdpClientStream, YAMUX_OPEN_STREAM_TIMEOUT_MESSAGE,
     YAMUX_SESSION_STREAM_CAPACITY_EXHAUSTED_MESSAGE, YAMUX_TARGET_CONNECT_RESPONSE_TIMEOUT_MESSAGE,
     YamuxClientConnection, YamuxClientStream, bind_socket_to_interface,
 };
 pub use error::{CommonError, Result};
 pub use quic::{QuicPolicy, QuicUdpStats, QuicUdpStatsSnapshot};
+pub use routing::{
+    DirectAccessConfig, DirectAccessMode, GeoIpConfig, Outbound, RouteDecision, RoutingConfig,
+    RoutingPolicy, RoutingRuleConfig, SourceProcess,
+};
 pub use task_guard::{install_known_smoltcp_panic_hook, panic_payload_message, spawn_guarded};
 pub use tcp_keepalive::{
     PROXY_TCP_KEEPALIVE_INTERVAL, PROXY_TCP_KEEPALIVE_RETRIES, PROXY_TCP_KEEPALIVE_TIME,
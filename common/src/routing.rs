@@ -0,0 +1,903 @@
+//! agent 分流策略引擎。
+//!
+//! desktop 与 Android 的所有入口（HTTP、SOCKS、TUN TCP/UDP）都先把目标转换成
+//! `protocol::Address`，再交给这里按顺序匹配 `[routing]` 规则，得到一个命名出口：
+//! `direct` 本机直连、`block` 拒绝，或某个 proxy 分组。第一条命中的规则生效，
+//! 全部未命中时使用 `final` 出口。
+//!
+//! 旧版 `[direct_access]` 三态配置仍然可用：未配置 `[routing]` 时会被转换成
+//! 等价的规则集（直连规则 + `final = "proxy"`）。
+//!
+//! 每条规则的目标模式（含引用的规则集文件）编译成一个 [`RuleSet`]，再与端口、
+//! 网络类型条件组合：目标、端口、网络三类条件各自内部为“或”，三类之间为“与”，
+//! 规则中未出现的类别不做限制。`geoip:CN` 也是目标条件，按 IP 查询离线 GeoIP
+//! 数据库，只对 IP 目标（含 TUN 中由 DNS 缓存还原出域名的 IP）生效，不会为域名目标
+//! 额外发起解析。
+//!
+//! `process-name:`/`process-path:` 按发起连接的本机进程匹配，与目标、端口、网络
+//! 同为“与”关系。只有能反查到进程的入口（Linux TUN）会提供进程信息，其余入口
+//! 上带进程条件的规则不会命中。
+
+mod geoip;
+mod outbound;
+mod pac;
+mod rule_set;
+
+use crate::{CommonError, Result};
+use protocol::{Address, TransportProtocol};
+use serde::{Deserialize, Serialize};
+use std::cell::OnceCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+
+pub use geoip::{CountryCode, GeoIpConfig, GeoIpDatabase};
+pub use outbound::{
+    OutboundRouter, ProxyGroup, ResolvedDecision, Route, RoutingOverrides, RoutingSource,
+};
+pub use pac::PacScript;
+pub use rule_set::{
+    RuleSet, RuleSetEntry, compile_rule_set_file, decode_rule_set, encode_rule_set,
+    load_rule_set_file, parse_text_rule_set,
+};
+
+/// 内置出口：本机直连目标。
+pub const OUTBOUND_DIRECT: &str = "direct";
+/// 内置出口：拒绝连接（TCP 返回错误/RST，UDP 丢包）。
+pub const OUTBOUND_BLOCK: &str = "block";
+/// 内置出口：顶层 `proxy_addrs`/`username`/私钥组成的默认 proxy 分组。
+pub const OUTBOUND_PROXY: &str = "proxy";
+
+/// 默认 proxy 分组在分组列表中的下标。
+pub const DEFAULT_PROXY_GROUP: usize = 0;
+
+/// 内置强制代理规则集：这些域名即使命中指向 `direct` 的域名规则也不会直连。
+/// 可通过 `force_proxy_rule_set` 换成自定义规则集文件。
+const DEFAULT_FORCE_PROXY_RULE_SET: &str = include_str!("routing/force_proxy.list");
+
+/// 旧版直连模式；仅用于兼容 `[direct_access]` 配置。
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DirectAccessMode {
+    /// 所有流量通过代理（默认）
+    #[default]
+    ProxyAll,
+    /// 所有流量直连（完全绕过代理）
+    DirectAll,
+    /// 命中规则的目标直连，其余通过代理
+    Rules,
+}
+
+/// 旧版直连访问配置，加载时转换为 [`RoutingPolicy`]。
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct DirectAccessConfig {
+    /// 访问模式: proxy_all、direct_all 或 rules
+    #[serde(default)]
+    pub mode: DirectAccessMode,
+
+    /// 直连访问规则列表（当 mode = "rules" 时使用），格式与 `[routing]` 规则的 `match` 相同。
+    #[serde(default)]
+    pub rules: Vec<String>,
+
+    /// 强制代理规则集文件；未设置时使用内置列表，设置为空字符串表示关闭。
+    #[serde(default)]
+    pub force_proxy_rule_set: Option<String>,
+}
+
+/// 多出口分流配置。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoutingConfig {
+    /// 按顺序匹配的规则，第一条命中的规则决定出口。
+    #[serde(default)]
+    pub rules: Vec<RoutingRuleConfig>,
+
+    /// 所有规则都未命中时使用的出口，默认 `proxy`。
+    #[serde(rename = "final", default = "default_final_outbound")]
+    pub final_outbound: String,
+
+    /// 强制代理规则集文件；未设置时使用内置列表，设置为空字符串表示关闭。
+    #[serde(default)]
+    pub force_proxy_rule_set: Option<String>,
+}
+
+impl Default for RoutingConfig {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            final_outbound: default_final_outbound(),
+            force_proxy_rule_set: None,
+        }
+    }
+}
+
+/// 一条分流规则：`match` 中的条件全部满足时使用 `outbound`。
+///
+/// 目标模式（任一命中即可）:
+/// - 精确域名: "localhost"、"example.com"
+/// - 通配符域名: "*.local"、"*.example.com"
+/// - 域名后缀（含自身）: "domain-suffix:example.com"
+/// - 域名关键字: "domain-keyword:google"
+/// - 域名正则: "domain-regex:^ads?\\."
+/// - 精确 IP: "127.0.0.1"、"::1"
+/// - CIDR 范围: "10.0.0.0/8"、"fd00::/8"
+/// - 规则集文件: "rule-set:rules/cn.list"
+/// - GeoIP 国家/地区: "geoip:CN"，需要配置 `[geoip]` 数据库
+///
+/// 附加条件:
+/// - 目标端口: "port:443"、"port:8000-9000"
+/// - 网络类型: "network:tcp"、"network:udp"
+/// - 发起进程名: "process-name:slack"，按可执行文件名比较，不区分大小写
+/// - 发起进程路径: "process-path:/usr/bin/firefox"，按可执行文件完整路径比较
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoutingRuleConfig {
+    #[serde(rename = "match")]
+    pub matches: Vec<String>,
+    pub outbound: String,
+}
+
+fn default_final_outbound() -> String {
+    OUTBOUND_PROXY.to_string()
+}
+
+/// 一次分流决策的结果。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outbound {
+    Direct,
+    Block,
+    /// proxy 分组下标；[`DEFAULT_PROXY_GROUP`] 是默认分组，其余按配置顺序排列。
+    Proxy(usize),
+}
+
+/// 出口以及决定它的规则下标；`rule` 为 `None` 表示使用了 `final` 出口或内部目标。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RouteDecision {
+    pub outbound: Outbound,
+    pub rule: Option<usize>,
+}
+
+/// 发起连接的本机进程，由入口按源地址反查得到。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceProcess {
+    pub pid: u32,
+    /// 可执行文件名，如 `slack`、`firefox`。
+    pub name: String,
+    /// 可执行文件完整路径；无法读取时为空。
+    pub path: String,
+}
+
+impl fmt::Display for SourceProcess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}({})", self.name, self.pid)
+    }
+}
+
+/// `match` 中的单个条件。
+enum Condition {
+    Destination(RuleSetEntry),
+    RuleSetFile(String),
+    GeoIp(CountryCode),
+    Port(RangeInclusive<u16>),
+    Network(TransportProtocol),
+    ProcessName(String),
+    ProcessPath(String),
+}
+
+impl Condition {
+    fn parse(pattern: &str) -> Option<Self> {
+        let pattern = pattern.trim();
+        if let Some(path) = pattern.strip_prefix("rule-set:") {
+            let path = path.trim();
+            return (!path.is_empty()).then(|| Self::RuleSetFile(path.to_string()));
+        }
+        if let Some(code) = pattern.strip_prefix("geoip:") {
+            return CountryCode::parse(code).map(Self::GeoIp);
+        }
+        if let Some(ports) = pattern.strip_prefix("port:") {
+            let (start, end) = ports.split_once('-').unwrap_or((ports, ports));
+            let start: u16 = start.trim().parse().ok()?;
+            let end: u16 = end.trim().parse().ok()?;
+            return (start <= end).then_some(Self::Port(start..=end));
+        }
+        if let Some(name) = pattern.strip_prefix("process-name:") {
+            let name = name.trim();
+            return (!name.is_empty()).then(|| Self::ProcessName(name.to_string()));
+        }
+        if let Some(path) = pattern.strip_prefix("process-path:") {
+            let path = path.trim();
+            return (!path.is_empty()).then(|| Self::ProcessPath(path.to_string()));
+        }
+        if let Some(network) = pattern.strip_prefix("network:") {
+            return match network.trim().to_ascii_lowercase().as_str() {
+                "tcp" => Some(Self::Network(TransportProtocol::Tcp)),
+                "udp" => Some(Self::Network(TransportProtocol::Udp)),
+                _ => None,
+            };
+        }
+        RuleSetEntry::parse(pattern).map(Self::Destination)
+    }
+}
+
+#[derive(Debug)]
+struct Rule {
+    // 没有域名/IP 目标模式时为 None；与 countries 都为空表示不限制目标。
+    destinations: Option<RuleSet>,
+    countries: Vec<CountryCode>,
+    ports: Vec<RangeInclusive<u16>>,
+    networks: Vec<TransportProtocol>,
+    process_names: Vec<String>,
+    process_paths: Vec<String>,
+    outbound: Outbound,
+    // 规则的配置原文，用于连接列表等展示。
+    label: String,
+}
+
+impl Rule {
+    /// 把条件编译成规则；规则集文件按路径缓存，同一策略中只读取一次。
+    fn compile(
+        conditions: Vec<Condition>,
+        outbound: Outbound,
+        files: &mut HashMap<String, Vec<RuleSetEntry>>,
+    ) -> Result<Self> {
+        let mut entries = Vec::new();
+        let mut has_destinations = false;
+        let mut countries = Vec::new();
+        let mut ports = Vec::new();
+        let mut networks = Vec::new();
+        let mut process_names = Vec::new();
+        let mut process_paths = Vec::new();
+        for condition in conditions {
+            match condition {
+                Condition::Destination(entry) => {
+                    has_destinations = true;
+                    entries.push(entry);
+                }
+                Condition::RuleSetFile(path) => {
+                    has_destinations = true;
+                    if !files.contains_key(&path) {
+                        let loaded = load_rule_set_file(&path)?;
+                        info!("规则集 {path} 已加载：{} 条", loaded.len());
+                        files.insert(path.clone(), loaded);
+                    }
+                    entries.extend(files[&path].iter().cloned());
+                }
+                Condition::GeoIp(code) => countries.push(code),
+                Condition::Port(range) => ports.push(range),
+                Condition::Network(network) => networks.push(network),
+                Condition::ProcessName(name) => process_names.push(name),
+                Condition::ProcessPath(path) => process_paths.push(path),
+            }
+        }
+        Ok(Self {
+            destinations: has_destinations
+                .then(|| RuleSet::new(&entries))
+                .transpose()?,
+            countries,
+            ports,
+            networks,
+            process_names,
+            process_paths,
+            outbound,
+            label: String::new(),
+        })
+    }
+
+    /// 端口与网络类型条件是否满足。
+    fn matches_transport(&self, port: Option<u16>, network: TransportProtocol) -> bool {
+        (self.networks.is_empty() || self.networks.contains(&network))
+            && (self.ports.is_empty()
+                || port.is_some_and(|port| self.ports.iter().any(|range| range.contains(&port))))
+    }
+
+    /// 进程条件是否满足；规则带进程条件但入口没有提供进程时不命中。
+    fn matches_process(&self, process: Option<&SourceProcess>) -> bool {
+        if !self.has_process_conditions() {
+            return true;
+        }
+        process.is_some_and(|process| {
+            self.process_names
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(&process.name))
+                || self.process_paths.contains(&process.path)
+        })
+    }
+
+    fn has_process_conditions(&self) -> bool {
+        !self.process_names.is_empty() || !self.process_paths.is_empty()
+    }
+
+    fn has_destination_conditions(&self) -> bool {
+        self.destinations.is_some() || !self.countries.is_empty()
+    }
+
+    fn has_domain_entries(&self) -> bool {
+        self.destinations
+            .as_ref()
+            .is_some_and(RuleSet::has_domain_entries)
+    }
+
+    fn match_domain(&self, host: &str) -> bool {
+        self.destinations
+            .as_ref()
+            .is_some_and(|destinations| destinations.match_domain(host))
+    }
+}
+
+/// 分流目标在匹配前的规范化形式。
+enum Target {
+    Domain(String),
+    Ip(IpAddr),
+    /// ProxyDns/UdpRelay 等内部虚拟目标，只能经默认 proxy 分组访问。
+    Internal,
+}
+
+impl Target {
+    fn from_address(address: &Address) -> Self {
+        match address {
+            Address::Domain { host, .. } => {
+                let host = normalize_domain(host);
+                // 有时域名会以 IP 字符串形式传入，如 "10.0.0.1"
+                match host.parse::<IpAddr>() {
+                    Ok(ip) => Self::Ip(ip),
+                    Err(_) => Self::Domain(host),
+                }
+            }
+            Address::Ipv4 { addr, .. } => Self::Ip(IpAddr::V4(Ipv4Addr::from(*addr))),
+            Address::Ipv6 { addr, .. } => Self::Ip(IpAddr::V6(Ipv6Addr::from(*addr))),
+            Address::ProxyDns { .. } | Address::UdpRelay => Self::Internal,
+        }
+    }
+}
+
+/// 已编译的分流策略。规则在构造时一次性解析，连接处理路径只做匹配。
+#[derive(Debug)]
+pub struct RoutingPolicy {
+    rules: Vec<Rule>,
+    final_outbound: Outbound,
+    // 命中的域名不会经由指向 direct 的域名规则直连。
+    force_proxy: Option<RuleSet>,
+    // 只有规则用到 geoip: 时才加载。
+    geoip: Option<Arc<GeoIpDatabase>>,
+    // 下标与 Outbound::Proxy 对应，第 0 个是默认分组 "proxy"。
+    proxy_groups: Vec<String>,
+    // 指向默认分组 "proxy" 的出口实际使用的分组，运行中可以切换。
+    default_proxy: usize,
+}
+
+impl RoutingPolicy {
+    /// 从 `[routing]` 配置构建策略。`proxy_groups` 是除默认分组外按顺序声明的
+    /// 分组名，规则引用未知出口或包含无法解析的目标模式时返回错误。
+    pub fn new(
+        config: &RoutingConfig,
+        geoip: Option<&GeoIpConfig>,
+        proxy_groups: &[String],
+    ) -> Result<Self> {
+        let proxy_groups = Self::group_names(proxy_groups)?;
+        let resolve = |name: &str| -> Result<Outbound> {
+            let name = name.trim();
+            match name {
+                OUTBOUND_DIRECT => Ok(Outbound::Direct),
+                OUTBOUND_BLOCK => Ok(Outbound::Block),
+                _ => proxy_groups
+                    .iter()
+                    .position(|group| group == name)
+                    .map(Outbound::Proxy)
+                    .ok_or_else(|| {
+                        CommonError::Config(format!("routing outbound {name:?} is not defined"))
+                    }),
+            }
+        };
+
+        let mut parsed = Vec::with_capacity(config.rules.len());
+        for (index, rule) in config.rules.iter().enumerate() {
+            let outbound = resolve(&rule.outbound)?;
+            let conditions = rule
+                .matches
+                .iter()
+                .map(|pattern| {
+                    Condition::parse(pattern).ok_or_else(|| {
+                        CommonError::Config(format!(
+                            "routing rule #{index} has invalid match pattern {pattern:?}"
+                        ))
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            if conditions.is_empty() {
+                return Err(CommonError::Config(format!(
+                    "routing rule #{index} has no match patterns"
+                )));
+            }
+            parsed.push((conditions, outbound));
+        }
+
+        let uses_geoip = parsed
+            .iter()
+            .flat_map(|(conditions, _)| conditions)
+            .any(|condition| matches!(condition, Condition::GeoIp(_)));
+        let geoip = match (uses_geoip, geoip) {
+            (false, _) => None,
+            (true, Some(geoip)) => Some(load_geoip(geoip)?),
+            (true, None) => {
+                return Err(CommonError::Config(
+                    "routing rules use geoip: but no [geoip] database is configured".to_string(),
+                ));
+            }
+        };
+
+        let mut files = HashMap::new();
+        let mut rules = Vec::with_capacity(parsed.len());
+        for (index, (conditions, outbound)) in parsed.into_iter().enumerate() {
+            let mut rule = Rule::compile(conditions, outbound, &mut files).map_err(|e| {
+                CommonError::Config(format!("routing rule #{index}: {}", config_message(e)))
+            })?;
+            rule.label = config.rules[index].matches.join(" ");
+            rules.push(rule);
+        }
+
+        let policy = Self {
+            rules,
+            final_outbound: resolve(&config.final_outbound)?,
+            force_proxy: load_force_proxy(config.force_proxy_rule_set.as_deref())?,
+            geoip,
+            proxy_groups,
+            default_proxy: DEFAULT_PROXY_GROUP,
+        };
+        policy.log_loaded("分流策略");
+        Ok(policy)
+    }
+
+    /// 把旧版 `[direct_access]` 转换成等价策略：direct_all 对应 `final = "direct"`，
+    /// rules 模式下每条规则指向 `direct`，其余走默认 proxy 分组。
+    ///
+    /// 旧配置中无效的规则会被跳过，避免一个坏规则让整个 agent 无法启动。
+    pub fn from_direct_access(
+        config: &DirectAccessConfig,
+        geoip: Option<&GeoIpConfig>,
+        proxy_groups: &[String],
+    ) -> Self {
+        let mut geoip_database = None;
+        let (mut rules, final_outbound) = match config.mode {
+            DirectAccessMode::ProxyAll => (Vec::new(), Outbound::Proxy(DEFAULT_PROXY_GROUP)),
+            DirectAccessMode::DirectAll => (Vec::new(), Outbound::Direct),
+            DirectAccessMode::Rules => {
+                // 旧配置各项之间是“或”：目标模式合并成一条规则，端口/网络条件
+                // 各自单独成规则，避免与目标模式变成“与”。
+                let mut destinations = Vec::new();
+                let mut transports = Vec::new();
+                for rule in &config.rules {
+                    match Condition::parse(rule) {
+                        Some(
+                            condition @ (Condition::Destination(_)
+                            | Condition::RuleSetFile(_)
+                            | Condition::GeoIp(_)),
+                        ) => destinations.push(condition),
+                        Some(condition) => transports.push(condition),
+                        None if !rule.trim().is_empty() => warn!("忽略无效的直连规则：{rule}"),
+                        None => {}
+                    }
+                }
+
+                let mut files = HashMap::new();
+                // 单个规则集文件加载失败只跳过该文件。
+                destinations.retain(|condition| match condition {
+                    Condition::RuleSetFile(path) => match load_rule_set_file(path) {
+                        Ok(entries) => {
+                            info!("规则集 {path} 已加载：{} 条", entries.len());
+                            files.insert(path.clone(), entries);
+                            true
+                        }
+                        Err(e) => {
+                            warn!("忽略无法加载的直连规则集 {path}：{e}");
+                            false
+                        }
+                    },
+                    _ => true,
+                });
+                if destinations
+                    .iter()
+                    .any(|condition| matches!(condition, Condition::GeoIp(_)))
+                {
+                    match geoip.map(load_geoip).transpose() {
+                        Ok(Some(database)) => geoip_database = Some(database),
+                        Ok(None) => warn!("未配置 [geoip] 数据库，忽略 geoip: 直连规则"),
+                        Err(e) => warn!("GeoIP 数据库加载失败，忽略 geoip: 直连规则：{e}"),
+                    }
+                    if geoip_database.is_none() {
+                        destinations.retain(|condition| !matches!(condition, Condition::GeoIp(_)));
+                    }
+                }
+                let mut rules = Vec::new();
+                if !destinations.is_empty() {
+                    match Rule::compile(destinations, Outbound::Direct, &mut files) {
+                        Ok(rule) => rules.push(rule),
+                        Err(e) => warn!("忽略无法编译的直连规则：{e}"),
+                    }
+                }
+                for condition in transports {
+                    rules.extend(Rule::compile(vec![condition], Outbound::Direct, &mut files));
+                }
+                (rules, Outbound::Proxy(DEFAULT_PROXY_GROUP))
+            }
+        };
+        let force_proxy =
+            load_force_proxy(config.force_proxy_rule_set.as_deref()).unwrap_or_else(|e| {
+                warn!("强制代理规则集加载失败，改用内置列表：{e}");
+                load_force_proxy(None).expect("built-in force proxy rule-set is valid")
+            });
+
+        // 旧配置没有引用分组，分组名只用于日志，重复名称交给 `new` 的调用方报错。
+        let mut groups = vec![OUTBOUND_PROXY.to_string()];
+        groups.extend(proxy_groups.iter().map(|group| group.trim().to_string()));
+        for rule in &mut rules {
+            rule.label = "direct_access".to_string();
+        }
+        let policy = Self {
+            rules,
+            final_outbound,
+            force_proxy,
+            geoip: geoip_database,
+            proxy_groups: groups,
+            default_proxy: DEFAULT_PROXY_GROUP,
+        };
+        policy.log_loaded(&format!("旧版直连配置（mode={:?}）", config.mode));
+        policy
+    }
+
+    /// 把指向默认分组 `proxy` 的规则与 final 改由 `index` 分组承担；DNS proxy 等
+    /// 内部目标仍使用默认分组。下标超出分组列表时返回错误。
+    pub fn with_default_proxy_group(mut self, index: usize) -> Result<Self> {
+        if index >= self.proxy_groups.len() {
+            return Err(CommonError::Config(format!(
+                "proxy group #{index} is not defined"
+            )));
+        }
+        self.default_proxy = index;
+        Ok(self)
+    }
+
+    /// 指向默认分组的出口当前实际使用的分组下标。
+    pub fn default_proxy_group(&self) -> usize {
+        self.default_proxy
+    }
+
+    /// 优先使用 `[routing]`；未配置时回退到旧版 `[direct_access]`。
+    pub fn from_configs(
+        routing: Option<&RoutingConfig>,
+        direct_access: &DirectAccessConfig,
+        geoip: Option<&GeoIpConfig>,
+        proxy_groups: &[String],
+    ) -> Result<Self> {
+        match routing {
+            Some(routing) => {
+                if direct_access.mode != DirectAccessMode::ProxyAll
+                    || !direct_access.rules.is_empty()
+                    || direct_access.force_proxy_rule_set.is_some()
+                {
+                    warn!("同时配置了 [routing] 与 [direct_access]，忽略 [direct_access]");
+                }
+                Self::new(routing, geoip, proxy_groups)
+            }
+            None => {
+                Self::group_names(proxy_groups)?;
+                Ok(Self::from_direct_access(direct_access, geoip, proxy_groups))
+            }
+        }
+    }
+
+    fn group_names(proxy_groups: &[String]) -> Result<Vec<String>> {
+        let mut names = vec![OUTBOUND_PROXY.to_string()];
+        let mut seen = HashSet::from([OUTBOUND_DIRECT, OUTBOUND_BLOCK, OUTBOUND_PROXY]);
+        for group in proxy_groups {
+            let group = group.trim();
+            if group.is_empty() {
+                return Err(CommonError::Config(
+                    "proxy group name must not be empty".to_string(),
+                ));
+            }
+            if !seen.insert(group) {
+                return Err(CommonError::Config(format!(
+                    "proxy group name {group:?} is reserved or duplicated"
+                )));
+            }
+            names.push(group.to_string());
+        }
+        Ok(names)
+    }
+
+    fn log_loaded(&self, source: &str) {
+        info!(
+            "{source}已加载：{} 条规则，final={}",
+            self.rules.len(),
+            self.describe(self.final_outbound)
+        );
+        if let Some(force_proxy) = &self.force_proxy {
+            info!("强制代理规则集：{force_proxy}");
+        }
+        for (index, rule) in self.rules.iter().enumerate() {
+            let destinations = rule
+                .destinations
+                .as_ref()
+                .map_or_else(|| "任意目标".to_string(), ToString::to_string);
+            debug!(
+                "  规则[{index}] -> {}: {destinations}，GeoIP {:?}，端口 {:?}，网络 {:?}，进程 {:?} {:?}",
+                self.describe(rule.outbound),
+                rule.countries,
+                rule.ports,
+                rule.networks,
+                rule.process_names,
+                rule.process_paths
+            );
+        }
+    }
+
+    /// 为目标地址选择出口，`network` 是该连接的传输协议。
+    pub fn route(&self, address: &Address, network: TransportProtocol) -> Outbound {
+        self.route_with_domain(address, network, None)
+    }
+
+    /// 为目标地址选择出口，并把 `domain` 当作该 IP 目标的已知域名一起参与匹配。
+    ///
+    /// 用于 TUN 场景：目标已经是 IP，但 DNS proxy 记录的 IP->域名缓存可以还原
+    /// 原始域名。规则仍按顺序匹配，某条规则命中 IP 或域名即生效。
+    pub fn route_with_domain(
+        &self,
+        address: &Address,
+        network: TransportProtocol,
+        domain: Option<&str>,
+    ) -> Outbound {
+        self.route_with_process(address, network, domain, None)
+    }
+
+    /// 在 [`route_with_domain`](Self::route_with_domain) 的基础上带上发起连接的本机进程，
+    /// 供 `process-name:`/`process-path:` 规则匹配。
+    pub fn route_with_process(
+        &self,
+        address: &Address,
+        network: TransportProtocol,
+        domain: Option<&str>,
+        process: Option<&SourceProcess>,
+    ) -> Outbound {
+        self.decide(address, network, domain, process).outbound
+    }
+
+    /// 与 [`route_with_process`](Self::route_with_process) 相同，同时返回命中的规则。
+    pub fn decide(
+        &self,
+        address: &Address,
+        network: TransportProtocol,
+        domain: Option<&str>,
+        process: Option<&SourceProcess>,
+    ) -> RouteDecision {
+        let port = address_port(address);
+        let (outbound, rule) = match Target::from_address(address) {
+            Target::Internal => (Outbound::Proxy(DEFAULT_PROXY_GROUP), None),
+            target => {
+                let rule = match target {
+                    Target::Domain(host) => {
+                        self.match_rules(None, Some(&host), port, network, process)
+                    }
+                    Target::Ip(ip) => {
+                        let domain = domain.map(normalize_domain);
+                        self.match_rules(Some(ip), domain.as_deref(), port, network, process)
+                    }
+                    Target::Internal => None,
+                };
+                let outbound =
+                    match rule.map_or(self.final_outbound, |index| self.rules[index].outbound) {
+                        Outbound::Proxy(DEFAULT_PROXY_GROUP) => Outbound::Proxy(self.default_proxy),
+                        outbound => outbound,
+                    };
+                (outbound, rule)
+            }
+        };
+        match process {
+            Some(process) => debug!(
+                "分流 {:?} ({network:?}，进程 {process}) -> {}",
+                address,
+                self.describe(outbound)
+            ),
+            None => debug!(
+                "分流 {:?} ({network:?}) -> {}",
+                address,
+                self.describe(outbound)
+            ),
+        }
+        RouteDecision { outbound, rule }
+    }
+
+    /// 规则的展示名：配置里的匹配条件原文；`None` 表示 `final`。
+    pub fn rule_label(&self, rule: Option<usize>) -> String {
+        match rule.and_then(|index| self.rules.get(index)) {
+            Some(rule) => rule.label.clone(),
+            None => "final".to_string(),
+        }
+    }
+
+    fn match_rules(
+        &self,
+        ip: Option<IpAddr>,
+        domain: Option<&str>,
+        port: Option<u16>,
+        network: TransportProtocol,
+        process: Option<&SourceProcess>,
+    ) -> Option<usize> {
+        let force_proxy = domain.is_some_and(|host| {
+            self.force_proxy
+                .as_ref()
+                .is_some_and(|force_proxy| force_proxy.match_domain(host))
+        });
+        // 同一次分流最多查询一次 GeoIP。
+        let country = OnceCell::new();
+        let country_of = |ip: IpAddr| {
+            *country.get_or_init(|| self.geoip.as_ref().and_then(|geoip| geoip.country(ip)))
+        };
+        self.rules.iter().position(|rule| {
+            if !rule.matches_transport(port, network) || !rule.matches_process(process) {
+                return false;
+            }
+            if !rule.has_destination_conditions() {
+                return true;
+            }
+            let ip_hit = ip.is_some_and(|ip| {
+                rule.destinations
+                    .as_ref()
+                    .is_some_and(|destinations| destinations.match_ip(&ip))
+                    || (!rule.countries.is_empty()
+                        && country_of(ip).is_some_and(|code| rule.countries.contains(&code)))
+            });
+            let domain_hit = !(force_proxy && rule.outbound == Outbound::Direct)
+                && domain.is_some_and(|host| rule.match_domain(host));
+            ip_hit || domain_hit
+        })
+    }
+
+    /// 只知道域名时，这个域名是否可能分流到 direct。
+    ///
+    /// TUN fake-IP 在 DNS 阶段用它决定要不要返回假地址：端口、网络类型、进程和 GeoIP 条件
+    /// 要到建连时才知道，这里一律当作可能满足。只要某条可能命中的规则指向 direct 就返回 true，
+    /// 调用方应改为返回真实地址，让建连时的分流仍能直连。IP/CIDR 条目只匹配 IP 字面量目标，
+    /// 不参与判断。
+    pub fn may_route_direct(&self, host: &str) -> bool {
+        let host = normalize_domain(host);
+        let force_proxy = self
+            .force_proxy
+            .as_ref()
+            .is_some_and(|force_proxy| force_proxy.match_domain(&host));
+        for rule in &self.rules {
+            let domain_hit =
+                !(force_proxy && rule.outbound == Outbound::Direct) && rule.match_domain(&host);
+            let destination_certain = !rule.has_destination_conditions() || domain_hit;
+            let destination_possible =
+                destination_certain || (!rule.countries.is_empty() && self.geoip.is_some());
+            if !destination_possible {
+                continue;
+            }
+            if rule.outbound == Outbound::Direct {
+                return true;
+            }
+            let unconditional =
+                rule.ports.is_empty() && rule.networks.is_empty() && !rule.has_process_conditions();
+            if destination_certain && unconditional {
+                return false;
+            }
+        }
+        self.final_outbound == Outbound::Direct
+    }
+
+    /// 域名是否命中任意一条域名规则（不论出口）。
+    ///
+    /// TUN 用它从 IP->域名缓存中挑出能影响分流结果的域名。
+    pub fn matches_domain_rule(&self, host: &str) -> bool {
+        let host = normalize_domain(host);
+        self.rules.iter().any(|rule| rule.match_domain(&host))
+    }
+
+    /// 当前规则集是否可能通过“域名”改变一个 IP 目标的出口。
+    ///
+    /// TUN TCP/UDP 拿到的目标通常是系统已经解析后的 IP。没有域名规则时，
+    /// 查询 IP->域名缓存不会改变路由结果，可以跳过。
+    pub fn has_domain_rules(&self) -> bool {
+        self.rules.iter().any(Rule::has_domain_entries)
+    }
+
+    /// 是否有规则按发起进程匹配；没有时入口可以跳过进程反查。
+    pub fn has_process_rules(&self) -> bool {
+        self.rules.iter().any(Rule::has_process_conditions)
+    }
+
+    /// 出口的配置名，用于日志。
+    pub fn describe(&self, outbound: Outbound) -> OutboundName<'_> {
+        OutboundName {
+            policy: self,
+            outbound,
+        }
+    }
+}
+
+/// [`RoutingPolicy::describe`] 的返回值，按配置名显示出口。
+pub struct OutboundName<'a> {
+    policy: &'a RoutingPolicy,
+    outbound: Outbound,
+}
+
+impl fmt::Display for OutboundName<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.outbound {
+            Outbound::Direct => f.write_str(OUTBOUND_DIRECT),
+            Outbound::Block => f.write_str(OUTBOUND_BLOCK),
+            Outbound::Proxy(index) => match self.policy.proxy_groups.get(index) {
+                Some(name) => f.write_str(name),
+                None => write!(f, "proxy#{index}"),
+            },
+        }
+    }
+}
+
+fn normalize_domain(host: &str) -> String {
+    host.trim().trim_end_matches('.').to_lowercase()
+}
+
+fn address_port(address: &Address) -> Option<u16> {
+    match address {
+        Address::Domain { port, .. }
+        | Address::Ipv4 { port, .. }
+        | Address::Ipv6 { port, .. }
+        | Address::ProxyDns { port } => Some(*port),
+        Address::UdpRelay => None,
+    }
+}
+
+/// 加载强制代理规则集：`None` 使用内置列表，空字符串关闭，其余按文件路径加载。
+fn load_force_proxy(path: Option<&str>) -> Result<Option<RuleSet>> {
+    force_proxy_entries(path)?
+        .map(|entries| RuleSet::new(&entries))
+        .transpose()
+}
+
+fn force_proxy_entries(path: Option<&str>) -> Result<Option<Vec<RuleSetEntry>>> {
+    let entries = match path.map(str::trim) {
+        None => parse_text_rule_set(DEFAULT_FORCE_PROXY_RULE_SET)
+            .map_err(|e| CommonError::Config(format!("built-in force proxy rule-set: {e}")))?,
+        Some("") => return Ok(None),
+        Some(path) => load_rule_set_file(path)?,
+    };
+    Ok(Some(entries))
+}
+
+fn load_geoip(config: &GeoIpConfig) -> Result<Arc<GeoIpDatabase>> {
+    let database = GeoIpDatabase::load(config.database.trim())?;
+    info!("GeoIP 数据库 {} 已加载：{database}", config.database);
+    Ok(Arc::new(database))
+}
+
+fn config_message(error: CommonError) -> String {
+    match error {
+        CommonError::Config(message) => message,
+        other => other.to_string(),
+    }
+}
+
+/// 将协议 Address 转换为可连接的地址字符串，
+/// 适用于 TcpStream::connect() 或 UdpSocket::connect()
+pub fn address_to_string(address: &Address) -> String {
+    // IPv6 必须加方括号才能形成合法 host:port。
+    match address {
+        Address::Domain { host, port } => format!("{}:{}", host, port),
+        Address::Ipv4 { addr, port } => {
+            format!("{}.{}.{}.{}:{}", addr[0], addr[1], addr[2], addr[3], port)
+        }
+        Address::Ipv6 { addr, port } => {
+            let ipv6 = Ipv6Addr::from(*addr);
+            format!("[{}]:{}", ipv6, port)
+        }
+        Address::ProxyDns { port } => format!("proxy-dns:{port}"),
+        Address::UdpRelay => "udp-relay".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,292 @@
+//! 离线 GeoIP 数据库，供 `geoip:CN` 这类按国家/地区分流的规则使用。
+//!
+//! 支持两种格式，加载时按内容识别：
+//! - MaxMind `.mmdb`（GeoLite2-Country/GeoIP2-Country 等），查询走库内的二叉搜索树；
+//! - CIDR 列表文本：每行 `<CIDR> <国家代码>`，分隔符可以是空白或逗号，`#` 开头为注释。
+//!   加载时排序并按最长前缀切分成不重叠区间，查询用二分查找。
+
+use crate::{CommonError, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::IpAddr;
+use std::path::Path;
+
+/// MaxMind DB 元数据段的起始标记，位于文件末尾附近。
+const MMDB_METADATA_MARKER: &[u8] = b"\xab\xcd\xefMaxMind.com";
+
+/// GeoIP 数据库配置。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GeoIpConfig {
+    /// MaxMind `.mmdb` 或 CIDR 列表文件路径，按文件内容自动识别格式。
+    pub database: String,
+}
+
+/// 两个大写 ASCII 字母组成的国家/地区代码，如 `CN`。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CountryCode([u8; 2]);
+
+impl CountryCode {
+    pub fn parse(code: &str) -> Option<Self> {
+        match code.trim().as_bytes() {
+            [a, b] if a.is_ascii_alphabetic() && b.is_ascii_alphabetic() => {
+                Some(Self([a.to_ascii_uppercase(), b.to_ascii_uppercase()]))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CountryCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.0[0] as char, self.0[1] as char)
+    }
+}
+
+// 只反序列化需要的字段，避免每次查询都构造多语言名称表。
+#[derive(Deserialize)]
+struct MmdbCountryRecord<'a> {
+    #[serde(borrow)]
+    country: Option<MmdbCountry<'a>>,
+    #[serde(borrow)]
+    registered_country: Option<MmdbCountry<'a>>,
+}
+
+#[derive(Deserialize)]
+struct MmdbCountry<'a> {
+    iso_code: Option<&'a str>,
+}
+
+/// 已加载的 GeoIP 数据库。
+pub enum GeoIpDatabase {
+    Mmdb(maxminddb::Reader<Vec<u8>>),
+    List {
+        // 按起始地址排序且互不重叠的闭区间。
+        ipv4: Vec<(u32, u32, CountryCode)>,
+        ipv6: Vec<(u128, u128, CountryCode)>,
+    },
+}
+
+impl fmt::Debug for GeoIpDatabase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mmdb(reader) => f
+                .debug_struct("Mmdb")
+                .field("database_type", &reader.metadata.database_type)
+                .finish(),
+            Self::List { ipv4, ipv6 } => f
+                .debug_struct("List")
+                .field("ipv4", &ipv4.len())
+                .field("ipv6", &ipv6.len())
+                .finish(),
+        }
+    }
+}
+
+impl fmt::Display for GeoIpDatabase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mmdb(reader) => write!(
+                f,
+                "MaxMind 数据库 {}（{} 个节点）",
+                reader.metadata.database_type, reader.metadata.node_count
+            ),
+            Self::List { ipv4, ipv6 } => {
+                write!(
+                    f,
+                    "CIDR 列表（IPv4 {} 段，IPv6 {} 段）",
+                    ipv4.len(),
+                    ipv6.len()
+                )
+            }
+        }
+    }
+}
+
+impl GeoIpDatabase {
+    /// 读取数据库文件，按内容识别 `.mmdb` 或 CIDR 列表。
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).map_err(|e| {
+            CommonError::Config(format!(
+                "failed to read GeoIP database {}: {e}",
+                path.display()
+            ))
+        })?;
+        Self::from_bytes(data)
+            .map_err(|e| CommonError::Config(format!("GeoIP database {}: {e}", path.display())))
+    }
+
+    fn from_bytes(data: Vec<u8>) -> std::result::Result<Self, String> {
+        if is_mmdb(&data) {
+            let reader = maxminddb::Reader::from_source(data).map_err(|e| e.to_string())?;
+            return Ok(Self::Mmdb(reader));
+        }
+        let text = std::str::from_utf8(&data)
+            .map_err(|e| format!("neither a MaxMind database nor UTF-8 CIDR list: {e}"))?;
+        Self::parse_list(text)
+    }
+
+    /// 解析 CIDR 列表文本；错误信息带行号。
+    pub fn parse_list(text: &str) -> std::result::Result<Self, String> {
+        let mut ipv4 = Vec::new();
+        let mut ipv6 = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || format!("line {}: expected \"<CIDR> <country>\"", index + 1);
+            let mut fields = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|field| !field.is_empty());
+            let (Some(cidr), Some(code), None) = (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(invalid());
+            };
+            let code = CountryCode::parse(code).ok_or_else(invalid)?;
+            let (ip, prefix_len) = match cidr.split_once('/') {
+                Some((ip, len)) => (
+                    ip.parse::<IpAddr>().map_err(|_| invalid())?,
+                    len.parse::<u8>().map_err(|_| invalid())?,
+                ),
+                None => {
+                    let ip = cidr.parse::<IpAddr>().map_err(|_| invalid())?;
+                    (ip, if ip.is_ipv4() { 32 } else { 128 })
+                }
+            };
+            match ip {
+                IpAddr::V4(ip) if prefix_len <= 32 => {
+                    let (start, end) = prefix_range(u32::from(ip) as u128, 32, prefix_len);
+                    ipv4.push((start as u32, end as u32, code));
+                }
+                IpAddr::V6(ip) if prefix_len <= 128 => {
+                    let (start, end) = prefix_range(u128::from(ip), 128, prefix_len);
+                    ipv6.push((start, end, code));
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(Self::List {
+            ipv4: merge_ranges(ipv4),
+            ipv6: merge_ranges(ipv6),
+        })
+    }
+
+    /// 查询 IP 所属的国家/地区；数据库中没有记录时返回 `None`。
+    pub fn country(&self, ip: IpAddr) -> Option<CountryCode> {
+        match self {
+            Self::Mmdb(reader) => {
+                let record = reader.lookup::<MmdbCountryRecord<'_>>(ip).ok()?;
+                record
+                    .country
+                    .and_then(|country| country.iso_code)
+                    .or_else(|| record.registered_country.and_then(|c| c.iso_code))
+                    .and_then(CountryCode::parse)
+            }
+            Self::List { ipv4, ipv6 } => match ip {
+                IpAddr::V4(ip) => lookup_range(ipv4, u32::from(ip)),
+                IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+                    Some(ip) => lookup_range(ipv4, u32::from(ip)),
+                    None => lookup_range(ipv6, u128::from(ip)),
+                },
+            },
+        }
+    }
+}
+
+fn is_mmdb(data: &[u8]) -> bool {
+    // 元数据段最大 128KiB，只需要在文件尾部查找标记。
+    let tail = &data[data.len().saturating_sub(128 * 1024)..];
+    tail.windows(MMDB_METADATA_MARKER.len())
+        .any(|window| window == MMDB_METADATA_MARKER)
+}
+
+fn prefix_range(bits: u128, width: u8, prefix_len: u8) -> (u128, u128) {
+    let host_bits = u32::from(width - prefix_len);
+    let width_mask = if width == 128 {
+        u128::MAX
+    } else {
+        (1u128 << width) - 1
+    };
+    let host_mask = 1u128.checked_shl(host_bits).map_or(u128::MAX, |v| v - 1) & width_mask;
+    let start = bits & !host_mask & width_mask;
+    (start, start | host_mask)
+}
+
+/// 区间端点：IPv4 用 `u32`，IPv6 用 `u128`。
+trait RangeBound: Copy + Ord {
+    fn prev(self) -> Self;
+    fn next(self) -> Self;
+}
+
+impl RangeBound for u32 {
+    fn prev(self) -> Self {
+        self - 1
+    }
+
+    fn next(self) -> Self {
+        self + 1
+    }
+}
+
+impl RangeBound for u128 {
+    fn prev(self) -> Self {
+        self - 1
+    }
+
+    fn next(self) -> Self {
+        self + 1
+    }
+}
+
+/// 排序并切分成不重叠区间。CIDR 之间只会嵌套或不相交，嵌套时按最长前缀匹配：
+/// 外层区间在内层前后各留一段，内层（前缀更长的）记录覆盖中间部分。同一 CIDR 重复出现时第一条生效。
+fn merge_ranges<T: RangeBound>(mut ranges: Vec<(T, T, CountryCode)>) -> Vec<(T, T, CountryCode)> {
+    ranges.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+    ranges.dedup_by(|later, first| later.0 == first.0 && later.1 == first.1);
+
+    let mut merged = Vec::with_capacity(ranges.len());
+    // 尚未输出完的外层区间，栈顶是最内层；游标为下一段的起点，None 表示已经输出完。
+    let mut open: Vec<(Option<T>, T, CountryCode)> = Vec::new();
+    for (start, end, code) in ranges {
+        close_ranges(&mut open, &mut merged, Some(start));
+        // 外层在内层之前的部分先输出；内层结束时再恢复外层游标。
+        if let Some((cursor, _, parent)) = open.last_mut()
+            && let Some(from) = cursor.take()
+            && from < start
+        {
+            merged.push((from, start.prev(), *parent));
+        }
+        open.push((Some(start), end, code));
+    }
+    close_ranges(&mut open, &mut merged, None);
+    merged
+}
+
+/// 输出在 `before` 之前结束的区间剩余部分；`before` 为 None 时全部输出。
+fn close_ranges<T: RangeBound>(
+    open: &mut Vec<(Option<T>, T, CountryCode)>,
+    merged: &mut Vec<(T, T, CountryCode)>,
+    before: Option<T>,
+) {
+    while let Some(&(cursor, end, code)) = open.last() {
+        if before.is_some_and(|start| start <= end) {
+            break;
+        }
+        open.pop();
+        if let Some(from) = cursor {
+            merged.push((from, end, code));
+        }
+        // 外层从内层结束后的下一个地址继续；两者同时结束时外层已经没有剩余部分。
+        if let Some((parent_cursor, parent_end, _)) = open.last_mut() {
+            *parent_cursor = (end < *parent_end).then(|| end.next());
+        }
+    }
+}
+
+fn lookup_range<T: Copy + Ord>(ranges: &[(T, T, CountryCode)], ip: T) -> Option<CountryCode> {
+    let index = ranges.partition_point(|(start, _, _)| *start <= ip);
+    let (_, end, code) = ranges.get(index.checked_sub(1)?)?;
+    (ip <= *end).then_some(*code)
+}
@@ -0,0 +1,278 @@
+//! 分流出口管理。
+//!
+//! desktop 与 Android 的 router 都由这里的 [`OutboundRouter`] 实现：它持有
+//! [`RoutingPolicy`] 与同源的 PAC 脚本，把策略给出的 [`Outbound`] 还原成具体的
+//! proxy 分组。分组里的会话管理器类型 `S` 由各平台决定，分组的创建和重载也留在
+//! 平台侧。
+
+use super::{
+    DEFAULT_PROXY_GROUP, DirectAccessConfig, DirectAccessMode, GeoIpConfig, OUTBOUND_BLOCK,
+    OUTBOUND_DIRECT, Outbound, PacScript, RoutingConfig, RoutingPolicy, SourceProcess,
+};
+use crate::{CommonError, Result};
+use parking_lot::{Mutex, RwLock};
+use protocol::{Address, TransportProtocol};
+use std::sync::Arc;
+use tracing::info;
+
+/// 一个 proxy 分组：TCP 与 UDP 语义各一个会话管理器。
+pub struct ProxyGroup<S> {
+    // 在 OutboundRouter 分组列表中的下标，入口可按它缓存每个分组的 relay。
+    index: usize,
+    name: String,
+    // 供 HTTP CONNECT、SOCKS CONNECT、TUN TCP 使用。
+    tcp_sessions: Arc<S>,
+    // 供 SOCKS UDP、TUN UDP、DNS proxy 使用。
+    udp_sessions: Arc<S>,
+}
+
+impl<S> ProxyGroup<S> {
+    pub fn new(index: usize, name: &str, tcp_sessions: Arc<S>, udp_sessions: Arc<S>) -> Self {
+        Self {
+            index,
+            name: name.trim().to_string(),
+            tcp_sessions,
+            udp_sessions,
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn tcp_sessions(&self) -> &Arc<S> {
+        &self.tcp_sessions
+    }
+
+    pub fn udp_sessions(&self) -> &Arc<S> {
+        &self.udp_sessions
+    }
+}
+
+/// 一次分流的结果。
+pub enum Route<'a, S> {
+    Direct,
+    Block,
+    Proxy(&'a ProxyGroup<S>),
+}
+
+impl<S> Route<'_, S> {
+    /// 出口名：`direct`、`block` 或 proxy 分组名。
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Direct => OUTBOUND_DIRECT,
+            Self::Block => OUTBOUND_BLOCK,
+            Self::Proxy(group) => group.name(),
+        }
+    }
+}
+
+/// 分流结果以及命中规则的配置原文，供连接列表展示。
+pub struct ResolvedDecision<'a, S> {
+    pub route: Route<'a, S>,
+    /// 没有规则命中时为 `final`。
+    pub rule: String,
+}
+
+/// 运行中通过控制 API 临时覆盖的分流设置，热重载后保留，重启后失效。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RoutingOverrides {
+    /// 覆盖访问模式：proxy_all/direct_all 忽略所有规则，rules 使用配置中的规则。
+    pub mode: Option<DirectAccessMode>,
+    /// 原本走默认分组 `proxy` 的流量改用的分组下标。
+    pub proxy_group: Option<usize>,
+}
+
+/// 生成分流策略所需的配置段。
+#[derive(Debug, Clone, Default)]
+pub struct RoutingSource {
+    pub routing: Option<RoutingConfig>,
+    pub direct_access: DirectAccessConfig,
+    pub geoip: Option<GeoIpConfig>,
+}
+
+struct RoutingState {
+    source: RoutingSource,
+    overrides: RoutingOverrides,
+}
+
+pub struct OutboundRouter<S> {
+    // 热重载时整体替换；正在匹配的连接继续使用取到的旧快照。
+    policy: RwLock<Arc<RoutingPolicy>>,
+    // 由同一份配置生成的 PAC 脚本，随分流规则一起替换。
+    pac: RwLock<Arc<PacScript>>,
+    // 生成当前策略的配置与覆盖项；持锁重建策略，避免重载与切换互相覆盖。
+    state: Mutex<RoutingState>,
+    // 下标与 Outbound::Proxy 一致，第 0 个是顶层配置组成的默认分组。
+    groups: Vec<ProxyGroup<S>>,
+}
+
+impl<S> OutboundRouter<S> {
+    /// `groups` 的下标必须与 [`ProxyGroup::index`] 一致，第 0 个是默认分组 `proxy`。
+    pub fn new(source: RoutingSource, groups: Vec<ProxyGroup<S>>) -> Result<Self> {
+        debug_assert!(groups.iter().enumerate().all(|(i, group)| group.index == i));
+        let overrides = RoutingOverrides::default();
+        // 规则在启动时解析成运行时结构，连接处理路径只做快速匹配。
+        let (policy, pac) = build_policy(&source, &group_names(&groups), overrides)?;
+        Ok(Self {
+            policy: RwLock::new(Arc::new(policy)),
+            pac: RwLock::new(Arc::new(pac)),
+            state: Mutex::new(RoutingState { source, overrides }),
+            groups,
+        })
+    }
+
+    /// 热重载分流规则；新规则引用了不存在的分组时失败，继续使用旧规则。
+    pub fn reload(&self, source: RoutingSource) -> Result<()> {
+        self.rebuild(|state| state.source = source)
+    }
+
+    /// 为目标地址选择出口，`network` 是该连接的传输协议。
+    pub fn route(&self, address: &Address, network: TransportProtocol) -> Route<'_, S> {
+        self.resolve(self.policy.read().route(address, network))
+    }
+
+    /// 为 IP 目标选择出口，`domain` 是 DNS 缓存中与该 IP 对应的域名。
+    pub fn route_with_domain(
+        &self,
+        address: &Address,
+        network: TransportProtocol,
+        domain: Option<&str>,
+    ) -> Route<'_, S> {
+        self.resolve(
+            self.policy
+                .read()
+                .route_with_domain(address, network, domain),
+        )
+    }
+
+    /// 为目标选择出口并给出命中的规则。`domain` 是 DNS 缓存中与 IP 目标对应的域名，
+    /// `process` 是 TUN 反查到的发起进程；逐包的 UDP 分流直接用 [`policy`](Self::policy)，
+    /// 避免规则名的字符串分配。
+    pub fn decide(
+        &self,
+        address: &Address,
+        network: TransportProtocol,
+        domain: Option<&str>,
+        process: Option<&SourceProcess>,
+    ) -> ResolvedDecision<'_, S> {
+        let policy = self.policy();
+        let decision = policy.decide(address, network, domain, process);
+        ResolvedDecision {
+            route: self.resolve(decision.outbound),
+            rule: policy.rule_label(decision.rule),
+        }
+    }
+
+    /// 运行中覆盖访问模式；`None` 恢复配置文件中的设置。
+    pub fn set_direct_access_mode(&self, mode: Option<DirectAccessMode>) -> Result<()> {
+        self.rebuild(|state| state.overrides.mode = mode)?;
+        info!("访问模式覆盖已更新：{mode:?}");
+        Ok(())
+    }
+
+    /// 让原本走默认分组的流量改用指定分组；`None` 恢复默认分组。
+    pub fn select_proxy_group(&self, name: Option<&str>) -> Result<()> {
+        let index = match name {
+            Some(name) => Some(
+                self.groups
+                    .iter()
+                    .position(|group| group.name == name.trim())
+                    .ok_or_else(|| CommonError::Config(format!("proxy 分组 {name:?} 不存在")))?,
+            ),
+            None => None,
+        };
+        self.rebuild(|state| state.overrides.proxy_group = index)?;
+        info!(
+            "默认出口分组已切换为 {}",
+            self.groups[index.unwrap_or(DEFAULT_PROXY_GROUP)].name
+        );
+        Ok(())
+    }
+
+    /// 当前生效的运行时覆盖项。
+    pub fn overrides(&self) -> RoutingOverrides {
+        self.state.lock().overrides
+    }
+
+    /// 在锁内修改配置或覆盖项并重建策略；重建失败时保持原状态。
+    fn rebuild(&self, update: impl FnOnce(&mut RoutingState)) -> Result<()> {
+        let mut state = self.state.lock();
+        let mut next = RoutingState {
+            source: state.source.clone(),
+            overrides: state.overrides,
+        };
+        update(&mut next);
+        let (policy, pac) = build_policy(&next.source, &group_names(&self.groups), next.overrides)?;
+        *self.policy.write() = Arc::new(policy);
+        *self.pac.write() = Arc::new(pac);
+        *state = next;
+        Ok(())
+    }
+
+    fn resolve(&self, outbound: Outbound) -> Route<'_, S> {
+        match outbound {
+            Outbound::Direct => Route::Direct,
+            Outbound::Block => Route::Block,
+            Outbound::Proxy(index) => Route::Proxy(&self.groups[index]),
+        }
+    }
+
+    pub fn policy(&self) -> Arc<RoutingPolicy> {
+        self.policy.read().clone()
+    }
+
+    /// 当前分流规则对应的 PAC 脚本。
+    pub fn pac_script(&self) -> Arc<PacScript> {
+        self.pac.read().clone()
+    }
+
+    /// 顶层配置组成的默认分组；DNS proxy 等内部目标固定使用它。
+    pub fn default_group(&self) -> &ProxyGroup<S> {
+        &self.groups[DEFAULT_PROXY_GROUP]
+    }
+
+    pub fn groups(&self) -> &[ProxyGroup<S>] {
+        &self.groups
+    }
+}
+
+/// 具名分组（不含默认分组 `proxy`）的名称，顺序与 `Outbound::Proxy` 下标一致。
+fn group_names<S>(groups: &[ProxyGroup<S>]) -> Vec<String> {
+    groups
+        .iter()
+        .skip(DEFAULT_PROXY_GROUP + 1)
+        .map(|group| group.name.clone())
+        .collect()
+}
+
+/// 由配置与运行时覆盖项生成分流策略和 PAC 脚本。
+fn build_policy(
+    source: &RoutingSource,
+    group_names: &[String],
+    overrides: RoutingOverrides,
+) -> Result<(RoutingPolicy, PacScript)> {
+    let mut routing = source.routing.as_ref();
+    let mut direct_access = source.direct_access.clone();
+    if let Some(mode) = overrides.mode {
+        // proxy_all/direct_all 不看任何规则；rules 沿用配置的 `[routing]`，
+        // 只有旧版配置才需要把 `[direct_access]` 切到 rules 模式。
+        if mode != DirectAccessMode::Rules {
+            routing = None;
+        }
+        if routing.is_none() {
+            direct_access.mode = mode;
+        }
+    }
+    let mut policy =
+        RoutingPolicy::from_configs(routing, &direct_access, source.geoip.as_ref(), group_names)?;
+    if let Some(index) = overrides.proxy_group {
+        policy = policy.with_default_proxy_group(index)?;
+    }
+    let pac = PacScript::from_configs(routing, &direct_access);
+    Ok((policy, pac))
+}
@@ -0,0 +1,502 @@
+//! 由分流配置生成 PAC（Proxy Auto-Config）脚本。
+//!
+//! 浏览器按 PAC 决定直连还是交给本地 agent，交给 agent 的目标会再按完整规则
+//! 分流。所以翻译只需保证“PAC 判为直连的目标，agent 也会直连”：
+//! - 指向 `direct` 的规则里无法表达的条件（正则、GeoIP、IPv6 CIDR、端口）直接
+//!   丢弃，这些目标会落到 agent；
+//! - 指向其他出口的规则一旦含有无法表达的条件，后续规则不再翻译，全部交给 agent。
+//!
+//! 域名模式编译成查找表，`*.example.com` 与后缀按父域名逐级查表，效果等同
+//! `shExpMatch`；IPv4 CIDR 翻译成 `isInNet`，只对 IP 字面量目标生效，与 agent
+//! 不为域名目标额外解析的行为一致。
+
+use super::{
+    Condition, DirectAccessConfig, DirectAccessMode, OUTBOUND_DIRECT, RoutingConfig, RuleSetEntry,
+    force_proxy_entries, load_rule_set_file,
+};
+use protocol::TransportProtocol;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use tracing::warn;
+
+/// PAC 的固定匹配逻辑；数据部分（`PROXY`、`FINAL`、`FORCE_PROXY`、`RULES`）由
+/// [`PacScript::render`] 在前面生成。
+const PAC_MATCHER: &str = r#"
+function hasKey(map, key) {
+  return Object.prototype.hasOwnProperty.call(map, key);
+}
+
+function matchDomain(set, host) {
+  if (hasKey(set.exact, host) || hasKey(set.suffixes, host)) {
+    return true;
+  }
+  for (var dot = host.indexOf("."); dot >= 0; dot = host.indexOf(".", dot + 1)) {
+    var parent = host.substring(dot + 1);
+    if (hasKey(set.suffixes, parent) || hasKey(set.subdomains, parent)) {
+      return true;
+    }
+  }
+  for (var i = 0; i < set.keywords.length; i++) {
+    if (host.indexOf(set.keywords[i]) >= 0) {
+      return true;
+    }
+  }
+  return false;
+}
+
+function matchIp(set, host) {
+  for (var i = 0; i < set.networks.length; i++) {
+    if (isInNet(host, set.networks[i][0], set.networks[i][1])) {
+      return true;
+    }
+  }
+  return false;
+}
+
+function FindProxyForURL(url, host) {
+  host = host.toLowerCase().replace(/\.$/, "");
+  var ipv6 = host.indexOf(":") >= 0;
+  var ipv4 = /^\d+\.\d+\.\d+\.\d+$/.test(host);
+  var forced = !ipv4 && !ipv6 && (FORCE_PROXY.all || matchDomain(FORCE_PROXY, host));
+  for (var i = 0; i < RULES.length; i++) {
+    var rule = RULES[i];
+    var hit = rule.all;
+    if (!hit && ipv4) {
+      hit = matchIp(rule, host);
+    } else if (!hit && !ipv6) {
+      hit = !(forced && rule.direct) && matchDomain(rule, host);
+    }
+    if (hit) {
+      return rule.direct ? "DIRECT" : PROXY;
+    }
+  }
+  return FINAL;
+}
+"#;
+
+/// 某一版分流配置对应的 PAC 脚本，渲染时再填入浏览器访问 agent 的地址。
+#[derive(Debug, Default)]
+pub struct PacScript {
+    rules: Vec<PacRule>,
+    // 命中的域名不会经由 direct 规则直连；`all` 表示规则集含无法翻译的域名模式。
+    force_proxy: PacRule,
+    final_direct: bool,
+}
+
+#[derive(Debug, Default)]
+struct PacRule {
+    direct: bool,
+    // 规则没有目标条件，匹配所有目标。
+    all: bool,
+    exact: Vec<String>,
+    suffixes: Vec<String>,
+    subdomains: Vec<String>,
+    keywords: Vec<String>,
+    // IPv4 网络地址与掩码，按 isInNet 的参数格式保存。
+    networks: Vec<(String, String)>,
+}
+
+/// 单条规则的翻译结果。
+enum Translation {
+    Rule(PacRule),
+    /// 规则在 PAC 中不会命中或可以安全丢弃。
+    Skip,
+    /// 规则无法安全表达，从这里开始全部交给 agent。
+    Stop,
+}
+
+impl PacScript {
+    /// 与 [`RoutingPolicy::from_configs`](super::RoutingPolicy::from_configs) 一致：
+    /// 优先使用 `[routing]`，未配置时回退到旧版 `[direct_access]`。
+    pub fn from_configs(
+        routing: Option<&RoutingConfig>,
+        direct_access: &DirectAccessConfig,
+    ) -> Self {
+        let (rules, final_direct, force_proxy_path) = match routing {
+            Some(routing) => {
+                let rules = routing
+                    .rules
+                    .iter()
+                    .map(|rule| {
+                        // 无效模式在构建分流策略时已经报错，这里按无法翻译处理。
+                        let conditions = rule
+                            .matches
+                            .iter()
+                            .map(|pattern| Condition::parse(pattern))
+                            .collect::<Option<Vec<_>>>();
+                        (conditions, rule.outbound.trim() == OUTBOUND_DIRECT)
+                    })
+                    .collect::<Vec<_>>();
+                (
+                    rules,
+                    routing.final_outbound.trim() == OUTBOUND_DIRECT,
+                    routing.force_proxy_rule_set.as_deref(),
+                )
+            }
+            None => {
+                let mut rules = Vec::new();
+                if direct_access.mode == DirectAccessMode::Rules {
+                    // 与策略转换相同：目标模式合并成一条规则，端口/网络条件各自成规则。
+                    let mut destinations = Vec::new();
+                    for condition in direct_access
+                        .rules
+                        .iter()
+                        .filter_map(|rule| Condition::parse(rule))
+                    {
+                        match condition {
+                            Condition::Port(_)
+                            | Condition::Network(_)
+                            | Condition::ProcessName(_)
+                            | Condition::ProcessPath(_) => {
+                                rules.push((Some(vec![condition]), true));
+                            }
+                            condition => destinations.push(condition),
+                        }
+                    }
+                    if !destinations.is_empty() {
+                        rules.insert(0, (Some(destinations), true));
+                    }
+                }
+                (
+                    rules,
+                    direct_access.mode == DirectAccessMode::DirectAll,
+                    direct_access.force_proxy_rule_set.as_deref(),
+                )
+            }
+        };
+
+        let mut script = Self {
+            final_direct,
+            ..Self::default()
+        };
+        let mut files = HashMap::new();
+        for (conditions, direct) in rules {
+            match PacRule::translate(conditions, direct, &mut files) {
+                Translation::Rule(rule) => script.rules.push(rule),
+                Translation::Skip => {}
+                Translation::Stop => {
+                    script.final_direct = false;
+                    break;
+                }
+            }
+        }
+
+        // 自定义规则集加载失败时分流策略会改用内置列表或直接报错，这里跟随内置列表。
+        let force_proxy = force_proxy_entries(force_proxy_path)
+            .or_else(|_| force_proxy_entries(None))
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+        for entry in &force_proxy {
+            if !matches!(entry, RuleSetEntry::Cidr(..)) && !script.force_proxy.add(entry) {
+                script.force_proxy.all = true;
+            }
+        }
+        script
+    }
+
+    /// 生成完整的 PAC 文件，`proxy` 是浏览器连接 agent HTTP 入口使用的 `host:port`。
+    pub fn render(&self, proxy: &str) -> String {
+        let mut out = String::new();
+        out.push_str(&format!(
+            "var PROXY = {};\n",
+            js_string(&format!("PROXY {proxy}"))
+        ));
+        out.push_str(if self.final_direct {
+            "var FINAL = \"DIRECT\";\n"
+        } else {
+            "var FINAL = PROXY;\n"
+        });
+        out.push_str("var FORCE_PROXY = ");
+        self.force_proxy.write_js(&mut out);
+        out.push_str(";\nvar RULES = [\n");
+        for rule in &self.rules {
+            out.push_str("  ");
+            rule.write_js(&mut out);
+            out.push_str(",\n");
+        }
+        out.push_str("];\n");
+        out.push_str(PAC_MATCHER);
+        out
+    }
+}
+
+impl PacRule {
+    fn translate(
+        conditions: Option<Vec<Condition>>,
+        direct: bool,
+        files: &mut HashMap<String, Option<Vec<RuleSetEntry>>>,
+    ) -> Translation {
+        let unsupported = if direct {
+            Translation::Skip
+        } else {
+            Translation::Stop
+        };
+        let Some(conditions) = conditions else {
+            return unsupported;
+        };
+
+        let mut rule = Self {
+            direct,
+            ..Self::default()
+        };
+        let mut has_destinations = false;
+        let mut complete = true;
+        let mut has_ports = false;
+        let mut has_process = false;
+        let mut networks = Vec::new();
+        for condition in conditions {
+            match condition {
+                Condition::Destination(entry) => {
+                    has_destinations = true;
+                    complete &= rule.add(&entry);
+                }
+                Condition::RuleSetFile(path) => {
+                    has_destinations = true;
+                    let entries = files.entry(path).or_insert_with_key(|path| {
+                        load_rule_set_file(path)
+                            .inspect_err(|e| warn!("PAC 忽略无法加载的规则集 {path}：{e}"))
+                            .ok()
+                    });
+                    match entries {
+                        Some(entries) => {
+                            for entry in entries.iter() {
+                                complete &= rule.add(entry);
+                            }
+                        }
+                        None => complete = false,
+                    }
+                }
+                Condition::GeoIp(_) => {
+                    has_destinations = true;
+                    complete = false;
+                }
+                Condition::Port(_) => has_ports = true,
+                Condition::Network(network) => networks.push(network),
+                Condition::ProcessName(_) | Condition::ProcessPath(_) => has_process = true,
+            }
+        }
+
+        // HTTP 入口拿不到发起进程，进程规则在这条路径上不会命中。
+        if has_process {
+            return Translation::Skip;
+        }
+        // 浏览器经 PAC 发出的都是 TCP 请求。
+        if !networks.is_empty() && !networks.contains(&TransportProtocol::Tcp) {
+            return Translation::Skip;
+        }
+        if has_ports || (!complete && !direct) {
+            return unsupported;
+        }
+        if !has_destinations {
+            if !direct {
+                return Translation::Stop;
+            }
+            rule.all = true;
+        } else if rule.is_empty() {
+            return Translation::Skip;
+        }
+        Translation::Rule(rule)
+    }
+
+    /// 加入一个目标模式；无法在 PAC 中表达时返回 false。
+    fn add(&mut self, entry: &RuleSetEntry) -> bool {
+        match entry {
+            RuleSetEntry::Domain(domain) => self.exact.push(domain.clone()),
+            RuleSetEntry::Subdomains(domain) => self.subdomains.push(domain.clone()),
+            RuleSetEntry::Suffix(domain) => self.suffixes.push(domain.clone()),
+            RuleSetEntry::Keyword(keyword) => self.keywords.push(keyword.clone()),
+            RuleSetEntry::Cidr(IpAddr::V4(ip), prefix_len) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(*prefix_len))
+                    .unwrap_or(0);
+                let network = std::net::Ipv4Addr::from(u32::from(*ip) & mask);
+                self.networks.push((
+                    network.to_string(),
+                    std::net::Ipv4Addr::from(mask).to_string(),
+                ));
+            }
+            RuleSetEntry::Regex(_) | RuleSetEntry::Cidr(IpAddr::V6(_), _) => return false,
+        }
+        true
+    }
+
+    fn is_empty(&self) -> bool {
+        self.exact.is_empty()
+            && self.suffixes.is_empty()
+            && self.subdomains.is_empty()
+            && self.keywords.is_empty()
+            && self.networks.is_empty()
+    }
+
+    fn write_js(&self, out: &mut String) {
+        out.push_str(&format!(
+            "{{direct: {}, all: {}, exact: ",
+            self.direct, self.all
+        ));
+        write_js_set(out, &self.exact);
+        out.push_str(", suffixes: ");
+        write_js_set(out, &self.suffixes);
+        out.push_str(", subdomains: ");
+        write_js_set(out, &self.subdomains);
+        out.push_str(", keywords: [");
+        let keywords = self
+            .keywords
+            .iter()
+            .map(|keyword| js_string(keyword))
+            .collect::<Vec<_>>();
+        out.push_str(&keywords.join(", "));
+        out.push_str("], networks: [");
+        let networks = self
+            .networks
+            .iter()
+            .map(|(network, mask)| format!("[\"{network}\", \"{mask}\"]"))
+            .collect::<Vec<_>>();
+        out.push_str(&networks.join(", "));
+        out.push_str("]}");
+    }
+}
+
+fn write_js_set(out: &mut String, keys: &[String]) {
+    let keys = keys
+        .iter()
+        .map(|key| format!("{}: 1", js_string(key)))
+        .collect::<Vec<_>>();
+    out.push('{');
+    out.push_str(&keys.join(", "));
+    out.push('}');
+}
+
+/// 转成 JavaScript 字符串字面量；非 ASCII 与控制字符用 `\uXXXX` 转义。
+fn js_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' '..='~' => out.push(c),
+            _ => {
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    out.push_str(&format!("\\u{unit:04x}"));
+                }
+            }
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routing::RoutingRuleConfig;
+
+    fn routing(rules: &[(&[&str], &str)], final_outbound: &str) -> PacScript {
+        let config = RoutingConfig {
+            rules: rules
+                .iter()
+                .map(|(matches, outbound)| RoutingRuleConfig {
+                    matches: matches.iter().map(ToString::to_string).collect(),
+                    outbound: outbound.to_string(),
+                })
+                .collect(),
+            final_outbound: final_outbound.to_string(),
+            force_proxy_rule_set: Some(String::new()),
+        };
+        PacScript::from_configs(Some(&config), &DirectAccessConfig::default())
+    }
+
+    #[test]
+    fn translates_domains_and_ipv4_cidrs() {
+        let script = routing(
+            &[
+                (
+                    &["*.lan", "domain-suffix:corp.example", "10.1.2.3/8"],
+                    "direct",
+                ),
+                (&["domain-keyword:ads", "fd00::/8"], "block"),
+            ],
+            "direct",
+        );
+        let pac = script.render("192.168.1.2:10080");
+
+        assert!(pac.starts_with("var PROXY = \"PROXY 192.168.1.2:10080\";\n"));
+        assert!(pac.contains(
+            "{direct: true, all: false, exact: {}, suffixes: {\"corp.example\": 1}, \
+             subdomains: {\"lan\": 1}, keywords: [], networks: [[\"10.0.0.0\", \"255.0.0.0\"]]}"
+        ));
+        // 含 IPv6 CIDR 的 block 规则无法完整表达：不生成该规则，之后全部交给 agent。
+        assert_eq!(script.rules.len(), 1);
+        assert!(pac.contains("var FINAL = PROXY;"));
+        assert!(pac.contains("function FindProxyForURL(url, host)"));
+    }
+
+    #[test]
+    fn drops_unsupported_conditions_only_from_direct_rules() {
+        let script = routing(
+            &[
+                // HTTP 入口没有进程信息，进程规则跳过而不是截断后续规则。
+                (&["process-name:slack"], "proxy"),
+                (&["domain-regex:^cdn\\.", "example.com"], "direct"),
+                (&["port:22"], "direct"),
+                (&["network:udp"], "proxy"),
+                (&["10.0.0.0/8", "network:tcp"], "proxy"),
+                (&["example.org"], "direct"),
+            ],
+            "proxy",
+        );
+
+        assert_eq!(script.rules.len(), 3);
+        assert_eq!(script.rules[0].exact, ["example.com"]);
+        assert!(!script.rules[1].direct);
+        assert_eq!(script.rules[2].exact, ["example.org"]);
+        assert!(!script.final_direct);
+        assert!(!script.force_proxy.all);
+    }
+
+    #[test]
+    fn legacy_direct_access_uses_builtin_force_proxy_list() {
+        let script = PacScript::from_configs(
+            None,
+            &DirectAccessConfig {
+                mode: DirectAccessMode::Rules,
+                rules: vec!["network:tcp".to_string(), "localhost".to_string()],
+                force_proxy_rule_set: None,
+            },
+        );
+
+        assert_eq!(script.rules.len(), 2);
+        assert_eq!(script.rules[0].exact, ["localhost"]);
+        assert!(script.rules[1].all);
+        assert!(
+            script
+                .force_proxy
+                .suffixes
+                .iter()
+                .any(|d| d == "google.com")
+        );
+
+        let direct_all = PacScript::from_configs(
+            None,
+            &DirectAccessConfig {
+                mode: DirectAccessMode::DirectAll,
+                ..DirectAccessConfig::default()
+            },
+        );
+        assert!(
+            direct_all
+                .render("127.0.0.1:10080")
+                .contains("var FINAL = \"DIRECT\";")
+        );
+    }
+
+    #[test]
+    fn escapes_javascript_strings() {
+        assert_eq!(js_string("a\"b\\c"), "\"a\\\"b\\\\c\"");
+        assert_eq!(js_string("例.cn"), "\"\\u4f8b.cn\"");
+    }
+}
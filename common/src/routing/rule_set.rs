@@ -0,0 +1,460 @@
+//! 分流规则集：目标模式的编译结构与规则集文件格式。
+//!
+//! 一条分流规则里的所有域名/IP 目标模式（包括引用的规则集文件）会编译进同一个
+//! [`RuleSet`]：域名按标签反转后放进前缀树，IP/CIDR 放进按位展开的前缀树，
+//! 匹配耗时只与域名标签数、地址位数有关，与规则条数无关。关键字与正则无法
+//! 建树，按条目逐个匹配。
+//!
+//! 规则集文件有两种格式，加载时按文件头自动识别：
+//! - 文本：每行一个目标模式，空行和 `#` 开头的行会被忽略；
+//! - 二进制：以 [`BINARY_MAGIC`] 开头，保存预先解析、校验过的条目，
+//!   大规则集加载时可以跳过文本解析。由 [`compile_rule_set_file`] 生成。
+
+use crate::{CommonError, Result};
+use regex::RegexSet;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+
+/// 二进制规则集文件头。
+pub const BINARY_MAGIC: &[u8; 4] = b"PPRS";
+const BINARY_VERSION: u8 = 1;
+
+const TAG_DOMAIN: u8 = 1;
+const TAG_SUBDOMAINS: u8 = 2;
+const TAG_SUFFIX: u8 = 3;
+const TAG_KEYWORD: u8 = 4;
+const TAG_REGEX: u8 = 5;
+const TAG_CIDR_V4: u8 = 6;
+const TAG_CIDR_V6: u8 = 7;
+
+/// 一个已解析的目标模式。
+///
+/// 文本写法:
+/// - 精确域名: "example.com"
+/// - 子域名: "*.example.com"，不含 "example.com" 本身
+/// - 域名后缀: "domain-suffix:example.com"，含 "example.com" 本身及所有子域名
+/// - 域名关键字: "domain-keyword:google"
+/// - 域名正则: "domain-regex:^ad[0-9]+\.example\.com$"
+/// - 精确 IP 或 CIDR: "127.0.0.1"、"::1"、"10.0.0.0/8"、"fd00::/8"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleSetEntry {
+    Domain(String),
+    Subdomains(String),
+    Suffix(String),
+    Keyword(String),
+    Regex(String),
+    Cidr(IpAddr, u8),
+}
+
+impl RuleSetEntry {
+    /// 解析一个目标模式；不是合法目标模式时返回 `None`。
+    pub fn parse(pattern: &str) -> Option<Self> {
+        let pattern = pattern.trim();
+        if let Some(keyword) = pattern.strip_prefix("domain-keyword:") {
+            let keyword = keyword.trim().to_lowercase();
+            return (!keyword.is_empty()).then_some(Self::Keyword(keyword));
+        }
+        if let Some(regex) = pattern.strip_prefix("domain-regex:") {
+            let regex = regex.trim();
+            // 提前校验，避免到构建 RegexSet 时才发现是哪条规则写错了。
+            return (!regex.is_empty() && regex::Regex::new(regex).is_ok())
+                .then(|| Self::Regex(regex.to_string()));
+        }
+
+        let pattern = pattern.trim_end_matches('.');
+        if pattern.is_empty() {
+            return None;
+        }
+        if let Some(suffix) = pattern.strip_prefix("domain-suffix:") {
+            return parse_domain(suffix).map(Self::Suffix);
+        }
+
+        if let Some((ip_str, prefix_str)) = pattern.split_once('/') {
+            let prefix_len: u8 = prefix_str.parse().ok()?;
+            let ip = ip_str.parse::<IpAddr>().ok()?;
+            let max_len = if ip.is_ipv4() { 32 } else { 128 };
+            if prefix_len > max_len {
+                return None;
+            }
+            return Some(Self::Cidr(ip, prefix_len));
+        }
+
+        if let Ok(ip) = pattern.parse::<IpAddr>() {
+            let len = if ip.is_ipv4() { 32 } else { 128 };
+            return Some(Self::Cidr(ip, len));
+        }
+
+        if let Some(suffix) = pattern.strip_prefix("*.") {
+            return parse_domain(suffix).map(Self::Subdomains);
+        }
+
+        parse_domain(pattern).map(Self::Domain)
+    }
+
+    fn is_domain(&self) -> bool {
+        !matches!(self, Self::Cidr(..))
+    }
+}
+
+fn parse_domain(domain: &str) -> Option<String> {
+    let domain = domain.trim().trim_end_matches('.').to_lowercase();
+    (!domain.is_empty() && !domain.split('.').any(str::is_empty)).then_some(domain)
+}
+
+/// 按标签反转的域名前缀树："www.example.com" 依次经过 com -> example -> www。
+#[derive(Debug, Default)]
+struct DomainTrie {
+    root: DomainNode,
+}
+
+#[derive(Debug, Default)]
+struct DomainNode {
+    children: HashMap<Box<str>, DomainNode>,
+    // 到此节点为止的域名本身命中。
+    exact: bool,
+    // 此节点之下的任意子域名命中。
+    subdomains: bool,
+}
+
+impl DomainTrie {
+    fn insert(&mut self, domain: &str, exact: bool, subdomains: bool) {
+        let mut node = &mut self.root;
+        for label in domain.rsplit('.') {
+            node = node.children.entry(label.into()).or_default();
+        }
+        node.exact |= exact;
+        node.subdomains |= subdomains;
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        let mut node = &self.root;
+        let mut labels = host.rsplit('.').peekable();
+        while let Some(label) = labels.next() {
+            let Some(child) = node.children.get(label) else {
+                return false;
+            };
+            node = child;
+            match labels.peek() {
+                Some(_) if node.subdomains => return true,
+                Some(_) => {}
+                None => return node.exact,
+            }
+        }
+        false
+    }
+}
+
+/// 按位展开的 CIDR 前缀树，IPv4/IPv6 各一棵。
+#[derive(Debug)]
+struct CidrTree {
+    // nodes[0] 是根；children 为 0 表示没有子节点（根不会被引用为子节点）。
+    nodes: Vec<CidrNode>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct CidrNode {
+    children: [u32; 2],
+    terminal: bool,
+}
+
+impl Default for CidrTree {
+    fn default() -> Self {
+        Self {
+            nodes: vec![CidrNode::default()],
+        }
+    }
+}
+
+impl CidrTree {
+    fn insert(&mut self, bits: u128, width: u8, prefix_len: u8) {
+        let mut index = 0;
+        for depth in 0..prefix_len {
+            if self.nodes[index].terminal {
+                // 更短的前缀已经覆盖了这段地址。
+                return;
+            }
+            let bit = ((bits >> (width - 1 - depth)) & 1) as usize;
+            let child = self.nodes[index].children[bit];
+            index = if child == 0 {
+                self.nodes.push(CidrNode::default());
+                let child = (self.nodes.len() - 1) as u32;
+                self.nodes[index].children[bit] = child;
+                child as usize
+            } else {
+                child as usize
+            };
+        }
+        self.nodes[index].terminal = true;
+    }
+
+    fn matches(&self, bits: u128, width: u8) -> bool {
+        let mut index = 0;
+        for depth in 0..width {
+            if self.nodes[index].terminal {
+                return true;
+            }
+            let bit = ((bits >> (width - 1 - depth)) & 1) as usize;
+            match self.nodes[index].children[bit] {
+                0 => return false,
+                child => index = child as usize,
+            }
+        }
+        self.nodes[index].terminal
+    }
+
+    fn is_empty(&self) -> bool {
+        self.nodes.len() == 1 && !self.nodes[0].terminal
+    }
+}
+
+/// 编译后的目标模式集合。
+#[derive(Debug, Default)]
+pub struct RuleSet {
+    domains: DomainTrie,
+    ipv4: CidrTree,
+    ipv6: CidrTree,
+    keywords: Vec<String>,
+    regexes: Option<RegexSet>,
+    entries: usize,
+    domain_entries: usize,
+}
+
+impl RuleSet {
+    /// 把条目编译成规则集。
+    pub fn new(entries: &[RuleSetEntry]) -> Result<Self> {
+        let mut rule_set = Self::default();
+        let mut regexes = Vec::new();
+        for entry in entries {
+            rule_set.entries += 1;
+            if entry.is_domain() {
+                rule_set.domain_entries += 1;
+            }
+            match entry {
+                RuleSetEntry::Domain(domain) => rule_set.domains.insert(domain, true, false),
+                RuleSetEntry::Subdomains(domain) => rule_set.domains.insert(domain, false, true),
+                RuleSetEntry::Suffix(domain) => rule_set.domains.insert(domain, true, true),
+                RuleSetEntry::Keyword(keyword) => rule_set.keywords.push(keyword.clone()),
+                RuleSetEntry::Regex(regex) => regexes.push(regex.as_str()),
+                RuleSetEntry::Cidr(IpAddr::V4(ip), len) => {
+                    rule_set.ipv4.insert(u32::from(*ip) as u128, 32, *len)
+                }
+                RuleSetEntry::Cidr(IpAddr::V6(ip), len) => {
+                    rule_set.ipv6.insert(u128::from(*ip), 128, *len)
+                }
+            }
+        }
+        if !regexes.is_empty() {
+            rule_set.regexes = Some(
+                RegexSet::new(regexes)
+                    .map_err(|e| CommonError::Config(format!("invalid domain-regex: {e}")))?,
+            );
+        }
+        Ok(rule_set)
+    }
+
+    /// `host` 必须已经规范化为小写且不带结尾的点。
+    pub fn match_domain(&self, host: &str) -> bool {
+        self.domains.matches(host)
+            || self
+                .keywords
+                .iter()
+                .any(|keyword| host.contains(keyword.as_str()))
+            || self
+                .regexes
+                .as_ref()
+                .is_some_and(|regexes| regexes.is_match(host))
+    }
+
+    pub fn match_ip(&self, ip: &IpAddr) -> bool {
+        match ip {
+            IpAddr::V4(ip) => self.ipv4.matches(u32::from(*ip) as u128, 32),
+            IpAddr::V6(ip) => self.ipv6.matches(u128::from(*ip), 128),
+        }
+    }
+
+    /// 是否包含域名类条目（精确/子域名/后缀/关键字/正则）。
+    pub fn has_domain_entries(&self) -> bool {
+        self.domain_entries > 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0 && self.ipv4.is_empty() && self.ipv6.is_empty()
+    }
+}
+
+impl fmt::Display for RuleSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} 条目标模式（域名类 {}，关键字 {}，正则 {}）",
+            self.entries,
+            self.domain_entries,
+            self.keywords.len(),
+            self.regexes.as_ref().map_or(0, RegexSet::len)
+        )
+    }
+}
+
+/// 解析文本规则集；错误信息带行号。
+pub fn parse_text_rule_set(text: &str) -> std::result::Result<Vec<RuleSetEntry>, String> {
+    let mut entries = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let entry = RuleSetEntry::parse(line)
+            .ok_or_else(|| format!("line {}: invalid pattern {line:?}", index + 1))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// 把条目编码成二进制规则集；字符串条目超过 65535 字节时报错而不是截断。
+pub fn encode_rule_set(entries: &[RuleSetEntry]) -> std::result::Result<Vec<u8>, String> {
+    let count = u32::try_from(entries.len())
+        .map_err(|_| format!("too many rule-set entries: {}", entries.len()))?;
+    let mut out = Vec::with_capacity(9 + entries.len() * 16);
+    out.extend_from_slice(BINARY_MAGIC);
+    out.push(BINARY_VERSION);
+    out.extend_from_slice(&count.to_be_bytes());
+    for entry in entries {
+        match entry {
+            RuleSetEntry::Domain(value) => encode_str(&mut out, TAG_DOMAIN, value)?,
+            RuleSetEntry::Subdomains(value) => encode_str(&mut out, TAG_SUBDOMAINS, value)?,
+            RuleSetEntry::Suffix(value) => encode_str(&mut out, TAG_SUFFIX, value)?,
+            RuleSetEntry::Keyword(value) => encode_str(&mut out, TAG_KEYWORD, value)?,
+            RuleSetEntry::Regex(value) => encode_str(&mut out, TAG_REGEX, value)?,
+            RuleSetEntry::Cidr(IpAddr::V4(ip), len) => {
+                out.push(TAG_CIDR_V4);
+                out.extend_from_slice(&ip.octets());
+                out.push(*len);
+            }
+            RuleSetEntry::Cidr(IpAddr::V6(ip), len) => {
+                out.push(TAG_CIDR_V6);
+                out.extend_from_slice(&ip.octets());
+                out.push(*len);
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn encode_str(out: &mut Vec<u8>, tag: u8, value: &str) -> std::result::Result<(), String> {
+    let len = u16::try_from(value.len())
+        .map_err(|_| format!("rule-set entry is {} bytes, limit is 65535", value.len()))?;
+    out.push(tag);
+    out.extend_from_slice(&len.to_be_bytes());
+    out.extend_from_slice(value.as_bytes());
+    Ok(())
+}
+
+/// 解码二进制规则集。
+pub fn decode_rule_set(data: &[u8]) -> std::result::Result<Vec<RuleSetEntry>, String> {
+    let mut reader = Reader { data, pos: 0 };
+    if reader.take(4)? != BINARY_MAGIC {
+        return Err("missing binary rule-set header".to_string());
+    }
+    let version = reader.take(1)?[0];
+    if version != BINARY_VERSION {
+        return Err(format!("unsupported binary rule-set version {version}"));
+    }
+    let count = u32::from_be_bytes(reader.take(4)?.try_into().expect("4 bytes"));
+    let mut entries = Vec::with_capacity(count.min(1 << 20) as usize);
+    for _ in 0..count {
+        let tag = reader.take(1)?[0];
+        let entry = match tag {
+            TAG_CIDR_V4 => {
+                let octets: [u8; 4] = reader.take(4)?.try_into().expect("4 bytes");
+                let len = reader.take(1)?[0];
+                if len > 32 {
+                    return Err(format!("invalid IPv4 prefix length {len}"));
+                }
+                RuleSetEntry::Cidr(IpAddr::V4(Ipv4Addr::from(octets)), len)
+            }
+            TAG_CIDR_V6 => {
+                let octets: [u8; 16] = reader.take(16)?.try_into().expect("16 bytes");
+                let len = reader.take(1)?[0];
+                if len > 128 {
+                    return Err(format!("invalid IPv6 prefix length {len}"));
+                }
+                RuleSetEntry::Cidr(IpAddr::V6(Ipv6Addr::from(octets)), len)
+            }
+            _ => {
+                let len = u16::from_be_bytes(reader.take(2)?.try_into().expect("2 bytes"));
+                let value = std::str::from_utf8(reader.take(len as usize)?)
+                    .map_err(|e| format!("invalid UTF-8 in entry: {e}"))?;
+                // 与文本规则集走同样的校验和规范化，手工构造的二进制文件不能绕过。
+                let entry = match tag {
+                    TAG_DOMAIN => parse_domain(value).map(RuleSetEntry::Domain),
+                    TAG_SUBDOMAINS => parse_domain(value).map(RuleSetEntry::Subdomains),
+                    TAG_SUFFIX => parse_domain(value).map(RuleSetEntry::Suffix),
+                    TAG_KEYWORD => {
+                        let keyword = value.trim().to_lowercase();
+                        (!keyword.is_empty()).then_some(RuleSetEntry::Keyword(keyword))
+                    }
+                    TAG_REGEX => (!value.is_empty() && regex::Regex::new(value).is_ok())
+                        .then(|| RuleSetEntry::Regex(value.to_string())),
+                    _ => return Err(format!("unknown entry tag {tag}")),
+                };
+                entry.ok_or_else(|| format!("invalid entry {value:?} with tag {tag}"))?
+            }
+        };
+        entries.push(entry);
+    }
+    if reader.pos != data.len() {
+        return Err("trailing bytes after last entry".to_string());
+    }
+    Ok(entries)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> std::result::Result<&'a [u8], String> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| "truncated binary rule-set".to_string())?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+}
+
+/// 读取规则集文件，按文件头识别文本或二进制格式。
+pub fn load_rule_set_file(path: impl AsRef<Path>) -> Result<Vec<RuleSetEntry>> {
+    let path = path.as_ref();
+    let data = std::fs::read(path).map_err(|e| {
+        CommonError::Config(format!("failed to read rule-set {}: {e}", path.display()))
+    })?;
+    let parsed = if data.starts_with(BINARY_MAGIC) {
+        decode_rule_set(&data)
+    } else {
+        std::str::from_utf8(&data)
+            .map_err(|e| format!("rule-set is neither binary nor UTF-8 text: {e}"))
+            .and_then(parse_text_rule_set)
+    };
+    parsed.map_err(|e| CommonError::Config(format!("rule-set {}: {e}", path.display())))
+}
+
+/// 把文本规则集编译成二进制规则集，返回条目数。
+pub fn compile_rule_set_file(input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<usize> {
+    let entries = load_rule_set_file(input)?;
+    let output = output.as_ref();
+    let data = encode_rule_set(&entries)
+        .map_err(|e| CommonError::Config(format!("rule-set {}: {e}", output.display())))?;
+    std::fs::write(output, data).map_err(|e| {
+        CommonError::Config(format!(
+            "failed to write rule-set {}: {e}",
+            output.display()
+        ))
+    })?;
+    Ok(entries.len())
+}
@@ -0,0 +1,844 @@
+use super::*;
+
+fn legacy(mode: DirectAccessMode, rules: &[&str]) -> RoutingPolicy {
+    RoutingPolicy::from_direct_access(
+        &DirectAccessConfig {
+            mode,
+            rules: rules.iter().map(ToString::to_string).collect(),
+            force_proxy_rule_set: None,
+        },
+        None,
+        &[],
+    )
+}
+
+fn routing(
+    rules: &[(&[&str], &str)],
+    final_outbound: &str,
+    groups: &[&str],
+) -> Result<RoutingPolicy> {
+    let config = RoutingConfig {
+        rules: rules
+            .iter()
+            .map(|(matches, outbound)| RoutingRuleConfig {
+                matches: matches.iter().map(ToString::to_string).collect(),
+                outbound: outbound.to_string(),
+            })
+            .collect(),
+        final_outbound: final_outbound.to_string(),
+        force_proxy_rule_set: None,
+    };
+    let groups = groups.iter().map(ToString::to_string).collect::<Vec<_>>();
+    RoutingPolicy::new(&config, None, &groups)
+}
+
+fn domain(host: &str) -> Address {
+    Address::Domain {
+        host: host.to_string(),
+        port: 443,
+    }
+}
+
+fn ipv4(addr: [u8; 4]) -> Address {
+    Address::Ipv4 { addr, port: 80 }
+}
+
+const TCP: TransportProtocol = TransportProtocol::Tcp;
+const UDP: TransportProtocol = TransportProtocol::Udp;
+
+const PROXY: Outbound = Outbound::Proxy(DEFAULT_PROXY_GROUP);
+
+#[test]
+fn test_proxy_all_mode() {
+    let policy = legacy(DirectAccessMode::ProxyAll, &["localhost", "10.0.0.0/8"]);
+    assert_eq!(policy.route(&domain("localhost"), TCP), PROXY);
+    assert_eq!(policy.route(&ipv4([10, 1, 2, 3]), TCP), PROXY);
+}
+
+#[test]
+fn test_direct_all_mode() {
+    let policy = legacy(DirectAccessMode::DirectAll, &[]);
+    assert_eq!(policy.route(&domain("example.com"), TCP), Outbound::Direct);
+    // 内部虚拟目标只能经 proxy 访问。
+    assert_eq!(policy.route(&Address::ProxyDns { port: 53 }, TCP), PROXY);
+}
+
+#[test]
+fn test_exact_domain_match() {
+    let policy = legacy(DirectAccessMode::Rules, &["localhost", "example.com"]);
+
+    assert_eq!(policy.route(&domain("localhost"), TCP), Outbound::Direct);
+    assert_eq!(policy.route(&domain("example.com"), TCP), Outbound::Direct);
+    assert_eq!(policy.route(&domain("example.org"), TCP), PROXY);
+}
+
+#[test]
+fn test_wildcard_domain_match() {
+    let policy = legacy(DirectAccessMode::Rules, &["*.local", "*.example.com"]);
+
+    assert_eq!(policy.route(&domain("myhost.local"), TCP), Outbound::Direct);
+    // "*.local" 不匹配 "local" 本身，只匹配子域名
+    assert_eq!(policy.route(&domain("local"), TCP), PROXY);
+    assert_eq!(
+        policy.route(&domain("sub.example.com"), TCP),
+        Outbound::Direct
+    );
+    assert_eq!(policy.route(&domain("example.com"), TCP), PROXY);
+    assert_eq!(policy.route(&domain("example.org"), TCP), PROXY);
+}
+
+#[test]
+fn test_exact_ip_and_cidr_match() {
+    let policy = legacy(
+        DirectAccessMode::Rules,
+        &["127.0.0.1", "::1", "10.0.0.0/8", "172.16.0.0/12"],
+    );
+
+    assert_eq!(policy.route(&ipv4([127, 0, 0, 1]), TCP), Outbound::Direct);
+    assert_eq!(
+        policy.route(&ipv4([10, 255, 255, 255]), TCP),
+        Outbound::Direct
+    );
+    assert_eq!(policy.route(&ipv4([172, 20, 0, 1]), TCP), Outbound::Direct);
+    assert_eq!(policy.route(&ipv4([172, 32, 0, 1]), TCP), PROXY);
+    assert_eq!(policy.route(&ipv4([192, 168, 1, 1]), TCP), PROXY);
+    assert_eq!(
+        policy.route(
+            &Address::Ipv6 {
+                addr: Ipv6Addr::LOCALHOST.octets(),
+                port: 80,
+            },
+            TCP
+        ),
+        Outbound::Direct
+    );
+}
+
+#[test]
+fn test_domain_with_ip_string() {
+    let policy = legacy(DirectAccessMode::Rules, &["10.0.0.0/8"]);
+
+    // 实际为 IP 字符串的域名应匹配 CIDR 规则
+    assert_eq!(policy.route(&domain("10.1.2.3"), TCP), Outbound::Direct);
+    assert_eq!(policy.route(&domain("8.8.8.8"), TCP), PROXY);
+}
+
+#[test]
+fn test_has_domain_rules_only_when_rules_can_match_domain() {
+    assert!(!legacy(DirectAccessMode::ProxyAll, &["example.com"]).has_domain_rules());
+    assert!(!legacy(DirectAccessMode::Rules, &["10.0.0.0/8", "127.0.0.1"]).has_domain_rules());
+    assert!(legacy(DirectAccessMode::Rules, &["*.example.com"]).has_domain_rules());
+}
+
+#[test]
+fn test_google_service_domains_are_forced_proxy_in_rules_mode() {
+    let policy = legacy(DirectAccessMode::Rules, &["*.cn", "*.com"]);
+    let tun_ip = ipv4([203, 0, 113, 1]);
+
+    for host in [
+        "services.googleapis.cn",
+        "www.google.com",
+        "rr1---sn-2x3eenel.xn--ngstr-lra8j.com",
+        "play.googleapis.com",
+    ] {
+        assert_eq!(policy.route(&domain(host), TCP), PROXY, "{host}");
+        assert_eq!(
+            policy.route_with_domain(&tun_ip, TCP, Some(host)),
+            PROXY,
+            "{host}"
+        );
+    }
+    assert_eq!(policy.route(&domain("example.cn"), TCP), Outbound::Direct);
+    assert_eq!(
+        policy.route(&domain("notgoogle.com"), TCP),
+        Outbound::Direct
+    );
+}
+
+#[test]
+fn test_case_insensitive_domain_and_trailing_dot() {
+    let policy = legacy(DirectAccessMode::Rules, &["LocalHost", "*.Example.COM."]);
+
+    assert_eq!(policy.route(&domain("LOCALHOST"), TCP), Outbound::Direct);
+    assert_eq!(
+        policy.route(&domain("sub.example.com."), TCP),
+        Outbound::Direct
+    );
+}
+
+#[test]
+fn test_invalid_legacy_rules_ignored() {
+    let policy = legacy(DirectAccessMode::Rules, &["", "10.0.0.0/99", "localhost"]);
+    assert_eq!(policy.route(&domain("localhost"), TCP), Outbound::Direct);
+}
+
+#[test]
+fn test_mixed_rules() {
+    let policy = legacy(
+        DirectAccessMode::Rules,
+        &[
+            "localhost",
+            "*.local",
+            "127.0.0.0/8",
+            "10.0.0.0/8",
+            "192.168.0.0/16",
+            "::1",
+        ],
+    );
+
+    // 域名匹配
+    assert_eq!(policy.route(&domain("localhost"), TCP), Outbound::Direct);
+    assert_eq!(policy.route(&domain("mypc.local"), TCP), Outbound::Direct);
+    // IP 匹配
+    assert_eq!(policy.route(&ipv4([127, 0, 0, 1]), TCP), Outbound::Direct);
+    assert_eq!(policy.route(&ipv4([10, 0, 0, 1]), TCP), Outbound::Direct);
+    assert_eq!(policy.route(&ipv4([192, 168, 1, 1]), UDP), Outbound::Direct);
+    // 应通过代理访问
+    assert_eq!(policy.route(&domain("google.com"), TCP), PROXY);
+    assert_eq!(policy.route(&ipv4([8, 8, 8, 8]), UDP), PROXY);
+}
+
+#[test]
+fn test_empty_rules() {
+    let policy = legacy(DirectAccessMode::Rules, &[]);
+
+    // 规则模式下没有规则时，任何地址都不应直连
+    assert_eq!(policy.route(&domain("localhost"), TCP), PROXY);
+    assert_eq!(policy.route(&ipv4([127, 0, 0, 1]), TCP), PROXY);
+    assert_eq!(
+        policy.route(
+            &Address::Ipv6 {
+                addr: Ipv6Addr::LOCALHOST.octets(),
+                port: 80,
+            },
+            UDP
+        ),
+        PROXY
+    );
+    assert!(!policy.has_domain_rules());
+    assert!(!policy.may_route_direct("localhost"));
+}
+
+#[test]
+fn rules_are_evaluated_in_order_with_final_fallback() {
+    let policy = routing(
+        &[
+            (&["ads.example.com"], "block"),
+            (&["*.example.com", "10.0.0.0/8"], "direct"),
+            (&["*.video.test"], "us"),
+            (&["*.example.com"], "us"),
+        ],
+        "eu",
+        &["us", "eu"],
+    )
+    .unwrap();
+
+    assert_eq!(
+        policy.route(&domain("ads.example.com"), TCP),
+        Outbound::Block
+    );
+    assert_eq!(
+        policy.route(&domain("www.example.com"), TCP),
+        Outbound::Direct
+    );
+    assert_eq!(policy.route(&ipv4([10, 0, 0, 1]), TCP), Outbound::Direct);
+    assert_eq!(
+        policy.route(&domain("cdn.video.test"), TCP),
+        Outbound::Proxy(1)
+    );
+    assert_eq!(
+        policy.route(&domain("example.org"), TCP),
+        Outbound::Proxy(2)
+    );
+    assert_eq!(policy.describe(Outbound::Proxy(2)).to_string(), "eu");
+    assert_eq!(policy.describe(PROXY).to_string(), "proxy");
+}
+
+#[test]
+fn decisions_report_the_matched_rule() {
+    let policy = routing(
+        &[
+            (&["ads.example.com"], "block"),
+            (&["*.example.com", "10.0.0.0/8"], "direct"),
+        ],
+        "proxy",
+        &[],
+    )
+    .unwrap();
+    let decide = |address: &Address| policy.decide(address, TCP, None, None);
+
+    let decision = decide(&ipv4([10, 1, 2, 3]));
+    assert_eq!(decision.outbound, Outbound::Direct);
+    assert_eq!(decision.rule, Some(1));
+    assert_eq!(policy.rule_label(decision.rule), "*.example.com 10.0.0.0/8");
+    let decision = decide(&domain("example.org"));
+    assert_eq!(
+        decision,
+        RouteDecision {
+            outbound: PROXY,
+            rule: None
+        }
+    );
+    assert_eq!(policy.rule_label(decision.rule), "final");
+
+    let legacy = legacy(DirectAccessMode::Rules, &["example.com"]);
+    let decision = legacy.decide(&domain("example.com"), TCP, None, None);
+    assert_eq!(legacy.rule_label(decision.rule), "direct_access");
+}
+
+#[test]
+fn default_proxy_group_can_be_switched() {
+    let policy = routing(
+        &[
+            (&["*.example.com"], "proxy"),
+            (&["*.example.net"], "us"),
+            (&["10.0.0.0/8"], "direct"),
+        ],
+        "proxy",
+        &["hk", "us"],
+    )
+    .unwrap()
+    .with_default_proxy_group(1)
+    .unwrap();
+
+    assert_eq!(policy.default_proxy_group(), 1);
+    assert_eq!(
+        policy.route(&domain("www.example.com"), TCP),
+        Outbound::Proxy(1)
+    );
+    assert_eq!(
+        policy.route(&domain("example.org"), TCP),
+        Outbound::Proxy(1)
+    );
+    assert_eq!(
+        policy.route(&domain("www.example.net"), TCP),
+        Outbound::Proxy(2)
+    );
+    assert_eq!(policy.route(&ipv4([10, 0, 0, 1]), TCP), Outbound::Direct);
+    // DNS proxy 等内部目标固定走默认分组。
+    assert_eq!(policy.route(&Address::ProxyDns { port: 53 }, UDP), PROXY);
+
+    let policy = legacy(DirectAccessMode::ProxyAll, &[]);
+    assert!(policy.with_default_proxy_group(1).is_err());
+}
+
+#[test]
+fn ip_targets_match_cached_domain_in_rule_order() {
+    let policy = routing(
+        &[(&["*.video.test"], "us"), (&["198.51.100.0/24"], "direct")],
+        "block",
+        &["us"],
+    )
+    .unwrap();
+    let target = ipv4([198, 51, 100, 7]);
+
+    assert_eq!(policy.route(&target, TCP), Outbound::Direct);
+    assert_eq!(
+        policy.route_with_domain(&target, TCP, Some("cdn.video.test")),
+        Outbound::Proxy(1)
+    );
+    assert_eq!(
+        policy.route_with_domain(&ipv4([192, 0, 2, 1]), TCP, Some("other.test")),
+        Outbound::Block
+    );
+    assert!(policy.matches_domain_rule("CDN.video.test."));
+    assert!(!policy.matches_domain_rule("198.51.100.7"));
+}
+
+#[test]
+fn rejects_unknown_outbounds_and_bad_patterns() {
+    assert!(routing(&[(&["example.com"], "missing")], "proxy", &[]).is_err());
+    assert!(routing(&[], "missing", &[]).is_err());
+    assert!(routing(&[(&["10.0.0.0/99"], "direct")], "proxy", &[]).is_err());
+    assert!(routing(&[(&[], "direct")], "proxy", &[]).is_err());
+    assert!(routing(&[], "proxy", &["direct"]).is_err());
+    assert!(routing(&[], "proxy", &["us", "us"]).is_err());
+}
+
+#[test]
+fn routing_takes_precedence_over_legacy_direct_access() {
+    let legacy_config = DirectAccessConfig {
+        mode: DirectAccessMode::DirectAll,
+        rules: Vec::new(),
+        force_proxy_rule_set: None,
+    };
+    let policy = RoutingPolicy::from_configs(None, &legacy_config, None, &[]).unwrap();
+    assert_eq!(policy.route(&domain("example.com"), TCP), Outbound::Direct);
+
+    let config: RoutingConfig = toml::from_str(
+        r#"
+final = "block"
+rules = [{ match = ["example.com"], outbound = "proxy" }]
+"#,
+    )
+    .unwrap();
+    let policy = RoutingPolicy::from_configs(Some(&config), &legacy_config, None, &[]).unwrap();
+    assert_eq!(policy.route(&domain("example.com"), TCP), PROXY);
+    assert_eq!(policy.route(&domain("example.org"), TCP), Outbound::Block);
+}
+
+#[test]
+fn test_address_to_string() {
+    assert_eq!(address_to_string(&domain("example.com")), "example.com:443");
+    assert_eq!(address_to_string(&ipv4([192, 168, 1, 1])), "192.168.1.1:80");
+    assert_eq!(
+        address_to_string(&Address::Ipv6 {
+            addr: Ipv6Addr::LOCALHOST.octets(),
+            port: 443,
+        }),
+        "[::1]:443"
+    );
+}
+
+#[test]
+fn domain_trie_distinguishes_exact_subdomain_and_suffix() {
+    let policy = routing(
+        &[
+            (&["domain-suffix:suffix.test"], "direct"),
+            (&["*.wild.test"], "block"),
+            (&["exact.test"], "us"),
+        ],
+        "proxy",
+        &["us"],
+    )
+    .unwrap();
+
+    assert_eq!(policy.route(&domain("suffix.test"), TCP), Outbound::Direct);
+    assert_eq!(
+        policy.route(&domain("a.b.suffix.test"), TCP),
+        Outbound::Direct
+    );
+    assert_eq!(policy.route(&domain("notsuffix.test"), TCP), PROXY);
+    assert_eq!(policy.route(&domain("x.wild.test"), TCP), Outbound::Block);
+    assert_eq!(policy.route(&domain("wild.test"), TCP), PROXY);
+    assert_eq!(policy.route(&domain("exact.test"), TCP), Outbound::Proxy(1));
+    assert_eq!(policy.route(&domain("www.exact.test"), TCP), PROXY);
+}
+
+#[test]
+fn keyword_and_regex_patterns_match_domains() {
+    let policy = routing(
+        &[
+            (&["domain-keyword:tracker"], "block"),
+            (&[r"domain-regex:^ad[0-9]+\.example\.com$"], "block"),
+        ],
+        "proxy",
+        &[],
+    )
+    .unwrap();
+
+    assert_eq!(
+        policy.route(&domain("eu.tracker.net"), TCP),
+        Outbound::Block
+    );
+    assert_eq!(
+        policy.route(&domain("ad42.example.com"), TCP),
+        Outbound::Block
+    );
+    assert_eq!(policy.route(&domain("ads.example.com"), TCP), PROXY);
+    assert!(policy.has_domain_rules());
+    assert!(routing(&[(&["domain-regex:("], "block")], "proxy", &[]).is_err());
+}
+
+#[test]
+fn cidr_tree_handles_overlapping_prefixes() {
+    let policy = routing(
+        &[
+            (&["10.1.0.0/16"], "block"),
+            (&["10.0.0.0/8", "2001:db8::/32", "0.0.0.0/0"], "direct"),
+        ],
+        "proxy",
+        &[],
+    )
+    .unwrap();
+
+    assert_eq!(policy.route(&ipv4([10, 1, 2, 3]), TCP), Outbound::Block);
+    assert_eq!(policy.route(&ipv4([10, 2, 0, 1]), TCP), Outbound::Direct);
+    assert_eq!(policy.route(&ipv4([8, 8, 8, 8]), TCP), Outbound::Direct);
+    let v6 = |addr: Ipv6Addr| Address::Ipv6 {
+        addr: addr.octets(),
+        port: 443,
+    };
+    assert_eq!(
+        policy.route(&v6("2001:db8::1".parse().unwrap()), TCP),
+        Outbound::Direct
+    );
+    assert_eq!(policy.route(&v6(Ipv6Addr::LOCALHOST), TCP), PROXY);
+}
+
+#[test]
+fn port_and_network_conditions_are_anded_with_destinations() {
+    let policy = routing(
+        &[
+            (&["*.example.com", "port:443", "network:udp"], "block"),
+            (&["port:8000-9000", "port:22"], "direct"),
+        ],
+        "proxy",
+        &[],
+    )
+    .unwrap();
+
+    assert_eq!(
+        policy.route(&domain("www.example.com"), UDP),
+        Outbound::Block
+    );
+    assert_eq!(policy.route(&domain("www.example.com"), TCP), PROXY);
+    let on_port = |port| Address::Domain {
+        host: "www.example.com".to_string(),
+        port,
+    };
+    assert_eq!(policy.route(&on_port(80), UDP), PROXY);
+    assert_eq!(policy.route(&on_port(8080), TCP), Outbound::Direct);
+    assert_eq!(policy.route(&on_port(22), UDP), Outbound::Direct);
+    assert_eq!(policy.route(&on_port(9001), TCP), PROXY);
+    assert!(!policy.matches_domain_rule("example.org"));
+
+    assert!(routing(&[(&["port:9000-8000"], "direct")], "proxy", &[]).is_err());
+    assert!(routing(&[(&["network:icmp"], "direct")], "proxy", &[]).is_err());
+}
+
+#[test]
+fn process_conditions_only_match_when_source_process_is_known() {
+    let policy = routing(
+        &[
+            (&["process-name:Slack"], "direct"),
+            (&["process-path:/usr/bin/firefox", "*.example.com"], "block"),
+        ],
+        "proxy",
+        &[],
+    )
+    .unwrap();
+    let process = |name: &str, path: &str| SourceProcess {
+        pid: 42,
+        name: name.to_string(),
+        path: path.to_string(),
+    };
+    let slack = process("slack", "/opt/slack/slack");
+    let firefox = process("firefox", "/usr/bin/firefox");
+    let route = |address: &Address, process: Option<&SourceProcess>| {
+        policy.route_with_process(address, TCP, None, process)
+    };
+
+    assert!(policy.has_process_rules());
+    assert_eq!(route(&domain("chat.test"), Some(&slack)), Outbound::Direct);
+    assert_eq!(route(&domain("chat.test"), None), PROXY);
+    assert_eq!(
+        route(&domain("www.example.com"), Some(&firefox)),
+        Outbound::Block
+    );
+    assert_eq!(route(&domain("www.example.org"), Some(&firefox)), PROXY);
+    assert_eq!(route(&domain("www.example.com"), None), PROXY);
+    assert!(!legacy(DirectAccessMode::Rules, &["example.com"]).has_process_rules());
+    assert!(routing(&[(&["process-name:"], "direct")], "proxy", &[]).is_err());
+}
+
+#[test]
+fn may_route_direct_treats_connection_time_conditions_as_possible() {
+    let policy = routing(
+        &[
+            (&["ads.example.com"], "block"),
+            (&["*.example.com", "port:22"], "direct"),
+            (&["*.chat.test", "process-name:slack"], "direct"),
+            (&["*.video.test", "network:udp"], "proxy"),
+            (&["*.video.test"], "block"),
+            (&["10.0.0.0/8"], "direct"),
+        ],
+        "proxy",
+        &[],
+    )
+    .unwrap();
+
+    // 仅按域名（端口 0、TCP、无进程）判断会漏掉这些只在建连时才命中的直连规则。
+    assert_eq!(policy.route(&domain("git.example.com"), TCP), PROXY);
+    assert!(policy.may_route_direct("git.example.com"));
+    assert!(policy.may_route_direct("api.chat.test."));
+    // 前面的规则已确定分流结果时不再往下看；带条件的非直连规则不会挡住后面的规则。
+    assert!(!policy.may_route_direct("ads.example.com"));
+    assert!(!policy.may_route_direct("cdn.video.test"));
+    assert!(!policy.may_route_direct("example.org"));
+
+    let transport_only = routing(&[(&["network:udp"], "direct")], "proxy", &[]).unwrap();
+    assert!(transport_only.may_route_direct("example.org"));
+    assert!(legacy(DirectAccessMode::DirectAll, &[]).may_route_direct("example.org"));
+}
+
+#[test]
+fn legacy_port_rules_do_not_narrow_destination_rules() {
+    let policy = legacy(DirectAccessMode::Rules, &["example.com", "port:22"]);
+    assert_eq!(policy.route(&domain("example.com"), TCP), Outbound::Direct);
+    assert_eq!(policy.route(&ipv4([192, 0, 2, 1]), TCP), PROXY);
+    assert_eq!(
+        policy.route(
+            &Address::Ipv4 {
+                addr: [192, 0, 2, 1],
+                port: 22
+            },
+            TCP
+        ),
+        Outbound::Direct
+    );
+}
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("ppaass-rule-set-{}-{name}", std::process::id()))
+}
+
+#[test]
+fn text_and_binary_rule_sets_round_trip() {
+    let text = "# comment\n\ndomain-suffix:cn.test\n*.wild.test\ndomain-keyword:kw\n\
+                domain-regex:^re\\.\n192.0.2.0/24\n2001:db8::1\n";
+    let entries = parse_text_rule_set(text).unwrap();
+    assert_eq!(entries.len(), 6);
+    assert_eq!(
+        decode_rule_set(&encode_rule_set(&entries).unwrap()).unwrap(),
+        entries
+    );
+
+    let error = parse_text_rule_set("example.com\n10.0.0.0/40\n").unwrap_err();
+    assert!(error.contains("line 2"), "{error}");
+    let mut truncated = encode_rule_set(&entries).unwrap();
+    truncated.pop();
+    assert!(decode_rule_set(&truncated).is_err());
+
+    let text_path = temp_path("round-trip.list");
+    let binary_path = temp_path("round-trip.bin");
+    std::fs::write(&text_path, text).unwrap();
+    assert_eq!(compile_rule_set_file(&text_path, &binary_path).unwrap(), 6);
+    assert_eq!(load_rule_set_file(&binary_path).unwrap(), entries);
+
+    for path in [&text_path, &binary_path] {
+        let pattern = format!("rule-set:{}", path.display());
+        let policy = routing(&[(&[pattern.as_str()], "direct")], "proxy", &[]).unwrap();
+        assert_eq!(policy.route(&domain("a.cn.test"), TCP), Outbound::Direct);
+        assert_eq!(policy.route(&domain("re.example"), TCP), Outbound::Direct);
+        assert_eq!(policy.route(&ipv4([192, 0, 2, 9]), TCP), Outbound::Direct);
+        assert_eq!(policy.route(&domain("other.test"), TCP), PROXY);
+    }
+    std::fs::remove_file(&text_path).unwrap();
+    std::fs::remove_file(&binary_path).unwrap();
+
+    let missing = format!("rule-set:{}", temp_path("missing.list").display());
+    assert!(routing(&[(&[missing.as_str()], "direct")], "proxy", &[]).is_err());
+    assert_eq!(
+        legacy(DirectAccessMode::Rules, &[missing.as_str(), "localhost"])
+            .route(&domain("localhost"), TCP),
+        Outbound::Direct
+    );
+}
+
+#[test]
+fn binary_rule_sets_reject_invalid_or_oversized_entries() {
+    // 手工构造的二进制规则集也要经过域名校验，空标签不能进入前缀树。
+    for entry in [
+        RuleSetEntry::Domain("bad..example".to_string()),
+        RuleSetEntry::Suffix(String::new()),
+        RuleSetEntry::Keyword(" ".to_string()),
+        RuleSetEntry::Regex("(".to_string()),
+    ] {
+        let data = encode_rule_set(std::slice::from_ref(&entry)).unwrap();
+        assert!(decode_rule_set(&data).is_err(), "{entry:?}");
+    }
+    let data = encode_rule_set(&[RuleSetEntry::Subdomains("Example.COM.".to_string())]).unwrap();
+    assert_eq!(
+        decode_rule_set(&data).unwrap(),
+        [RuleSetEntry::Subdomains("example.com".to_string())]
+    );
+
+    let oversized = RuleSetEntry::Keyword("k".repeat(usize::from(u16::MAX) + 1));
+    assert!(encode_rule_set(&[oversized]).is_err());
+}
+
+#[test]
+fn force_proxy_rule_set_can_be_overridden_or_disabled() {
+    let path = temp_path("force-proxy.list");
+    std::fs::write(&path, "domain-suffix:private.test\n").unwrap();
+    let with_force_proxy = |force_proxy_rule_set: Option<String>| {
+        RoutingPolicy::new(
+            &RoutingConfig {
+                rules: vec![RoutingRuleConfig {
+                    matches: vec!["*.com".to_string(), "*.test".to_string()],
+                    outbound: "direct".to_string(),
+                }],
+                final_outbound: "proxy".to_string(),
+                force_proxy_rule_set,
+            },
+            None,
+            &[],
+        )
+        .unwrap()
+    };
+
+    let builtin = with_force_proxy(None);
+    assert_eq!(builtin.route(&domain("www.google.com"), TCP), PROXY);
+    assert_eq!(
+        builtin.route(&domain("a.private.test"), TCP),
+        Outbound::Direct
+    );
+
+    let custom = with_force_proxy(Some(path.display().to_string()));
+    assert_eq!(
+        custom.route(&domain("www.google.com"), TCP),
+        Outbound::Direct
+    );
+    assert_eq!(custom.route(&domain("a.private.test"), TCP), PROXY);
+
+    let disabled = with_force_proxy(Some(String::new()));
+    assert_eq!(
+        disabled.route(&domain("www.google.com"), TCP),
+        Outbound::Direct
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+fn geoip_config(name: &str, list: &str) -> GeoIpConfig {
+    let path = temp_path(name);
+    std::fs::write(&path, list).unwrap();
+    GeoIpConfig {
+        database: path.display().to_string(),
+    }
+}
+
+#[test]
+fn geoip_cidr_list_resolves_countries() {
+    let database = GeoIpDatabase::parse_list(
+        "# comment\n1.0.1.0/24 CN\n1.0.0.0/8,us\n2400:da00::/32 cn\n203.0.113.7 JP\n",
+    )
+    .unwrap();
+    let country = |ip: &str| database.country(ip.parse().unwrap()).map(|c| c.to_string());
+
+    // 嵌套时更具体的前缀生效，外层覆盖其余部分。
+    assert_eq!(country("1.0.1.9").as_deref(), Some("CN"));
+    assert_eq!(country("1.0.0.255").as_deref(), Some("US"));
+    assert_eq!(country("1.0.2.0").as_deref(), Some("US"));
+    assert_eq!(country("1.255.0.1").as_deref(), Some("US"));
+    assert_eq!(country("2400:da00::1").as_deref(), Some("CN"));
+    assert_eq!(country("::ffff:203.0.113.7").as_deref(), Some("JP"));
+    assert_eq!(country("203.0.113.8"), None);
+    assert_eq!(country("8.8.8.8"), None);
+
+    // 多层嵌套、共享起点或终点的区间都按最长前缀匹配。
+    let nested = GeoIpDatabase::parse_list(
+        "1.0.0.0/8 CN\n1.2.3.0/24 US\n1.2.0.0/16 JP\n1.2.3.128/25 DE\n1.0.0.0/16 KR\n1.255.255.0/24 FR\n0.0.0.0/0 AU\n255.255.255.255 NZ\n",
+    )
+    .unwrap();
+    let country = |ip: &str| nested.country(ip.parse().unwrap()).map(|c| c.to_string());
+    assert_eq!(country("1.2.3.4").as_deref(), Some("US"));
+    assert_eq!(country("1.2.3.200").as_deref(), Some("DE"));
+    assert_eq!(country("1.2.4.1").as_deref(), Some("JP"));
+    assert_eq!(country("1.2.2.255").as_deref(), Some("JP"));
+    assert_eq!(country("1.0.0.1").as_deref(), Some("KR"));
+    assert_eq!(country("1.3.0.1").as_deref(), Some("CN"));
+    assert_eq!(country("1.255.255.255").as_deref(), Some("FR"));
+    assert_eq!(country("2.0.0.0").as_deref(), Some("AU"));
+    assert_eq!(country("0.255.255.255").as_deref(), Some("AU"));
+    assert_eq!(country("255.255.255.254").as_deref(), Some("AU"));
+    assert_eq!(country("255.255.255.255").as_deref(), Some("NZ"));
+
+    let error = GeoIpDatabase::parse_list("1.0.0.0/8 US\n1.0.0.0/33 CN\n").unwrap_err();
+    assert!(error.contains("line 2"), "{error}");
+    assert!(GeoIpDatabase::parse_list("1.0.0.0/8 USA\n").is_err());
+}
+
+#[test]
+fn geoip_rules_match_ip_targets_and_cached_domains() {
+    let geoip = geoip_config(
+        "geoip-routing.list",
+        "198.51.100.0/24 CN\n2001:db8::/32 CN\n",
+    );
+    let config = RoutingConfig {
+        rules: vec![
+            RoutingRuleConfig {
+                matches: vec!["*.blocked.test".to_string()],
+                outbound: "block".to_string(),
+            },
+            RoutingRuleConfig {
+                matches: vec!["geoip:cn".to_string()],
+                outbound: "direct".to_string(),
+            },
+        ],
+        ..RoutingConfig::default()
+    };
+    let policy = RoutingPolicy::new(&config, Some(&geoip), &[]).unwrap();
+
+    assert_eq!(
+        policy.route(&ipv4([198, 51, 100, 7]), TCP),
+        Outbound::Direct
+    );
+    assert_eq!(policy.route(&ipv4([192, 0, 2, 1]), UDP), PROXY);
+    assert_eq!(
+        policy.route(
+            &Address::Ipv6 {
+                addr: "2001:db8::1".parse::<Ipv6Addr>().unwrap().octets(),
+                port: 443,
+            },
+            TCP
+        ),
+        Outbound::Direct
+    );
+    // 域名规则排在前面时，DNS 缓存还原出的域名仍然先命中。
+    assert_eq!(
+        policy.route_with_domain(&ipv4([198, 51, 100, 7]), TCP, Some("a.blocked.test")),
+        Outbound::Block
+    );
+    // 域名目标不会为 GeoIP 额外解析。
+    assert_eq!(policy.route(&domain("cn.example"), TCP), PROXY);
+    // 但解析后的真实地址可能命中 GeoIP 直连，fake-IP 不能给它假地址。
+    assert!(policy.may_route_direct("cn.example"));
+    assert!(!policy.may_route_direct("a.blocked.test"));
+
+    assert!(RoutingPolicy::new(&config, None, &[]).is_err());
+
+    let legacy_config = DirectAccessConfig {
+        mode: DirectAccessMode::Rules,
+        rules: vec!["geoip:CN".to_string(), "localhost".to_string()],
+        force_proxy_rule_set: None,
+    };
+    let legacy = RoutingPolicy::from_direct_access(&legacy_config, Some(&geoip), &[]);
+    assert_eq!(
+        legacy.route(&ipv4([198, 51, 100, 7]), TCP),
+        Outbound::Direct
+    );
+    let without_database = RoutingPolicy::from_direct_access(&legacy_config, None, &[]);
+    assert_eq!(without_database.route(&ipv4([198, 51, 100, 7]), TCP), PROXY);
+    assert_eq!(
+        without_database.route(&domain("localhost"), TCP),
+        Outbound::Direct
+    );
+    std::fs::remove_file(&geoip.database).unwrap();
+}
+
+#[test]
+fn outbound_router_resolves_groups_and_keeps_overrides_across_reload() {
+    let groups = ["proxy", "us"]
+        .iter()
+        .enumerate()
+        .map(|(index, name)| ProxyGroup::new(index, name, Arc::new(()), Arc::new(())))
+        .collect();
+    let source = |final_outbound: &str| RoutingSource {
+        routing: Some(RoutingConfig {
+            rules: vec![RoutingRuleConfig {
+                matches: vec!["*.example.com".to_string()],
+                outbound: "us".to_string(),
+            }],
+            final_outbound: final_outbound.to_string(),
+            force_proxy_rule_set: None,
+        }),
+        ..RoutingSource::default()
+    };
+    let router = OutboundRouter::new(source("direct"), groups).unwrap();
+
+    let route = router.route(&domain("www.example.com"), TransportProtocol::Tcp);
+    assert_eq!(route.name(), "us");
+    let decision = router.decide(&domain("other.test"), TransportProtocol::Tcp, None, None);
+    assert_eq!(decision.route.name(), OUTBOUND_DIRECT);
+    assert_eq!(decision.rule, "final");
+
+    router.select_proxy_group(Some("us")).unwrap();
+    assert!(router.select_proxy_group(Some("missing")).is_err());
+    // 引用不存在分组的新规则整体失败，旧规则与覆盖项保持不变。
+    assert!(router.reload(source("eu")).is_err());
+    router.reload(source("proxy")).unwrap();
+    assert_eq!(router.overrides().proxy_group, Some(1));
+    let route = router.route(&domain("other.test"), TransportProtocol::Tcp);
+    assert_eq!(route.name(), "us");
+}
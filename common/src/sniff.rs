@@ -0,0 +1,594 @@
+//! 连接首包域名嗅探。
+//!
+//! TUN 入口只能看到目标 IP；DNS 缓存里没有对应域名时（DoH、硬编码 IP），
+//! 可以从客户端首包恢复域名：TLS ClientHello 的 SNI、HTTP/1 请求的 Host 头，
+//! 以及 QUIC v1 Initial 包里加密的 ClientHello。这里只做只读解析，不改动调用方数据。
+
+use aes_gcm::aead::{AeadInPlace, KeyInit};
+use aes_gcm::aes::Aes128;
+use aes_gcm::aes::cipher::{BlockEncrypt, generic_array::GenericArray};
+use aes_gcm::{Aes128Gcm, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::net::IpAddr;
+
+const TLS_HANDSHAKE_RECORD: u8 = 0x16;
+const TLS_CLIENT_HELLO: u8 = 0x01;
+const TLS_EXTENSION_SERVER_NAME: u16 = 0x0000;
+const TLS_SERVER_NAME_HOST: u8 = 0x00;
+
+const HTTP_METHODS: [&[u8]; 9] = [
+    b"GET ",
+    b"POST ",
+    b"HEAD ",
+    b"PUT ",
+    b"DELETE ",
+    b"OPTIONS ",
+    b"PATCH ",
+    b"TRACE ",
+    b"CONNECT ",
+];
+
+const QUIC_VERSION_1: u32 = 0x0000_0001;
+/// RFC 9001 5.2 规定的 QUIC v1 Initial salt。
+const QUIC_V1_INITIAL_SALT: [u8; 20] = [
+    0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad,
+    0xcc, 0xbb, 0x7f, 0x0a,
+];
+const QUIC_MAX_CID_LEN: usize = 20;
+const QUIC_HP_SAMPLE_LEN: usize = 16;
+const QUIC_AEAD_TAG_LEN: usize = 16;
+
+/// TCP 首包嗅探结果。
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SniffResult {
+    /// 识别到域名，已统一为小写并去掉末尾根点。
+    Domain(String),
+    /// 数据像 TLS/HTTP 但还不完整，调用方可以继续读取后重试。
+    Incomplete,
+    /// 不是可识别的协议，或协议里没有可用域名。
+    NotMatched,
+}
+
+/// 从 TCP 流开头的字节中识别 TLS SNI 或 HTTP/1 Host。
+///
+/// SNI/Host 是 IP 字面量时返回 `NotMatched`，这种情况下域名信息并不比目标 IP 多。
+pub fn sniff_tcp_domain(data: &[u8]) -> SniffResult {
+    match data.first() {
+        None => SniffResult::Incomplete,
+        Some(&TLS_HANDSHAKE_RECORD) => sniff_tls(data),
+        Some(_) => sniff_http(data),
+    }
+}
+
+/// 从单个 UDP 数据报中识别 QUIC v1 Initial 包携带的 SNI。
+///
+/// 只使用本包内从偏移 0 开始连续的 CRYPTO 数据；ClientHello 跨多个 Initial 包且
+/// SNI 不在首段时返回 `None`，调用方按原有 IP 规则处理即可。
+pub fn sniff_quic_sni(packet: &[u8]) -> Option<String> {
+    let first = *packet.first()?;
+    // 长包头、fixed bit 置位且类型为 Initial。
+    if first & 0xc0 != 0xc0 || (first >> 4) & 0x03 != 0 {
+        return None;
+    }
+    let mut reader = Reader::new(packet);
+    reader.take(1)?;
+    if reader.u32()? != QUIC_VERSION_1 {
+        return None;
+    }
+    let dcid_len = reader.u8()? as usize;
+    if dcid_len > QUIC_MAX_CID_LEN {
+        return None;
+    }
+    let dcid = reader.take(dcid_len)?;
+    let scid_len = reader.u8()? as usize;
+    if scid_len > QUIC_MAX_CID_LEN {
+        return None;
+    }
+    reader.take(scid_len)?;
+    let token_len = usize::try_from(reader.varint()?).ok()?;
+    reader.take(token_len)?;
+    let length = usize::try_from(reader.varint()?).ok()?;
+    let pn_offset = reader.position();
+    let packet_end = pn_offset.checked_add(length)?;
+    if packet_end > packet.len() || length < 4 + QUIC_HP_SAMPLE_LEN {
+        return None;
+    }
+
+    // 去掉包头保护：采样从包号字段后第 4 字节开始，与包号实际长度无关。
+    let keys = QuicInitialKeys::client(dcid)?;
+    let mask = keys.header_mask(&packet[pn_offset + 4..pn_offset + 4 + QUIC_HP_SAMPLE_LEN]);
+    let mut header = packet[..pn_offset + 4].to_vec();
+    header[0] ^= mask[0] & 0x0f;
+    let pn_len = (header[0] & 0x03) as usize + 1;
+    let mut packet_number = 0u64;
+    for i in 0..pn_len {
+        header[pn_offset + i] ^= mask[1 + i];
+        packet_number = (packet_number << 8) | header[pn_offset + i] as u64;
+    }
+    header.truncate(pn_offset + pn_len);
+
+    let mut payload = packet[pn_offset + pn_len..packet_end].to_vec();
+    if payload.len() < QUIC_AEAD_TAG_LEN {
+        return None;
+    }
+    keys.decrypt(packet_number, &header, &mut payload)?;
+    let crypto = collect_crypto_stream(&payload)?;
+    match sniff_client_hello(&crypto) {
+        SniffResult::Domain(domain) => Some(domain),
+        SniffResult::Incomplete | SniffResult::NotMatched => None,
+    }
+}
+
+fn sniff_tls(data: &[u8]) -> SniffResult {
+    // ClientHello 可能被拆进多个 TLS record，先拼出 handshake 字节。
+    let mut handshake = Vec::new();
+    let mut rest = data;
+    while let Some(&TLS_HANDSHAKE_RECORD) = rest.first() {
+        if rest.len() >= 2 && rest[1] != 0x03 {
+            return SniffResult::NotMatched;
+        }
+        if rest.len() < 5 {
+            break;
+        }
+        let record_len = u16::from_be_bytes([rest[3], rest[4]]) as usize;
+        let body = &rest[5..];
+        let available = record_len.min(body.len());
+        handshake.extend_from_slice(&body[..available]);
+        rest = &body[available..];
+    }
+    sniff_client_hello(&handshake)
+}
+
+fn sniff_client_hello(handshake: &[u8]) -> SniffResult {
+    match handshake.first() {
+        None => return SniffResult::Incomplete,
+        Some(&TLS_CLIENT_HELLO) => {}
+        Some(_) => return SniffResult::NotMatched,
+    }
+    let mut reader = Reader::new(handshake);
+    reader.take(1);
+    let Some(body_len) = reader.u24() else {
+        return SniffResult::Incomplete;
+    };
+    let body_end = (4 + body_len as usize).min(handshake.len());
+    let complete = handshake.len() >= 4 + body_len as usize;
+    match client_hello_server_name(&handshake[4..body_end]) {
+        Some(Some(name)) => match normalize_sniffed_host(name) {
+            Some(domain) => SniffResult::Domain(domain),
+            None => SniffResult::NotMatched,
+        },
+        Some(None) => SniffResult::NotMatched,
+        None if complete => SniffResult::NotMatched,
+        None => SniffResult::Incomplete,
+    }
+}
+
+/// 外层 `None` 表示数据在找到 SNI 之前就用完了；内层 `None` 表示没有 SNI 扩展。
+fn client_hello_server_name(body: &[u8]) -> Option<Option<&[u8]>> {
+    let mut reader = Reader::new(body);
+    // legacy_version + random
+    reader.take(2 + 32)?;
+    let session_id_len = reader.u8()? as usize;
+    reader.take(session_id_len)?;
+    let cipher_suites_len = reader.u16()? as usize;
+    reader.take(cipher_suites_len)?;
+    let compression_len = reader.u8()? as usize;
+    reader.take(compression_len)?;
+    let extensions_len = reader.u16()? as usize;
+    let extensions_end = reader.position() + extensions_len;
+    while reader.position() < extensions_end {
+        let extension_type = reader.u16()?;
+        let extension_len = reader.u16()? as usize;
+        let extension = reader.take(extension_len)?;
+        if extension_type == TLS_EXTENSION_SERVER_NAME {
+            return Some(server_name_from_extension(extension));
+        }
+    }
+    Some(None)
+}
+
+fn server_name_from_extension(extension: &[u8]) -> Option<&[u8]> {
+    let mut reader = Reader::new(extension);
+    let list_len = reader.u16()? as usize;
+    let mut list = Reader::new(reader.take(list_len)?);
+    while !list.is_empty() {
+        let name_type = list.u8()?;
+        let name_len = list.u16()? as usize;
+        let name = list.take(name_len)?;
+        if name_type == TLS_SERVER_NAME_HOST {
+            return Some(name);
+        }
+    }
+    None
+}
+
+fn sniff_http(data: &[u8]) -> SniffResult {
+    if !HTTP_METHODS.iter().any(|method| data.starts_with(method)) {
+        return if HTTP_METHODS.iter().any(|method| method.starts_with(data)) {
+            SniffResult::Incomplete
+        } else {
+            SniffResult::NotMatched
+        };
+    }
+
+    // 跳过请求行，只检查已经完整到达的头部行。
+    for line in data.split_inclusive(|&byte| byte == b'\n').skip(1) {
+        if !line.ends_with(b"\n") {
+            break;
+        }
+        let line = line.trim_ascii_end();
+        if line.is_empty() {
+            // 头部结束仍没有 Host。
+            return SniffResult::NotMatched;
+        }
+        let Some(colon) = line.iter().position(|&byte| byte == b':') else {
+            continue;
+        };
+        let (name, value) = (&line[..colon], &line[colon + 1..]);
+        if name.trim_ascii().eq_ignore_ascii_case(b"host") {
+            return match host_without_port(value.trim_ascii()).and_then(normalize_sniffed_host) {
+                Some(domain) => SniffResult::Domain(domain),
+                None => SniffResult::NotMatched,
+            };
+        }
+    }
+    SniffResult::Incomplete
+}
+
+fn host_without_port(host: &[u8]) -> Option<&[u8]> {
+    // "[::1]:443" 这类 IPv6 字面量不提供额外的域名信息。
+    if host.first() == Some(&b'[') {
+        return None;
+    }
+    match host.iter().rposition(|&byte| byte == b':') {
+        Some(colon) if host[colon + 1..].iter().all(u8::is_ascii_digit) => Some(&host[..colon]),
+        Some(_) => None,
+        None => Some(host),
+    }
+}
+
+fn normalize_sniffed_host(raw: &[u8]) -> Option<String> {
+    let host = std::str::from_utf8(raw).ok()?.trim().trim_end_matches('.');
+    if host.is_empty()
+        || host.len() > 253
+        || host.parse::<IpAddr>().is_ok()
+        || !host
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_'))
+    {
+        return None;
+    }
+    Some(host.to_ascii_lowercase())
+}
+
+/// 拼出 Initial 包里从偏移 0 开始连续的 CRYPTO 数据。
+///
+/// 浏览器会打乱 CRYPTO 帧顺序并在中间插入 PADDING/PING，所以按偏移排序后再拼接。
+fn collect_crypto_stream(payload: &[u8]) -> Option<Vec<u8>> {
+    let mut reader = Reader::new(payload);
+    let mut fragments = Vec::new();
+    while !reader.is_empty() {
+        match reader.varint()? {
+            // PADDING / PING
+            0x00 | 0x01 => {}
+            // ACK / ACK_ECN
+            frame_type @ (0x02 | 0x03) => {
+                reader.varint()?;
+                reader.varint()?;
+                let range_count = reader.varint()?;
+                reader.varint()?;
+                for _ in 0..range_count {
+                    reader.varint()?;
+                    reader.varint()?;
+                }
+                if frame_type == 0x03 {
+                    for _ in 0..3 {
+                        reader.varint()?;
+                    }
+                }
+            }
+            // CRYPTO
+            0x06 => {
+                let offset = usize::try_from(reader.varint()?).ok()?;
+                let len = usize::try_from(reader.varint()?).ok()?;
+                fragments.push((offset, reader.take(len)?));
+            }
+            // CONNECTION_CLOSE
+            0x1c => {
+                reader.varint()?;
+                reader.varint()?;
+                let reason_len = usize::try_from(reader.varint()?).ok()?;
+                reader.take(reason_len)?;
+            }
+            // Initial 包里不应出现其它帧，后面的内容不再解析。
+            _ => break,
+        }
+    }
+
+    fragments.sort_by_key(|(offset, _)| *offset);
+    let mut crypto = Vec::new();
+    for (offset, data) in fragments {
+        if offset > crypto.len() {
+            break;
+        }
+        let end = offset + data.len();
+        if end > crypto.len() {
+            crypto.extend_from_slice(&data[crypto.len() - offset..]);
+        }
+    }
+    Some(crypto)
+}
+
+struct QuicInitialKeys {
+    key: [u8; 16],
+    iv: [u8; 12],
+    hp: [u8; 16],
+}
+
+impl QuicInitialKeys {
+    /// 按 RFC 9001 5.2 从客户端选择的 DCID 推导客户端 Initial 密钥。
+    fn client(dcid: &[u8]) -> Option<Self> {
+        let initial = Hkdf::<Sha256>::new(Some(&QUIC_V1_INITIAL_SALT), dcid);
+        let mut client_secret = [0u8; 32];
+        hkdf_expand_label(&initial, b"client in", &mut client_secret)?;
+        let client = Hkdf::<Sha256>::from_prk(&client_secret).ok()?;
+
+        let mut keys = Self {
+            key: [0; 16],
+            iv: [0; 12],
+            hp: [0; 16],
+        };
+        hkdf_expand_label(&client, b"quic key", &mut keys.key)?;
+        hkdf_expand_label(&client, b"quic iv", &mut keys.iv)?;
+        hkdf_expand_label(&client, b"quic hp", &mut keys.hp)?;
+        Some(keys)
+    }
+
+    fn header_mask(&self, sample: &[u8]) -> [u8; 16] {
+        let cipher = Aes128::new(GenericArray::from_slice(&self.hp));
+        let mut block = GenericArray::clone_from_slice(sample);
+        cipher.encrypt_block(&mut block);
+        block.into()
+    }
+
+    fn nonce(&self, packet_number: u64) -> [u8; 12] {
+        let mut nonce = self.iv;
+        for (byte, pn) in nonce[4..].iter_mut().zip(packet_number.to_be_bytes()) {
+            *byte ^= pn;
+        }
+        nonce
+    }
+
+    fn decrypt(&self, packet_number: u64, header: &[u8], payload: &mut Vec<u8>) -> Option<()> {
+        let cipher = Aes128Gcm::new_from_slice(&self.key).ok()?;
+        let nonce = self.nonce(packet_number);
+        cipher
+            .decrypt_in_place(Nonce::from_slice(&nonce), header, payload)
+            .ok()
+    }
+}
+
+/// TLS 1.3 HKDF-Expand-Label，上下文为空。
+fn hkdf_expand_label(hkdf: &Hkdf<Sha256>, label: &[u8], output: &mut [u8]) -> Option<()> {
+    let mut info = Vec::with_capacity(4 + 6 + label.len());
+    info.extend_from_slice(&(output.len() as u16).to_be_bytes());
+    info.push((6 + label.len()) as u8);
+    info.extend_from_slice(b"tls13 ");
+    info.extend_from_slice(label);
+    info.push(0);
+    hkdf.expand(&info, output).ok()
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn position(&self) -> usize {
+        self.position
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.position.checked_add(len)?;
+        let bytes = self.data.get(self.position..end)?;
+        self.position = end;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.take(2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u24(&mut self) -> Option<u32> {
+        let bytes = self.take(3)?;
+        Some(u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.take(4)?;
+        Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// QUIC 变长整数：首字节高两位给出 1/2/4/8 字节长度。
+    fn varint(&mut self) -> Option<u64> {
+        let first = *self.data.get(self.position)?;
+        let len = 1usize << (first >> 6);
+        let bytes = self.take(len)?;
+        let mut value = (bytes[0] & 0x3f) as u64;
+        for &byte in &bytes[1..] {
+            value = (value << 8) | byte as u64;
+        }
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client_hello(server_name: &str) -> Vec<u8> {
+        let name = server_name.as_bytes();
+        let mut server_name_ext = Vec::new();
+        server_name_ext.extend_from_slice(&((name.len() + 3) as u16).to_be_bytes());
+        server_name_ext.push(TLS_SERVER_NAME_HOST);
+        server_name_ext.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        server_name_ext.extend_from_slice(name);
+
+        let mut extensions = Vec::new();
+        // 先放一个无关扩展，确认解析器会跳过它。
+        extensions.extend_from_slice(&[0x00, 0x2b, 0x00, 0x03, 0x02, 0x03, 0x04]);
+        extensions.extend_from_slice(&TLS_EXTENSION_SERVER_NAME.to_be_bytes());
+        extensions.extend_from_slice(&(server_name_ext.len() as u16).to_be_bytes());
+        extensions.extend_from_slice(&server_name_ext);
+
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[0x11; 32]);
+        body.push(0);
+        body.extend_from_slice(&[0x00, 0x02, 0x13, 0x01]);
+        body.extend_from_slice(&[0x01, 0x00]);
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+
+        let mut handshake = vec![TLS_CLIENT_HELLO];
+        handshake.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        handshake.extend_from_slice(&body);
+        handshake
+    }
+
+    fn tls_records(handshake: &[u8], record_size: usize) -> Vec<u8> {
+        let mut records = Vec::new();
+        for chunk in handshake.chunks(record_size) {
+            records.extend_from_slice(&[TLS_HANDSHAKE_RECORD, 0x03, 0x01]);
+            records.extend_from_slice(&(chunk.len() as u16).to_be_bytes());
+            records.extend_from_slice(chunk);
+        }
+        records
+    }
+
+    fn quic_initial(dcid: &[u8], packet_number: u8, frames: &[u8]) -> Vec<u8> {
+        let keys = QuicInitialKeys::client(dcid).unwrap();
+        let length = 1 + frames.len() + QUIC_AEAD_TAG_LEN;
+        let mut packet = vec![0xc0];
+        packet.extend_from_slice(&QUIC_VERSION_1.to_be_bytes());
+        packet.push(dcid.len() as u8);
+        packet.extend_from_slice(dcid);
+        packet.push(0);
+        packet.push(0);
+        packet.extend_from_slice(&(0x4000 | length as u16).to_be_bytes());
+        let pn_offset = packet.len();
+        packet.push(packet_number);
+
+        let mut payload = frames.to_vec();
+        let cipher = Aes128Gcm::new_from_slice(&keys.key).unwrap();
+        let nonce = keys.nonce(packet_number as u64);
+        cipher
+            .encrypt_in_place(Nonce::from_slice(&nonce), &packet, &mut payload)
+            .unwrap();
+        packet.extend_from_slice(&payload);
+
+        let mask = keys.header_mask(&packet[pn_offset + 4..pn_offset + 4 + QUIC_HP_SAMPLE_LEN]);
+        packet[0] ^= mask[0] & 0x0f;
+        packet[pn_offset] ^= mask[1];
+        packet
+    }
+
+    fn crypto_frame(offset: usize, data: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x06];
+        frame.extend_from_slice(&(0x4000 | offset as u16).to_be_bytes());
+        frame.extend_from_slice(&(0x4000 | data.len() as u16).to_be_bytes());
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    #[test]
+    fn derives_rfc9001_client_initial_keys() {
+        let keys = QuicInitialKeys::client(&hex::decode("8394c8f03e515708").unwrap()).unwrap();
+
+        assert_eq!(hex::encode(keys.key), "1f369613dd76d5467730efcbe3b1a22d");
+        assert_eq!(hex::encode(keys.iv), "fa044b2f42a3fd3b46fb255c");
+        assert_eq!(hex::encode(keys.hp), "9f50449e04a0e810283a1e9933adedd2");
+    }
+
+    #[test]
+    fn sniffs_tls_server_name_across_records() {
+        let records = tls_records(&client_hello("Video.Example.COM."), 40);
+
+        assert_eq!(
+            sniff_tcp_domain(&records),
+            SniffResult::Domain("video.example.com".to_string())
+        );
+        assert_eq!(sniff_tcp_domain(&records[..60]), SniffResult::Incomplete);
+        assert_eq!(
+            sniff_tcp_domain(&tls_records(&client_hello("203.0.113.7"), 512)),
+            SniffResult::NotMatched
+        );
+        assert_eq!(
+            sniff_tcp_domain(&[TLS_HANDSHAKE_RECORD, 0x01, 0x00]),
+            SniffResult::NotMatched
+        );
+    }
+
+    #[test]
+    fn sniffs_http_host_header() {
+        let request =
+            b"GET /index.html HTTP/1.1\r\nUser-Agent: test\r\nhost: Example.org:8080\r\n\r\n";
+
+        assert_eq!(
+            sniff_tcp_domain(request),
+            SniffResult::Domain("example.org".to_string())
+        );
+        assert_eq!(sniff_tcp_domain(&request[..40]), SniffResult::Incomplete);
+        assert_eq!(sniff_tcp_domain(b"GE"), SniffResult::Incomplete);
+        assert_eq!(
+            sniff_tcp_domain(b"GET / HTTP/1.1\r\nHost: [::1]:80\r\n\r\n"),
+            SniffResult::NotMatched
+        );
+        assert_eq!(
+            sniff_tcp_domain(b"GET / HTTP/1.0\r\n\r\n"),
+            SniffResult::NotMatched
+        );
+        assert_eq!(
+            sniff_tcp_domain(b"SSH-2.0-OpenSSH"),
+            SniffResult::NotMatched
+        );
+    }
+
+    #[test]
+    fn sniffs_quic_initial_with_reordered_crypto_frames() {
+        let dcid = [0x5a; 8];
+        let hello = client_hello("quic.example.net");
+        let split = hello.len() / 2;
+        let mut frames = crypto_frame(split, &hello[split..]);
+        frames.push(0x01);
+        frames.extend_from_slice(&crypto_frame(0, &hello[..split]));
+        frames.resize(1162, 0x00);
+        let packet = quic_initial(&dcid, 2, &frames);
+
+        assert_eq!(sniff_quic_sni(&packet).as_deref(), Some("quic.example.net"));
+
+        let mut tampered = packet.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 0xff;
+        assert_eq!(sniff_quic_sni(&tampered), None);
+
+        // 只带后半段 CRYPTO 数据的包无法拼出 ClientHello 开头。
+        let mut tail_only = crypto_frame(split, &hello[split..]);
+        tail_only.resize(1162, 0x00);
+        assert_eq!(sniff_quic_sni(&quic_initial(&dcid, 3, &tail_only)), None);
+    }
+}
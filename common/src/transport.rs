@@ -11,7 +11,8 @@ use serde::{Deserialize, Serialize};
 #[serde(rename_all = "snake_case")]
 pub enum TransportMode {
     /// 自动模式：优先使用原生加密 UDP；原生 UDP 控制传输超时后，由 agent
-    /// 将对应的 UDP session slot 在当前进程生命周期内切换为 TCP/Yamux。
+    /// 将对应的 UDP session slot 切换为 TCP/Yamux。桌面 agent 随后在后台按
+    /// 指数退避重新探测原生 UDP，探测成功后把该 slot 切回。
     Auto,
     /// 混合模式：TCP 目标继续使用 direct framed TCP；只有 UDP 目标使用
     /// PPAASS 原生加密 UDP 会话。
@@ -44,6 +45,11 @@ impl TransportMode {
     pub fn automatically_falls_back_to_tcp(self) -> bool {
         matches!(self, Self::Auto)
     }
+
+    /// 前置代理只能转发 TCP，配置后 UDP 目标统一改走 TCP/Yamux。
+    pub fn effective_with_pre_proxy(self, has_pre_proxy: bool) -> Self {
+        if has_pre_proxy { Self::Tcp } else { self }
+    }
 }
 
 #[cfg(test)]
@@ -78,6 +84,22 @@ mod tests {
         assert!(TransportMode::Auto.automatically_falls_back_to_tcp());
     }
 
+    #[test]
+    fn pre_proxy_forces_tcp_transport() {
+        assert_eq!(
+            TransportMode::Udp.effective_with_pre_proxy(true),
+            TransportMode::Tcp
+        );
+        assert_eq!(
+            TransportMode::Auto.effective_with_pre_proxy(true),
+            TransportMode::Tcp
+        );
+        assert_eq!(
+            TransportMode::Auto.effective_with_pre_proxy(false),
+            TransportMode::Auto
+        );
+    }
+
     #[test]
     fn rejects_removed_quic_mode() {
         #[derive(Deserialize)]
@@ -14,7 +14,7 @@ pub const DEFAULT_YAMUX_SERVER_MAX_STREAMS_PER_SESSION: usize = 128;
 pub const DEFAULT_YAMUX_SERVER_CONNECTION_WRITE_TIMEOUT_SECS: u64 = 300;
 pub const DEFAULT_YAMUX_SERVER_STREAM_WINDOW_SIZE_KB: usize = 8192;
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct YamuxConfig {
     #[serde(default)]
@@ -78,7 +78,7 @@ impl YamuxServerConfig {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct YamuxTransportConfig {
     #[serde(default = "default_yamux_sessions")]
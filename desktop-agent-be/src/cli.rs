@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -83,4 +83,63 @@ pub struct CliArgs {
     /// 限制允许连接 macOS TUN helper socket 的用户 UID
     #[arg(long, hide = true)]
     pub tun_helper_allowed_uid: Option<u32>,
+
+    // ── 透明代理（Linux） ──────────────────────────────────────────────────────
+    /// 按配置文件的 [transparent] 安装 nftables 规则与 TPROXY 策略路由后退出
+    #[arg(long, conflicts_with = "remove_transparent_rules")]
+    pub install_transparent_rules: bool,
+
+    /// 按状态文件删除 --install-transparent-rules 安装过的规则后退出
+    #[arg(long)]
+    pub remove_transparent_rules: bool,
+
+    // ── 规则集 ────────────────────────────────────────────────────────────────
+    /// 把文本规则集编译成二进制规则集后退出（如 --compile-rule-set cn.list cn.bin）
+    #[arg(long, num_args = 2, value_names = ["INPUT", "OUTPUT"])]
+    pub compile_rule_set: Option<Vec<String>>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// 通过本机控制 API 操作运行中的 agent
+    Ctl(CtlArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct CtlArgs {
+    /// 控制 API 地址（如 127.0.0.1:9090、unix:/run/ppaass-agent.sock），默认读取配置文件的 [control]
+    #[arg(long)]
+    pub addr: Option<String>,
+
+    /// 控制 API 令牌，默认读取配置文件的 [control]
+    #[arg(long)]
+    pub token: Option<String>,
+
+    #[command(subcommand)]
+    pub action: CtlAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CtlAction {
+    /// 查看运行状态、分组与运行时覆盖项
+    Status,
+    /// 查看累计流量
+    Traffic,
+    /// 查看最近的 DNS 解析记录
+    Dns,
+    /// 查看活动连接
+    Connections,
+    /// 关闭一条活动连接
+    Close { id: u64 },
+    /// 重新读取配置文件并热重载
+    Reload,
+    /// 覆盖访问模式（proxy_all、direct_all、rules），省略时恢复配置文件的设置
+    Mode { mode: Option<String> },
+    /// 切换默认出口分组，省略时恢复默认分组 proxy
+    Group { name: Option<String> },
+    /// 输出 Prometheus 指标
+    Metrics,
 }
@@ -1,4 +1,10 @@
 mod agent_config;
 
 pub use agent_config::AgentConfig;
+pub use agent_config::ControlConfig;
+pub use agent_config::DnsServerConfig;
+pub use agent_config::ListenerAccessConfig;
+pub use agent_config::ProxySelectionStrategy;
+pub use agent_config::TransparentConfig;
 pub use agent_config::TunConfig;
+pub use agent_config::UnixListenerConfig;
@@ -0,0 +1,242 @@
+//! 活动连接表。
+//!
+//! HTTP CONNECT、普通 HTTP 请求、SOCKS5 CONNECT/BIND、透明代理与 TUN 的连接在分流后登记：入口、
+//! 来源、目标、出口、命中规则、传输协议和开始时间，中继过程中持续累加两个方向的
+//! 字节数。UI 轮询 [`active_connections`] 展示，[`close_connection`] 取消对应连接的
+//! 中继。连接结束时租约 drop，条目随之移除。
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use common::SourceProcess;
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use protocol::TransportProtocol;
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::routing::RouteDecision;
+use crate::telemetry;
+
+static CONNECTIONS: OnceLock<ConnectionRegistry> = OnceLock::new();
+
+/// 一条活动连接的快照。字节数以本地客户端为视角：outbound 为发往目标，inbound 为收到的回复。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionRecord {
+    pub id: u64,
+    pub inbound: String,
+    pub source: String,
+    pub target: String,
+    pub transport: String,
+    pub route: String,
+    pub rule: String,
+    #[serde(default)]
+    pub process: Option<String>,
+    pub outbound_bytes: u64,
+    pub inbound_bytes: u64,
+    pub started_ms: u128,
+}
+
+struct ConnectionRegistry {
+    next_id: AtomicU64,
+    active: DashMap<u64, Arc<Connection>>,
+}
+
+#[derive(Debug)]
+struct Connection {
+    id: u64,
+    inbound: &'static str,
+    source: String,
+    target: String,
+    network: TransportProtocol,
+    route: String,
+    rule: String,
+    process: Mutex<Option<String>>,
+    outbound_bytes: AtomicU64,
+    inbound_bytes: AtomicU64,
+    started_ms: u128,
+    cancel: CancellationToken,
+}
+
+#[derive(Debug)]
+pub(crate) struct ConnectionLease {
+    connection: Arc<Connection>,
+}
+
+impl ConnectionRegistry {
+    fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            active: DashMap::new(),
+        }
+    }
+}
+
+impl Connection {
+    fn record(&self) -> ConnectionRecord {
+        ConnectionRecord {
+            id: self.id,
+            inbound: self.inbound.to_string(),
+            source: self.source.clone(),
+            target: self.target.clone(),
+            transport: match self.network {
+                TransportProtocol::Tcp => "tcp",
+                TransportProtocol::Udp => "udp",
+            }
+            .to_string(),
+            route: self.route.clone(),
+            rule: self.rule.clone(),
+            process: self.process.lock().clone(),
+            outbound_bytes: self.outbound_bytes.load(Ordering::Relaxed),
+            inbound_bytes: self.inbound_bytes.load(Ordering::Relaxed),
+            started_ms: self.started_ms,
+        }
+    }
+}
+
+impl ConnectionLease {
+    pub(crate) fn set_process(&self, process: Option<&SourceProcess>) {
+        *self.connection.process.lock() = process.map(ToString::to_string);
+    }
+
+    pub(crate) fn outbound_counter(&self) -> &AtomicU64 {
+        &self.connection.outbound_bytes
+    }
+
+    pub(crate) fn inbound_counter(&self) -> &AtomicU64 {
+        &self.connection.inbound_bytes
+    }
+
+    pub(crate) fn add_outbound(&self, bytes: usize) {
+        self.outbound_counter()
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_inbound(&self, bytes: usize) {
+        self.inbound_counter()
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// UI 关闭该连接时取消。
+    pub(crate) fn cancel_token(&self) -> &CancellationToken {
+        &self.connection.cancel
+    }
+}
+
+impl Drop for ConnectionLease {
+    fn drop(&mut self) {
+        connection_registry().active.remove(&self.connection.id);
+    }
+}
+
+fn connection_registry() -> &'static ConnectionRegistry {
+    CONNECTIONS.get_or_init(ConnectionRegistry::new)
+}
+
+/// 登记一条已经完成分流的连接；`inbound` 是入口标签，如 `HTTP CONNECT`，`source` 是
+/// 客户端地址，Unix socket 入口没有 IP 地址时为 `unix`。
+pub(crate) fn register_connection(
+    inbound: &'static str,
+    source: impl fmt::Display,
+    target: impl Into<String>,
+    network: TransportProtocol,
+    decision: &RouteDecision<'_>,
+) -> ConnectionLease {
+    let registry = connection_registry();
+    let id = registry.next_id.fetch_add(1, Ordering::Relaxed);
+    let connection = Arc::new(Connection {
+        id,
+        inbound,
+        source: source.to_string(),
+        target: target.into(),
+        network,
+        route: decision.route.name().to_string(),
+        rule: decision.rule.clone(),
+        process: Mutex::new(None),
+        outbound_bytes: AtomicU64::new(0),
+        inbound_bytes: AtomicU64::new(0),
+        started_ms: telemetry::current_time_millis(),
+        cancel: CancellationToken::new(),
+    });
+    registry.active.insert(id, connection.clone());
+    ConnectionLease { connection }
+}
+
+/// 当前活动连接，按开始时间从早到晚排列。
+#[allow(dead_code)]
+pub fn active_connections() -> Vec<ConnectionRecord> {
+    let mut records = connection_registry()
+        .active
+        .iter()
+        .map(|entry| entry.value().record())
+        .collect::<Vec<_>>();
+    records.sort_by_key(|record| (record.started_ms, record.id));
+    records
+}
+
+/// 关闭一条活动连接；连接已经结束时返回 `false`。
+#[allow(dead_code)]
+pub fn close_connection(id: u64) -> bool {
+    match connection_registry().active.get(&id) {
+        Some(connection) => {
+            connection.cancel.cancel();
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routing::Route;
+
+    fn register(target: &str) -> ConnectionLease {
+        register_connection(
+            "SOCKS5 CONNECT",
+            "127.0.0.1:50000".parse::<std::net::SocketAddr>().unwrap(),
+            target,
+            TransportProtocol::Tcp,
+            &RouteDecision {
+                route: Route::Direct,
+                rule: "*.example.com".to_string(),
+            },
+        )
+    }
+
+    fn find_record(id: u64) -> Option<ConnectionRecord> {
+        active_connections()
+            .into_iter()
+            .find(|record| record.id == id)
+    }
+
+    #[test]
+    fn leases_are_listed_until_dropped() {
+        let lease = register("www.example.com:443");
+        lease.add_outbound(3);
+        lease.add_inbound(5);
+        let id = lease.connection.id;
+
+        let record = find_record(id).unwrap();
+        assert_eq!(record.inbound, "SOCKS5 CONNECT");
+        assert_eq!(record.source, "127.0.0.1:50000");
+        assert_eq!(record.target, "www.example.com:443");
+        assert_eq!(record.transport, "tcp");
+        assert_eq!(record.route, "direct");
+        assert_eq!(record.rule, "*.example.com");
+        assert_eq!((record.outbound_bytes, record.inbound_bytes), (3, 5));
+
+        drop(lease);
+        assert!(find_record(id).is_none());
+        assert!(!close_connection(id));
+    }
+
+    #[test]
+    fn closing_cancels_the_lease() {
+        let lease = register("www.example.org:443");
+        assert!(!lease.cancel_token().is_cancelled());
+        assert!(close_connection(lease.connection.id));
+        assert!(lease.cancel_token().is_cancelled());
+    }
+}
@@ -0,0 +1,823 @@
+//! 本机控制 API。
+//!
+//! 配置 `[control]` 后，agent 在回环地址或 Unix socket 上提供一个小型 HTTP/1.1 接口，
+//! `desktop-agent ctl`、脚本和监控系统通过它查看运行状态、调整分流：
+//!
+//! - `GET /status`：版本、运行时长、分组与运行时覆盖项；
+//! - `GET /traffic`、`GET /dns`、`GET /connections`：与 UI 相同的流量、DNS 与连接快照；
+//! - `DELETE /connections/{id}`：关闭一条活动连接；
+//! - `POST /reload`：重新读取配置文件并热重载；
+//! - `PUT /routing/mode`、`PUT /routing/proxy-group`：覆盖访问模式、切换默认出口分组；
+//! - `GET /metrics`：Prometheus 文本格式指标。
+//!
+//! 除 `/metrics` 外响应体都是 JSON，出错时为 `{"error": "..."}`。
+//!
+//! 监听 TCP 时必须配置令牌；另外拒绝带 `Origin` 的浏览器请求和 Host 不是 localhost/回环地址的
+//! 请求，防止网页通过 CSRF 或 DNS rebinding 操作控制面。
+
+use crate::config::{AgentConfig, ControlConfig};
+use crate::connections::{active_connections, close_connection};
+use crate::error::{AgentError, Result};
+use crate::reload::ConfigSource;
+use crate::routing::OutboundRouter;
+use crate::telemetry;
+use bytes::Bytes;
+use common::{CommonError, DirectAccessMode, spawn_guarded};
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::Body;
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE, HOST, HeaderValue, ORIGIN};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+// 控制请求体只有几个字段，限制大小避免本机进程灌入大请求。
+const MAX_REQUEST_BODY: usize = 64 * 1024;
+
+/// 控制 API 的监听位置。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlEndpoint {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl ControlEndpoint {
+    /// 解析 `[control] listen`；TCP 只接受回环地址，避免把控制面暴露到局域网。
+    pub fn parse(listen: &str) -> Result<Self> {
+        let listen = listen.trim();
+        if let Some(path) = listen.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err(config_error("[control] listen 缺少 Unix socket 路径"));
+            }
+            #[cfg(unix)]
+            return Ok(Self::Unix(PathBuf::from(path)));
+            #[cfg(not(unix))]
+            return Err(config_error("[control] Unix socket 只支持 Unix 系统"));
+        }
+        let addr = listen
+            .parse::<SocketAddr>()
+            .map_err(|_| config_error(format!("[control] listen {listen:?} 不是有效的监听地址")))?;
+        if !addr.ip().is_loopback() {
+            return Err(config_error(format!(
+                "[control] listen 只能使用回环地址，当前为 {addr}"
+            )));
+        }
+        Ok(Self::Tcp(addr))
+    }
+}
+
+enum ControlListener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener, PathBuf),
+}
+
+struct ControlState {
+    // 启动时的配置，只用于展示监听地址等需要重启的字段。
+    config: Arc<AgentConfig>,
+    router: Arc<OutboundRouter>,
+    token: Option<String>,
+    // 只有命令行入口知道如何重新读取配置文件；UI 内嵌运行时为空。
+    source: Option<ConfigSource>,
+    started: Instant,
+}
+
+pub(crate) struct ControlServer {
+    listener: ControlListener,
+    state: Arc<ControlState>,
+}
+
+impl ControlServer {
+    pub(crate) async fn bind(
+        control: &ControlConfig,
+        config: Arc<AgentConfig>,
+        router: Arc<OutboundRouter>,
+        source: Option<ConfigSource>,
+    ) -> Result<Self> {
+        let listener = match ControlEndpoint::parse(&control.listen)? {
+            ControlEndpoint::Tcp(addr) => ControlListener::Tcp(TcpListener::bind(addr).await?),
+            #[cfg(unix)]
+            ControlEndpoint::Unix(path) => {
+                // 控制 API 能关闭连接、切换出口，只允许 agent 所属用户访问。
+                let listener = crate::unix_socket::bind_unix_socket(&path, 0o600)?;
+                ControlListener::Unix(listener, path)
+            }
+        };
+        let token = control
+            .token
+            .as_deref()
+            .map(str::trim)
+            .filter(|token| !token.is_empty())
+            .map(str::to_string);
+        // 回环 TCP 端口对本机任何进程和浏览器里的网页都可达，只有 Unix socket 能靠文件权限免令牌。
+        if token.is_none() && matches!(listener, ControlListener::Tcp(_)) {
+            return Err(config_error(
+                "[control] 监听 TCP 地址时必须设置 token；不想使用令牌请改用 unix: socket",
+            ));
+        }
+        Ok(Self {
+            listener,
+            state: Arc::new(ControlState {
+                config,
+                router,
+                token,
+                source,
+                started: Instant::now(),
+            }),
+        })
+    }
+
+    pub(crate) async fn run(self, shutdown: CancellationToken) {
+        match &self.listener {
+            ControlListener::Tcp(listener) => {
+                match listener.local_addr() {
+                    Ok(addr) => info!("控制 API 正在监听 {addr}"),
+                    Err(_) => info!("控制 API 已启动"),
+                }
+                loop {
+                    tokio::select! {
+                        _ = shutdown.cancelled() => break,
+                        accepted = listener.accept() => match accepted {
+                            Ok((stream, _)) => serve(stream, self.state.clone()),
+                            Err(e) => debug!("控制 API 接受连接失败：{e}"),
+                        }
+                    }
+                }
+            }
+            #[cfg(unix)]
+            ControlListener::Unix(listener, path) => {
+                info!("控制 API 正在监听 Unix socket {}", path.display());
+                loop {
+                    tokio::select! {
+                        _ = shutdown.cancelled() => break,
+                        accepted = listener.accept() => match accepted {
+                            Ok((stream, _)) => serve(stream, self.state.clone()),
+                            Err(e) => debug!("控制 API 接受连接失败：{e}"),
+                        }
+                    }
+                }
+                if let Err(e) = std::fs::remove_file(path) {
+                    debug!("删除控制 API socket {} 失败：{e}", path.display());
+                }
+            }
+        }
+        info!("控制 API 已停止");
+    }
+}
+
+impl ControlState {
+    fn authorized(&self, value: Option<&HeaderValue>) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+        value
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|value| constant_time_eq(value.trim().as_bytes(), token.as_bytes()))
+    }
+}
+
+/// 比较令牌时不因第一个不同字节提前返回，避免按响应耗时逐字节猜测令牌。
+pub(crate) fn constant_time_eq(left: &[u8], right: &[u8]) -> bool {
+    if left.len() != right.len() {
+        return false;
+    }
+    left.iter()
+        .zip(right)
+        .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+/// Host 只能是 `localhost` 或回环 IP 字面量（可带端口）。
+///
+/// DNS rebinding 页面发出的请求 Host 是攻击者的域名，据此拒绝。
+fn is_loopback_host(value: Option<&HeaderValue>) -> bool {
+    let Some(host) = value.and_then(|value| value.to_str().ok()) else {
+        return false;
+    };
+    let host = host.trim();
+    let name = match host.strip_prefix('[') {
+        // [::1]:9090
+        Some(rest) => match rest.split_once(']') {
+            Some((name, _)) => name,
+            None => return false,
+        },
+        None => host.rsplit_once(':').map_or(host, |(name, _)| name),
+    };
+    name.eq_ignore_ascii_case("localhost")
+        || name
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+fn serve<S>(stream: S, state: Arc<ControlState>)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    spawn_guarded("desktop control api connection", async move {
+        let service = service_fn(move |req| {
+            let state = state.clone();
+            async move { Ok::<_, Infallible>(handle(&state, req).await) }
+        });
+        if let Err(e) = http1::Builder::new()
+            .serve_connection(TokioIo::new(stream), service)
+            .await
+        {
+            debug!("控制 API 连接结束：{e}");
+        }
+    });
+}
+
+#[derive(Serialize)]
+struct StatusResponse<'a> {
+    version: &'static str,
+    uptime_secs: u64,
+    listen_addr: &'a str,
+    tun_enabled: bool,
+    /// 运行时覆盖的访问模式；为空时使用配置文件中的分流设置。
+    mode_override: Option<DirectAccessMode>,
+    /// 原本走默认分组的流量当前使用的分组。
+    proxy_group: &'a str,
+    proxy_groups: Vec<&'a str>,
+    active_connections: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModeRequest {
+    mode: Option<DirectAccessMode>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProxyGroupRequest {
+    group: Option<String>,
+}
+
+async fn handle<B>(state: &ControlState, req: Request<B>) -> Response<Full<Bytes>>
+where
+    B: Body,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    // 浏览器发出的跨站请求总会带 Origin；ctl、脚本和 Prometheus 都不会带。
+    if req.headers().contains_key(ORIGIN) {
+        return error_response(StatusCode::FORBIDDEN, "控制 API 不接受浏览器跨站请求");
+    }
+    if !is_loopback_host(req.headers().get(HOST)) {
+        return error_response(
+            StatusCode::FORBIDDEN,
+            "控制 API 只接受 localhost 或回环地址作为 Host",
+        );
+    }
+    if !state.authorized(req.headers().get(AUTHORIZATION)) {
+        return error_response(StatusCode::UNAUTHORIZED, "缺少或错误的控制 API 令牌");
+    }
+    let method = req.method().clone();
+    let path = req.uri().path().trim_end_matches('/').to_string();
+    let body = match Limited::new(req.into_body(), MAX_REQUEST_BODY)
+        .collect()
+        .await
+    {
+        Ok(body) => body.to_bytes(),
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, format!("读取请求体失败：{e}")),
+    };
+    debug!("控制 API 请求：{method} {path}");
+
+    match (&method, path.as_str()) {
+        (&Method::GET, "/status") => json_response(&status(state)),
+        (&Method::GET, "/traffic") => json_response(&telemetry::traffic_snapshot()),
+        (&Method::GET, "/dns") => json_response(&telemetry::dns_resolution_records()),
+        (&Method::GET, "/connections") => json_response(&active_connections()),
+        (&Method::DELETE, path) if path.starts_with("/connections/") => {
+            let Ok(id) = path["/connections/".len()..].parse::<u64>() else {
+                return error_response(StatusCode::BAD_REQUEST, "连接 id 必须是整数");
+            };
+            if close_connection(id) {
+                info!("控制 API 关闭连接 #{id}");
+                json_response(&json!({ "closed": id }))
+            } else {
+                error_response(StatusCode::NOT_FOUND, format!("连接 #{id} 不存在或已结束"))
+            }
+        }
+        (&Method::POST, "/reload") => {
+            let Some(source) = &state.source else {
+                return error_response(
+                    StatusCode::NOT_IMPLEMENTED,
+                    "当前运行方式不支持从控制 API 重新读取配置",
+                );
+            };
+            match source.reload().await {
+                Ok(report) => {
+                    json_response(&json!({ "restart_required": report.restart_required }))
+                }
+                Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+            }
+        }
+        (&Method::PUT, "/routing/mode") => match serde_json::from_slice::<ModeRequest>(&body) {
+            Ok(request) => match state.router.set_direct_access_mode(request.mode) {
+                Ok(()) => json_response(&status(state)),
+                Err(e) => error_response(StatusCode::BAD_REQUEST, e.to_string()),
+            },
+            Err(e) => error_response(StatusCode::BAD_REQUEST, format!("请求体无效：{e}")),
+        },
+        (&Method::PUT, "/routing/proxy-group") => {
+            match serde_json::from_slice::<ProxyGroupRequest>(&body) {
+                Ok(request) => match state.router.select_proxy_group(request.group.as_deref()) {
+                    Ok(()) => json_response(&status(state)),
+                    Err(e) => error_response(StatusCode::BAD_REQUEST, e.to_string()),
+                },
+                Err(e) => error_response(StatusCode::BAD_REQUEST, format!("请求体无效：{e}")),
+            }
+        }
+        (&Method::GET, "/metrics") => Response::builder()
+            .header(
+                CONTENT_TYPE,
+                HeaderValue::from_static("text/plain; version=0.0.4; charset=utf-8"),
+            )
+            .body(Full::new(Bytes::from(metrics(state))))
+            .unwrap(),
+        _ => error_response(
+            StatusCode::NOT_FOUND,
+            format!("未知的控制接口：{method} {path}"),
+        ),
+    }
+}
+
+fn status(state: &ControlState) -> StatusResponse<'_> {
+    let overrides = state.router.overrides();
+    let groups = state.router.groups();
+    StatusResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        uptime_secs: state.started.elapsed().as_secs(),
+        listen_addr: &state.config.listen_addr,
+        tun_enabled: state.config.tun.enabled,
+        mode_override: overrides.mode,
+        proxy_group: groups[state.router.policy().default_proxy_group()].name(),
+        proxy_groups: groups.iter().map(|group| group.name()).collect(),
+        active_connections: active_connections().len(),
+    }
+}
+
+/// Prometheus 文本格式的指标快照。
+fn metrics(state: &ControlState) -> String {
+    let mut out = String::new();
+    let traffic = telemetry::traffic_snapshot();
+    metric_header(
+        &mut out,
+        "ppaass_agent_uptime_seconds",
+        "gauge",
+        "Seconds since the agent started.",
+    );
+    let _ = writeln!(
+        out,
+        "ppaass_agent_uptime_seconds {}",
+        state.started.elapsed().as_secs()
+    );
+
+    metric_header(
+        &mut out,
+        "ppaass_agent_traffic_bytes_total",
+        "counter",
+        "Bytes relayed by all inbounds, from the local client's point of view.",
+    );
+    let _ = writeln!(
+        out,
+        "ppaass_agent_traffic_bytes_total{{direction=\"outbound\"}} {}",
+        traffic.outbound_bytes
+    );
+    let _ = writeln!(
+        out,
+        "ppaass_agent_traffic_bytes_total{{direction=\"inbound\"}} {}",
+        traffic.inbound_bytes
+    );
+
+    let mut connections = BTreeMap::<(String, String), u64>::new();
+    for connection in active_connections() {
+        *connections
+            .entry((connection.inbound, connection.route))
+            .or_default() += 1;
+    }
+    metric_header(
+        &mut out,
+        "ppaass_agent_active_connections",
+        "gauge",
+        "Active relayed connections by inbound and route.",
+    );
+    for ((inbound, route), count) in connections {
+        let _ = writeln!(
+            out,
+            "ppaass_agent_active_connections{{inbound=\"{}\",route=\"{}\"}} {count}",
+            escape_label(&inbound),
+            escape_label(&route)
+        );
+    }
+
+    metric_header(
+        &mut out,
+        "ppaass_agent_dns_records",
+        "gauge",
+        "DNS resolutions kept in the recent record buffer.",
+    );
+    let _ = writeln!(
+        out,
+        "ppaass_agent_dns_records {}",
+        telemetry::dns_resolution_records().len()
+    );
+
+    let slots = telemetry::transport_slot_records();
+    metric_header(
+        &mut out,
+        "ppaass_agent_transport_slot_native_udp",
+        "gauge",
+        "1 when an auto transport UDP slot uses native UDP, 0 after falling back to TCP.",
+    );
+    for slot in &slots {
+        let _ = writeln!(
+            out,
+            "ppaass_agent_transport_slot_native_udp{{group=\"{}\",slot=\"{}\"}} {}",
+            escape_label(&slot.group),
+            slot.slot,
+            u8::from(slot.transport == "native_udp")
+        );
+    }
+    metric_header(
+        &mut out,
+        "ppaass_agent_transport_slot_recovery_failures",
+        "gauge",
+        "Consecutive failed native UDP recovery probes of a fallen-back slot.",
+    );
+    for slot in &slots {
+        let _ = writeln!(
+            out,
+            "ppaass_agent_transport_slot_recovery_failures{{group=\"{}\",slot=\"{}\"}} {}",
+            escape_label(&slot.group),
+            slot.slot,
+            slot.recovery_failures
+        );
+    }
+    out
+}
+
+fn metric_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn json_response<T: Serialize + ?Sized>(value: &T) -> Response<Full<Bytes>> {
+    match serde_json::to_vec(value) {
+        Ok(body) => Response::builder()
+            .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+            .body(Full::new(Bytes::from(body)))
+            .unwrap(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+fn error_response(status: StatusCode, message: impl Into<String>) -> Response<Full<Bytes>> {
+    let message = message.into();
+    if status.is_server_error() {
+        warn!("控制 API 请求失败：{message}");
+    }
+    let body = serde_json::to_vec(&json!({ "error": message })).unwrap_or_default();
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
+
+fn config_error(message: impl Into<String>) -> AgentError {
+    CommonError::Config(message.into()).into()
+}
+
+/// 向运行中 agent 的控制 API 发送一次请求，返回状态码与响应体。
+pub async fn request(
+    listen: &str,
+    token: Option<&str>,
+    method: Method,
+    path: &str,
+    body: Option<String>,
+) -> Result<(StatusCode, String)> {
+    let mut builder = Request::builder()
+        .method(method)
+        .uri(path)
+        .header(HOST, "localhost");
+    if let Some(token) = token {
+        builder = builder.header(AUTHORIZATION, format!("Bearer {}", token.trim()));
+    }
+    if body.is_some() {
+        builder = builder.header(CONTENT_TYPE, "application/json");
+    }
+    let request = builder
+        .body(Full::new(Bytes::from(body.unwrap_or_default())))
+        .map_err(|e| AgentError::Connection(format!("构造控制 API 请求失败：{e}")))?;
+
+    let connect_error =
+        |e: std::io::Error| AgentError::Connection(format!("连接控制 API {listen} 失败：{e}"));
+    match ControlEndpoint::parse(listen)? {
+        ControlEndpoint::Tcp(addr) => {
+            let stream = TcpStream::connect(addr).await.map_err(connect_error)?;
+            send(stream, request).await
+        }
+        #[cfg(unix)]
+        ControlEndpoint::Unix(path) => {
+            let stream = tokio::net::UnixStream::connect(&path)
+                .await
+                .map_err(connect_error)?;
+            send(stream, request).await
+        }
+    }
+}
+
+async fn send<S>(stream: S, request: Request<Full<Bytes>>) -> Result<(StatusCode, String)>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+    tokio::spawn(async move {
+        if let Err(e) = conn.await {
+            debug!("控制 API 客户端连接结束：{e}");
+        }
+    });
+    let response = sender.send_request(request).await?;
+    let status = response.status();
+    let body = response.into_body().collect().await?.to_bytes();
+    Ok((status, String::from_utf8_lossy(&body).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    const CONFIG: &str = r#"
+proxy_addrs = ["127.0.0.1:8080"]
+username = "user1"
+private_key_path = "keys/user1.pem"
+
+[[proxy_groups]]
+name = "hk"
+proxy_addrs = ["127.0.0.2:8080"]
+username = "user1"
+private_key_path = "keys/user1.pem"
+
+[control]
+listen = "127.0.0.1:0"
+token = "secret"
+"#;
+
+    fn state() -> ControlState {
+        let config: AgentConfig = toml::from_str(CONFIG).unwrap();
+        let config = Arc::new(config);
+        ControlState {
+            router: Arc::new(OutboundRouter::new(config.clone()).unwrap()),
+            config,
+            token: Some("secret".to_string()),
+            source: None,
+            started: Instant::now(),
+        }
+    }
+
+    async fn call(
+        state: &ControlState,
+        method: Method,
+        path: &str,
+        body: &str,
+    ) -> (StatusCode, String) {
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .header(HOST, "127.0.0.1:9090")
+            .header(AUTHORIZATION, "Bearer secret")
+            .body(Full::new(Bytes::from(body.to_string())))
+            .unwrap();
+        let response = handle(state, request).await;
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[test]
+    fn only_loopback_and_unix_endpoints_are_accepted() {
+        assert_eq!(
+            ControlEndpoint::parse("127.0.0.1:9090").unwrap(),
+            ControlEndpoint::Tcp("127.0.0.1:9090".parse().unwrap())
+        );
+        assert!(ControlEndpoint::parse("[::1]:9090").is_ok());
+        assert!(ControlEndpoint::parse("0.0.0.0:9090").is_err());
+        assert!(ControlEndpoint::parse("192.168.1.2:9090").is_err());
+        assert!(ControlEndpoint::parse("unix:").is_err());
+        #[cfg(unix)]
+        assert_eq!(
+            ControlEndpoint::parse("unix:/run/agent.sock").unwrap(),
+            ControlEndpoint::Unix(PathBuf::from("/run/agent.sock"))
+        );
+    }
+
+    #[tokio::test]
+    async fn requests_without_the_token_are_rejected() {
+        let state = state();
+        let request = |token: &str| {
+            Request::builder()
+                .uri("/status")
+                .header(HOST, "localhost")
+                .header(AUTHORIZATION, token)
+                .body(Full::new(Bytes::new()))
+                .unwrap()
+        };
+        assert_eq!(
+            handle(&state, request("")).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            handle(&state, request("Bearer secreT")).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            handle(&state, request("Bearer secret")).await.status(),
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn browser_and_rebinding_requests_are_rejected() {
+        let state = state();
+        let request = |host: &str, origin: Option<&str>| {
+            let mut builder = Request::builder()
+                .method(Method::POST)
+                .uri("/reload")
+                .header(HOST, host)
+                .header(AUTHORIZATION, "Bearer secret");
+            if let Some(origin) = origin {
+                builder = builder.header(ORIGIN, origin);
+            }
+            builder.body(Full::new(Bytes::new())).unwrap()
+        };
+        for (host, origin) in [
+            ("localhost:9090", Some("https://evil.example")),
+            ("evil.example:9090", None),
+            ("127.0.0.1.evil.example", None),
+            ("192.168.1.2:9090", None),
+        ] {
+            assert_eq!(
+                handle(&state, request(host, origin)).await.status(),
+                StatusCode::FORBIDDEN,
+                "{host}"
+            );
+        }
+        for host in [
+            "localhost",
+            "LOCALHOST:9090",
+            "127.0.0.1:9090",
+            "[::1]:9090",
+        ] {
+            assert!(
+                is_loopback_host(Some(&HeaderValue::from_static(host))),
+                "{host}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn tcp_listener_requires_a_token() {
+        let config: AgentConfig = toml::from_str(CONFIG).unwrap();
+        let control = ControlConfig {
+            token: None,
+            ..config.control.clone().unwrap()
+        };
+        let config = Arc::new(config);
+        let router = Arc::new(OutboundRouter::new(config.clone()).unwrap());
+        assert!(
+            ControlServer::bind(&control, config, router, None)
+                .await
+                .is_err()
+        );
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secrex"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+    }
+
+    #[tokio::test]
+    async fn routing_overrides_are_applied_and_reported() {
+        let state = state();
+        let (status, body) = call(&state, Method::GET, "/status", "").await;
+        assert_eq!(status, StatusCode::OK);
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["proxy_group"], "proxy");
+        assert_eq!(body["proxy_groups"], json!(["proxy", "hk"]));
+
+        let (status, body) = call(
+            &state,
+            Method::PUT,
+            "/routing/proxy-group",
+            r#"{"group":"hk"}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::from_str::<Value>(&body).unwrap()["proxy_group"],
+            "hk"
+        );
+        let target = protocol::Address::Domain {
+            host: "example.com".to_string(),
+            port: 443,
+        };
+        let route = state
+            .router
+            .route(&target, protocol::TransportProtocol::Tcp);
+        assert_eq!(route.name(), "hk");
+
+        let (status, body) = call(
+            &state,
+            Method::PUT,
+            "/routing/mode",
+            r#"{"mode":"direct_all"}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::from_str::<Value>(&body).unwrap()["mode_override"],
+            "direct_all"
+        );
+        let route = state
+            .router
+            .route(&target, protocol::TransportProtocol::Tcp);
+        assert_eq!(route.name(), "direct");
+
+        let (status, _) = call(
+            &state,
+            Method::PUT,
+            "/routing/proxy-group",
+            r#"{"group":"missing"}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = call(&state, Method::POST, "/reload", "").await;
+        assert_eq!(status, StatusCode::NOT_IMPLEMENTED);
+        let (status, _) = call(&state, Method::DELETE, "/connections/0", "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn metrics_use_the_prometheus_text_format() {
+        let state = state();
+        let (status, body) = call(&state, Method::GET, "/metrics", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("# TYPE ppaass_agent_traffic_bytes_total counter\n"));
+        assert!(body.contains("ppaass_agent_traffic_bytes_total{direction=\"outbound\"} "));
+        assert!(body.contains("# TYPE ppaass_agent_active_connections gauge\n"));
+        assert_eq!(escape_label("a\"b\\c"), "a\\\"b\\\\c");
+    }
+
+    #[tokio::test]
+    async fn client_talks_to_a_running_server() {
+        let config: AgentConfig = toml::from_str(CONFIG).unwrap();
+        let control = config.control.clone().unwrap();
+        let config = Arc::new(config);
+        let router = Arc::new(OutboundRouter::new(config.clone()).unwrap());
+        let server = ControlServer::bind(&control, config, router, None)
+            .await
+            .unwrap();
+        let ControlListener::Tcp(listener) = &server.listener else {
+            unreachable!();
+        };
+        let listen = listener.local_addr().unwrap().to_string();
+        let shutdown = CancellationToken::new();
+        let task = tokio::spawn(server.run(shutdown.clone()));
+
+        let (status, body) = request(&listen, Some("secret"), Method::GET, "/traffic", None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert!(serde_json::from_str::<Value>(&body).unwrap()["outbound_bytes"].is_u64());
+        let (status, _) = request(&listen, Some("wrong"), Method::GET, "/traffic", None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        shutdown.cancel();
+        task.await.unwrap();
+    }
+}
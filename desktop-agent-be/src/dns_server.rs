@@ -0,0 +1,562 @@
+//! 本地 DNS 监听。
+//!
+//! TUN 的 DNS proxy 只处理 TUN 捕获的 53 端口流量；这里在 `[dns_server]` 配置的地址上
+//! 同时监听 UDP 与 TCP，让只使用 HTTP/SOCKS 的用户和局域网其他设备也能使用 agent 的 DNS。
+//! 每个查询依次经过：
+//! 1. 静态 hosts，A/AAAA 直接在本地作答；
+//! 2. 与 TUN DNS proxy 相同实现的响应缓存；
+//! 3. 按域名后缀选择的上游：命中 `upstreams` 的查询直接发给对应 DNS 服务器，
+//!    其余通过默认分组的 `Address::ProxyDns` 交给 proxy 端解析。
+//!
+//! 每个查询结果都会记录到 `telemetry::emit_dns_resolution`。
+//!
+//! 来源地址与 HTTP/SOCKS 入口一样按 `listener_access.allowed_sources` 和客户端黑名单过滤，
+//! 并发处理的 UDP 查询和 TCP 连接数有上限，绑定到局域网地址时不会成为开放解析器。
+
+use crate::config::DnsServerConfig;
+use crate::error::{AgentError, Result};
+use crate::inbound_clients::is_inbound_client_blocked;
+use crate::listener_access::ListenerAccess;
+use crate::routing::OutboundRouter;
+use crate::telemetry::{self, DnsResolutionRecord};
+use crate::tun_handler::dns_proxy::{
+    DnsResponseCache, DnsResponseSummary, dns_id, local_dns_response, parse_dns_response,
+};
+use common::dns::parse_dns_query_packet;
+use common::spawn_guarded;
+use parking_lot::{Mutex, RwLock};
+use protocol::{Address, TransportProtocol};
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+const DNS_UPSTREAM_TIMEOUT: Duration = Duration::from_secs(5);
+const DNS_TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+const DNS_HOSTS_TTL: u32 = 60;
+// 同时处理的 UDP 查询与 TCP 连接总数；超过时丢弃新查询、关闭新连接，由客户端重试。
+const DNS_MAX_CONCURRENT_QUERIES: usize = 256;
+
+pub(crate) struct DnsServer {
+    listen_addr: String,
+    udp: Arc<UdpSocket>,
+    tcp: TcpListener,
+    resolver: Arc<DnsResolver>,
+    access: Arc<RwLock<Arc<ListenerAccess>>>,
+    permits: Arc<Semaphore>,
+}
+
+impl DnsServer {
+    /// 绑定 UDP 与 TCP 监听；任一端口不可用时返回错误，避免静默缺少一半服务。
+    pub(crate) async fn bind(
+        config: &DnsServerConfig,
+        router: Arc<OutboundRouter>,
+        access: Arc<RwLock<Arc<ListenerAccess>>>,
+    ) -> Result<Self> {
+        let resolver = DnsResolver::new(config, router)?;
+        let udp = UdpSocket::bind(config.listen_addr.as_str()).await?;
+        let tcp = TcpListener::bind(config.listen_addr.as_str()).await?;
+        Ok(Self {
+            listen_addr: config.listen_addr.clone(),
+            udp: Arc::new(udp),
+            tcp,
+            resolver: Arc::new(resolver),
+            access,
+            permits: Arc::new(Semaphore::new(DNS_MAX_CONCURRENT_QUERIES)),
+        })
+    }
+
+    /// 来源是否允许使用本地 DNS：与 HTTP/SOCKS 入口共用白名单和客户端黑名单。
+    fn allows_client(&self, client: SocketAddr) -> bool {
+        !is_inbound_client_blocked(client.ip()) && self.access.read().allows_source(client.ip())
+    }
+
+    pub(crate) async fn run(self, shutdown: CancellationToken) {
+        info!("本地 DNS 服务正在监听 {}（UDP+TCP）", self.listen_addr);
+        let mut buf = vec![0u8; 65535];
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                received = self.udp.recv_from(&mut buf) => {
+                    let (n, client) = match received {
+                        Ok(received) => received,
+                        Err(e) => {
+                            debug!("本地 DNS UDP 接收失败：{e}");
+                            continue;
+                        }
+                    };
+                    if !self.allows_client(client) {
+                        debug!("本地 DNS 拒绝来自 {client} 的查询：来源不在白名单内或已被拉黑");
+                        continue;
+                    }
+                    let Ok(permit) = self.permits.clone().try_acquire_owned() else {
+                        debug!("本地 DNS 并发查询已达上限，丢弃来自 {client} 的查询");
+                        continue;
+                    };
+                    let packet = buf[..n].to_vec();
+                    let udp = self.udp.clone();
+                    let resolver = self.resolver.clone();
+                    spawn_guarded("desktop dns server udp query", async move {
+                        let _permit = permit;
+                        if let Some(response) = resolver.resolve(client, &packet).await
+                            && let Err(e) = udp.send_to(&response, client).await
+                        {
+                            debug!("本地 DNS UDP 回复 {client} 失败：{e}");
+                        }
+                    });
+                }
+                accepted = self.tcp.accept() => {
+                    let (stream, client) = match accepted {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            debug!("本地 DNS TCP 接受连接失败：{e}");
+                            continue;
+                        }
+                    };
+                    if !self.allows_client(client) {
+                        debug!("本地 DNS 拒绝来自 {client} 的 TCP 连接：来源不在白名单内或已被拉黑");
+                        continue;
+                    }
+                    let Ok(permit) = self.permits.clone().try_acquire_owned() else {
+                        debug!("本地 DNS 并发查询已达上限，关闭来自 {client} 的 TCP 连接");
+                        continue;
+                    };
+                    let resolver = self.resolver.clone();
+                    spawn_guarded("desktop dns server tcp connection", async move {
+                        let _permit = permit;
+                        if let Err(e) = serve_tcp_client(stream, client, resolver).await {
+                            debug!("本地 DNS TCP 连接 {client} 结束：{e}");
+                        }
+                    });
+                }
+            }
+        }
+        info!("本地 DNS 服务已停止");
+    }
+}
+
+async fn serve_tcp_client(
+    mut stream: TcpStream,
+    client: SocketAddr,
+    resolver: Arc<DnsResolver>,
+) -> io::Result<()> {
+    // DNS over TCP 每条消息前有 2 字节长度，同一连接可以连续发送多个查询。
+    loop {
+        let mut len = [0u8; 2];
+        match timeout(DNS_TCP_IDLE_TIMEOUT, stream.read_exact(&mut len)).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Ok(()),
+        }
+        // 报文主体同样受空闲超时约束，避免只发长度前缀的连接一直占用名额。
+        let mut packet = vec![0u8; usize::from(u16::from_be_bytes(len))];
+        timeout(DNS_TCP_IDLE_TIMEOUT, stream.read_exact(&mut packet))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "DNS over TCP 报文读取超时"))??;
+        let Some(response) = resolver.resolve(client, &packet).await else {
+            continue;
+        };
+        let Ok(response_len) = u16::try_from(response.len()) else {
+            continue;
+        };
+        stream.write_all(&response_len.to_be_bytes()).await?;
+        stream.write_all(&response).await?;
+        stream.flush().await?;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DnsUpstream {
+    // 直接发给指定 DNS 服务器。
+    Server(SocketAddr),
+    // 通过默认分组交给 proxy 端 DNS。
+    Proxy,
+}
+
+struct DnsResolver {
+    hosts: HashMap<String, Vec<IpAddr>>,
+    // (规范化的域名后缀, 上游)，按配置顺序匹配。
+    upstreams: Vec<(Vec<String>, SocketAddr)>,
+    cache: Mutex<DnsResponseCache>,
+    router: Arc<OutboundRouter>,
+}
+
+impl DnsResolver {
+    fn new(config: &DnsServerConfig, router: Arc<OutboundRouter>) -> Result<Self> {
+        let hosts = config
+            .hosts
+            .iter()
+            .map(|(domain, addrs)| (normalize_domain(domain), addrs.clone()))
+            .collect();
+        let upstreams = config
+            .upstreams
+            .iter()
+            .map(|upstream| {
+                let server = parse_dns_server_addr(&upstream.server).ok_or_else(|| {
+                    AgentError::Connection(format!("无效的 DNS 上游地址：{}", upstream.server))
+                })?;
+                let suffixes = upstream
+                    .domains
+                    .iter()
+                    .map(|domain| normalize_domain(domain.trim_start_matches("*.")))
+                    .filter(|domain| !domain.is_empty())
+                    .collect();
+                Ok((suffixes, server))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            hosts,
+            upstreams,
+            cache: Mutex::new(DnsResponseCache::default()),
+            router,
+        })
+    }
+
+    fn upstream_for(&self, query: &str) -> DnsUpstream {
+        self.upstreams
+            .iter()
+            .find(|(suffixes, _)| {
+                suffixes.iter().any(|suffix| {
+                    query == suffix
+                        || query
+                            .strip_suffix(suffix.as_str())
+                            .is_some_and(|prefix| prefix.ends_with('.'))
+                })
+            })
+            .map_or(DnsUpstream::Proxy, |(_, server)| {
+                DnsUpstream::Server(*server)
+            })
+    }
+
+    /// hosts 命中 A/AAAA 查询时返回对应地址族的记录；没有该地址族时返回空列表。
+    fn hosts_answers(&self, query: &str, record_type: &str) -> Option<Vec<IpAddr>> {
+        let want_ipv6 = match record_type {
+            "A" => false,
+            "AAAA" => true,
+            _ => return None,
+        };
+        let addrs = self.hosts.get(query)?;
+        Some(
+            addrs
+                .iter()
+                .copied()
+                .filter(|addr| addr.is_ipv6() == want_ipv6)
+                .collect(),
+        )
+    }
+
+    /// 解析一个 DNS 查询包；不是合法查询或上游失败时返回 None，由客户端自行重试。
+    async fn resolve(&self, client: SocketAddr, packet: &[u8]) -> Option<Vec<u8>> {
+        let Some(query) = parse_dns_query_packet(packet) else {
+            debug!("本地 DNS 收到无效查询，已丢弃：client={client}");
+            return None;
+        };
+        let request_id = dns_id(packet)?;
+        let started_at = Instant::now();
+        let record = |resolver: &str, upstream: String, summary: DnsResponseSummary| {
+            telemetry::emit_dns_resolution(DnsResolutionRecord {
+                timestamp_ms: telemetry::current_time_millis(),
+                resolver: resolver.to_string(),
+                client: client.to_string(),
+                upstream,
+                query: query.query.clone(),
+                record_type: query.record_type.clone(),
+                status: summary.status,
+                answers: summary.answers,
+                duration_ms: started_at.elapsed().as_millis(),
+            });
+        };
+
+        if let Some(answers) = self.hosts_answers(&query.query, &query.record_type) {
+            let response = local_dns_response(packet, &answers, DNS_HOSTS_TTL)?;
+            record(
+                "agent-hosts",
+                "hosts".to_string(),
+                DnsResponseSummary {
+                    status: "NOERROR".to_string(),
+                    answers: answers.iter().map(ToString::to_string).collect(),
+                    min_ttl: Some(DNS_HOSTS_TTL),
+                },
+            );
+            return Some(response);
+        }
+
+        let cached = self
+            .cache
+            .lock()
+            .get(&query.query, &query.record_type, request_id);
+        if let Some(response) = cached {
+            record("agent-cache", "cache".to_string(), summarize(&response));
+            return Some(response);
+        }
+
+        let upstream = self.upstream_for(&query.query);
+        let upstream_label = match upstream {
+            DnsUpstream::Server(server) => server.to_string(),
+            DnsUpstream::Proxy => "proxy默认DNS".to_string(),
+        };
+        let result = timeout(DNS_UPSTREAM_TIMEOUT, async {
+            match upstream {
+                DnsUpstream::Server(server) => query_dns_server(server, packet).await,
+                DnsUpstream::Proxy => query_proxy_dns(&self.router, packet).await,
+            }
+        })
+        .await;
+        let response = match result {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) => {
+                warn!(
+                    "本地 DNS 查询 {} {} 经 {} 失败：{e}",
+                    query.query, query.record_type, upstream_label
+                );
+                record("agent-dns-server", upstream_label, failed("ERROR"));
+                return None;
+            }
+            Err(_) => {
+                debug!(
+                    "本地 DNS 查询 {} {} 经 {} 超时",
+                    query.query, query.record_type, upstream_label
+                );
+                record("agent-dns-server", upstream_label, failed("TIMEOUT"));
+                return None;
+            }
+        };
+
+        let summary = summarize(&response);
+        self.cache
+            .lock()
+            .insert(&query.query, &query.record_type, &summary, &response);
+        record("agent-dns-server", upstream_label, summary);
+        Some(response)
+    }
+}
+
+async fn query_dns_server(server: SocketAddr, packet: &[u8]) -> Result<Vec<u8>> {
+    let bind_addr = if server.is_ipv4() {
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0)
+    } else {
+        SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0)
+    };
+    let socket = UdpSocket::bind(bind_addr).await?;
+    socket.connect(server).await?;
+    socket.send(packet).await?;
+    let mut buf = vec![0u8; 65535];
+    loop {
+        let n = socket.recv(&mut buf).await?;
+        // 忽略 ID 不匹配的迟到响应。
+        if dns_id(&buf[..n]) == dns_id(packet) {
+            return Ok(buf[..n].to_vec());
+        }
+    }
+}
+
+async fn query_proxy_dns(router: &OutboundRouter, packet: &[u8]) -> Result<Vec<u8>> {
+    let connected = router
+        .default_group()
+        .udp_sessions()
+        .connect_to_target(Address::ProxyDns { port: 53 }, TransportProtocol::Udp)
+        .await?;
+    let mut proxy_io = connected.into_async_io();
+    proxy_io.write_all(packet).await?;
+    proxy_io.flush().await?;
+    // UDP 语义的 proxy 流每次读取对应一个完整数据报。
+    let mut buf = vec![0u8; 65535];
+    let n = proxy_io.read(&mut buf).await?;
+    let _ = proxy_io.shutdown().await;
+    if n == 0 {
+        return Err(AgentError::Connection(
+            "proxy DNS 连接在回复前关闭".to_string(),
+        ));
+    }
+    Ok(buf[..n].to_vec())
+}
+
+fn summarize(response: &[u8]) -> DnsResponseSummary {
+    parse_dns_response(response).unwrap_or_else(|| failed("INVALID"))
+}
+
+fn failed(status: &str) -> DnsResponseSummary {
+    DnsResponseSummary {
+        status: status.to_string(),
+        answers: Vec::new(),
+        min_ttl: None,
+    }
+}
+
+fn parse_dns_server_addr(server: &str) -> Option<SocketAddr> {
+    let server = server.trim();
+    server
+        .parse::<SocketAddr>()
+        .ok()
+        .or_else(|| Some(SocketAddr::new(server.parse().ok()?, 53)))
+}
+
+fn normalize_domain(domain: &str) -> String {
+    domain
+        .trim()
+        .trim_start_matches('.')
+        .trim_end_matches('.')
+        .to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AgentConfig;
+
+    fn router() -> Arc<OutboundRouter> {
+        let agent_config: AgentConfig = toml::from_str(
+            r#"
+proxy_addrs = ["127.0.0.1:8080"]
+username = "user1"
+private_key_path = "keys/user1.pem"
+"#,
+        )
+        .unwrap();
+        Arc::new(OutboundRouter::new(Arc::new(agent_config)).unwrap())
+    }
+
+    fn resolver(config: &str) -> Result<DnsResolver> {
+        let config: DnsServerConfig = toml::from_str(config).unwrap();
+        DnsResolver::new(&config, router())
+    }
+
+    #[test]
+    fn selects_upstream_by_domain_suffix() {
+        let resolver = resolver(
+            r#"
+listen_addr = "127.0.0.1:0"
+upstreams = [{ domains = ["Corp.Example.com.", "*.internal"], server = "10.0.0.53" }]
+"#,
+        )
+        .unwrap();
+        let corp = DnsUpstream::Server("10.0.0.53:53".parse().unwrap());
+
+        assert_eq!(resolver.upstream_for("corp.example.com"), corp);
+        assert_eq!(resolver.upstream_for("git.corp.example.com"), corp);
+        assert_eq!(resolver.upstream_for("wiki.internal"), corp);
+        assert_eq!(
+            resolver.upstream_for("notcorp.example.com"),
+            DnsUpstream::Proxy
+        );
+        assert_eq!(resolver.upstream_for("example.com"), DnsUpstream::Proxy);
+        assert!(
+            self::resolver(
+                r#"
+listen_addr = "127.0.0.1:0"
+upstreams = [{ domains = ["corp"], server = "not-an-ip" }]
+"#
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn hosts_answer_only_matching_address_family() {
+        let resolver = resolver(
+            r#"
+listen_addr = "127.0.0.1:0"
+hosts = { "NAS.Home." = ["192.168.1.10", "fd00::10"] }
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            resolver.hosts_answers("nas.home", "A"),
+            Some(vec!["192.168.1.10".parse().unwrap()])
+        );
+        assert_eq!(
+            resolver.hosts_answers("nas.home", "AAAA"),
+            Some(vec!["fd00::10".parse().unwrap()])
+        );
+        assert_eq!(resolver.hosts_answers("nas.home", "MX"), None);
+        assert_eq!(resolver.hosts_answers("other.home", "A"), None);
+    }
+
+    #[tokio::test]
+    async fn answers_hosts_queries_and_serves_dns_over_tcp() {
+        let resolver = Arc::new(
+            resolver(
+                r#"
+listen_addr = "127.0.0.1:0"
+hosts = { "example.com" = ["192.0.2.1"] }
+"#,
+            )
+            .unwrap(),
+        );
+        let query = vec![
+            0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, b'e',
+            b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00, 0x00, 0x01, 0x00,
+            0x01,
+        ];
+
+        let client = "127.0.0.1:10000".parse().unwrap();
+        let response = resolver.resolve(client, &query).await.unwrap();
+        assert_eq!(dns_id(&response), Some(0x1234));
+        assert_eq!(summarize(&response).answers, vec!["192.0.2.1"]);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, client) = listener.accept().await.unwrap();
+            serve_tcp_client(stream, client, resolver).await
+        });
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(&(query.len() as u16).to_be_bytes())
+            .await
+            .unwrap();
+        stream.write_all(&query).await.unwrap();
+        let mut len = [0u8; 2];
+        stream.read_exact(&mut len).await.unwrap();
+        let mut tcp_response = vec![0u8; usize::from(u16::from_be_bytes(len))];
+        stream.read_exact(&mut tcp_response).await.unwrap();
+        assert_eq!(tcp_response, response);
+        drop(stream);
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn ignores_queries_from_sources_outside_allowlist() {
+        let config: DnsServerConfig = toml::from_str(
+            r#"
+listen_addr = "127.0.0.1:0"
+hosts = { "example.com" = ["192.0.2.1"] }
+"#,
+        )
+        .unwrap();
+        let access = |sources: &str| {
+            let config = toml::from_str(&format!("allowed_sources = [{sources}]")).unwrap();
+            Arc::new(RwLock::new(Arc::new(ListenerAccess::new(&config).unwrap())))
+        };
+        let query = [
+            0x56, 0x78, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, b'e',
+            b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00, 0x00, 0x01, 0x00,
+            0x01,
+        ];
+
+        for (sources, answered) in [("\"10.0.0.0/8\"", false), ("\"127.0.0.1\"", true)] {
+            let server = DnsServer::bind(&config, router(), access(sources))
+                .await
+                .unwrap();
+            let addr = server.udp.local_addr().unwrap();
+            let shutdown = CancellationToken::new();
+            tokio::spawn(server.run(shutdown.clone()));
+
+            let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            client.send_to(&query, addr).await.unwrap();
+            let mut buf = [0u8; 512];
+            let received = timeout(Duration::from_millis(300), client.recv(&mut buf)).await;
+            assert_eq!(received.is_ok(), answered, "allowed_sources = [{sources}]");
+            shutdown.cancel();
+        }
+    }
+}
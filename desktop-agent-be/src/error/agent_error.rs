@@ -17,6 +17,12 @@ pub enum AgentError {
 
     #[error("SOCKS5 error: {0}")]
     Socks5(String),
+    #[error("Reload error: {0}")]
+    Reload(String),
+
+    #[error("{0}")]
+    Config(#[from] common::CommonError),
+
     #[error("Hyper error: {0}")]
     HyperError(#[from] hyper::Error),
 }
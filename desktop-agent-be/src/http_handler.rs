@@ -1,28 +1,96 @@
 //! 本地 HTTP 代理入口。
 //!
-//! HTTP CONNECT 会升级成裸 TCP 隧道，普通 HTTP 请求则通过 hyper client 转发。
-//! 两条路径都会先由 `DirectAccessChecker` 判定是否直连；否则通过 proxy session
-//! manager 取得 agent->proxy 的目标流。
+//! 同一个监听口按连接前言同时服务 HTTP/1.1 与 HTTP/2；不提供 HTTP/3（QUIC）入口。
+//! HTTP CONNECT 与 HTTP/2 扩展 CONNECT 的 connect-tcp 会升级成裸 TCP 隧道（其他 `:protocol` 返回 501），
+//! 普通 HTTP 请求则通过 hyper client 转发，
+//! 上游连接按（出口，目标）放进 `upstream_pool.rs` 供后续 keep-alive 请求复用。
+//! 两条路径都会先由 `OutboundRouter` 选择出口：直连、拒绝，或通过对应 proxy
+//! 分组的 session manager 取得 agent->proxy 的目标流。配置了本地凭据时，
+//! 每个请求都要先通过 `Proxy-Authorization: Basic` 校验。
+//!
+//! 直接请求 agent 本身的 `GET /proxy.pac`、`GET /wpad.dat` 返回由当前分流规则
+//! 生成的 PAC 文件，浏览器或系统的自动代理配置可以直接指向本地入口。
 
-use crate::direct_access::{DirectAccessChecker, address_to_string};
+use crate::connections::{ConnectionLease, register_connection};
 use crate::error::{AgentError, Result};
+use crate::listener_access::ListenerAccess;
+use crate::routing::{OutboundRouter, Route, address_to_string};
+use crate::server::InboundPeer;
 use crate::tcp_relay::{TcpRelayOptions, relay_tcp_bidirectional};
 use crate::telemetry;
-use crate::yamux_session::{YamuxSessionManager, YamuxTargetStream};
+use crate::yamux_session::YamuxTargetStream;
 use bytes::Bytes;
 use http_body_util::{BodyExt, Full, combinators::BoxBody};
 use hyper::body::Incoming;
-use hyper::header::HeaderValue;
-use hyper::server::conn::http1;
+use hyper::ext::Protocol;
+use hyper::header::{
+    CACHE_CONTROL, CONNECTION, CONTENT_TYPE, HOST, HeaderMap, HeaderName, HeaderValue,
+    PROXY_AUTHENTICATE, PROXY_AUTHORIZATION, TE, TRAILER, TRANSFER_ENCODING, UPGRADE,
+};
 use hyper::service::service_fn;
 use hyper::upgrade::Upgraded;
-use hyper::{Method, Request, Response, StatusCode, Uri};
-use hyper_util::rt::TokioIo;
+use hyper::{Method, Request, Response, StatusCode, Uri, Version};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
 use protocol::{Address, TransportProtocol};
+use std::net::SocketAddr;
 use std::str::FromStr;
 use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpStream;
-use tracing::{debug, error, instrument};
+use tracing::{debug, error, instrument, warn};
+
+#[cfg(test)]
+mod tests;
+mod tracked_body;
+mod upstream_pool;
+
+use tracked_body::{BoxError, TrackedBody};
+pub(crate) use upstream_pool::UpstreamPool;
+use upstream_pool::UpstreamSender;
+
+/// 提供 PAC 文件的路径；`wpad.dat` 供 WPAD 自动发现使用。
+const PAC_PATHS: [&str; 2] = ["/proxy.pac", "/wpad.dat"];
+
+/// 唯一支持的扩展 CONNECT 协议：按 connect-tcp 草案打开到路径所指目标的 TCP 隧道。
+const CONNECT_TCP_PROTOCOL: &str = "connect-tcp";
+/// connect-tcp 的 URI 模板 `/.well-known/masque/tcp/{target_host}/{tcp_port}/` 的固定前缀。
+const CONNECT_TCP_PATH_PREFIX: &str = "/.well-known/masque/tcp/";
+
+/// 按 connect-tcp 模板从路径中取出目标主机和端口。
+///
+/// 主机经过百分号编码（IPv6 字面量的冒号写成 `%3A`），解码后 IPv6 加上方括号，
+/// 与 authority-form 的写法一致。
+fn connect_tcp_target(path: &str) -> Option<(String, u16)> {
+    let rest = path.strip_prefix(CONNECT_TCP_PATH_PREFIX)?;
+    let rest = rest.strip_suffix('/').unwrap_or(rest);
+    let (host, port) = rest.split_once('/')?;
+    let port = port.parse::<u16>().ok().filter(|port| *port != 0)?;
+    let host = percent_decode(host)?;
+    if host.is_empty() {
+        return None;
+    }
+    if host.parse::<std::net::Ipv6Addr>().is_ok() {
+        return Some((format!("[{host}]"), port));
+    }
+    Some((host, port))
+}
+
+fn percent_decode(input: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut rest = input.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let encoded = tail.get(..2)?;
+            bytes.extend(hex::decode(encoded).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
 
 /// 从 HTTP 请求中提取主机和端口，正确处理 IPv6 地址
 fn extract_host_port(req: &Request<Incoming>, uri: &Uri) -> (String, u16) {
@@ -64,62 +132,128 @@ fn extract_host_port(req: &Request<Incoming>, uri: &Uri) -> (String, u16) {
     (host, port)
 }
 
-#[instrument(skip(stream, sessions, direct_checker))]
-pub async fn handle_http_connection(
-    stream: TcpStream,
-    sessions: Arc<YamuxSessionManager>,
-    direct_checker: Arc<DirectAccessChecker>,
-) -> Result<()> {
-    debug!("处理 HTTP 连接: {stream:?}");
+#[instrument(skip(stream, router, access, pool))]
+pub async fn handle_http_connection<S>(
+    stream: S,
+    peer: InboundPeer,
+    local_addr: SocketAddr,
+    router: Arc<OutboundRouter>,
+    access: Arc<ListenerAccess>,
+    pool: Arc<UpstreamPool>,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    debug!("处理来自 {peer} 的 HTTP 连接");
     let io = TokioIo::new(stream);
-    let sessions_clone = sessions.clone();
-    let checker_clone = direct_checker.clone();
 
-    // 每个 HTTP 请求都共享 proxy session 管理器和直连规则，service_fn 只做轻量克隆。
+    // 每个 HTTP 请求都共享分流规则和各分组的 proxy session 管理器，service_fn 只做轻量克隆。
     let service = service_fn(move |req| {
-        let sessions = sessions_clone.clone();
-        let checker = checker_clone.clone();
-        async move { handle_http_request(req, sessions, checker).await }
+        let router = router.clone();
+        let access = access.clone();
+        let pool = pool.clone();
+        let peer = peer.clone();
+        async move { handle_http_request(req, router, access, pool, local_addr, peer).await }
     });
 
-    let conn = http1::Builder::new()
-        .serve_connection(io, service)
-        .with_upgrades();
+    // 按连接前言自动识别 HTTP/1.1 与 HTTP/2（h2c prior knowledge）。HTTP/2 客户端可以在
+    // 一条本地连接上复用多个 CONNECT 隧道，每个流各自建立目标连接。
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    builder.http2().enable_connect_protocol();
+    let conn = builder.serve_connection_with_upgrades(io, service);
 
     if let Err(e) = conn.await {
         error!("HTTP 连接服务出错: {}", e);
-        return Err(AgentError::HyperError(e));
+        return Err(match e.downcast::<hyper::Error>() {
+            Ok(e) => AgentError::HyperError(*e),
+            Err(e) => AgentError::Connection(e.to_string()),
+        });
     }
 
     Ok(())
 }
 
 async fn handle_http_request(
-    req: Request<Incoming>,
-    sessions: Arc<YamuxSessionManager>,
-    direct_checker: Arc<DirectAccessChecker>,
-) -> std::result::Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error> {
+    mut req: Request<Incoming>,
+    router: Arc<OutboundRouter>,
+    access: Arc<ListenerAccess>,
+    pool: Arc<UpstreamPool>,
+    local_addr: SocketAddr,
+    peer: InboundPeer,
+) -> std::result::Result<Response<AgentBody>, hyper::Error> {
     debug!("HTTP 请求: {} {}", req.method(), req.uri());
 
+    // 浏览器拉取 PAC 时直接请求 agent（origin-form），不会携带代理凭据。
+    if req.method() == Method::GET
+        && req.uri().authority().is_none()
+        && PAC_PATHS.contains(&req.uri().path())
+    {
+        debug!(
+            "提供 PAC 文件: {}，代理地址 {}",
+            req.uri().path(),
+            local_addr
+        );
+        return Ok(pac_response(&router, local_addr));
+    }
+
+    // Proxy-Authorization 只属于本地入口这一跳，校验后移除，不能随请求转发给目标。
+    let authorization = req.headers_mut().remove(PROXY_AUTHORIZATION);
+    if !access.check_proxy_authorization(authorization.as_ref()) {
+        warn!(
+            "HTTP 代理请求缺少有效的本地凭据: {} {}",
+            req.method(),
+            req.uri()
+        );
+        return Ok(proxy_auth_required_response());
+    }
+
     if req.method() == Method::CONNECT {
         // CONNECT 需要升级为原始双向字节流，常用于 HTTPS。
-        handle_connect(req, sessions, direct_checker).await
+        handle_connect(req, router, &peer).await
     } else {
-        // 普通 HTTP 请求走 hyper client handshake 转发。
-        handle_regular_request(req, sessions, direct_checker).await
+        // 普通 HTTP 请求优先复用池中的上游连接，没有时再建连握手。
+        handle_regular_request(req, router, &pool, &peer).await
     }
 }
 
 async fn handle_connect(
     mut req: Request<Incoming>,
-    sessions: Arc<YamuxSessionManager>,
-    direct_checker: Arc<DirectAccessChecker>,
-) -> std::result::Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error> {
+    router: Arc<OutboundRouter>,
+    peer: &InboundPeer,
+) -> std::result::Result<Response<AgentBody>, hyper::Error> {
     let uri = req.uri().clone();
-    let host = uri.host().unwrap_or("").to_string();
-    let port = uri.port_u16().unwrap_or(443);
-
-    debug!("CONNECT 请求到 {}:{}", host, port);
+    // 普通 CONNECT 是 authority-form，目标就是 :authority。HTTP/2 扩展 CONNECT（RFC 8441）
+    // 的 :authority 是 agent 自己，目标由 :protocol 的语义决定；只实现 connect-tcp。
+    let (host, port) = match req.extensions().get::<Protocol>() {
+        None => {
+            let host = uri.host().unwrap_or("").to_string();
+            let port = uri.port_u16().unwrap_or(443);
+            debug!("CONNECT 请求到 {}:{}", host, port);
+            (host, port)
+        }
+        Some(protocol) if protocol.as_str() == CONNECT_TCP_PROTOCOL => {
+            match connect_tcp_target(uri.path()) {
+                Some((host, port)) => {
+                    debug!("扩展 CONNECT（connect-tcp）请求到 {}:{}", host, port);
+                    (host, port)
+                }
+                None => {
+                    warn!("connect-tcp 请求路径不符合模板: {}", uri.path());
+                    return Ok(text_response(
+                        StatusCode::BAD_REQUEST,
+                        "connect-tcp path must be /.well-known/masque/tcp/{target_host}/{tcp_port}/",
+                    ));
+                }
+            }
+        }
+        Some(protocol) => {
+            warn!("不支持的扩展 CONNECT 协议: {}", protocol.as_str());
+            return Ok(text_response(
+                StatusCode::NOT_IMPLEMENTED,
+                "Unsupported extended CONNECT protocol",
+            ));
+        }
+    };
 
     let address = Address::Domain {
         host: host.clone(),
@@ -128,106 +262,132 @@ async fn handle_connect(
 
     let target = format!("{host}:{port}");
 
-    if direct_checker.is_direct(&address) {
-        // === 直连路径: 直接连接目标 ===
-        debug!("CONNECT 使用直连连接到 {}", target);
-
-        // CONNECT 的 200 只应该表示“隧道已经可用”。
-        // 如果先回复 200 再异步连接目标，浏览器会立刻把 TLS ClientHello 写进本地隧道；
-        // 一旦后续远端连接失败或建立过慢，这个已经成功的 CONNECT 会表现成异常 TCP/TLS
-        // 连接，而不是一次可重试的代理建连失败。视频分片场景下这会让播放器状态机很难判断
-        // 当前分片到底是网络失败、解析失败还是响应中断。
-        let target_stream = match TcpStream::connect(&target).await {
-            Ok(stream) => stream,
-            Err(err) => {
-                error!("HTTP CONNECT 直连到 {} 失败: {}", target, err);
-                return Ok(Response::builder()
-                    .status(StatusCode::BAD_GATEWAY)
-                    .body(boxed(
-                        Full::new(Bytes::from("Failed to connect to target"))
-                            .map_err(|e| match e {}),
-                    ))
-                    .unwrap());
+    let decision = router.decide(&address, TransportProtocol::Tcp, None, None);
+    match decision.route {
+        Route::Direct => {
+            // === 直连路径: 直接连接目标 ===
+            debug!("CONNECT 使用直连连接到 {}", target);
+
+            // CONNECT 的 200 只应该表示“隧道已经可用”。
+            // 如果先回复 200 再异步连接目标，浏览器会立刻把 TLS ClientHello 写进本地隧道；
+            // 一旦后续远端连接失败或建立过慢，这个已经成功的 CONNECT 会表现成异常 TCP/TLS
+            // 连接，而不是一次可重试的代理建连失败。视频分片场景下这会让播放器状态机很难判断
+            // 当前分片到底是网络失败、解析失败还是响应中断。
+            let target_stream = match TcpStream::connect(&target).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    error!("HTTP CONNECT 直连到 {} 失败: {}", target, err);
+                    return Ok(Response::builder()
+                        .status(StatusCode::BAD_GATEWAY)
+                        .body(boxed(
+                            Full::new(Bytes::from("Failed to connect to target"))
+                                .map_err(|e| match e {}),
+                        ))
+                        .unwrap());
+                }
+            };
+            if let Err(err) = target_stream.set_nodelay(true) {
+                debug!("HTTP CONNECT 直连目标 TCP_NODELAY 设置失败，继续使用默认行为：{err}");
             }
-        };
-        if let Err(err) = target_stream.set_nodelay(true) {
-            debug!("HTTP CONNECT 直连目标 TCP_NODELAY 设置失败，继续使用默认行为：{err}");
-        }
+            let connection = register_connection(
+                "HTTP CONNECT",
+                peer,
+                &target,
+                TransportProtocol::Tcp,
+                &decision,
+            );
 
-        tokio::spawn(async move {
-            match hyper::upgrade::on(&mut req).await {
-                Ok(upgraded) => {
-                    debug!("HTTP CONNECT 升级成功（直连） {}:{}", host, port);
-                    if let Err(e) = tunnel_direct(upgraded, target_stream, &target).await {
-                        error!("直连隧道错误: {}", e);
+            tokio::spawn(async move {
+                match hyper::upgrade::on(&mut req).await {
+                    Ok(upgraded) => {
+                        debug!("HTTP CONNECT 升级成功（直连） {}:{}", host, port);
+                        if let Err(e) =
+                            tunnel_direct(upgraded, target_stream, &target, &connection).await
+                        {
+                            error!("直连隧道错误: {}", e);
+                        }
+                    }
+                    Err(e) => {
+                        error!("HTTP CONNECT 升级失败: {}", e);
                     }
                 }
+            });
+
+            // 目标连接成功后再回复 200，随后升级任务接管底层 TCP 流。
+            Ok(Response::builder()
+                .status(StatusCode::OK)
+                .body(empty())
+                .unwrap())
+        }
+        Route::Block => {
+            debug!("CONNECT 目标 {} 命中 block 规则，拒绝连接", target);
+            Ok(blocked_response())
+        }
+        Route::Proxy(group) => {
+            // === 代理路径: 通过代理隧道连接 ===
+            // 代理路径同样必须先确认远端 proxy/目标通道可用，再向浏览器返回 CONNECT 200。
+            // 浏览器把 200 视为“之后就是透明 TCP 字节流”；如果此时 proxy stream 还没建立，
+            // TLS/HTTP2 的开头字节会先堆在本地 upgraded 连接里，后续失败只能体现为隧道
+            // 被动断开。对媒体分片来说，这类“看似建连成功、随后字节流异常”的失败很容易
+            // 表现成分片大小接近正常但播放器无法解析或缓冲状态卡住。
+            let connected_stream = match group
+                .tcp_sessions()
+                .connect_to_target(address, TransportProtocol::Tcp)
+                .await
+            {
+                Ok(stream) => {
+                    debug!(
+                        "通过 proxy session manager 获取目标流, stream_id: {}",
+                        stream.stream_id()
+                    );
+                    stream
+                }
                 Err(e) => {
-                    error!("HTTP CONNECT 升级失败: {}", e);
+                    error!("HTTP CONNECT 获取 proxy 流失败: {}", e);
+                    return Ok(Response::builder()
+                        .status(StatusCode::BAD_GATEWAY)
+                        .body(boxed(
+                            Full::new(Bytes::from("Failed to connect to proxy"))
+                                .map_err(|e| match e {}),
+                        ))
+                        .unwrap());
                 }
-            }
-        });
-
-        // 目标连接成功后再回复 200，随后升级任务接管底层 TCP 流。
-        Ok(Response::builder()
-            .status(StatusCode::OK)
-            .body(empty())
-            .unwrap())
-    } else {
-        // === 代理路径: 通过代理隧道连接 ===
-        // 代理路径同样必须先确认远端 proxy/目标通道可用，再向浏览器返回 CONNECT 200。
-        // 浏览器把 200 视为“之后就是透明 TCP 字节流”；如果此时 proxy stream 还没建立，
-        // TLS/HTTP2 的开头字节会先堆在本地 upgraded 连接里，后续失败只能体现为隧道
-        // 被动断开。对媒体分片来说，这类“看似建连成功、随后字节流异常”的失败很容易
-        // 表现成分片大小接近正常但播放器无法解析或缓冲状态卡住。
-        let connected_stream = match sessions
-            .as_ref()
-            .connect_to_target(address, TransportProtocol::Tcp)
-            .await
-        {
-            Ok(stream) => {
-                debug!(
-                    "通过 proxy session manager 获取目标流, stream_id: {}",
-                    stream.stream_id()
-                );
-                stream
-            }
-            Err(e) => {
-                error!("HTTP CONNECT 获取 proxy 流失败: {}", e);
-                return Ok(Response::builder()
-                    .status(StatusCode::BAD_GATEWAY)
-                    .body(boxed(
-                        Full::new(Bytes::from("Failed to connect to proxy"))
-                            .map_err(|e| match e {}),
-                    ))
-                    .unwrap());
-            }
-        };
+            };
+            let connection = register_connection(
+                "HTTP CONNECT",
+                peer,
+                &target,
+                TransportProtocol::Tcp,
+                &decision,
+            );
 
-        tokio::spawn(async move {
-            match hyper::upgrade::on(&mut req).await {
-                Ok(upgraded) => {
-                    debug!("HTTP CONNECT 升级成功 {}:{}", host, port);
-                    // 代理路径必须把 Domain 原样交给 proxy 端解析。
-                    // agent 端本地解析会改变出口 DNS 语义：目标 IP 由本机网络决定，
-                    // 不再由 proxy 所在地域、proxy DNS 缓存和远端分流策略决定。对
-                    // CDN/HLS 这类强地域相关流量尤其容易选错节点，因此这里只负责
-                    // 透传域名，不做任何 agent 侧 DNS fallback。
-                    if let Err(e) = tunnel(upgraded, connected_stream, target).await {
-                        error!("隧道错误: {}", e);
+            tokio::spawn(async move {
+                match hyper::upgrade::on(&mut req).await {
+                    Ok(upgraded) => {
+                        debug!("HTTP CONNECT 升级成功 {}:{}", host, port);
+                        // 代理路径必须把 Domain 原样交给 proxy 端解析。
+                        // agent 端本地解析会改变出口 DNS 语义：目标 IP 由本机网络决定，
+                        // 不再由 proxy 所在地域、proxy DNS 缓存和远端分流策略决定。对
+                        // CDN/HLS 这类强地域相关流量尤其容易选错节点，因此这里只负责
+                        // 透传域名，不做任何 agent 侧 DNS fallback。
+                        if let Err(e) =
+                            tunnel(upgraded, connected_stream, target, &connection).await
+                        {
+                            error!("隧道错误: {}", e);
+                        }
+                    }
+                    Err(e) => {
+                        error!("HTTP CONNECT 升级失败: {}", e);
                     }
                 }
-                Err(e) => {
-                    error!("HTTP CONNECT 升级失败: {}", e);
-                }
-            }
-        });
+            });
 
-        // CONNECT 成功响应本身没有 body，数据随后走 upgraded stream。
-        Ok(Response::builder()
-            .status(StatusCode::OK)
-            .body(empty())
-            .unwrap())
+            // CONNECT 成功响应本身没有 body，数据随后走 upgraded stream。
+            Ok(Response::builder()
+                .status(StatusCode::OK)
+                .body(empty())
+                .unwrap())
+        }
     }
 }
 
@@ -235,6 +395,7 @@ async fn tunnel(
     upgraded: Upgraded,
     connected_stream: YamuxTargetStream,
     target: String,
+    connection: &ConnectionLease,
 ) -> std::result::Result<(), AgentError> {
     // HTTP CONNECT、SOCKS、TUN 的 TCP 字节流统一走 copy_bidirectional。
     // direct framed TCP 和 Yamux 都先通过 YamuxTargetStream 转成 AsyncRead/AsyncWrite，
@@ -245,7 +406,7 @@ async fn tunnel(
     match relay_tcp_bidirectional(
         &mut client_io,
         &mut proxy_io,
-        TcpRelayOptions::standard(&target),
+        TcpRelayOptions::standard(&target).with_connection(connection),
     )
     .await
     {
@@ -275,6 +436,7 @@ async fn tunnel_direct(
     upgraded: Upgraded,
     mut target_stream: TcpStream,
     target: &str,
+    connection: &ConnectionLease,
 ) -> std::result::Result<(), AgentError> {
     // 直连 CONNECT 跳过 proxy，直接把 upgraded client 和目标 TCP 流相连。
     let mut client_io = TokioIo::new(upgraded);
@@ -282,7 +444,7 @@ async fn tunnel_direct(
     match relay_tcp_bidirectional(
         &mut client_io,
         &mut target_stream,
-        TcpRelayOptions::standard(target),
+        TcpRelayOptions::standard(target).with_connection(connection),
     )
     .await
     {
@@ -308,9 +470,10 @@ async fn tunnel_direct(
 
 async fn handle_regular_request(
     mut req: Request<Incoming>,
-    sessions: Arc<YamuxSessionManager>,
-    direct_checker: Arc<DirectAccessChecker>,
-) -> std::result::Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error> {
+    router: Arc<OutboundRouter>,
+    pool: &Arc<UpstreamPool>,
+    peer: &InboundPeer,
+) -> std::result::Result<Response<AgentBody>, hyper::Error> {
     let uri = req.uri();
 
     // 从 Host 头或 URI 中提取主机和端口
@@ -333,6 +496,17 @@ async fn handle_regular_request(
         port,
     };
 
+    // HTTP/2 请求没有 Host 头，目标只在 :authority 里；以 HTTP/1.1 发给目标前补上。
+    if req.version() == Version::HTTP_2 {
+        if !req.headers().contains_key(HOST)
+            && let Some(authority) = req.uri().authority()
+            && let Ok(value) = HeaderValue::from_str(authority.as_str())
+        {
+            req.headers_mut().insert(HOST, value);
+        }
+        *req.version_mut() = Version::HTTP_11;
+    }
+
     // 将 URI 修正为目标服务器的相对路径（origin-form）
     // 代理收到的请求可能是 absolute-form，发给 origin server 时应转成 path/query。
     let path = req
@@ -344,142 +518,250 @@ async fn handle_regular_request(
     if let Ok(new_uri) = Uri::from_str(path) {
         *req.uri_mut() = new_uri;
     }
-    // 每个普通 HTTP proxy 请求都会创建一条独立的目标连接。
-    // 显式关闭上游 keep-alive，避免 per-request 子流被误当成可复用连接。
-    req.headers_mut()
-        .insert(hyper::header::CONNECTION, HeaderValue::from_static("close"));
-
-    if direct_checker.is_direct(&address) {
-        // === 直连路径: 直接连接目标 ===
-        let target = address_to_string(&address);
-        debug!("HTTP 请求使用直连连接到 {}", target);
-
-        let target_stream = match TcpStream::connect(&target).await {
-            Ok(s) => {
-                if let Err(err) = s.set_nodelay(true) {
-                    debug!("HTTP 普通请求直连目标 TCP_NODELAY 设置失败，继续使用默认行为：{err}");
-                }
-                s
-            }
-            Err(e) => {
-                error!("直连到 {} 失败: {}", target, e);
-                return Ok(Response::builder()
-                    .status(StatusCode::BAD_GATEWAY)
-                    .body(boxed(
-                        Full::new(Bytes::from("Failed to connect to target"))
-                            .map_err(|e| match e {}),
-                    ))
-                    .unwrap());
-            }
+    // 客户端要求关闭时，上游连接也不再复用，并把关闭意图转告目标。
+    let reusable = !wants_close(&req);
+    strip_hop_by_hop_headers(req.headers_mut());
+    if !reusable {
+        req.headers_mut()
+            .insert(CONNECTION, HeaderValue::from_static("close"));
+    }
+
+    let decision = router.decide(&address, TransportProtocol::Tcp, None, None);
+    let proxy_group = match &decision.route {
+        Route::Block => {
+            debug!("HTTP 请求目标 {}:{} 命中 block 规则，拒绝连接", host, port);
+            return Ok(blocked_response());
+        }
+        Route::Direct => None,
+        Route::Proxy(group) => Some(*group),
+    };
+    let target = address_to_string(&address);
+    let key = pool.key(decision.route.name(), &target);
+    // 每个请求登记一条活动连接，请求与响应 body 都转发完才移除；UI 关闭时中止这次交换。
+    let connection = Arc::new(register_connection(
+        "HTTP",
+        peer,
+        &target,
+        TransportProtocol::Tcp,
+        &decision,
+    ));
+    let cancel = connection.cancel_token().clone();
+    let mut req = req.map(|body| TrackedBody::outbound(body, connection.clone()));
+
+    // 先复用空闲连接。请求还没写出就失败（连接恰好被对端关闭）时拿回请求，换下一条或新建连接。
+    while let Some(mut sender) = pool.take(&key) {
+        let sent = tokio::select! {
+            sent = sender.try_send_request(req) => sent,
+            _ = cancel.cancelled() => return Ok(closed_response(&target)),
         };
+        match sent {
+            Ok(response) => {
+                debug!("HTTP 请求复用到 {} 的上游连接", target);
+                pool.release_when_idle(key, sender, reusable);
+                return Ok(forward_response(response, connection));
+            }
+            Err(mut err) => match err.take_message() {
+                Some(message) => {
+                    debug!("到 {} 的空闲上游连接不可用，重试：{}", target, err.error());
+                    req = message;
+                }
+                None => return Err(err.into_error()),
+            },
+        }
+    }
 
-        // 直接与目标进行握手
-        let (mut sender, conn) =
-            hyper::client::conn::http1::handshake(TokioIo::new(target_stream)).await?;
-
-        let (sender_guard_tx, sender_guard_rx) = tokio::sync::oneshot::channel();
-
-        // hyper connection future 驱动读写状态机，必须放到后台持续运行。
-        // sender 需要至少活到 response body 被驱动完成；否则慢速响应在远端链路上
-        // 可能被提前收尾，表现成 Content-Length 和实际 body 不一致。
-        tokio::spawn(async move {
-            tokio::pin!(conn);
-            let mut sender_guard = None;
-            tokio::select! {
-                guard = sender_guard_rx => {
-                    sender_guard = guard.ok();
-                    if let Err(err) = (&mut conn).await {
-                        error!("直连连接失败: {:?}", err);
+    let mut sender = match proxy_group {
+        None => {
+            // === 直连路径: 直接连接目标 ===
+            debug!("HTTP 请求使用直连连接到 {}", target);
+
+            let target_stream = match TcpStream::connect(&target).await {
+                Ok(s) => {
+                    if let Err(err) = s.set_nodelay(true) {
+                        debug!(
+                            "HTTP 普通请求直连目标 TCP_NODELAY 设置失败，继续使用默认行为：{err}"
+                        );
                     }
+                    s
                 }
-                result = &mut conn => {
-                    if let Err(err) = result {
-                        error!("直连连接失败: {:?}", err);
-                    }
+                Err(e) => {
+                    error!("直连到 {} 失败: {}", target, e);
+                    return Ok(Response::builder()
+                        .status(StatusCode::BAD_GATEWAY)
+                        .body(boxed(
+                            Full::new(Bytes::from("Failed to connect to target"))
+                                .map_err(|e| match e {}),
+                        ))
+                        .unwrap());
                 }
-            }
-            drop(sender_guard);
-        });
+            };
 
-        let response = sender.send_request(req).await?;
-        let _ = sender_guard_tx.send(sender);
-        let (parts, body) = response.into_parts();
-        let body = boxed(body);
+            // 直接与目标进行握手
+            upstream_handshake(target_stream, "直连连接失败").await?
+        }
+        Some(group) => {
+            // === 代理路径: 通过代理隧道连接 ===
+            // 普通 HTTP 代理同样不能在 agent 端解析域名。这里把 Domain 目标透传给
+            // proxy，使 DNS、CDN 节点选择和远端策略都发生在真正出口侧。
+            let connected_stream = match group
+                .tcp_sessions()
+                .connect_to_target(address, TransportProtocol::Tcp)
+                .await
+            {
+                Ok(stream) => stream,
+                Err(e) => {
+                    error!("通过 proxy session manager 获取目标流失败: {}", e);
+                    return Ok(Response::builder()
+                        .status(StatusCode::BAD_GATEWAY)
+                        .body(boxed(
+                            Full::new(Bytes::from("Failed to connect to proxy"))
+                                .map_err(|e| match e {}),
+                        ))
+                        .unwrap());
+                }
+            };
 
-        Ok(Response::from_parts(parts, body))
-    } else {
-        // === 代理路径: 通过代理隧道连接 ===
-        // 普通 HTTP 代理同样不能在 agent 端解析域名。这里把 Domain 目标透传给
-        // proxy，使 DNS、CDN 节点选择和远端策略都发生在真正出口侧。
-        let connected_stream = match sessions
-            .as_ref()
-            .connect_to_target(address, TransportProtocol::Tcp)
-            .await
-        {
-            Ok(stream) => stream,
-            Err(e) => {
-                error!("通过 proxy session manager 获取目标流失败: {}", e);
-                return Ok(Response::builder()
-                    .status(StatusCode::BAD_GATEWAY)
-                    .body(boxed(
-                        Full::new(Bytes::from("Failed to connect to proxy"))
-                            .map_err(|e| match e {}),
-                    ))
-                    .unwrap());
-            }
-        };
+            // 通过代理隧道与目标进行握手
+            upstream_handshake(connected_stream.into_async_io(), "连接失败").await?
+        }
+    };
 
-        // 转换为异步 IO
-        let proxy_io = connected_stream.into_async_io();
-
-        // 通过代理隧道与目标进行握手
-        let (mut sender, conn) =
-            hyper::client::conn::http1::handshake(TokioIo::new(proxy_io)).await?;
-
-        let (sender_guard_tx, sender_guard_rx) = tokio::sync::oneshot::channel();
-
-        // 代理路径也需要后台驱动 hyper client connection。
-        // 和直连路径一样保留 sender，直到 response body 对应的连接自然结束。
-        tokio::spawn(async move {
-            tokio::pin!(conn);
-            let mut sender_guard = None;
-            tokio::select! {
-                guard = sender_guard_rx => {
-                    sender_guard = guard.ok();
-                    if let Err(err) = (&mut conn).await {
-                        error!("连接失败: {:?}", err);
-                    }
-                }
-                result = &mut conn => {
-                    if let Err(err) = result {
-                        error!("连接失败: {:?}", err);
-                    }
-                }
-            }
-            drop(sender_guard);
-        });
+    let response = tokio::select! {
+        response = sender.send_request(req) => response?,
+        _ = cancel.cancelled() => return Ok(closed_response(&target)),
+    };
+    pool.release_when_idle(key, sender, reusable);
+    Ok(forward_response(response, connection))
+}
+
+/// 与目标完成 HTTP/1.1 握手，并在后台驱动 hyper client connection 的读写状态机。
+async fn upstream_handshake<T>(io: T, error_label: &'static str) -> hyper::Result<UpstreamSender>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(io)).await?;
+    // 连接在池里空闲期间也要继续运行；sender 被丢弃后它会自然结束。
+    tokio::spawn(async move {
+        if let Err(err) = conn.await {
+            error!("{}: {:?}", error_label, err);
+        }
+    });
+    Ok(sender)
+}
 
-        // 发送请求
-        let response = sender.send_request(req).await?;
-        let _ = sender_guard_tx.send(sender);
+fn forward_response(
+    response: Response<Incoming>,
+    connection: Arc<ConnectionLease>,
+) -> Response<AgentBody> {
+    // 将响应体转换为 BoxBody 类型，转发过程中累计 inbound 字节
+    let (mut parts, body) = response.into_parts();
+    strip_hop_by_hop_headers(&mut parts.headers);
+    Response::from_parts(parts, BoxBody::new(TrackedBody::inbound(body, connection)))
+}
 
-        // 将响应体转换为 BoxBody 类型
-        let (parts, body) = response.into_parts();
-        let body = boxed(body);
+fn closed_response(target: &str) -> Response<AgentBody> {
+    debug!("到 {} 的 HTTP 请求在收到响应前被手动关闭", target);
+    text_response(StatusCode::BAD_GATEWAY, "Connection closed")
+}
+
+/// 客户端是否要求这次请求后关闭连接：显式 `close`，或未声明 keep-alive 的 HTTP/1.0。
+fn wants_close<B>(req: &Request<B>) -> bool {
+    let tokens = connection_tokens(req.headers());
+    if tokens.iter().any(|token| token == "close") {
+        return true;
+    }
+    req.version() == Version::HTTP_10 && !tokens.iter().any(|token| token == "keep-alive")
+}
 
-        Ok(Response::from_parts(parts, body))
+/// 移除只属于当前这一跳的头：`Connection` 及其列出的头、`Keep-Alive`、`Proxy-Connection`、
+/// `TE`、`Trailer`、`Upgrade` 与 `Proxy-Authenticate`。`Transfer-Encoding` 由 hyper 按 body
+/// 重新生成分帧，这里保持不动。
+fn strip_hop_by_hop_headers(headers: &mut HeaderMap) {
+    for token in connection_tokens(headers) {
+        if let Ok(name) = HeaderName::from_bytes(token.as_bytes())
+            && name != TRANSFER_ENCODING
+        {
+            headers.remove(name);
+        }
+    }
+    for name in [CONNECTION, TE, TRAILER, UPGRADE, PROXY_AUTHENTICATE] {
+        headers.remove(name);
+    }
+    for name in ["keep-alive", "proxy-connection"] {
+        headers.remove(name);
     }
 }
 
-// 未知体的辅助类型
-type AgentBody = BoxBody<Bytes, hyper::Error>;
+/// `Connection` 与非标准 `Proxy-Connection` 中列出的选项，统一转成小写。
+fn connection_tokens(headers: &HeaderMap) -> Vec<String> {
+    headers
+        .get_all(CONNECTION)
+        .iter()
+        .chain(headers.get_all("proxy-connection").iter())
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|token| token.trim().to_ascii_lowercase())
+        .filter(|token| !token.is_empty())
+        .collect()
+}
+
+// 未知体的辅助类型。错误类型用 BoxError：连接被手动关闭时转发中的 body 要以自己的错误中止。
+type AgentBody = BoxBody<Bytes, BoxError>;
 
 fn boxed<B>(body: B) -> AgentBody
 where
     B: hyper::body::Body<Data = Bytes, Error = hyper::Error> + Send + Sync + 'static,
 {
     // 统一响应 body 类型，便于不同分支返回同一个 Response 类型。
-    BoxBody::new(body)
+    BoxBody::new(body.map_err(BoxError::from))
+}
+
+fn text_response(status: StatusCode, message: &'static str) -> Response<AgentBody> {
+    Response::builder()
+        .status(status)
+        .body(boxed(
+            Full::new(Bytes::from(message)).map_err(|e| match e {}),
+        ))
+        .unwrap()
+}
+
+fn blocked_response() -> Response<AgentBody> {
+    // block 出口直接拒绝，不建立任何目标或 proxy 连接。
+    Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .body(boxed(
+            Full::new(Bytes::from("Blocked by routing policy")).map_err(|e| match e {}),
+        ))
+        .unwrap()
+}
+
+fn pac_response(router: &OutboundRouter, local_addr: SocketAddr) -> Response<AgentBody> {
+    let script = router.pac_script().render(&local_addr.to_string());
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/x-ns-proxy-autoconfig"),
+        )
+        // 规则热重载后浏览器重新拉取即可拿到新脚本。
+        .header(CACHE_CONTROL, HeaderValue::from_static("no-cache"))
+        .body(boxed(
+            Full::new(Bytes::from(script)).map_err(|e| match e {}),
+        ))
+        .unwrap()
+}
+
+fn proxy_auth_required_response() -> Response<AgentBody> {
+    Response::builder()
+        .status(StatusCode::PROXY_AUTHENTICATION_REQUIRED)
+        .header(
+            PROXY_AUTHENTICATE,
+            HeaderValue::from_static("Basic realm=\"ppaass\""),
+        )
+        .body(boxed(
+            Full::new(Bytes::from("Proxy authentication required")).map_err(|e| match e {}),
+        ))
+        .unwrap()
 }
 
 fn empty() -> AgentBody {
@@ -0,0 +1,394 @@
+use super::upstream_pool::PoolKey;
+use super::*;
+use hyper::server::conn::http1;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+const DIRECT_CONFIG: &str = r#"
+proxy_addrs = ["127.0.0.1:8080"]
+username = "user1"
+private_key_path = "keys/user1.pem"
+
+[direct_access]
+mode = "direct_all"
+"#;
+
+/// 本地 origin：记录接受的 TCP 连接数，响应里回显 Host 以及是否收到了 hop-by-hop 头。
+async fn spawn_origin() -> (SocketAddr, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let counter = accepted.clone();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            counter.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                let service = service_fn(|req: Request<Incoming>| async move {
+                    let leaked = req.headers().contains_key("x-hop");
+                    let host_matches = req.headers().get(HOST).is_some_and(|host| {
+                        host.to_str()
+                            .is_ok_and(|host| host.starts_with("127.0.0.1:"))
+                    });
+                    Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from(format!(
+                        "{} host={host_matches} leaked={leaked}",
+                        req.uri()
+                    )))))
+                });
+                let _ = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    });
+    (addr, accepted)
+}
+
+/// 本地 echo 目标：每条连接原样回写收到的字节。
+async fn spawn_echo() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let (mut reader, mut writer) = stream.split();
+                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+            });
+        }
+    });
+    addr
+}
+
+/// 在内存管道上启动一个直连出口的本地 HTTP 入口，返回客户端一侧。
+fn spawn_agent() -> tokio::io::DuplexStream {
+    spawn_agent_with_pool(Arc::new(UpstreamPool::default()))
+}
+
+fn spawn_agent_with_pool(pool: Arc<UpstreamPool>) -> tokio::io::DuplexStream {
+    let config: crate::config::AgentConfig = toml::from_str(DIRECT_CONFIG).unwrap();
+    let router = Arc::new(OutboundRouter::new(Arc::new(config)).unwrap());
+    let access = Arc::new(ListenerAccess::new(&Default::default()).unwrap());
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(handle_http_connection(
+        server,
+        InboundPeer::Tcp("127.0.0.1:50002".parse().unwrap()),
+        "127.0.0.1:1080".parse().unwrap(),
+        router,
+        access,
+        pool,
+    ));
+    client
+}
+
+async fn connect_agent() -> hyper::client::conn::http1::SendRequest<Full<Bytes>> {
+    connect_agent_with_pool(Arc::new(UpstreamPool::default())).await
+}
+
+async fn connect_agent_with_pool(
+    pool: Arc<UpstreamPool>,
+) -> hyper::client::conn::http1::SendRequest<Full<Bytes>> {
+    let (sender, conn) =
+        hyper::client::conn::http1::handshake(TokioIo::new(spawn_agent_with_pool(pool)))
+            .await
+            .unwrap();
+    tokio::spawn(conn);
+    sender
+}
+
+async fn get(
+    sender: &mut hyper::client::conn::http1::SendRequest<Full<Bytes>>,
+    origin: SocketAddr,
+    path: &str,
+    close: bool,
+) -> String {
+    let mut builder = Request::get(format!("http://{origin}{path}"))
+        .header(HOST, origin.to_string())
+        .header("x-hop", "1")
+        .header(CONNECTION, "x-hop");
+    if close {
+        builder = builder.header(CONNECTION, "close");
+    }
+    sender.ready().await.unwrap();
+    let response = sender
+        .send_request(builder.body(Full::new(Bytes::new())).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+async fn wait_for_idle(pool: &UpstreamPool, key: &PoolKey, expected: usize) {
+    for _ in 0..100 {
+        if pool.idle_count(key) == expected {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("上游连接池空闲数没有变成 {expected}");
+}
+
+#[tokio::test]
+async fn keep_alive_requests_reuse_the_upstream_connection() {
+    let (origin, accepted) = spawn_origin().await;
+    let pool = Arc::new(UpstreamPool::default());
+    let key = pool.key("direct", &origin.to_string());
+    let mut agent = connect_agent_with_pool(pool.clone()).await;
+
+    assert_eq!(
+        get(&mut agent, origin, "/a", false).await,
+        "/a host=true leaked=false"
+    );
+    wait_for_idle(&pool, &key, 1).await;
+    assert_eq!(
+        get(&mut agent, origin, "/b?x=1", false).await,
+        "/b?x=1 host=true leaked=false"
+    );
+    wait_for_idle(&pool, &key, 1).await;
+    assert_eq!(accepted.load(Ordering::SeqCst), 1);
+
+    // 客户端要求关闭时用掉池里的连接后不再归还，下一次请求重新建连。
+    assert_eq!(
+        get(&mut agent, origin, "/c", true).await,
+        "/c host=true leaked=false"
+    );
+    let mut agent = connect_agent_with_pool(pool).await;
+    assert_eq!(
+        get(&mut agent, origin, "/d", false).await,
+        "/d host=true leaked=false"
+    );
+    assert_eq!(accepted.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn idle_upstream_connections_expire_and_are_dropped_on_clear() {
+    let (origin, _) = spawn_origin().await;
+    let connect = || async {
+        let stream = TcpStream::connect(origin).await.unwrap();
+        upstream_handshake(stream, "测试上游连接失败")
+            .await
+            .unwrap()
+    };
+
+    // 之后不再有 take 或归还，空闲连接也会按时被移除。
+    let expiring = Arc::new(UpstreamPool::new(Duration::from_millis(50)));
+    let key = expiring.key("direct", &origin.to_string());
+    expiring.release_when_idle(key.clone(), connect().await, true);
+    wait_for_idle(&expiring, &key, 1).await;
+    wait_for_idle(&expiring, &key, 0).await;
+
+    // 清空后，清空前取 key 的请求用完的连接不再归还。
+    let pool = Arc::new(UpstreamPool::new(Duration::from_secs(60)));
+    let stale = pool.key("direct", &origin.to_string());
+    pool.release_when_idle(stale.clone(), connect().await, true);
+    wait_for_idle(&pool, &stale, 1).await;
+    pool.clear();
+    assert_eq!(pool.idle_count(&stale), 0);
+    pool.release_when_idle(stale.clone(), connect().await, true);
+    let fresh = pool.key("direct", &origin.to_string());
+    pool.release_when_idle(fresh.clone(), connect().await, true);
+    wait_for_idle(&pool, &fresh, 1).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(pool.idle_count(&stale), 0);
+    assert!(pool.take(&stale).is_none());
+}
+
+#[tokio::test]
+async fn plain_requests_are_listed_and_can_be_closed() {
+    use crate::connections::{active_connections, close_connection};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // origin 只发出部分响应 body 后挂起，直到连接被关闭。
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let origin = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = [0u8; 1024];
+        let _ = stream.read(&mut buf).await.unwrap();
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 10\r\n\r\nabc")
+            .await
+            .unwrap();
+        let _ = stream.read(&mut buf).await;
+    });
+
+    let mut agent = connect_agent().await;
+    let request = Request::get(format!("http://{origin}/slow"))
+        .header(HOST, origin.to_string())
+        .body(Full::new(Bytes::new()))
+        .unwrap();
+    let response = agent.send_request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let target = origin.to_string();
+    let find = || {
+        active_connections()
+            .into_iter()
+            .find(|record| record.inbound == "HTTP" && record.target == target)
+    };
+    let mut record = None;
+    for _ in 0..100 {
+        record = find().filter(|record| record.inbound_bytes == 3);
+        if record.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let record = record.expect("普通 HTTP 请求没有登记到活动连接表");
+    assert_eq!(record.route, "direct");
+    assert_eq!(record.source, "127.0.0.1:50002");
+
+    assert!(close_connection(record.id));
+    assert!(response.into_body().collect().await.is_err());
+    for _ in 0..100 {
+        if find().is_none() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("关闭后活动连接表仍有该请求");
+}
+
+async fn connect_agent_h2() -> hyper::client::conn::http2::SendRequest<Full<Bytes>> {
+    let (sender, conn) =
+        hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(spawn_agent()))
+            .await
+            .unwrap();
+    tokio::spawn(conn);
+    sender
+}
+
+/// 通过 HTTP/2 CONNECT 打开隧道并确认 echo 目标原样回写。
+async fn h2_tunnel_echo(
+    mut sender: hyper::client::conn::http2::SendRequest<Full<Bytes>>,
+    request: Request<Full<Bytes>>,
+    payload: &'static [u8],
+) {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let response = sender.send_request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let mut tunnel = TokioIo::new(hyper::upgrade::on(response).await.unwrap());
+    tunnel.write_all(payload).await.unwrap();
+    let mut echoed = vec![0u8; payload.len()];
+    tunnel.read_exact(&mut echoed).await.unwrap();
+    assert_eq!(echoed, payload);
+}
+
+#[tokio::test]
+async fn http2_connect_tunnels_are_multiplexed_on_one_connection() {
+    let echo = spawn_echo().await;
+    let sender = connect_agent_h2().await;
+
+    let connect = |payload| {
+        let request = Request::connect(echo.to_string())
+            .version(Version::HTTP_2)
+            .body(Full::new(Bytes::new()))
+            .unwrap();
+        h2_tunnel_echo(sender.clone(), request, payload)
+    };
+    tokio::join!(connect(b"first"), connect(b"second"));
+
+    // connect-tcp 的 :authority 是 agent 自己，目标取自路径模板。
+    let extended = |protocol: &'static str, path: String| {
+        let mut request = Request::connect(format!("http://127.0.0.1:1080{path}"))
+            .version(Version::HTTP_2)
+            .body(Full::new(Bytes::new()))
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(Protocol::from_static(protocol));
+        request
+    };
+    let path = format!("/.well-known/masque/tcp/{}/{}/", echo.ip(), echo.port());
+    h2_tunnel_echo(
+        sender.clone(),
+        extended("connect-tcp", path.clone()),
+        b"extended",
+    )
+    .await;
+
+    // 其他扩展协议（如 RFC 8441 WebSocket）不是裸 TCP 隧道，不能当成 connect-tcp。
+    let response = sender
+        .clone()
+        .send_request(extended("websocket", "/chat".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED);
+    let response = sender
+        .clone()
+        .send_request(extended("connect-tcp", "/chat".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn connect_tcp_target_follows_the_path_template() {
+    assert_eq!(
+        connect_tcp_target("/.well-known/masque/tcp/example.com/443/"),
+        Some(("example.com".to_string(), 443))
+    );
+    assert_eq!(
+        connect_tcp_target("/.well-known/masque/tcp/2001%3adb8%3A%3A1/22"),
+        Some(("[2001:db8::1]".to_string(), 22))
+    );
+    assert_eq!(
+        connect_tcp_target("/.well-known/masque/tcp/example.com/0/"),
+        None
+    );
+    assert_eq!(connect_tcp_target("/.well-known/masque/tcp//443/"), None);
+    assert_eq!(connect_tcp_target("/.well-known/masque/tcp/a%2/443/"), None);
+    assert_eq!(connect_tcp_target("/masque/tcp/example.com/443/"), None);
+}
+
+#[tokio::test]
+async fn http2_plain_requests_are_forwarded_with_host() {
+    let (origin, _) = spawn_origin().await;
+    let mut sender = connect_agent_h2().await;
+
+    let request = Request::get(format!("http://{origin}/h2?q=1"))
+        .version(Version::HTTP_2)
+        .body(Full::new(Bytes::new()))
+        .unwrap();
+    let response = sender.send_request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, "/h2?q=1 host=true leaked=false");
+}
+
+#[test]
+fn hop_by_hop_headers_are_stripped() {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONNECTION, HeaderValue::from_static("keep-alive, X-Trace"));
+    headers.insert("x-trace", HeaderValue::from_static("1"));
+    headers.insert("keep-alive", HeaderValue::from_static("timeout=5"));
+    headers.insert("proxy-connection", HeaderValue::from_static("keep-alive"));
+    headers.insert(TE, HeaderValue::from_static("trailers"));
+    headers.insert(TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+    headers.insert("x-end-to-end", HeaderValue::from_static("1"));
+
+    strip_hop_by_hop_headers(&mut headers);
+
+    let mut names = headers.keys().map(|name| name.as_str()).collect::<Vec<_>>();
+    names.sort_unstable();
+    assert_eq!(names, ["transfer-encoding", "x-end-to-end"]);
+}
+
+#[test]
+fn close_intent_follows_connection_header_and_version() {
+    let request = |version, connection: Option<&str>| {
+        let mut builder = Request::get("/").version(version);
+        if let Some(connection) = connection {
+            builder = builder.header(CONNECTION, connection);
+        }
+        builder.body(()).unwrap()
+    };
+
+    assert!(!wants_close(&request(Version::HTTP_11, None)));
+    assert!(wants_close(&request(Version::HTTP_11, Some("Close"))));
+    assert!(wants_close(&request(Version::HTTP_10, None)));
+    assert!(!wants_close(&request(Version::HTTP_10, Some("keep-alive"))));
+}
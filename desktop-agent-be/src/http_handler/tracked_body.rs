@@ -0,0 +1,80 @@
+//! 普通 HTTP 请求在活动连接表中的计数与中止。
+//!
+//! 请求 body 与响应 body 共享同一份 [`ConnectionLease`]：两者都结束、被 drop 后条目才移除。
+//! UI 关闭该连接时，正在转发的 body 以错误结束：请求 body 出错会让 hyper client 中止
+//! 上游连接，响应 body 出错会让本地入口中止这次响应，连接都不会再进连接池。
+
+use crate::connections::ConnectionLease;
+use bytes::Bytes;
+use hyper::body::{Body, Frame, SizeHint};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
+use tokio_util::sync::WaitForCancellationFutureOwned;
+
+pub(super) type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+pub(super) struct TrackedBody<B> {
+    inner: B,
+    connection: Arc<ConnectionLease>,
+    // 请求 body 记为 outbound，响应 body 记为 inbound。
+    count: fn(&ConnectionLease, usize),
+    cancelled: Pin<Box<WaitForCancellationFutureOwned>>,
+}
+
+impl<B> TrackedBody<B> {
+    pub(super) fn outbound(inner: B, connection: Arc<ConnectionLease>) -> Self {
+        Self::new(inner, connection, ConnectionLease::add_outbound)
+    }
+
+    pub(super) fn inbound(inner: B, connection: Arc<ConnectionLease>) -> Self {
+        Self::new(inner, connection, ConnectionLease::add_inbound)
+    }
+
+    fn new(inner: B, connection: Arc<ConnectionLease>, count: fn(&ConnectionLease, usize)) -> Self {
+        let cancelled = Box::pin(connection.cancel_token().clone().cancelled_owned());
+        Self {
+            inner,
+            connection,
+            count,
+            cancelled,
+        }
+    }
+}
+
+impl<B> Body for TrackedBody<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+    B::Error: Into<BoxError>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, BoxError>>> {
+        let this = &mut *self;
+        if this.cancelled.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(Some(Err("连接已在活动连接列表中被关闭".into())));
+        }
+        let frame = match ready!(Pin::new(&mut this.inner).poll_frame(cx)) {
+            Some(Ok(frame)) => frame,
+            Some(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+            None => return Poll::Ready(None),
+        };
+        if let Some(data) = frame.data_ref() {
+            (this.count)(&this.connection, data.len());
+        }
+        Poll::Ready(Some(Ok(frame)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
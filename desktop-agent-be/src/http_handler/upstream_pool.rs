@@ -0,0 +1,154 @@
+//! 普通 HTTP 转发的上游连接池。
+//!
+//! 按（出口，目标）缓存已完成 HTTP/1.1 握手的 `SendRequest`。proxy 出口缓存的是已经通过
+//! Auth/Connect 的目标流，keep-alive 请求复用它就不必再建 proxy 连接、再认证。响应 body
+//! 读完、连接重新空闲后才会放回池里；每条空闲连接归还时各自启动一个定时器，空闲超过
+//! [`UPSTREAM_IDLE_TIMEOUT`] 就从池里移除并关闭，已被对端关闭的连接也会被丢弃。
+//! 池由 `AgentServer` 持有并传给各个 HTTP 入口；配置热重载后 proxy 地址或凭据可能已经变化，
+//! 由它清空整个池。
+
+use super::tracked_body::TrackedBody;
+use dashmap::DashMap;
+use hyper::body::Incoming;
+use hyper::client::conn::http1::SendRequest;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// 上游连接的最长空闲时间。常见 origin 的 keep-alive 超时在 5~75 秒，取偏小的值
+/// 减少复用到对端刚关闭的连接。
+const UPSTREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+/// 每个（出口，目标）最多保留的空闲连接数。
+const MAX_IDLE_PER_KEY: usize = 8;
+
+pub(super) type UpstreamSender = SendRequest<TrackedBody<Incoming>>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(super) struct PoolKey {
+    // 出口名（direct 或 proxy 分组名）：同一目标在不同出口上的连接不能混用。
+    route: String,
+    target: String,
+    // 取 key 时池的代数；清空后旧代数的连接不再归还。
+    generation: u64,
+}
+
+struct IdleSender {
+    sender: UpstreamSender,
+    idle_since: Instant,
+}
+
+pub(crate) struct UpstreamPool {
+    idle: DashMap<PoolKey, Vec<IdleSender>>,
+    idle_timeout: Duration,
+    generation: AtomicU64,
+}
+
+impl Default for UpstreamPool {
+    fn default() -> Self {
+        Self::new(UPSTREAM_IDLE_TIMEOUT)
+    }
+}
+
+impl UpstreamPool {
+    pub(super) fn new(idle_timeout: Duration) -> Self {
+        Self {
+            idle: DashMap::new(),
+            idle_timeout,
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// 请求开始建连或复用前取 key；之后池被清空，这次请求用过的连接就不会再放回池里。
+    pub(super) fn key(&self, route: &str, target: &str) -> PoolKey {
+        PoolKey {
+            route: route.to_string(),
+            target: target.to_string(),
+            generation: self.generation.load(Ordering::Acquire),
+        }
+    }
+
+    /// 取出最近归还、仍可用的空闲连接；沿途遇到的过期或已关闭连接直接丢弃。
+    pub(super) fn take(&self, key: &PoolKey) -> Option<UpstreamSender> {
+        let mut senders = self.idle.get_mut(key)?;
+        while let Some(idle) = senders.pop() {
+            if idle.idle_since.elapsed() < self.idle_timeout && idle.sender.is_ready() {
+                return Some(idle.sender);
+            }
+        }
+        None
+    }
+
+    /// 请求发出后在后台等连接重新空闲。
+    ///
+    /// sender 至少要活到响应 body 读完，否则慢速响应可能被提前收尾。body 中途被丢弃或任一端
+    /// 要求关闭时 `ready` 返回错误，连接不再复用；`reusable` 为 false 时也只等待、不归还。
+    pub(super) fn release_when_idle(
+        self: &Arc<Self>,
+        key: PoolKey,
+        mut sender: UpstreamSender,
+        reusable: bool,
+    ) {
+        let pool = self.clone();
+        tokio::spawn(async move {
+            if sender.ready().await.is_ok() && reusable {
+                pool.release(key, sender);
+            }
+        });
+    }
+
+    fn release(self: &Arc<Self>, key: PoolKey, sender: UpstreamSender) {
+        if sender.is_closed() || key.generation != self.generation.load(Ordering::Acquire) {
+            return;
+        }
+        let mut senders = self.idle.entry(key.clone()).or_default();
+        // 持有分片锁再比较一次代数：clear 先递增代数再清空分片，两者交错时也不会把旧连接留在池里。
+        if key.generation != self.generation.load(Ordering::Acquire) {
+            drop(senders);
+            self.idle.remove_if(&key, |_, senders| senders.is_empty());
+            return;
+        }
+        if senders.len() >= MAX_IDLE_PER_KEY {
+            return;
+        }
+        senders.push(IdleSender {
+            sender,
+            idle_since: Instant::now(),
+        });
+        drop(senders);
+        // 到期时清理这个目标上所有过期连接，刚放入的这条也在其中；期间被取走复用的连接
+        // 不受影响，再次归还时会重新计时。
+        let pool = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(pool.idle_timeout).await;
+            pool.evict_expired(&key);
+        });
+    }
+
+    fn evict_expired(&self, key: &PoolKey) {
+        self.idle.remove_if_mut(key, |_, senders| {
+            senders.retain(|idle| {
+                idle.idle_since.elapsed() < self.idle_timeout && !idle.sender.is_closed()
+            });
+            senders.is_empty()
+        });
+    }
+
+    /// 丢弃所有空闲连接；清空前取 key 的请求结束后，连接也不再归还。
+    ///
+    /// 配置热重载后调用：proxy 地址、凭据或分组配置可能已变，旧连接不能再复用。
+    pub(crate) fn clear(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        self.idle.clear();
+    }
+
+    /// 已清空的次数，测试用它确认热重载是否清空了连接池。
+    #[cfg(test)]
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    #[cfg(test)]
+    pub(super) fn idle_count(&self, key: &PoolKey) -> usize {
+        self.idle.get(key).map_or(0, |senders| senders.len())
+    }
+}
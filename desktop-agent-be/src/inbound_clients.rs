@@ -0,0 +1,246 @@
+//! 本地 HTTP/SOCKS5 入口的客户端登记与黑名单。
+//!
+//! 与 Android 端 `http_proxy_clients` 相同：按客户端 IP 聚合活动连接和最近连接，
+//! 拉黑某个 IP 后立即取消它的全部活动连接，之后的新连接在 accept 时直接丢弃。
+//! 黑名单只保存在内存中，agent 重启后清空。
+
+use std::collections::{BTreeMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use dashmap::DashMap;
+use serde_json::json;
+use tokio_util::sync::CancellationToken;
+
+use crate::telemetry;
+
+static INBOUND_CLIENTS: OnceLock<InboundClientRegistry> = OnceLock::new();
+
+struct InboundClientRegistry {
+    next_id: AtomicU64,
+    active: DashMap<u64, Arc<InboundClient>>,
+    recent: DashMap<IpAddr, RecentInboundClient>,
+    blocked: DashMap<IpAddr, ()>,
+}
+
+struct InboundClient {
+    id: u64,
+    peer_addr: SocketAddr,
+    ip: IpAddr,
+    cancel: CancellationToken,
+}
+
+struct RecentInboundClient {
+    last_peer_addr: String,
+    last_seen_ms: u128,
+    total_connections: u64,
+}
+
+pub(crate) struct InboundClientLease {
+    client: Arc<InboundClient>,
+}
+
+impl InboundClientRegistry {
+    fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            active: DashMap::new(),
+            recent: DashMap::new(),
+            blocked: DashMap::new(),
+        }
+    }
+
+    fn register(&self, peer_addr: SocketAddr) -> InboundClientLease {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let client = Arc::new(InboundClient {
+            id,
+            peer_addr,
+            ip: peer_addr.ip().to_canonical(),
+            cancel: CancellationToken::new(),
+        });
+        self.active.insert(id, client.clone());
+        self.record_recent(peer_addr, true);
+        InboundClientLease { client }
+    }
+
+    fn is_blocked(&self, ip: IpAddr) -> bool {
+        self.blocked.contains_key(&ip.to_canonical())
+    }
+
+    fn block(&self, ip: IpAddr) {
+        let ip = ip.to_canonical();
+        self.blocked.insert(ip, ());
+        self.cancel_ip(ip);
+    }
+
+    fn unblock(&self, ip: IpAddr) {
+        self.blocked.remove(&ip.to_canonical());
+    }
+
+    fn cancel_ip(&self, ip: IpAddr) {
+        for entry in self.active.iter() {
+            if entry.value().ip == ip {
+                entry.value().cancel.cancel();
+            }
+        }
+    }
+
+    fn remove_active(&self, id: u64) {
+        if let Some((_, client)) = self.active.remove(&id) {
+            self.record_recent(client.peer_addr, false);
+        }
+    }
+
+    fn record_recent(&self, peer_addr: SocketAddr, count_connection: bool) {
+        let now = telemetry::current_time_millis();
+        self.recent
+            .entry(peer_addr.ip().to_canonical())
+            .and_modify(|client| {
+                client.last_peer_addr = peer_addr.to_string();
+                client.last_seen_ms = now;
+                if count_connection {
+                    client.total_connections = client.total_connections.saturating_add(1);
+                }
+            })
+            .or_insert_with(|| RecentInboundClient {
+                last_peer_addr: peer_addr.to_string(),
+                last_seen_ms: now,
+                total_connections: 1,
+            });
+    }
+}
+
+impl InboundClientLease {
+    pub(crate) fn cancel_token(&self) -> CancellationToken {
+        self.client.cancel.clone()
+    }
+}
+
+impl Drop for InboundClientLease {
+    fn drop(&mut self) {
+        inbound_client_registry().remove_active(self.client.id);
+    }
+}
+
+fn inbound_client_registry() -> &'static InboundClientRegistry {
+    INBOUND_CLIENTS.get_or_init(InboundClientRegistry::new)
+}
+
+// 连接登记只按客户端 IP 聚合；局域网客户端通常会为同一个浏览器打开多个 TCP 连接。
+pub(crate) fn register_inbound_client(peer_addr: SocketAddr) -> InboundClientLease {
+    inbound_client_registry().register(peer_addr)
+}
+
+pub(crate) fn is_inbound_client_blocked(ip: IpAddr) -> bool {
+    inbound_client_registry().is_blocked(ip)
+}
+
+/// 活动客户端、最近客户端与黑名单的 JSON 快照，供 UI 展示。
+#[allow(dead_code)]
+pub fn inbound_clients_json() -> String {
+    let registry = inbound_client_registry();
+    let mut active_by_ip: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut active_ips = HashSet::new();
+    for entry in registry.active.iter() {
+        let client = entry.value();
+        active_ips.insert(client.ip);
+        active_by_ip
+            .entry(client.ip.to_string())
+            .or_default()
+            .push(client.peer_addr.to_string());
+    }
+
+    let active = active_by_ip
+        .into_iter()
+        .map(|(ip, mut peers)| {
+            peers.sort();
+            json!({
+                "ip": ip,
+                "connections": peers.len(),
+                "peers": peers,
+                "blocked": registry.is_blocked(
+                    ip.parse::<IpAddr>().unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+                ),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut recent = registry
+        .recent
+        .iter()
+        .filter(|entry| !active_ips.contains(entry.key()))
+        .map(|entry| {
+            let ip = *entry.key();
+            let client = entry.value();
+            json!({
+                "ip": ip.to_string(),
+                "connections": 0,
+                "total_connections": client.total_connections,
+                "last_seen_ms": client.last_seen_ms,
+                "peers": [client.last_peer_addr.clone()],
+                "blocked": registry.is_blocked(ip),
+            })
+        })
+        .collect::<Vec<_>>();
+    recent.sort_by(|a, b| {
+        b.get("last_seen_ms")
+            .and_then(|value| value.as_u64())
+            .cmp(&a.get("last_seen_ms").and_then(|value| value.as_u64()))
+    });
+    recent.truncate(64);
+
+    let mut blocked = registry
+        .blocked
+        .iter()
+        .map(|entry| entry.key().to_string())
+        .collect::<Vec<_>>();
+    blocked.sort();
+
+    json!({
+        "active": active,
+        "recent": recent,
+        "blocked": blocked,
+    })
+    .to_string()
+}
+
+#[allow(dead_code)]
+pub fn block_inbound_client(ip: &str) -> bool {
+    let Ok(ip) = ip.parse::<IpAddr>() else {
+        return false;
+    };
+    inbound_client_registry().block(ip);
+    true
+}
+
+#[allow(dead_code)]
+pub fn unblock_inbound_client(ip: &str) -> bool {
+    let Ok(ip) = ip.parse::<IpAddr>() else {
+        return false;
+    };
+    inbound_client_registry().unblock(ip);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dropped_clients_remain_visible_as_recent_clients() {
+        let peer_addr: SocketAddr = "203.0.113.10:49152".parse().unwrap();
+        let lease = register_inbound_client(peer_addr);
+        let active_state = inbound_clients_json();
+        assert!(active_state.contains("\"203.0.113.10\""));
+        assert!(active_state.contains("\"active\""));
+
+        drop(lease);
+
+        let recent_state = inbound_clients_json();
+        assert!(recent_state.contains("\"recent\""));
+        assert!(recent_state.contains("\"203.0.113.10\""));
+        assert!(recent_state.contains("\"203.0.113.10:49152\""));
+    }
+}
@@ -1,19 +1,35 @@
 pub mod config;
+pub mod connections;
+pub mod control;
+pub mod inbound_clients;
+pub mod reload;
 pub mod server;
 pub mod telemetry;
 
 mod cli;
-mod direct_access;
+mod dns_server;
 mod error;
 mod http_handler;
+mod listener_access;
 mod privilege;
+mod routing;
 mod socks5_handler;
 mod tcp_relay;
+#[cfg(target_os = "linux")]
+mod transparent;
 mod tun_handler;
 mod tun_helper_client;
+#[cfg(unix)]
+mod unix_inbound;
+#[cfg(unix)]
+mod unix_socket;
 mod yamux_session;
 
+#[cfg(target_os = "linux")]
+pub use crate::transparent::{install_transparent_rules, remove_transparent_rules};
+
 use crate::config::AgentConfig;
+use crate::reload::ConfigReloadReceiver;
 use crate::server::AgentServer;
 use anyhow::Result;
 use tokio_util::sync::CancellationToken;
@@ -28,10 +44,17 @@ pub fn run_tun_helper_service(
     tun_handler::helper_service::run(socket, allowed_uid, log_level)
 }
 
-pub async fn run_agent(config: AgentConfig, shutdown: CancellationToken) -> Result<()> {
+pub async fn run_agent(
+    config: AgentConfig,
+    shutdown: CancellationToken,
+    reloads: ConfigReloadReceiver,
+) -> Result<()> {
     info!("PPAASS Desktop Agent 启动中");
     info!("监听地址：    {}", config.listen_addr);
     info!("代理地址列表：[{}]", config.proxy_addrs.join(", "));
+    if let Some(pre_proxy) = &config.pre_proxy {
+        info!("前置代理：    {:?}", pre_proxy);
+    }
     info!("用户名：      {}", config.username);
     info!("压缩模式：    {}", config.get_compression_mode());
     info!("日志级别：    {}", config.log_level);
@@ -48,7 +71,7 @@ pub async fn run_agent(config: AgentConfig, shutdown: CancellationToken) -> Resu
 
     match AgentServer::new(config).await {
         Ok(server) => {
-            if let Err(err) = server.run(shutdown).await {
+            if let Err(err) = server.run(shutdown, reloads).await {
                 error!("Agent 服务器异常停止：{}", err);
                 return Err(err.into());
             }
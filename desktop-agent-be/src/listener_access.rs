@@ -0,0 +1,202 @@
+//! 本地 HTTP/SOCKS5 监听的访问控制。
+//!
+//! 新连接先按 `allowed_sources` 白名单和运行时客户端黑名单过滤来源地址；
+//! 配置了本地凭据时，SOCKS5 走 RFC 1929 用户名/密码认证，HTTP 校验
+//! `Proxy-Authorization: Basic`。这里的凭据只保护本地入口，和 agent->proxy
+//! 的密钥认证无关。
+
+use crate::config::ListenerAccessConfig;
+use crate::control::constant_time_eq;
+use crate::error::{AgentError, Result};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use common::CommonError;
+use common::routing::RuleSetEntry;
+use hyper::header::HeaderValue;
+use std::net::IpAddr;
+
+#[derive(Debug, Default)]
+pub(crate) struct ListenerAccess {
+    credentials: Option<Credentials>,
+    allowed_sources: Vec<(IpAddr, u8)>,
+}
+
+#[derive(Debug)]
+struct Credentials {
+    username: String,
+    password: String,
+}
+
+impl ListenerAccess {
+    pub(crate) fn new(config: &ListenerAccessConfig) -> Result<Self> {
+        let credentials = match (&config.username, &config.password) {
+            (None, None) => None,
+            // RFC 1929 的用户名和密码长度都在 1..=255 字节。
+            (Some(username), Some(password))
+                if (1..=255).contains(&username.len()) && (1..=255).contains(&password.len()) =>
+            {
+                Some(Credentials {
+                    username: username.clone(),
+                    password: password.clone(),
+                })
+            }
+            _ => {
+                return Err(invalid(
+                    "listener_access.username 与 password 必须同时设置，长度 1–255 字节",
+                ));
+            }
+        };
+
+        let allowed_sources = config
+            .allowed_sources
+            .iter()
+            .map(|source| match RuleSetEntry::parse(source) {
+                Some(RuleSetEntry::Cidr(ip, prefix_len)) => Ok((ip, prefix_len)),
+                _ => Err(invalid(&format!(
+                    "listener_access.allowed_sources 中的来源不是合法 IP/CIDR：{source}"
+                ))),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            credentials,
+            allowed_sources,
+        })
+    }
+
+    /// 来源地址是否在白名单内；未配置白名单时放行全部来源。
+    pub(crate) fn allows_source(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.allowed_sources.is_empty()
+            || self
+                .allowed_sources
+                .iter()
+                .any(|&(network, prefix_len)| cidr_contains(network, prefix_len, ip))
+    }
+
+    pub(crate) fn requires_auth(&self) -> bool {
+        self.credentials.is_some()
+    }
+
+    pub(crate) fn check_password(&self, username: &str, password: &str) -> bool {
+        // 用户名和密码都比较完再合并结果，耗时不透露哪一项不对。
+        self.credentials.as_ref().is_none_or(|credentials| {
+            constant_time_eq(credentials.username.as_bytes(), username.as_bytes())
+                & constant_time_eq(credentials.password.as_bytes(), password.as_bytes())
+        })
+    }
+
+    /// 校验 HTTP `Proxy-Authorization` 头；未配置凭据时始终通过。
+    pub(crate) fn check_proxy_authorization(&self, header: Option<&HeaderValue>) -> bool {
+        if !self.requires_auth() {
+            return true;
+        }
+        let Some(encoded) = header
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split_once(' '))
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("basic"))
+            .map(|(_, encoded)| encoded.trim())
+        else {
+            return false;
+        };
+        let Some(decoded) = STANDARD
+            .decode(encoded)
+            .ok()
+            .and_then(|decoded| String::from_utf8(decoded).ok())
+        else {
+            return false;
+        };
+        decoded
+            .split_once(':')
+            .is_some_and(|(username, password)| self.check_password(username, password))
+    }
+}
+
+fn cidr_contains(network: IpAddr, prefix_len: u8, ip: IpAddr) -> bool {
+    match (network, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) => {
+            let mask = u32::MAX
+                .checked_shl(32 - u32::from(prefix_len))
+                .unwrap_or(0);
+            u32::from(network) & mask == u32::from(ip) & mask
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) => {
+            let mask = u128::MAX
+                .checked_shl(128 - u32::from(prefix_len))
+                .unwrap_or(0);
+            u128::from(network) & mask == u128::from(ip) & mask
+        }
+        _ => false,
+    }
+}
+
+fn invalid(message: &str) -> AgentError {
+    AgentError::Config(CommonError::Config(message.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn access(
+        username: Option<&str>,
+        password: Option<&str>,
+        sources: &[&str],
+    ) -> Result<ListenerAccess> {
+        ListenerAccess::new(&ListenerAccessConfig {
+            username: username.map(str::to_string),
+            password: password.map(str::to_string),
+            allowed_sources: sources.iter().map(|source| source.to_string()).collect(),
+        })
+    }
+
+    #[test]
+    fn allowed_sources_match_cidrs_and_mapped_ipv4() {
+        let restricted = access(None, None, &["127.0.0.1", "192.168.1.0/24", "fd00::/8"]).unwrap();
+
+        assert!(restricted.allows_source("127.0.0.1".parse().unwrap()));
+        assert!(restricted.allows_source("192.168.1.77".parse().unwrap()));
+        assert!(restricted.allows_source("::ffff:192.168.1.9".parse().unwrap()));
+        assert!(restricted.allows_source("fd12::1".parse().unwrap()));
+        assert!(!restricted.allows_source("192.168.2.1".parse().unwrap()));
+        assert!(!restricted.allows_source("::1".parse().unwrap()));
+
+        let open = access(None, None, &[]).unwrap();
+        assert!(open.allows_source("203.0.113.10".parse().unwrap()));
+    }
+
+    #[test]
+    fn proxy_authorization_requires_matching_basic_credentials() {
+        let protected = access(Some("alice"), Some("s3:cret"), &[]).unwrap();
+        let header = |raw: &str| HeaderValue::from_str(raw).unwrap();
+
+        // "alice:s3:cret"，密码本身可以包含冒号。
+        assert!(protected.check_proxy_authorization(Some(&header("Basic YWxpY2U6czM6Y3JldA=="))));
+        assert!(protected.check_proxy_authorization(Some(&header("basic YWxpY2U6czM6Y3JldA=="))));
+        assert!(!protected.check_proxy_authorization(Some(&header("Basic YWxpY2U6d3Jvbmc="))));
+        assert!(!protected.check_proxy_authorization(Some(&header("Bearer YWxpY2U6czM6Y3JldA=="))));
+        assert!(!protected.check_proxy_authorization(None));
+
+        assert!(ListenerAccess::default().check_proxy_authorization(None));
+    }
+
+    #[test]
+    fn password_check_requires_both_fields_to_match_exactly() {
+        let protected = access(Some("alice"), Some("secret"), &[]).unwrap();
+
+        assert!(protected.check_password("alice", "secret"));
+        assert!(!protected.check_password("alice", "secreT"));
+        assert!(!protected.check_password("alice", "secret2"));
+        assert!(!protected.check_password("alice", "secre"));
+        assert!(!protected.check_password("bob", "secret"));
+        assert!(ListenerAccess::default().check_password("anyone", ""));
+    }
+
+    #[test]
+    fn rejects_partial_credentials_and_invalid_sources() {
+        assert!(access(Some("alice"), None, &[]).is_err());
+        assert!(access(Some(""), Some("secret"), &[]).is_err());
+        assert!(access(None, None, &["example.com"]).is_err());
+        assert!(access(None, None, &["10.0.0.0/33"]).is_err());
+    }
+}
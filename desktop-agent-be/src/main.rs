@@ -6,23 +6,37 @@
 
 mod cli;
 mod config;
-mod direct_access;
+mod connections;
+mod control;
+mod dns_server;
 mod error;
 mod http_handler;
+mod inbound_clients;
+mod listener_access;
 mod privilege;
+mod reload;
+mod routing;
 mod server;
 mod socks5_handler;
 mod tcp_relay;
 mod telemetry;
+#[cfg(target_os = "linux")]
+mod transparent;
 mod tun_handler;
 mod tun_helper_client;
+#[cfg(unix)]
+mod unix_inbound;
+#[cfg(unix)]
+mod unix_socket;
 mod yamux_session;
 
-use crate::cli::CliArgs;
+use crate::cli::{CliArgs, Command, CtlAction, CtlArgs};
 use crate::config::AgentConfig;
+use crate::reload::{ConfigReloader, ConfigSource};
 use crate::server::AgentServer;
 use anyhow::Result;
 use clap::Parser;
+use hyper::Method;
 #[cfg(feature = "mimalloc-allocator")]
 use mimalloc::MiMalloc;
 use tokio_util::sync::CancellationToken;
@@ -51,65 +65,22 @@ fn main() -> Result<()> {
         anyhow::bail!("TUN helper service mode is only supported on macOS");
     }
 
-    // 加载配置文件，再用命令行参数覆盖少量运行时选项。
-    // 这样本地调试可临时改 listen/proxy/TUN 参数，而不必修改配置文件。
-    let mut config = AgentConfig::load(&args.config)?;
-
-    // ── 基础参数覆盖 ──────────────────────────────────────────────────────────
-    if let Some(listen) = args.listen {
-        config.listen_addr = listen;
-    }
-    if let Some(proxy) = args.proxy {
-        config.proxy_addrs = vec![proxy];
-    }
-    if let Some(username) = args.username {
-        config.username = username;
-    }
-    if let Some(log_level) = args.log_level {
-        config.log_level = log_level;
-    }
-    if let Some(log_dir) = args.log_dir {
-        config.log_dir = Some(log_dir);
-    }
-    if let Some(log_file) = args.log_file {
-        config.log_file = log_file;
-    }
-    if let Some(compression_mode) = args.compression_mode {
-        config.compression_mode = compression_mode;
-    }
-    if let Some(runtime_threads) = args.runtime_threads {
-        config.runtime_threads = Some(runtime_threads);
+    // 编译规则集是一次性的离线操作，不需要配置文件。
+    if let Some(paths) = &args.compile_rule_set {
+        let count = common::routing::compile_rule_set_file(&paths[0], &paths[1])?;
+        println!("已编译 {count} 条规则：{} -> {}", paths[0], paths[1]);
+        return Ok(());
     }
 
-    // ── TUN 参数覆盖 ──────────────────────────────────────────────────────────
-    if args.tun_enabled {
-        config.tun.enabled = true;
-    }
-    if let Some(tun_name) = args.tun_name {
-        config.tun.name = tun_name;
-    }
-    if let Some(tun_ipv4) = args.tun_ipv4 {
-        config.tun.ipv4 = tun_ipv4;
-    }
-    if let Some(tun_ipv6) = args.tun_ipv6 {
-        config.tun.ipv6 = Some(tun_ipv6);
-    }
-    if let Some(tun_mtu) = args.tun_mtu {
-        config.tun.mtu = tun_mtu;
-    }
-    if let Some(tun_wintun_file) = args.tun_wintun_file {
-        config.tun.wintun_file = Some(tun_wintun_file);
-    }
-    if args.tun_no_helper {
-        config.tun.macos_helper_enabled = false;
-    }
-    if let Some(tun_helper_socket) = args.tun_helper_socket {
-        config.tun.macos_helper_socket = tun_helper_socket;
-    }
-    if args.tun_helper_no_fallback {
-        config.tun.macos_helper_fallback_to_privilege = false;
+    // ctl 只是控制 API 的客户端，不初始化日志，也不启动代理服务。
+    if let Some(Command::Ctl(ctl)) = &args.command {
+        return run_ctl(&args.config, ctl);
     }
 
+    // 加载配置文件，再用命令行参数覆盖少量运行时选项。
+    // 这样本地调试可临时改 listen/proxy/TUN 参数，而不必修改配置文件。
+    let config = load_config(&args)?;
+
     // 如有需要，创建日志目录
     if let Some(ref log_dir) = config.log_dir {
         std::fs::create_dir_all(log_dir)?;
@@ -121,6 +92,11 @@ fn main() -> Result<()> {
         &config.log_level,
     );
 
+    // 透明代理规则的安装/删除是一次性的特权操作，完成后直接退出。
+    if args.install_transparent_rules || args.remove_transparent_rules {
+        return manage_transparent_rules(&args, &config);
+    }
+
     // 构建 Tokio 运行时，线程数可配置
     let mut runtime_builder = tokio::runtime::Builder::new_multi_thread();
     // TUN/netstack 与大量中继任务会形成较深 async 栈，栈大小由配置控制。
@@ -136,13 +112,16 @@ fn main() -> Result<()> {
         info!("PPAASS Desktop Agent 启动中");
         info!("监听地址：    {}", config.listen_addr);
         info!("代理地址列表：[{}]", config.proxy_addrs.join(", "));
+        if let Some(pre_proxy) = &config.pre_proxy {
+            info!("前置代理：    {:?}", pre_proxy);
+        }
         info!("用户名：      {}", config.username);
         info!(
             "UDP 传输模式：{}（TCP 数据始终使用 TCP）",
-            config.transport_mode.as_str()
+            config.effective_transport_mode().as_str()
         );
         if config
-            .transport_mode
+            .effective_transport_mode()
             .uses_native_udp_for(protocol::TransportProtocol::Udp)
         {
             info!(
@@ -166,11 +145,23 @@ fn main() -> Result<()> {
         let shutdown = CancellationToken::new();
         // 关闭信号只触发取消，真正的资源清理由各任务在收到 token 后完成。
         setup_shutdown_signals(&shutdown);
+        let (reloader, reloads) = ConfigReloader::channel();
+        #[cfg(unix)]
+        let config_path = args.config.clone();
+        // SIGHUP 与控制 API 的 POST /reload 都重新读取配置文件并热重载，
+        // 命令行覆盖保持生效，TUN 与现有连接保持不动。
+        let source = ConfigSource::new(reloader, move || load_config(&args));
+        #[cfg(unix)]
+        setup_reload_signal(config_path, source.clone());
 
         match AgentServer::new(config).await {
             Ok(server) => {
                 // AgentServer::run 会根据模式启动 SOCKS/HTTP 或 TUN 转发器。
-                if let Err(err) = server.run(shutdown).await {
+                if let Err(err) = server
+                    .with_config_source(source)
+                    .run(shutdown, reloads)
+                    .await
+                {
                     error!("Agent 服务器异常停止：{}", err);
                     return Err::<(), anyhow::Error>(err.into());
                 }
@@ -185,6 +176,84 @@ fn main() -> Result<()> {
     })
 }
 
+/// 读取配置文件并应用命令行覆盖；SIGHUP 热重载时同样经过这里，覆盖不会丢失。
+#[cfg(target_os = "linux")]
+fn manage_transparent_rules(args: &CliArgs, config: &AgentConfig) -> Result<()> {
+    let transparent = config.transparent.clone().unwrap_or_default();
+    if args.install_transparent_rules {
+        transparent::install_transparent_rules(&transparent)?;
+    } else {
+        transparent::remove_transparent_rules(&transparent)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn manage_transparent_rules(_args: &CliArgs, _config: &AgentConfig) -> Result<()> {
+    anyhow::bail!("透明代理规则只支持 Linux")
+}
+
+fn load_config(args: &CliArgs) -> Result<AgentConfig> {
+    let mut config = AgentConfig::load(&args.config)?;
+
+    // ── 基础参数覆盖 ──────────────────────────────────────────────────────────
+    if let Some(listen) = args.listen.clone() {
+        config.listen_addr = listen;
+    }
+    if let Some(proxy) = args.proxy.clone() {
+        config.proxy_addrs = vec![proxy];
+    }
+    if let Some(username) = args.username.clone() {
+        config.username = username;
+    }
+    if let Some(log_level) = args.log_level.clone() {
+        config.log_level = log_level;
+    }
+    if let Some(log_dir) = args.log_dir.clone() {
+        config.log_dir = Some(log_dir);
+    }
+    if let Some(log_file) = args.log_file.clone() {
+        config.log_file = log_file;
+    }
+    if let Some(compression_mode) = args.compression_mode.clone() {
+        config.compression_mode = compression_mode;
+    }
+    if let Some(runtime_threads) = args.runtime_threads {
+        config.runtime_threads = Some(runtime_threads);
+    }
+
+    // ── TUN 参数覆盖 ──────────────────────────────────────────────────────────
+    if args.tun_enabled {
+        config.tun.enabled = true;
+    }
+    if let Some(tun_name) = args.tun_name.clone() {
+        config.tun.name = tun_name;
+    }
+    if let Some(tun_ipv4) = args.tun_ipv4.clone() {
+        config.tun.ipv4 = tun_ipv4;
+    }
+    if let Some(tun_ipv6) = args.tun_ipv6.clone() {
+        config.tun.ipv6 = Some(tun_ipv6);
+    }
+    if let Some(tun_mtu) = args.tun_mtu {
+        config.tun.mtu = tun_mtu;
+    }
+    if let Some(tun_wintun_file) = args.tun_wintun_file.clone() {
+        config.tun.wintun_file = Some(tun_wintun_file);
+    }
+    if args.tun_no_helper {
+        config.tun.macos_helper_enabled = false;
+    }
+    if let Some(tun_helper_socket) = args.tun_helper_socket.clone() {
+        config.tun.macos_helper_socket = tun_helper_socket;
+    }
+    if args.tun_helper_no_fallback {
+        config.tun.macos_helper_fallback_to_privilege = false;
+    }
+
+    Ok(config)
+}
+
 fn setup_shutdown_signals(shutdown: &CancellationToken) {
     let shutdown_for_ctrl_c = shutdown.clone();
     tokio::spawn(async move {
@@ -203,11 +272,6 @@ fn setup_shutdown_signals(shutdown: &CancellationToken) {
             tokio::signal::unix::SignalKind::terminate(),
             "SIGTERM",
         );
-        setup_unix_shutdown_signal(
-            shutdown,
-            tokio::signal::unix::SignalKind::hangup(),
-            "SIGHUP",
-        );
         setup_unix_shutdown_signal(shutdown, tokio::signal::unix::SignalKind::quit(), "SIGQUIT");
     }
 }
@@ -232,3 +296,82 @@ fn setup_unix_shutdown_signal(
         shutdown.cancel();
     });
 }
+
+#[cfg(unix)]
+fn setup_reload_signal(config_path: String, source: ConfigSource) {
+    let mut signal = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(signal) => signal,
+        Err(err) => {
+            error!("安装 SIGHUP 信号处理器失败：{err}");
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        while signal.recv().await.is_some() {
+            info!("收到 SIGHUP，正在重新加载配置 {config_path}");
+            // 重载结果由 AgentServer 记录日志，这里只等待本次完成再处理下一个信号。
+            let _ = source.reload().await;
+        }
+    });
+}
+
+fn run_ctl(config_path: &str, ctl: &CtlArgs) -> Result<()> {
+    // 显式给出 --addr 时配置文件可以不存在，例如在另一台机器的 SSH 会话里操作。
+    let control = match AgentConfig::load(config_path) {
+        Ok(config) => config.control,
+        Err(_) if ctl.addr.is_some() => None,
+        Err(err) => return Err(err),
+    };
+    let addr = ctl
+        .addr
+        .clone()
+        .or_else(|| control.as_ref().map(|control| control.listen.clone()))
+        .ok_or_else(|| {
+            anyhow::anyhow!("配置文件未启用 [control]，请用 --addr 指定控制 API 地址")
+        })?;
+    let token = ctl
+        .token
+        .clone()
+        .or_else(|| control.and_then(|control| control.token));
+
+    let (method, path, body) = match &ctl.action {
+        CtlAction::Status => (Method::GET, "/status".to_string(), None),
+        CtlAction::Traffic => (Method::GET, "/traffic".to_string(), None),
+        CtlAction::Dns => (Method::GET, "/dns".to_string(), None),
+        CtlAction::Connections => (Method::GET, "/connections".to_string(), None),
+        CtlAction::Close { id } => (Method::DELETE, format!("/connections/{id}"), None),
+        CtlAction::Reload => (Method::POST, "/reload".to_string(), None),
+        CtlAction::Mode { mode } => (
+            Method::PUT,
+            "/routing/mode".to_string(),
+            Some(serde_json::json!({ "mode": mode }).to_string()),
+        ),
+        CtlAction::Group { name } => (
+            Method::PUT,
+            "/routing/proxy-group".to_string(),
+            Some(serde_json::json!({ "group": name }).to_string()),
+        ),
+        CtlAction::Metrics => (Method::GET, "/metrics".to_string(), None),
+    };
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let (status, body) = runtime.block_on(control::request(
+        &addr,
+        token.as_deref(),
+        method,
+        &path,
+        body,
+    ))?;
+    // JSON 响应格式化后输出，/metrics 的文本原样输出。
+    let body = match serde_json::from_str::<serde_json::Value>(&body) {
+        Ok(value) => serde_json::to_string_pretty(&value)?,
+        Err(_) => body,
+    };
+    if !status.is_success() {
+        anyhow::bail!("控制 API 返回 {status}：{body}");
+    }
+    println!("{}", body.trim_end());
+    Ok(())
+}
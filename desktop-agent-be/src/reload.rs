@@ -0,0 +1,284 @@
+//! 运行中重新加载 agent 配置。
+//!
+//! 分流规则、proxy 地址、认证身份、超时和日志级别原地替换进 `OutboundRouter`
+//! 与各会话管理器共享的 `Arc`，TUN 设备、系统路由、DNS 接管和已建立的连接都
+//! 保持不动。监听地址、TUN 等需要重建资源的字段只在 [`ReloadReport`] 中报告；
+//! 启用 TUN 时 proxy 端点也属于这一类，重载后继续使用启动时的端点。
+
+use crate::config::AgentConfig;
+use crate::error::{AgentError, Result};
+use crate::http_handler::UpstreamPool;
+use crate::listener_access::ListenerAccess;
+use crate::routing::OutboundRouter;
+use crate::telemetry;
+use parking_lot::RwLock;
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{error, info, warn};
+
+/// 向运行中的 `AgentServer` 提交新配置的句柄，可被 SIGHUP 处理器和 UI 持有。
+#[derive(Clone)]
+pub struct ConfigReloader {
+    tx: mpsc::UnboundedSender<ReloadRequest>,
+}
+
+/// 重新读取配置文件并提交热重载，SIGHUP 处理器与控制 API 共用。
+#[derive(Clone)]
+pub struct ConfigSource {
+    reloader: ConfigReloader,
+    load: Arc<dyn Fn() -> anyhow::Result<AgentConfig> + Send + Sync>,
+}
+
+/// `AgentServer::run` 消费的重载请求队列。
+pub struct ConfigReloadReceiver {
+    rx: mpsc::UnboundedReceiver<ReloadRequest>,
+}
+
+pub(crate) struct ReloadRequest {
+    pub(crate) config: AgentConfig,
+    reply: oneshot::Sender<Result<ReloadReport>>,
+}
+
+/// 一次重载的结果。
+#[derive(Debug, Clone, Default)]
+pub struct ReloadReport {
+    /// 已改动但要重启才能生效的配置字段，例如 `listen_addr`、`tun`。
+    #[allow(dead_code)]
+    pub restart_required: Vec<&'static str>,
+}
+
+impl ConfigReloader {
+    pub fn channel() -> (Self, ConfigReloadReceiver) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Self { tx }, ConfigReloadReceiver { rx })
+    }
+
+    pub async fn reload(&self, config: AgentConfig) -> Result<ReloadReport> {
+        self.submit(config)?.await.map_err(|_| stopped())?
+    }
+
+    /// 在非异步线程中提交并等待结果，供 UI 的同步命令使用。
+    #[allow(dead_code)]
+    pub fn blocking_reload(&self, config: AgentConfig) -> Result<ReloadReport> {
+        self.submit(config)?
+            .blocking_recv()
+            .map_err(|_| stopped())?
+    }
+
+    fn submit(&self, config: AgentConfig) -> Result<oneshot::Receiver<Result<ReloadReport>>> {
+        let (reply, result) = oneshot::channel();
+        self.tx
+            .send(ReloadRequest { config, reply })
+            .map_err(|_| stopped())?;
+        Ok(result)
+    }
+}
+
+impl ConfigSource {
+    /// `load` 每次调用都重新读取配置，命令行覆盖也应在其中应用。
+    pub fn new(
+        reloader: ConfigReloader,
+        load: impl Fn() -> anyhow::Result<AgentConfig> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            reloader,
+            load: Arc::new(load),
+        }
+    }
+
+    pub async fn reload(&self) -> Result<ReloadReport> {
+        // 读取失败时还没有提交到 AgentServer，需要在这里记录日志。
+        let config = (self.load)().map_err(|e| {
+            error!("读取配置失败，继续使用当前配置：{e:#}");
+            AgentError::Reload(format!("读取配置失败：{e:#}"))
+        })?;
+        self.reloader.reload(config).await
+    }
+}
+
+impl ConfigReloadReceiver {
+    pub(crate) async fn recv(&mut self) -> Option<ReloadRequest> {
+        self.rx.recv().await
+    }
+}
+
+impl ReloadRequest {
+    pub(crate) fn respond(self, result: Result<ReloadReport>) {
+        let _ = self.reply.send(result);
+    }
+}
+
+/// 把 `next` 中可热重载的部分应用到运行中的状态，`current` 是启动时的配置。
+///
+/// 依次校验本地入口配置、替换分流规则与分组配置、清空上游连接池、替换入口访问控制；
+/// 任一步失败都不会留下半更新的状态，继续使用当前配置。
+pub(crate) async fn apply_reload(
+    current: &AgentConfig,
+    next: &AgentConfig,
+    router: &OutboundRouter,
+    access: &RwLock<Arc<ListenerAccess>>,
+    upstream_pool: &UpstreamPool,
+) -> Result<ReloadReport> {
+    let restart_required = current.restart_required_changes(next);
+    // TUN 的旁路路由只为启动时的端点安装，切换过去会让 proxy 连接回流进 TUN。
+    let applied = if restart_required.contains(&"proxy_addrs") {
+        next.with_proxy_endpoints_of(current)
+    } else {
+        next.clone()
+    };
+    if let Err(err) = apply_reloadable(&applied, router, access, upstream_pool).await {
+        error!("配置热重载失败，继续使用当前配置：{}", err);
+        return Err(err);
+    }
+    telemetry::set_log_level(&next.log_level);
+    if restart_required.is_empty() {
+        info!("配置已热重载");
+    } else {
+        warn!(
+            "配置已热重载，以下字段需要重启后生效：{}",
+            restart_required.join(", ")
+        );
+    }
+    Ok(ReloadReport { restart_required })
+}
+
+async fn apply_reloadable(
+    next: &AgentConfig,
+    router: &OutboundRouter,
+    access: &RwLock<Arc<ListenerAccess>>,
+    upstream_pool: &UpstreamPool,
+) -> Result<()> {
+    // 先校验本地入口配置，避免分流规则已替换而入口凭据无效的半更新状态。
+    let next_access = ListenerAccess::new(&next.listener_access)?;
+    router.reload(Arc::new(next.clone())).await?;
+    // 池里的上游连接是按旧 proxy 配置建立的。
+    upstream_pool.clear();
+    *access.write() = Arc::new(next_access);
+    Ok(())
+}
+
+fn stopped() -> AgentError {
+    AgentError::Reload("agent 已停止".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::{Address, TransportProtocol};
+
+    const CONFIG: &str = r#"
+listen_addr = "127.0.0.1:10080"
+proxy_addrs = ["127.0.0.1:8080"]
+username = "user1"
+private_key_path = "keys/user1.pem"
+"#;
+
+    const TUN: &str = "\n[tun]\nenabled = true\nipv4 = \"10.1.0.1/24\"\n";
+
+    struct Running {
+        config: AgentConfig,
+        router: OutboundRouter,
+        access: RwLock<Arc<ListenerAccess>>,
+        upstream_pool: UpstreamPool,
+    }
+
+    impl Running {
+        fn start(config: AgentConfig) -> Self {
+            Self {
+                router: OutboundRouter::new(Arc::new(config.clone())).unwrap(),
+                access: RwLock::new(Arc::new(
+                    ListenerAccess::new(&config.listener_access).unwrap(),
+                )),
+                upstream_pool: UpstreamPool::default(),
+                config,
+            }
+        }
+
+        async fn reload(&self, next: &AgentConfig) -> Result<ReloadReport> {
+            apply_reload(
+                &self.config,
+                next,
+                &self.router,
+                &self.access,
+                &self.upstream_pool,
+            )
+            .await
+        }
+
+        fn route_name(&self) -> String {
+            let target = Address::Domain {
+                host: "example.com".to_string(),
+                port: 443,
+            };
+            self.router
+                .route(&target, TransportProtocol::Tcp)
+                .name()
+                .to_string()
+        }
+    }
+
+    fn config(extra: &str) -> AgentConfig {
+        toml::from_str(&(CONFIG.to_owned() + extra)).unwrap()
+    }
+
+    const DIRECT_ROUTING: &str = "\n[routing]\nfinal = \"direct\"\n";
+    const LOCAL_CREDENTIALS: &str =
+        "\n[listener_access]\nusername = \"alice\"\npassword = \"secret\"\n";
+
+    #[tokio::test]
+    async fn invalid_config_is_rejected_and_current_config_kept() {
+        let running = Running::start(config(""));
+        let access = running.access.read().clone();
+
+        // 分流规则引用了不存在的分组。
+        let next = config("\n[routing]\nfinal = \"missing\"\n");
+        assert!(running.reload(&next).await.is_err());
+
+        assert_eq!(running.route_name(), "proxy");
+        assert_eq!(running.upstream_pool.generation(), 0);
+        assert!(Arc::ptr_eq(&running.access.read(), &access));
+    }
+
+    #[tokio::test]
+    async fn failed_step_leaves_later_steps_unapplied() {
+        let running = Running::start(config(""));
+        let access = running.access.read().clone();
+
+        // 入口凭据无效：分流规则虽然合法也不能先行替换。
+        let next =
+            config(&(DIRECT_ROUTING.to_owned() + "\n[listener_access]\nusername = \"alice\"\n"));
+        assert!(running.reload(&next).await.is_err());
+        assert_eq!(running.route_name(), "proxy");
+        assert_eq!(running.upstream_pool.generation(), 0);
+
+        // 分流规则无效：入口凭据合法，但连接池与访问控制都保持原样。
+        let next = config(&(LOCAL_CREDENTIALS.to_owned() + "\n[routing]\nfinal = \"missing\"\n"));
+        assert!(running.reload(&next).await.is_err());
+        assert_eq!(running.upstream_pool.generation(), 0);
+        assert!(Arc::ptr_eq(&running.access.read(), &access));
+
+        let next = config(&(DIRECT_ROUTING.to_owned() + LOCAL_CREDENTIALS));
+        let report = running.reload(&next).await.unwrap();
+        assert!(report.restart_required.is_empty());
+        assert_eq!(running.route_name(), "direct");
+        assert_eq!(running.upstream_pool.generation(), 1);
+        assert!(running.access.read().requires_auth());
+    }
+
+    #[tokio::test]
+    async fn restart_required_fields_are_reported_not_applied() {
+        let running = Running::start(config(TUN));
+
+        let mut next = config(TUN);
+        next.listen_addr = "127.0.0.1:10081".to_string();
+        next.proxy_addrs = vec!["10.0.0.1:8080".to_string()];
+        next.username = "user2".to_string();
+        let report = running.reload(&next).await.unwrap();
+
+        assert_eq!(report.restart_required, vec!["listen_addr", "proxy_addrs"]);
+        // TUN 旁路路由只覆盖启动时的端点，新地址不能生效；认证身份照常替换。
+        let applied = running.router.default_group().tcp_sessions().config();
+        assert_eq!(applied.proxy_addrs, vec!["127.0.0.1:8080"]);
+        assert_eq!(applied.username, "user2");
+    }
+}
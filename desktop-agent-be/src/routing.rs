@@ -0,0 +1,209 @@
+//! 分流出口管理。
+//!
+//! agent 的所有入口（HTTP、SOCKS、TUN TCP/UDP）都先把目标转换成 `protocol::Address`，
+//! 再由 [`OutboundRouter`] 按 `[routing]` 规则选择出口：本机直连、拒绝，或某个
+//! proxy 分组的传输会话管理器。每个分组拥有独立的 proxy 地址与认证身份。
+
+use crate::config::AgentConfig;
+use crate::error::Result;
+use crate::yamux_session::{ProxySelector, YamuxSessionManager};
+use common::routing::{DEFAULT_PROXY_GROUP, PacScript, RoutingSource};
+use common::{BindInterface, DirectAccessMode, RoutingPolicy, SourceProcess};
+use protocol::{Address, TransportProtocol};
+use std::net::IpAddr;
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+pub use common::routing::{RoutingOverrides, address_to_string};
+
+/// 一个 proxy 分组的传输会话管理器。
+pub type ProxyGroup = common::routing::ProxyGroup<YamuxSessionManager>;
+/// 一次分流的结果。
+pub type Route<'a> = common::routing::Route<'a, YamuxSessionManager>;
+/// 分流结果以及命中规则的配置原文，供连接列表展示。
+pub type RouteDecision<'a> = common::routing::ResolvedDecision<'a, YamuxSessionManager>;
+
+/// 桌面端 router：分流与分组选择由 `common::routing::OutboundRouter` 完成，
+/// 这里负责按 `AgentConfig` 创建、重载各分组的传输会话管理器。
+pub struct OutboundRouter {
+    routes: common::routing::OutboundRouter<YamuxSessionManager>,
+}
+
+impl OutboundRouter {
+    pub fn new(config: Arc<AgentConfig>) -> Result<Self> {
+        let mut groups = vec![new_group(
+            DEFAULT_PROXY_GROUP,
+            common::routing::OUTBOUND_PROXY,
+            config.clone(),
+        )];
+        for group in &config.proxy_groups {
+            info!(
+                "proxy 分组 {}：地址=[{}] 用户名={}",
+                group.name,
+                group.proxy_addrs.join(", "),
+                group.username
+            );
+            groups.push(new_group(
+                groups.len(),
+                &group.name,
+                Arc::new(config.for_proxy_group(group)),
+            ));
+        }
+
+        Ok(Self {
+            routes: common::routing::OutboundRouter::new(routing_source(&config), groups)?,
+        })
+    }
+
+    /// 热重载分流规则与各分组的 proxy 配置。
+    ///
+    /// 分组按名称对应，增删分组需要重启；新规则引用了不存在的分组时整体失败，
+    /// 继续使用旧配置。
+    pub async fn reload(&self, config: Arc<AgentConfig>) -> Result<()> {
+        let mut group_configs = vec![config.clone()];
+        for group in &self.groups()[DEFAULT_PROXY_GROUP + 1..] {
+            match config
+                .proxy_groups
+                .iter()
+                .find(|candidate| candidate.name.trim() == group.name())
+            {
+                Some(group_config) => {
+                    group_configs.push(Arc::new(config.for_proxy_group(group_config)));
+                }
+                None => {
+                    warn!(
+                        "proxy 分组 {} 已从配置中删除，重启前沿用原配置",
+                        group.name()
+                    );
+                    group_configs.push(group.tcp_sessions().config());
+                }
+            }
+        }
+
+        self.routes.reload(routing_source(&config))?;
+        for (group, group_config) in self.groups().iter().zip(group_configs) {
+            reload_group(group, group_config).await;
+        }
+        Ok(())
+    }
+
+    /// 为目标地址选择出口，`network` 是该连接的传输协议。
+    pub fn route(&self, address: &Address, network: TransportProtocol) -> Route<'_> {
+        self.routes.route(address, network)
+    }
+
+    /// 为目标选择出口并给出命中的规则，见 [`common::routing::OutboundRouter::decide`]。
+    pub fn decide(
+        &self,
+        address: &Address,
+        network: TransportProtocol,
+        domain: Option<&str>,
+        process: Option<&SourceProcess>,
+    ) -> RouteDecision<'_> {
+        self.routes.decide(address, network, domain, process)
+    }
+
+    /// 运行中覆盖访问模式；`None` 恢复配置文件中的设置。
+    pub fn set_direct_access_mode(&self, mode: Option<DirectAccessMode>) -> Result<()> {
+        Ok(self.routes.set_direct_access_mode(mode)?)
+    }
+
+    /// 让原本走默认分组的流量改用指定分组；`None` 恢复默认分组。
+    pub fn select_proxy_group(&self, name: Option<&str>) -> Result<()> {
+        Ok(self.routes.select_proxy_group(name)?)
+    }
+
+    /// 当前生效的运行时覆盖项。
+    pub fn overrides(&self) -> RoutingOverrides {
+        self.routes.overrides()
+    }
+
+    pub fn policy(&self) -> Arc<RoutingPolicy> {
+        self.routes.policy()
+    }
+
+    /// 当前分流规则对应的 PAC 脚本。
+    pub fn pac_script(&self) -> Arc<PacScript> {
+        self.routes.pac_script()
+    }
+
+    /// 顶层配置组成的默认分组；DNS proxy 等内部目标固定使用它。
+    pub fn default_group(&self) -> &ProxyGroup {
+        self.routes.default_group()
+    }
+
+    pub fn groups(&self) -> &[ProxyGroup] {
+        self.routes.groups()
+    }
+
+    /// 启动各分组的后台探测：多地址分组的 proxy 健康探测，以及自动传输模式下
+    /// 已回退 slot 的原生 UDP 恢复探测。
+    pub fn spawn_background_probes(&self, shutdown: CancellationToken) {
+        for group in self.groups() {
+            // TCP/UDP manager 共享选择器，探测一次即可；TUN 绑定物理出口后
+            // 两个 manager 的 bind 信息一致。
+            group
+                .tcp_sessions()
+                .spawn_proxy_health_checks(group.name(), shutdown.clone());
+            group
+                .udp_sessions()
+                .spawn_auto_udp_recovery(group.name(), shutdown.clone());
+        }
+    }
+
+    /// TUN 模式下把所有分组的 proxy 连接固定到物理出口，避免回流进 TUN。
+    pub fn set_proxy_bind(&self, ip: Option<IpAddr>, interface: Option<BindInterface>) {
+        for group in self.groups() {
+            for sessions in [group.tcp_sessions(), group.udp_sessions()] {
+                sessions.set_proxy_bind_ip(ip);
+                sessions.set_proxy_bind_interface(interface.clone());
+            }
+        }
+    }
+
+    /// Linux TUN 策略路由模式下给所有分组新建的 proxy 连接打上 SO_MARK。
+    pub fn set_proxy_fwmark(&self, fwmark: Option<u32>) {
+        for group in self.groups() {
+            for sessions in [group.tcp_sessions(), group.udp_sessions()] {
+                sessions.set_proxy_fwmark(fwmark);
+            }
+        }
+    }
+}
+
+fn new_group(index: usize, name: &str, config: Arc<AgentConfig>) -> ProxyGroup {
+    // TCP 始终使用 direct framed TCP；UDP 根据 transport_mode 选择
+    // 原生加密 UDP 会话池或 raw TCP 上的 Yamux session。两者共享同一个
+    // proxy 地址选择器。
+    let selector = Arc::new(ProxySelector::new(&config));
+    ProxyGroup::new(
+        index,
+        name,
+        Arc::new(YamuxSessionManager::new(config.clone(), selector.clone())),
+        Arc::new(YamuxSessionManager::new_udp(config, selector)),
+    )
+}
+
+async fn reload_group(group: &ProxyGroup, config: Arc<AgentConfig>) {
+    // 地址与选择策略不变时沿用选择器，保留已测得的 RTT 与可用状态。
+    let current = group.tcp_sessions().selector();
+    let selector = if current.matches(&config) {
+        current
+    } else {
+        Arc::new(ProxySelector::new(&config))
+    };
+    group
+        .tcp_sessions()
+        .reload(config.clone(), selector.clone())
+        .await;
+    group.udp_sessions().reload(config, selector).await;
+}
+
+fn routing_source(config: &AgentConfig) -> RoutingSource {
+    RoutingSource {
+        routing: config.routing.clone(),
+        direct_access: config.direct_access.clone(),
+        geoip: config.geoip.clone(),
+    }
+}
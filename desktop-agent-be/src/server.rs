@@ -1,20 +1,32 @@
 //! Desktop Agent 本地服务层。
 //!
-//! 这一层负责监听本地端口，自动识别 SOCKS5/HTTP 客户端，并在需要时并行启动
-//! TUN 模式。真正的目标连接不会在这里建立，而是交给传输会话管理器获取
-//! 已认证的 agent->proxy 流，或由 `DirectAccessChecker` 决定直连。
+//! 这一层负责监听本地端口（以及可选的 Unix socket），按来源白名单、客户端黑名单过滤连接
+//! 并自动识别 SOCKS4/4a、SOCKS5 与 HTTP 客户端，在需要时并行启动
+//! 本地 DNS 服务、控制 API、Linux 透明代理入口与 TUN 模式。真正的目标连接不会在这里建立，而是交给传输会话管理器获取
+//! 已认证的 agent->proxy 流，或由 `OutboundRouter` 按分流规则决定直连/拒绝。
 
 use crate::config::AgentConfig;
-use crate::direct_access::DirectAccessChecker;
+use crate::control::ControlServer;
+use crate::dns_server::DnsServer;
 use crate::error::Result;
-use crate::http_handler::handle_http_connection;
-use crate::socks5_handler::handle_socks5_connection;
+use crate::http_handler::{UpstreamPool, handle_http_connection};
+use crate::inbound_clients::{is_inbound_client_blocked, register_inbound_client};
+use crate::listener_access::ListenerAccess;
+use crate::reload::{ConfigReloadReceiver, ConfigSource, ReloadReport, apply_reload};
+use crate::routing::OutboundRouter;
+use crate::socks5_handler::{handle_socks4_connection, handle_socks5_connection};
+#[cfg(target_os = "linux")]
+use crate::transparent::TransparentProxy;
 use crate::tun_handler::run_tun_mode;
-use crate::yamux_session::YamuxSessionManager;
+#[cfg(unix)]
+use crate::unix_inbound::UnixInbound;
 use common::{DEFAULT_TCP_LISTEN_BACKLOG, bind_tcp_listener_with_backlog, spawn_guarded};
+use parking_lot::RwLock;
+use std::fmt;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::net::TcpStream;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::task::JoinSet;
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, instrument, warn};
@@ -22,37 +34,62 @@ use tracing::{debug, error, info, instrument, warn};
 const TUN_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(4);
 
 pub struct AgentServer {
-    // 全局只读配置；连接处理任务通过 Arc 克隆读取。
+    // 启动时的配置；热重载只替换 router 内的快照，需要重启的字段仍以它为准。
     config: Arc<AgentConfig>,
-    // TCP 语义的 proxy 传输管理器，供 HTTP CONNECT、SOCKS CONNECT、TUN TCP 使用。
-    tcp_sessions: Arc<YamuxSessionManager>,
-    // UDP 语义的 proxy 传输管理器，供 SOCKS UDP、TUN UDP、DNS proxy 使用。
-    udp_sessions: Arc<YamuxSessionManager>,
-    // 直连规则：命中后绕过 proxy，直接使用本机网络出口连接目标。
-    direct_access_checker: Arc<DirectAccessChecker>,
+    // 分流规则与各 proxy 分组的传输会话管理器。
+    router: Arc<OutboundRouter>,
+    // 本地入口的来源白名单与凭据，热重载时整体替换；Unix socket 入口共享同一份。
+    access: Arc<RwLock<Arc<ListenerAccess>>>,
+    // 普通 HTTP 转发的上游连接池，TCP 与 Unix socket 入口共享；热重载时清空。
+    upstream_pool: Arc<UpstreamPool>,
+    // 控制 API 的 POST /reload 用它重新读取配置文件。
+    config_source: Option<ConfigSource>,
 }
 
 impl AgentServer {
     #[instrument(skip(config))]
     pub async fn new(config: AgentConfig) -> Result<Self> {
-        // 直连规则在启动时解析成运行时结构，连接处理路径只做快速匹配。
-        let direct_access_checker = Arc::new(DirectAccessChecker::new(&config.direct_access));
+        #[cfg(not(target_os = "linux"))]
+        if config.transparent.is_some() {
+            return Err(common::CommonError::Config(
+                "[transparent] 透明代理入口只支持 Linux".to_string(),
+            )
+            .into());
+        }
+        #[cfg(not(unix))]
+        if config.unix_listener.is_some() {
+            return Err(common::CommonError::Config(
+                "[unix_listener] Unix socket 入口只支持 Unix 系统".to_string(),
+            )
+            .into());
+        }
         let config = Arc::new(config);
-        // TCP 始终使用 direct framed TCP；UDP 根据 transport_mode 选择
-        // 原生加密 UDP 会话池或 raw TCP 上的 Yamux session。
-        let tcp_sessions = Arc::new(YamuxSessionManager::new(config.clone()));
-        let udp_sessions = Arc::new(YamuxSessionManager::new_udp(config.clone()));
+        let router = Arc::new(OutboundRouter::new(config.clone())?);
+        let access = Arc::new(RwLock::new(Arc::new(ListenerAccess::new(
+            &config.listener_access,
+        )?)));
 
         Ok(Self {
             config,
-            tcp_sessions,
-            udp_sessions,
-            direct_access_checker,
+            router,
+            access,
+            upstream_pool: Arc::new(UpstreamPool::default()),
+            config_source: None,
         })
     }
 
-    #[instrument(skip(self))]
-    pub async fn run(self, shutdown: CancellationToken) -> Result<()> {
+    /// 提供重新读取配置文件的来源，控制 API 才能响应 `POST /reload`。
+    pub fn with_config_source(mut self, source: ConfigSource) -> Self {
+        self.config_source = Some(source);
+        self
+    }
+
+    #[instrument(skip(self, reloads))]
+    pub async fn run(
+        self,
+        shutdown: CancellationToken,
+        mut reloads: ConfigReloadReceiver,
+    ) -> Result<()> {
         // 本地 HTTP/SOCKS 入口始终启动。TUN 打开时作为额外入口并行运行，
         // 这样手动配置浏览器代理和系统 TUN 两种模式不会互相挤掉。
         let listener = bind_tcp_listener_with_backlog(
@@ -61,6 +98,49 @@ impl AgentServer {
         )?;
         info!("Agent 服务器正在监听 {}", self.config.listen_addr);
 
+        #[cfg(unix)]
+        if let Some(unix_config) = &self.config.unix_listener {
+            // PAC 与 UDP ASSOCIATE 回复需要一个 IP 地址，Unix 客户端使用 TCP 监听端口的回环地址。
+            let port = listener.local_addr()?.port();
+            let unix_inbound = UnixInbound::bind(
+                unix_config,
+                SocketAddr::from(([127, 0, 0, 1], port)),
+                self.router.clone(),
+                self.access.clone(),
+                self.upstream_pool.clone(),
+            )?;
+            spawn_guarded("desktop unix inbound", unix_inbound.run(shutdown.clone()));
+        }
+
+        self.router.spawn_background_probes(shutdown.clone());
+
+        if let Some(dns_config) = &self.config.dns_server {
+            let dns_server =
+                DnsServer::bind(dns_config, self.router.clone(), self.access.clone()).await?;
+            spawn_guarded("desktop dns server", dns_server.run(shutdown.clone()));
+        }
+
+        if let Some(control_config) = &self.config.control {
+            let control = ControlServer::bind(
+                control_config,
+                self.config.clone(),
+                self.router.clone(),
+                self.config_source.clone(),
+            )
+            .await?;
+            spawn_guarded("desktop control api", control.run(shutdown.clone()));
+        }
+
+        #[cfg(target_os = "linux")]
+        if let Some(transparent_config) = &self.config.transparent {
+            let transparent =
+                TransparentProxy::bind(transparent_config, self.router.clone()).await?;
+            spawn_guarded(
+                "desktop transparent proxy",
+                transparent.run(shutdown.clone()),
+            );
+        }
+
         let mut tun_tasks = JoinSet::new();
         let mut tun_task_running = false;
         if self.config.tun.enabled {
@@ -69,19 +149,16 @@ impl AgentServer {
                 self.config.listen_addr
             );
             let tun_cfg = self.config.tun.clone();
-            let transport_mode = self.config.transport_mode;
-            let proxy_addrs = self.config.proxy_addrs.clone();
-            let tcp_sessions = self.tcp_sessions.clone();
-            let udp_sessions = self.udp_sessions.clone();
-            let direct_access_checker = self.direct_access_checker.clone();
+            let transport_mode = self.config.effective_transport_mode();
+            // 前置代理模式下物理连接拨向前置代理，TUN 旁路也要保留给它。
+            let proxy_addrs = self.config.upstream_endpoints();
+            let router = self.router.clone();
             let tun_shutdown = shutdown.clone();
             tun_tasks.spawn(run_tun_mode(
                 tun_cfg,
                 transport_mode,
                 proxy_addrs,
-                tcp_sessions,
-                udp_sessions,
-                direct_access_checker,
+                router,
                 tun_shutdown,
             ));
             tun_task_running = true;
@@ -93,6 +170,10 @@ impl AgentServer {
                     info!("收到关闭信号，停止监听");
                     break;
                 }
+                Some(request) = reloads.recv() => {
+                    let result = self.reload(&request.config).await;
+                    request.respond(result);
+                }
                 tun_result = tun_tasks.join_next(), if tun_task_running => {
                     tun_task_running = false;
                     match tun_result {
@@ -112,6 +193,11 @@ impl AgentServer {
                 accept_result = listener.accept() => {
                     match accept_result {
                         Ok((stream, addr)) => {
+                            let access = self.access.read().clone();
+                            if is_inbound_client_blocked(addr.ip()) || !access.allows_source(addr.ip()) {
+                                debug!("拒绝来自 {} 的连接：来源不在白名单内或已被拉黑", addr);
+                                continue;
+                            }
                             debug!("接受来自 {} 的连接", addr);
                             // 本地浏览器到 agent 的连接多为 HTTP CONNECT/SOCKS 隧道。
                             // 关闭 Nagle 可以避免小的 TLS/HTTP2 控制帧在本地入口被延迟合并，
@@ -119,16 +205,24 @@ impl AgentServer {
                             if let Err(err) = stream.set_nodelay(true) {
                                 debug!("设置本地入口 TCP_NODELAY 失败，继续使用默认行为：{err}");
                             }
-                            // 每个客户端连接独立处理，复用对应协议的 Yamux session 管理器和直连规则。
-                            let tcp_sessions = self.tcp_sessions.clone();
-                            let udp_sessions = self.udp_sessions.clone();
-                            let direct_checker = self.direct_access_checker.clone();
+                            // 每个客户端连接独立处理，复用分流规则和各分组的 Yamux session 管理器。
+                            let router = self.router.clone();
+                            let pool = self.upstream_pool.clone();
+                            let client = register_inbound_client(addr);
                             spawn_guarded("desktop inbound connection", async move {
-                                if let Err(e) =
-                                    handle_connection(stream, tcp_sessions, udp_sessions, direct_checker).await
-                                {
-                                    error!("处理连接时出错：{}", e);
+                                // 拉黑客户端时取消令牌，丢弃 handler future 即关闭两端连接。
+                                let cancel = client.cancel_token();
+                                tokio::select! {
+                                    result = handle_tcp_connection(stream, addr, router, access, pool) => {
+                                        if let Err(e) = result {
+                                            error!("处理连接时出错：{}", e);
+                                        }
+                                    }
+                                    _ = cancel.cancelled() => {
+                                        info!("客户端 {} 已被拉黑，断开连接", addr);
+                                    }
                                 }
+                                drop(client);
                             });
                         }
                         Err(e) => {
@@ -159,30 +253,95 @@ impl AgentServer {
 
         Ok(())
     }
+
+    async fn reload(&self, next: &AgentConfig) -> Result<ReloadReport> {
+        apply_reload(
+            &self.config,
+            next,
+            &self.router,
+            &self.access,
+            &self.upstream_pool,
+        )
+        .await
+    }
+}
+
+/// 本地入口连接的来源，用于日志与活动连接列表。
+#[derive(Debug, Clone)]
+pub(crate) enum InboundPeer {
+    Tcp(SocketAddr),
+    /// Unix socket 客户端没有 IP 地址，系统支持时记录对端进程号。
+    #[cfg(unix)]
+    Unix(Option<i32>),
+}
+
+impl fmt::Display for InboundPeer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{addr}"),
+            #[cfg(unix)]
+            Self::Unix(Some(pid)) => write!(f, "unix(pid {pid})"),
+            #[cfg(unix)]
+            Self::Unix(None) => f.write_str("unix"),
+        }
+    }
 }
 
-#[instrument(skip(stream, tcp_sessions, udp_sessions, direct_checker))]
-async fn handle_connection(
-    stream: TcpStream,
-    tcp_sessions: Arc<YamuxSessionManager>,
-    udp_sessions: Arc<YamuxSessionManager>,
-    direct_checker: Arc<DirectAccessChecker>,
+#[instrument(skip(stream, router, access, pool))]
+async fn handle_tcp_connection(
+    stream: tokio::net::TcpStream,
+    peer_addr: SocketAddr,
+    router: Arc<OutboundRouter>,
+    access: Arc<ListenerAccess>,
+    pool: Arc<UpstreamPool>,
 ) -> Result<()> {
     // 通过窥探第一个字节来检测协议类型。
-    // 同一个 listen_addr 同时服务 SOCKS5 和 HTTP 代理，减少用户配置成本。
+    // 同一个 listen_addr 同时服务 SOCKS4/5 和 HTTP 代理，减少用户配置成本。
+    // peek 不消费字节，后续处理器仍能从完整流开始解析。
     let mut buffer = [0u8; 1];
     stream.peek(&mut buffer).await?;
+    // PAC 中的代理地址使用客户端实际连到的本地地址，监听 0.0.0.0 时也能给出可达地址。
+    let local_addr = stream.local_addr()?;
+    let local_addr = SocketAddr::new(local_addr.ip().to_canonical(), local_addr.port());
+    handle_connection(
+        stream,
+        buffer[0],
+        InboundPeer::Tcp(peer_addr),
+        local_addr,
+        router,
+        access,
+        pool,
+    )
+    .await
+}
 
-    // peek 不消费字节，后续 SOCKS5/HTTP 处理器仍能从完整流开始解析。
-    match buffer[0] {
+/// 按首字节把连接交给对应的协议处理器，`first_byte` 必须仍留在 `stream` 中。
+///
+/// `local_addr` 是客户端连到的本地地址，用于 PAC 中的代理地址与 UDP ASSOCIATE 回复；
+/// `pool` 是普通 HTTP 转发的上游连接池。
+pub(crate) async fn handle_connection<S>(
+    stream: S,
+    first_byte: u8,
+    peer: InboundPeer,
+    local_addr: SocketAddr,
+    router: Arc<OutboundRouter>,
+    access: Arc<ListenerAccess>,
+    pool: Arc<UpstreamPool>,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    match first_byte {
+        // SOCKS4/4a 版本号为 0x04
+        0x04 => handle_socks4_connection(stream, peer, router, access).await,
         // SOCKS5 版本号为 0x05
-        0x05 => handle_socks5_connection(stream, tcp_sessions, udp_sessions, direct_checker).await,
-        // HTTP 方法首字母（G、P、C 等）
+        0x05 => handle_socks5_connection(stream, peer, local_addr, router, access).await,
+        // HTTP 方法首字母（G、P、C 等）；HTTP/2 连接前言 `PRI * HTTP/2.0` 同样以 P 开头
         b'C' | b'D' | b'G' | b'H' | b'O' | b'P' | b'T' => {
-            handle_http_connection(stream, tcp_sessions, direct_checker).await
+            handle_http_connection(stream, peer, local_addr, router, access, pool).await
         }
         _ => {
-            error!("未知协议，首字节：0x{:02x}", buffer[0]);
+            error!("未知协议，首字节：0x{:02x}", first_byte);
             Ok(())
         }
     }
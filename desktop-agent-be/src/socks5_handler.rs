@@ -1,11 +1,14 @@
 //! 本地 SOCKS5 代理入口。
 //!
-//! TCP CONNECT/BIND 走 `tcp.rs`，UDP ASSOCIATE 走 `udp_associate.rs`。
+//! TCP CONNECT/BIND 走 `tcp.rs`，UDP ASSOCIATE 走 `udp_associate.rs`，
+//! 旧客户端使用的 SOCKS4/4a CONNECT 由 `socks4.rs` 解析后复用同一条 CONNECT 路径。
 //! 本模块只负责 SOCKS5 握手、命令分发，以及把 fast-socks5 的目标地址
 //! 转成项目内部的 `protocol::Address`。
 
-use crate::direct_access::{DirectAccessChecker, address_to_string};
 use crate::error::{AgentError, Result};
+use crate::listener_access::ListenerAccess;
+use crate::routing::{OutboundRouter, Route, address_to_string};
+use crate::server::InboundPeer;
 use crate::telemetry;
 use crate::yamux_session::{YamuxSessionManager, YamuxTargetStream};
 use dashmap::DashMap;
@@ -26,37 +29,58 @@ use tokio::net::{TcpListener, TcpStream, UdpSocket};
 use tokio::sync::mpsc::{Sender, channel};
 use tracing::{debug, error, info, instrument, trace, warn};
 
+mod socks4;
 mod tcp;
 #[cfg(test)]
 mod tests;
 mod udp_associate;
 mod udp_relay;
 
+pub use socks4::handle_socks4_connection;
 use tcp::{handle_tcp_bind, handle_tcp_connect};
 use udp_associate::handle_udp_associate;
 
-#[instrument(skip(stream, tcp_sessions, udp_sessions, direct_checker))]
-pub async fn handle_socks5_connection(
-    stream: TcpStream,
-    tcp_sessions: Arc<YamuxSessionManager>,
-    udp_sessions: Arc<YamuxSessionManager>,
-    direct_checker: Arc<DirectAccessChecker>,
-) -> Result<()> {
-    info!("处理 SOCKS5 连接");
+#[instrument(skip(stream, router, access))]
+pub async fn handle_socks5_connection<S>(
+    stream: S,
+    peer: InboundPeer,
+    local_addr: SocketAddr,
+    router: Arc<OutboundRouter>,
+    access: Arc<ListenerAccess>,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    info!("处理来自 {peer} 的 SOCKS5 连接");
     // UDP ASSOCIATE 回复地址尽量沿用 TCP 控制连接的本地地址族。
-    let control_local_ip = stream.local_addr().ok().map(|addr| addr.ip());
+    let control_local_ip = Some(local_addr.ip());
 
-    // 使用新的 fast-socks5 1.0 API 和 Socks5ServerProtocol
-    let protocol: Socks5ServerProtocol<TcpStream, Opened> = Socks5ServerProtocol::start(stream);
+    let authenticated = if access.requires_auth() {
+        // 配置了本地凭据时只提供 RFC 1929 用户名/密码方法，不支持该方法的客户端
+        // 在方法协商阶段就会被拒绝。
+        let (authenticated, _) =
+            Socks5ServerProtocol::accept_password_auth(stream, |username, password| {
+                access.check_password(&username, &password)
+            })
+            .await
+            .map_err(|e: SocksServerError| {
+                warn!("SOCKS5 本地认证失败：{}", e);
+                AgentError::Socks5(e.to_string())
+            })?;
+        authenticated
+    } else {
+        // 使用新的 fast-socks5 1.0 API 和 Socks5ServerProtocol
+        let protocol: Socks5ServerProtocol<S, Opened> = Socks5ServerProtocol::start(stream);
 
-    // 协商认证 - 本地代理默认无认证；用户身份由 agent->proxy 连接的密钥认证承担。
-    let auth_state = protocol
-        .negotiate_auth::<NoAuthentication>(&[NoAuthentication])
-        .await
-        .map_err(|e: SocksServerError| AgentError::Socks5(e.to_string()))?;
+        // 协商认证 - 未配置本地凭据时无认证；用户身份由 agent->proxy 连接的密钥认证承担。
+        let auth_state = protocol
+            .negotiate_auth::<NoAuthentication>(&[NoAuthentication])
+            .await
+            .map_err(|e: SocksServerError| AgentError::Socks5(e.to_string()))?;
 
-    // 完成认证并获取已认证状态
-    let authenticated = Socks5ServerProtocol::finish_auth(auth_state);
+        // 完成认证并获取已认证状态
+        Socks5ServerProtocol::finish_auth(auth_state)
+    };
 
     // 读取 SOCKS5 命令（CONNECT、BIND 等）
     let (protocol, command, target_addr) = authenticated
@@ -68,23 +92,20 @@ pub async fn handle_socks5_connection(
 
     match command {
         // CONNECT 是最常见路径：客户端要求 agent 主动连接目标。
-        Socks5Command::TCPConnect => {
-            handle_tcp_connect(protocol, target_addr, tcp_sessions, direct_checker).await
-        }
+        Socks5Command::TCPConnect => handle_tcp_connect(protocol, target_addr, router, &peer).await,
         // BIND 让 agent 监听一个端口等待远端主动连入。
-        Socks5Command::TCPBind => {
-            handle_tcp_bind(protocol, target_addr, tcp_sessions, direct_checker).await
+        Socks5Command::TCPBind => handle_tcp_bind(protocol, target_addr, router).await,
+        // UDP relay 监听在 IP 上并接受任意来源的数据报，Unix socket 的文件权限约束不到它，
+        // 因此 Unix 入口不提供 UDP ASSOCIATE。
+        #[cfg(unix)]
+        Socks5Command::UDPAssociate if matches!(peer, InboundPeer::Unix(_)) => {
+            warn!("Unix socket 入口不支持 UDP ASSOCIATE，拒绝 {peer}");
+            let _ = protocol.reply_error(&ReplyError::CommandNotSupported).await;
+            Ok(())
         }
         // UDP ASSOCIATE 通过 TCP 控制连接维持 UDP 会话生命周期。
         Socks5Command::UDPAssociate => {
-            handle_udp_associate(
-                protocol,
-                target_addr,
-                udp_sessions,
-                control_local_ip,
-                direct_checker,
-            )
-            .await
+            handle_udp_associate(protocol, target_addr, router, control_local_ip).await
         }
     }
 }
@@ -0,0 +1,134 @@
+//! SOCKS4/4a 兼容入口。
+//!
+//! 只支持 CONNECT，解析出目标后交给与 SOCKS5 CONNECT 相同的分流与中继路径。
+//! SOCKS4 没有密码认证，配置了本地凭据时直接拒绝；USERID 只用于日志。
+//! SOCKS4a 用 `0.0.0.x` 目标 IP 表示随后跟着域名，域名原样交给分流与 proxy 端解析。
+
+use super::tcp::{ConnectReply, connect_and_relay};
+use super::*;
+
+const SOCKS4_VERSION: u8 = 0x04;
+const SOCKS4_CMD_CONNECT: u8 = 0x01;
+const SOCKS4_REPLY_GRANTED: u8 = 90;
+const SOCKS4_REPLY_REJECTED: u8 = 91;
+// USERID 与 SOCKS4a 域名都以 NUL 结尾，限制长度避免客户端不发结束符时一直读下去。
+const SOCKS4_MAX_FIELD_LEN: usize = 255;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(super) struct Socks4Request {
+    pub(super) command: u8,
+    pub(super) address: Address,
+    pub(super) user_id: String,
+}
+
+struct Socks4Reply<S>(S);
+
+impl<S> ConnectReply for Socks4Reply<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    type Stream = S;
+
+    async fn reply_success(mut self) -> Result<S> {
+        write_reply(&mut self.0, SOCKS4_REPLY_GRANTED).await?;
+        Ok(self.0)
+    }
+
+    async fn reply_error(mut self, _error: ReplyError) {
+        // SOCKS4 只有一个通用的失败码。
+        let _ = write_reply(&mut self.0, SOCKS4_REPLY_REJECTED).await;
+    }
+}
+
+#[instrument(skip(stream, router, access))]
+pub async fn handle_socks4_connection<S>(
+    mut stream: S,
+    peer: InboundPeer,
+    router: Arc<OutboundRouter>,
+    access: Arc<ListenerAccess>,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    let request = read_request(&mut stream).await?;
+    info!(
+        "SOCKS4 命令: 0x{:02x}, 目标: {:?}, USERID: {:?}",
+        request.command, request.address, request.user_id
+    );
+
+    if access.requires_auth() {
+        warn!("已配置本地凭据，拒绝无法认证的 SOCKS4 连接：{peer}");
+        write_reply(&mut stream, SOCKS4_REPLY_REJECTED).await?;
+        return Ok(());
+    }
+    if request.command != SOCKS4_CMD_CONNECT {
+        warn!("SOCKS4 只支持 CONNECT，拒绝命令 0x{:02x}", request.command);
+        write_reply(&mut stream, SOCKS4_REPLY_REJECTED).await?;
+        return Ok(());
+    }
+
+    let target_label = address_to_string(&request.address);
+    connect_and_relay(
+        Socks4Reply(stream),
+        "SOCKS4 CONNECT",
+        request.address,
+        target_label,
+        router,
+        &peer,
+    )
+    .await
+}
+
+/// 读取 `VN CD DSTPORT DSTIP USERID NUL [HOST NUL]`。
+pub(super) async fn read_request<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Socks4Request> {
+    let mut header = [0u8; 8];
+    stream.read_exact(&mut header).await?;
+    if header[0] != SOCKS4_VERSION {
+        return Err(AgentError::Socks5(format!(
+            "不是 SOCKS4 请求，版本号 0x{:02x}",
+            header[0]
+        )));
+    }
+    let port = u16::from_be_bytes([header[2], header[3]]);
+    let ip = [header[4], header[5], header[6], header[7]];
+    let user_id = read_nul_terminated(stream).await?;
+
+    // SOCKS4a：0.0.0.x（x 非 0）表示目标是随后给出的域名。
+    let address = if ip[..3] == [0, 0, 0] && ip[3] != 0 {
+        let host = read_nul_terminated(stream).await?;
+        if host.is_empty() {
+            return Err(AgentError::Socks5("SOCKS4a 请求缺少目标域名".to_string()));
+        }
+        Address::Domain { host, port }
+    } else {
+        Address::Ipv4 { addr: ip, port }
+    };
+
+    Ok(Socks4Request {
+        command: header[1],
+        address,
+        user_id,
+    })
+}
+
+async fn read_nul_terminated<S: AsyncRead + Unpin>(stream: &mut S) -> Result<String> {
+    let mut bytes = Vec::new();
+    loop {
+        let byte = stream.read_u8().await?;
+        if byte == 0 {
+            break;
+        }
+        if bytes.len() == SOCKS4_MAX_FIELD_LEN {
+            return Err(AgentError::Socks5("SOCKS4 请求字段过长".to_string()));
+        }
+        bytes.push(byte);
+    }
+    String::from_utf8(bytes)
+        .map_err(|_| AgentError::Socks5("SOCKS4 请求字段不是 UTF-8".to_string()))
+}
+
+async fn write_reply<S: AsyncWrite + Unpin>(stream: &mut S, code: u8) -> std::io::Result<()> {
+    // DSTPORT/DSTIP 只在 BIND 回复中有意义，CONNECT 回复填 0。
+    stream.write_all(&[0, code, 0, 0, 0, 0, 0, 0]).await?;
+    stream.flush().await
+}
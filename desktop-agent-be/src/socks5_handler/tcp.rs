@@ -1,131 +1,199 @@
-//! SOCKS5 TCP 命令处理。
+//! SOCKS TCP 命令处理。
 //!
-//! CONNECT 是常规浏览器/应用代理路径；BIND 较少用，但同样会在直连或代理路径中
-//! 最终转换成一个 `AsyncRead + AsyncWrite` 双向中继。
+//! CONNECT 是常规浏览器/应用代理路径，SOCKS5 与 SOCKS4/4a 共用同一套分流与中继；
+//! BIND 较少用，但同样会在直连或代理路径中最终转换成一个 `AsyncRead + AsyncWrite` 双向中继。
 
 use super::*;
+use crate::connections::{ConnectionLease, register_connection};
 use crate::tcp_relay::{TcpRelayOptions, relay_tcp_bidirectional};
 
-pub(super) async fn handle_tcp_connect(
-    protocol: Socks5ServerProtocol<TcpStream, CommandRead>,
-    target_addr: TargetAddr,
-    sessions: Arc<YamuxSessionManager>,
-    direct_checker: Arc<DirectAccessChecker>,
-) -> Result<()> {
-    let target_label = format_target_addr(&target_addr);
+/// CONNECT 的结果回复。SOCKS5 与 SOCKS4 的回复格式不同，但都只能在目标连接真正
+/// 建立之后再回复成功。
+pub(super) trait ConnectReply: Send {
+    type Stream: AsyncRead + AsyncWrite + Unpin + Send;
 
+    /// 回复成功并交出客户端流，之后开始中继。
+    fn reply_success(self) -> impl Future<Output = Result<Self::Stream>> + Send;
+
+    fn reply_error(self, error: ReplyError) -> impl Future<Output = ()> + Send;
+}
+
+impl<S> ConnectReply for Socks5ServerProtocol<S, CommandRead>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    type Stream = S;
+
+    async fn reply_success(self) -> Result<S> {
+        let bind_addr: SocketAddr = "0.0.0.0:0".parse().unwrap();
+        Socks5ServerProtocol::reply_success(self, bind_addr)
+            .await
+            .map_err(|e: SocksServerError| AgentError::Socks5(e.to_string()))
+    }
+
+    async fn reply_error(self, error: ReplyError) {
+        let _ = Socks5ServerProtocol::reply_error(self, &error).await;
+    }
+}
+
+pub(super) async fn handle_tcp_connect<S>(
+    protocol: Socks5ServerProtocol<S, CommandRead>,
+    target_addr: TargetAddr,
+    router: Arc<OutboundRouter>,
+    peer: &InboundPeer,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+{
     // 将目标地址转换为协议 Address，之后直连规则和 proxy Connect 都使用同一表示。
-    // SOCKS5 入口不读取 TCP payload 做 SNI/Host 嗅探：直连判断只基于客户端
+    connect_and_relay(
+        protocol,
+        "SOCKS5 CONNECT",
+        convert_target_addr(&target_addr),
+        format_target_addr(&target_addr),
+        router,
+        peer,
+    )
+    .await
+}
+
+/// 按分流结果连接目标并中继，SOCKS5 与 SOCKS4/4a 的 CONNECT 共用。
+/// `inbound` 是入口标签，用于日志、流量记录与连接列表。
+pub(super) async fn connect_and_relay<R: ConnectReply>(
+    reply: R,
+    inbound: &'static str,
+    address: Address,
+    target_label: String,
+    router: Arc<OutboundRouter>,
+    peer: &InboundPeer,
+) -> Result<()> {
+    // SOCKS 入口不读取 TCP payload 做 SNI/Host 嗅探：直连判断只基于客户端
     // 握手里显式给出的 IP/域名。这样浏览器发起 CONNECT 后，视频分片数据不会
     // 被 agent 先抢读再补发。
-    let address = convert_target_addr(&target_addr);
+    let decision = router.decide(&address, TransportProtocol::Tcp, None, None);
+    match decision.route {
+        Route::Direct => {
+            // === 直连路径 ===
+            let target_str = address_to_string(&address);
+            info!("{inbound} 使用直连连接到 {}", target_str);
 
-    if direct_checker.is_direct(&address) {
-        // === 直连路径 ===
-        let target_str = address_to_string(&address);
-        info!("SOCKS5 CONNECT 使用直连连接到 {}", target_str);
-
-        match TcpStream::connect(&target_str).await {
-            Ok(mut target_stream) => {
-                // SOCKS5 直连隧道也关闭 Nagle，避免本地代理模式下小控制帧被延迟合并。
-                if let Err(err) = target_stream.set_nodelay(true) {
-                    debug!("SOCKS5 直连目标 TCP_NODELAY 设置失败，继续使用默认行为：{err}");
-                }
-                // SOCKS5 要先回复成功，客户端才会开始发送 TCP payload。
-                let bind_addr: SocketAddr = "0.0.0.0:0".parse().unwrap();
-                let mut client_stream = protocol
-                    .reply_success(bind_addr)
-                    .await
-                    .map_err(|e: SocksServerError| AgentError::Socks5(e.to_string()))?;
+            match TcpStream::connect(&target_str).await {
+                Ok(mut target_stream) => {
+                    // SOCKS 直连隧道也关闭 Nagle，避免本地代理模式下小控制帧被延迟合并。
+                    if let Err(err) = target_stream.set_nodelay(true) {
+                        debug!("{inbound} 直连目标 TCP_NODELAY 设置失败，继续使用默认行为：{err}");
+                    }
+                    // SOCKS 要先回复成功，客户端才会开始发送 TCP payload。
+                    let mut client_stream = reply.reply_success().await?;
 
-                info!("SOCKS5 直连隧道已建立，开始数据中继");
+                    info!("{inbound} 直连隧道已建立，开始数据中继");
+                    let connection = register_connection(
+                        inbound,
+                        peer,
+                        &target_label,
+                        TransportProtocol::Tcp,
+                        &decision,
+                    );
 
-                match relay_tcp_bidirectional(
-                    &mut client_stream,
-                    &mut target_stream,
-                    TcpRelayOptions::standard(&target_label),
-                )
-                .await
-                {
-                    Ok(stats) => {
-                        info!(
-                            "直连 SOCKS5 中继完成: {} 字节发出, {} 字节接收",
-                            stats.client_to_remote, stats.remote_to_client
-                        );
-                        telemetry::emit_traffic(
-                            "SOCKS5 CONNECT (direct)",
-                            target_label,
-                            stats.client_to_remote,
-                            stats.remote_to_client,
-                        );
-                    }
-                    Err(e) => {
-                        debug!("直连 SOCKS5 中继结束: {}", e);
+                    match relay_tcp_bidirectional(
+                        &mut client_stream,
+                        &mut target_stream,
+                        TcpRelayOptions::standard(&target_label).with_connection(&connection),
+                    )
+                    .await
+                    {
+                        Ok(stats) => {
+                            info!(
+                                "直连 {inbound} 中继完成: {} 字节发出, {} 字节接收",
+                                stats.client_to_remote, stats.remote_to_client
+                            );
+                            telemetry::emit_traffic(
+                                format!("{inbound} (direct)"),
+                                target_label,
+                                stats.client_to_remote,
+                                stats.remote_to_client,
+                            );
+                        }
+                        Err(e) => {
+                            debug!("直连 {inbound} 中继结束: {}", e);
+                        }
                     }
+                    Ok(())
+                }
+                Err(e) => {
+                    error!("直连到 {} 失败: {}", target_str, e);
+                    reply.reply_error(ReplyError::HostUnreachable).await;
+                    Err(AgentError::Connection(format!("直连失败: {}", e)))
                 }
-                Ok(())
-            }
-            Err(e) => {
-                error!("直连到 {} 失败: {}", target_str, e);
-                let _ = protocol.reply_error(&ReplyError::HostUnreachable).await;
-                Err(AgentError::Connection(format!("直连失败: {}", e)))
             }
         }
-    } else {
-        // === 代理路径 ===
-        // SOCKS5 的 DOMAIN 目标必须原样交给 proxy 端解析。
-        // 如果 agent 在本地先解析，再把 IP 发给 proxy，会破坏“从 proxy 出口访问”的
-        // DNS/CDN 语义，也会让远端分流规则失去域名上下文。这里刻意只透传
-        // Address::Domain，不做任何 agent 侧 DNS fallback。
-        //
-        // SOCKS5 reply success 也必须在 proxy stream 真实建立之后再发送。
-        // 否则浏览器会认为 CONNECT 已成功并开始写 TLS/HTTP2 字节；如果随后远端建连失败，
-        // 本地代理只能关闭一个“已经成功”的隧道，视频分片层面会变成更难诊断的解析/播放卡顿。
-        let connected_stream = match sessions
-            .as_ref()
-            .connect_to_target(address, TransportProtocol::Tcp)
-            .await
-        {
-            Ok(stream) => {
-                info!(
-                    "通过 proxy session manager 获取目标流, stream_id: {}",
-                    stream.stream_id()
-                );
-                stream
-            }
-            Err(e) => {
-                error!("SOCKS5 获取 proxy 流失败: {}", e);
-                let _ = protocol.reply_error(&ReplyError::HostUnreachable).await;
-                return Err(e);
-            }
-        };
+        Route::Block => {
+            info!("{inbound} 目标 {} 命中 block 规则，拒绝连接", target_label);
+            reply.reply_error(ReplyError::ConnectionNotAllowed).await;
+            Ok(())
+        }
+        Route::Proxy(group) => {
+            // === 代理路径 ===
+            // SOCKS 的 DOMAIN 目标必须原样交给 proxy 端解析。
+            // 如果 agent 在本地先解析，再把 IP 发给 proxy，会破坏“从 proxy 出口访问”的
+            // DNS/CDN 语义，也会让远端分流规则失去域名上下文。这里刻意只透传
+            // Address::Domain，不做任何 agent 侧 DNS fallback。
+            //
+            // SOCKS reply success 也必须在 proxy stream 真实建立之后再发送。
+            // 否则浏览器会认为 CONNECT 已成功并开始写 TLS/HTTP2 字节；如果随后远端建连失败，
+            // 本地代理只能关闭一个“已经成功”的隧道，视频分片层面会变成更难诊断的解析/播放卡顿。
+            let connected_stream = match group
+                .tcp_sessions()
+                .connect_to_target(address, TransportProtocol::Tcp)
+                .await
+            {
+                Ok(stream) => {
+                    info!(
+                        "通过 proxy session manager 获取目标流, stream_id: {}",
+                        stream.stream_id()
+                    );
+                    stream
+                }
+                Err(e) => {
+                    error!("{inbound} 获取 proxy 流失败: {}", e);
+                    reply.reply_error(ReplyError::HostUnreachable).await;
+                    return Err(e);
+                }
+            };
 
-        // proxy stream 建好后再回复成功，之后客户端才会开始发送隧道 payload。
-        let bind_addr: SocketAddr = "0.0.0.0:0".parse().unwrap();
-        let mut client_stream = protocol
-            .reply_success(bind_addr)
-            .await
-            .map_err(|e: SocksServerError| AgentError::Socks5(e.to_string()))?;
+            // proxy stream 建好后再回复成功，之后客户端才会开始发送隧道 payload。
+            let mut client_stream = reply.reply_success().await?;
 
-        info!("SOCKS5 隧道已建立，开始数据中继");
+            info!("{inbound} 隧道已建立，开始数据中继");
+            let connection = register_connection(
+                inbound,
+                peer,
+                &target_label,
+                TransportProtocol::Tcp,
+                &decision,
+            );
 
-        // 启动双向数据中继
-        relay_data(
-            &mut client_stream,
-            connected_stream,
-            "SOCKS5 CONNECT",
-            target_label,
-        )
-        .await
+            // 启动双向数据中继
+            relay_data(
+                &mut client_stream,
+                connected_stream,
+                inbound,
+                target_label,
+                &connection,
+            )
+            .await
+        }
     }
 }
 
-pub(super) async fn handle_tcp_bind(
-    protocol: Socks5ServerProtocol<TcpStream, CommandRead>,
+pub(super) async fn handle_tcp_bind<S>(
+    protocol: Socks5ServerProtocol<S, CommandRead>,
     target_addr: TargetAddr,
-    sessions: Arc<YamuxSessionManager>,
-    direct_checker: Arc<DirectAccessChecker>,
-) -> Result<()> {
+    router: Arc<OutboundRouter>,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
     info!("处理 SOCKS5 BIND 命令，目标: {:?}", target_addr);
     let target_label = format_target_addr(&target_addr);
 
@@ -158,80 +226,109 @@ pub(super) async fn handle_tcp_bind(
                 peer_addr, address
             );
 
-            if direct_checker.is_direct(&address) {
-                // === 直连路径 ===
-                let target_str = address_to_string(&address);
-                info!("SOCKS5 BIND 使用直连连接到 {}", target_str);
+            let decision = router.decide(&address, TransportProtocol::Tcp, None, None);
+            match decision.route {
+                Route::Direct => {
+                    // === 直连路径 ===
+                    let target_str = address_to_string(&address);
+                    info!("SOCKS5 BIND 使用直连连接到 {}", target_str);
 
-                match TcpStream::connect(&target_str).await {
-                    Ok(mut target_stream) => {
-                        // BIND 直连同样关闭 Nagle，保持与 CONNECT 直连一致的小包时延。
-                        if let Err(err) = target_stream.set_nodelay(true) {
-                            debug!(
-                                "SOCKS5 BIND 直连目标 TCP_NODELAY 设置失败，继续使用默认行为：{err}"
-                            );
-                        }
-                        info!("SOCKS5 BIND 直连隧道已建立，开始数据中继");
-                        match relay_tcp_bidirectional(
-                            &mut incoming_stream,
-                            &mut target_stream,
-                            TcpRelayOptions::standard(&target_label),
-                        )
-                        .await
-                        {
-                            Ok(stats) => {
-                                info!(
-                                    "直连 SOCKS5 BIND 中继完成: {} 字节发出, {} 字节接收",
-                                    stats.client_to_remote, stats.remote_to_client
-                                );
-                                telemetry::emit_traffic(
-                                    "SOCKS5 BIND (direct)",
-                                    target_label,
-                                    stats.client_to_remote,
-                                    stats.remote_to_client,
+                    match TcpStream::connect(&target_str).await {
+                        Ok(mut target_stream) => {
+                            // BIND 直连同样关闭 Nagle，保持与 CONNECT 直连一致的小包时延。
+                            if let Err(err) = target_stream.set_nodelay(true) {
+                                debug!(
+                                    "SOCKS5 BIND 直连目标 TCP_NODELAY 设置失败，继续使用默认行为：{err}"
                                 );
                             }
-                            Err(e) => {
-                                debug!("直连 SOCKS5 BIND 中继结束: {}", e);
+                            info!("SOCKS5 BIND 直连隧道已建立，开始数据中继");
+                            let connection = register_connection(
+                                "SOCKS5 BIND",
+                                peer_addr,
+                                &target_label,
+                                TransportProtocol::Tcp,
+                                &decision,
+                            );
+                            match relay_tcp_bidirectional(
+                                &mut incoming_stream,
+                                &mut target_stream,
+                                TcpRelayOptions::standard(&target_label)
+                                    .with_connection(&connection),
+                            )
+                            .await
+                            {
+                                Ok(stats) => {
+                                    info!(
+                                        "直连 SOCKS5 BIND 中继完成: {} 字节发出, {} 字节接收",
+                                        stats.client_to_remote, stats.remote_to_client
+                                    );
+                                    telemetry::emit_traffic(
+                                        "SOCKS5 BIND (direct)",
+                                        target_label,
+                                        stats.client_to_remote,
+                                        stats.remote_to_client,
+                                    );
+                                }
+                                Err(e) => {
+                                    debug!("直连 SOCKS5 BIND 中继结束: {}", e);
+                                }
                             }
+                            Ok(())
+                        }
+                        Err(e) => {
+                            error!("直连到目标失败: {}", e);
+                            Err(AgentError::Connection(format!("直连失败: {}", e)))
                         }
-                        Ok(())
-                    }
-                    Err(e) => {
-                        error!("直连到目标失败: {}", e);
-                        Err(AgentError::Connection(format!("直连失败: {}", e)))
                     }
                 }
-            } else {
-                // === 代理路径 ===
-                // BIND 代理路径与 CONNECT 保持一致：域名只透传，不在 agent 本地解析。
-                let connected_stream = match sessions
-                    .as_ref()
-                    .connect_to_target(address, TransportProtocol::Tcp)
-                    .await
-                {
-                    Ok(stream) => {
-                        info!(
-                            "通过 proxy session manager 获取目标流, stream_id: {}",
-                            stream.stream_id()
-                        );
-                        stream
-                    }
-                    Err(e) => {
-                        error!("通过 proxy session manager 获取目标流失败: {}", e);
-                        return Err(e);
-                    }
-                };
+                Route::Block => {
+                    info!(
+                        "SOCKS5 BIND 目标 {} 命中 block 规则，拒绝连接",
+                        target_label
+                    );
+                    Err(AgentError::Connection(format!(
+                        "目标 {target_label} 被分流规则拒绝"
+                    )))
+                }
+                Route::Proxy(group) => {
+                    // === 代理路径 ===
+                    // BIND 代理路径与 CONNECT 保持一致：域名只透传，不在 agent 本地解析。
+                    let connected_stream = match group
+                        .tcp_sessions()
+                        .connect_to_target(address, TransportProtocol::Tcp)
+                        .await
+                    {
+                        Ok(stream) => {
+                            info!(
+                                "通过 proxy session manager 获取目标流, stream_id: {}",
+                                stream.stream_id()
+                            );
+                            stream
+                        }
+                        Err(e) => {
+                            error!("通过 proxy session manager 获取目标流失败: {}", e);
+                            return Err(e);
+                        }
+                    };
 
-                info!("SOCKS5 BIND 隧道已建立，开始数据中继");
+                    info!("SOCKS5 BIND 隧道已建立，开始数据中继");
+                    let connection = register_connection(
+                        "SOCKS5 BIND",
+                        peer_addr,
+                        &target_label,
+                        TransportProtocol::Tcp,
+                        &decision,
+                    );
 
-                relay_data(
-                    &mut incoming_stream,
-                    connected_stream,
-                    "SOCKS5 BIND",
-                    target_label,
-                )
-                .await
+                    relay_data(
+                        &mut incoming_stream,
+                        connected_stream,
+                        "SOCKS5 BIND",
+                        target_label,
+                        &connection,
+                    )
+                    .await
+                }
             }
         }
         Ok(Err(e)) => {
@@ -245,11 +342,12 @@ pub(super) async fn handle_tcp_bind(
     }
 }
 
-async fn relay_data(
-    client_stream: &mut TcpStream,
+async fn relay_data<S: AsyncRead + AsyncWrite + Unpin>(
+    client_stream: &mut S,
     connected_stream: YamuxTargetStream,
     protocol: &str,
     target: String,
+    connection: &ConnectionLease,
 ) -> Result<()> {
     // YamuxTargetStream 隐藏 direct framed TCP/Yamux 差异，上层只看到一个可读写的 proxy 目标流。
     // SOCKS5 与 HTTP/TUN 使用同一个 copy_bidirectional relay，不再为底层传输
@@ -259,7 +357,7 @@ async fn relay_data(
     match relay_tcp_bidirectional(
         client_stream,
         &mut proxy_io,
-        TcpRelayOptions::standard(&target),
+        TcpRelayOptions::standard(&target).with_connection(connection),
     )
     .await
     {
@@ -36,3 +36,101 @@ fn resolve_udp_reply_uses_family_safe_fallback() {
         SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 53000)
     );
 }
+
+const DIRECT_CONFIG: &str = r#"
+proxy_addrs = ["127.0.0.1:8080"]
+username = "user1"
+private_key_path = "keys/user1.pem"
+
+[direct_access]
+mode = "direct_all"
+"#;
+
+fn direct_router() -> Arc<OutboundRouter> {
+    let config: crate::config::AgentConfig = toml::from_str(DIRECT_CONFIG).unwrap();
+    Arc::new(OutboundRouter::new(Arc::new(config)).unwrap())
+}
+
+#[tokio::test]
+async fn socks4_request_parses_ipv4_target_and_user_id() {
+    let mut request: &[u8] = &[4, 1, 0x01, 0xbb, 10, 0, 0, 1, b'b', b'o', b'b', 0];
+
+    let request = socks4::read_request(&mut request).await.unwrap();
+
+    assert_eq!(request.command, 1);
+    assert_eq!(
+        request.address,
+        Address::Ipv4 {
+            addr: [10, 0, 0, 1],
+            port: 443
+        }
+    );
+    assert_eq!(request.user_id, "bob");
+}
+
+#[tokio::test]
+async fn socks4a_request_carries_domain_target() {
+    let mut bytes = vec![4, 1, 0, 80, 0, 0, 0, 1, 0];
+    bytes.extend_from_slice(b"example.com\0");
+    let mut request = bytes.as_slice();
+
+    let request = socks4::read_request(&mut request).await.unwrap();
+
+    assert_eq!(
+        request.address,
+        Address::Domain {
+            host: "example.com".to_string(),
+            port: 80
+        }
+    );
+    assert!(request.user_id.is_empty());
+}
+
+#[tokio::test]
+async fn socks4_request_rejects_unterminated_user_id() {
+    let mut bytes = vec![4, 1, 0, 80, 10, 0, 0, 1];
+    bytes.extend(std::iter::repeat_n(b'a', 300));
+    let mut request = bytes.as_slice();
+
+    assert!(socks4::read_request(&mut request).await.is_err());
+}
+
+#[tokio::test]
+async fn socks4a_connect_relays_direct_target() {
+    let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let target_port = target.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        let (mut stream, _) = target.accept().await.unwrap();
+        let mut buf = [0u8; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        stream.write_all(&buf).await.unwrap();
+    });
+
+    let (mut client, server) = tokio::io::duplex(1024);
+    let access = Arc::new(ListenerAccess::new(&Default::default()).unwrap());
+    let peer = InboundPeer::Tcp("127.0.0.1:50000".parse().unwrap());
+    let handler = tokio::spawn(handle_socks4_connection(
+        server,
+        peer,
+        direct_router(),
+        access,
+    ));
+
+    let mut request = vec![4, 1];
+    request.extend_from_slice(&target_port.to_be_bytes());
+    request.extend_from_slice(&[0, 0, 0, 1, 0]);
+    request.extend_from_slice(b"127.0.0.1\0");
+    client.write_all(&request).await.unwrap();
+
+    let mut reply = [0u8; 8];
+    client.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[..2], [0, 90]);
+
+    client.write_all(b"ping").await.unwrap();
+    let mut echoed = [0u8; 4];
+    client.read_exact(&mut echoed).await.unwrap();
+    assert_eq!(&echoed, b"ping");
+
+    drop(client);
+    handler.await.unwrap().unwrap();
+}
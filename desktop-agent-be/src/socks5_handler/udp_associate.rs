@@ -1,18 +1,21 @@
 //! SOCKS5 UDP ASSOCIATE 控制与本地 UDP 入口。
 //!
 //! TCP 控制连接只负责告诉客户端“请把 UDP 包发到哪个本地地址”，并维持会话生命周期。
-//! 真正的 UDP 数据包在本模块解析 SOCKS5 UDP 头后，按直连规则选择本地直连或共享 UDP relay。
+//! 真正的 UDP 数据包在本模块解析 SOCKS5 UDP 头后，按分流规则选择本地直连、丢弃，
+//! 或对应 proxy 分组的共享 UDP relay。
 
 use super::udp_relay::SocksUdpRelay;
 use super::*;
 
-pub(super) async fn handle_udp_associate(
-    protocol: Socks5ServerProtocol<TcpStream, CommandRead>,
+pub(super) async fn handle_udp_associate<S>(
+    protocol: Socks5ServerProtocol<S, CommandRead>,
     _target_addr: TargetAddr,
-    udp_sessions: Arc<YamuxSessionManager>,
+    router: Arc<OutboundRouter>,
     control_local_ip: Option<IpAddr>,
-    direct_checker: Arc<DirectAccessChecker>,
-) -> Result<()> {
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
     info!("处理 UDP ASSOCIATE");
 
     // 在随机端口上绑定 UDP 套接字，客户端后续会把 SOCKS5 UDP datagram 发到这里。
@@ -35,7 +38,6 @@ pub(super) async fn handle_udp_associate(
         .map_err(|e: SocksServerError| AgentError::Socks5(e.to_string()))?;
 
     let udp_socket = Arc::new(udp_socket);
-    let udp_sessions = udp_sessions.clone();
 
     // 客户端向 `bind_addr` 发送 UDP 数据包
 
@@ -47,7 +49,7 @@ pub(super) async fn handle_udp_associate(
         debug!("UDP 关联 TCP 控制通道已关闭");
     };
 
-    let udp_handler = process_udp_traffic(udp_socket, udp_sessions, direct_checker);
+    let udp_handler = process_udp_traffic(udp_socket, router);
 
     tokio::select! {
         _ = keep_alive => {
@@ -97,13 +99,13 @@ pub(super) fn resolve_udp_associate_reply_addr(
 
 async fn process_udp_traffic(
     udp_socket: Arc<UdpSocket>,
-    udp_sessions: Arc<YamuxSessionManager>,
-    direct_checker: Arc<DirectAccessChecker>,
+    router: Arc<OutboundRouter>,
 ) -> Result<()> {
     let mut buf = [0u8; 65535];
     type StreamMap = DashMap<Address, Sender<Vec<u8>>>;
     let streams: Arc<StreamMap> = Arc::new(DashMap::new());
-    let udp_relay = SocksUdpRelay::spawn(udp_sessions.clone(), udp_socket.clone());
+    // 每个 proxy 分组在首次命中时才启动自己的共享 relay。
+    let mut udp_relays: Vec<Option<Arc<SocksUdpRelay>>> = vec![None; router.groups().len()];
 
     loop {
         // SOCKS5 UDP 是无连接的，这里按目标地址建立/复用会话任务。
@@ -131,10 +133,23 @@ async fn process_udp_traffic(
             }
         };
         let payload = packet_data[3 + header_len..].to_vec();
-        if !direct_checker.is_direct(&dest_addr) {
-            // 代理路径不维护逐目标 direct stream，直接交给共享 relay。
-            udp_relay.send(client_addr, dest_addr, payload).await;
-            continue;
+        match router.route(&dest_addr, TransportProtocol::Udp) {
+            Route::Direct => {}
+            Route::Block => {
+                trace!(
+                    "SOCKS5 UDP 目标 {:?} 命中 block 规则，丢弃数据包",
+                    dest_addr
+                );
+                continue;
+            }
+            Route::Proxy(group) => {
+                // 代理路径不维护逐目标 direct stream，直接交给分组的共享 relay。
+                let udp_relay = udp_relays[group.index()].get_or_insert_with(|| {
+                    SocksUdpRelay::spawn(group.udp_sessions().clone(), udp_socket.clone())
+                });
+                udp_relay.send(client_addr, dest_addr, payload).await;
+                continue;
+            }
         }
 
         // 只有直连路径才按目标地址创建/查询 UDP stream。
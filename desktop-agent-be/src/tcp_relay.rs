@@ -5,16 +5,20 @@
 //! 字节流搬运逻辑，避免 TUN、HTTP、SOCKS 在半关闭/flush 行为上出现分叉。
 //!
 //! `TcpRelayOptions` 仍保留不同入口的构造函数，方便日志和调用点表达语义；真正
-//! 的 relay 不再根据入口切换实现。
+//! 的 relay 不再根据入口切换实现。带上连接表租约时，字节数随写入实时累加，
+//! UI 关闭连接会中止 relay。
 
 use std::io;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::task::{Context, Poll};
 
 use common::TCP_RELAY_COPY_BUFFER_SIZE;
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tracing::debug;
 
+use crate::connections::ConnectionLease;
+
 /// 一次双向 TCP relay 的字节统计。
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct TcpRelayStats {
@@ -25,11 +29,17 @@ pub struct TcpRelayStats {
 struct RelayCopyIo<'a, S> {
     inner: &'a mut S,
     label: &'a str,
+    // 写入这一侧的字节计数，对应连接表中的一个方向。
+    written: Option<&'a AtomicU64>,
 }
 
 impl<'a, S> RelayCopyIo<'a, S> {
-    fn new(inner: &'a mut S, label: &'a str) -> Self {
-        Self { inner, label }
+    fn new(inner: &'a mut S, label: &'a str, written: Option<&'a AtomicU64>) -> Self {
+        Self {
+            inner,
+            label,
+            written,
+        }
     }
 }
 
@@ -57,7 +67,11 @@ where
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
         let this = self.get_mut();
-        Pin::new(&mut *this.inner).poll_write(cx, buf)
+        let result = Pin::new(&mut *this.inner).poll_write(cx, buf);
+        if let (Poll::Ready(Ok(n)), Some(written)) = (&result, this.written) {
+            written.fetch_add(*n as u64, Ordering::Relaxed);
+        }
+        result
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
@@ -87,15 +101,28 @@ where
 pub struct TcpRelayOptions<'a> {
     /// 日志标签，用于定位具体目标。
     pub label: &'a str,
+    /// 连接表租约；relay 实时更新其字节数，并在连接被关闭时结束。
+    pub connection: Option<&'a ConnectionLease>,
 }
 
 impl<'a> TcpRelayOptions<'a> {
     pub fn standard(label: &'a str) -> Self {
-        Self { label }
+        Self {
+            label,
+            connection: None,
+        }
     }
 
     pub fn tun(label: &'a str) -> Self {
-        Self { label }
+        Self {
+            label,
+            connection: None,
+        }
+    }
+
+    pub fn with_connection(mut self, connection: &'a ConnectionLease) -> Self {
+        self.connection = Some(connection);
+        self
     }
 }
 
@@ -115,15 +142,35 @@ where
     // 所有 TCP 入口都走同一个 copy_bidirectional。不要在这里按
     // TUN/HTTP/SOCKS/framed proxy 分叉，否则后续排查卡顿时会再次出现“某个入口
     // 修好了、另一个入口还保留旧半关闭语义”的问题。
-    let mut client_io = RelayCopyIo::new(client, options.label);
-    let mut remote_io = RelayCopyIo::new(remote, options.label);
-    let (client_to_remote, remote_to_client) = tokio::io::copy_bidirectional_with_sizes(
+    let connection = options.connection;
+    let mut client_io = RelayCopyIo::new(
+        client,
+        options.label,
+        connection.map(ConnectionLease::inbound_counter),
+    );
+    let mut remote_io = RelayCopyIo::new(
+        remote,
+        options.label,
+        connection.map(ConnectionLease::outbound_counter),
+    );
+    let copy = tokio::io::copy_bidirectional_with_sizes(
         &mut client_io,
         &mut remote_io,
         TCP_RELAY_COPY_BUFFER_SIZE,
         TCP_RELAY_COPY_BUFFER_SIZE,
-    )
-    .await?;
+    );
+    let (client_to_remote, remote_to_client) = match connection {
+        Some(connection) => tokio::select! {
+            result = copy => result?,
+            _ = connection.cancel_token().cancelled() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::ConnectionAborted,
+                    "连接已被手动关闭",
+                ));
+            }
+        },
+        None => copy.await?,
+    };
 
     Ok(TcpRelayStats {
         client_to_remote,
@@ -252,6 +299,61 @@ mod tests {
         assert_eq!(stats.remote_to_client, b"complete-body".len() as u64);
     }
 
+    #[tokio::test]
+    async fn relay_counts_bytes_live_and_stops_when_connection_is_closed() {
+        use crate::connections::{active_connections, close_connection, register_connection};
+        use crate::routing::{Route, RouteDecision};
+        use protocol::TransportProtocol;
+
+        let (mut client_relay, mut client_peer) = tokio::io::duplex(1024);
+        let (mut remote_relay, mut remote_peer) = tokio::io::duplex(1024);
+        let connection = register_connection(
+            "HTTP CONNECT",
+            "127.0.0.1:50001",
+            "example.com:443",
+            TransportProtocol::Tcp,
+            &RouteDecision {
+                route: Route::Direct,
+                rule: "final".to_string(),
+            },
+        );
+        let id = active_connections()
+            .into_iter()
+            .find(|record| record.source == "127.0.0.1:50001")
+            .unwrap()
+            .id;
+
+        let relay = tokio::spawn(async move {
+            relay_tcp_bidirectional(
+                &mut client_relay,
+                &mut remote_relay,
+                TcpRelayOptions::standard("test").with_connection(&connection),
+            )
+            .await
+        });
+
+        client_peer.write_all(b"GET").await.unwrap();
+        let mut request = [0u8; 3];
+        remote_peer.read_exact(&mut request).await.unwrap();
+        remote_peer.write_all(b"body").await.unwrap();
+        let mut response = [0u8; 4];
+        client_peer.read_exact(&mut response).await.unwrap();
+
+        let record = active_connections()
+            .into_iter()
+            .find(|record| record.id == id)
+            .unwrap();
+        assert_eq!((record.outbound_bytes, record.inbound_bytes), (3, 4));
+
+        assert!(close_connection(id));
+        let result = tokio::time::timeout(std::time::Duration::from_secs(5), relay)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::ConnectionAborted);
+        assert!(active_connections().iter().all(|record| record.id != id));
+    }
+
     struct ShutdownErrorRemote {
         state: Arc<Mutex<ShutdownErrorRemoteState>>,
     }
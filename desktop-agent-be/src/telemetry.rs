@@ -2,7 +2,7 @@
 //! 流量统计辅助函数 `emit_traffic`。
 
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Mutex, OnceLock};
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -10,14 +10,17 @@ use tracing::info;
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
-use tracing_subscriber::{EnvFilter, fmt};
+use tracing_subscriber::{EnvFilter, Registry, fmt, reload};
 
 static TOTAL_OUTBOUND_BYTES: AtomicU64 = AtomicU64::new(0);
 static TOTAL_INBOUND_BYTES: AtomicU64 = AtomicU64::new(0);
 static DNS_RECORDS: OnceLock<Mutex<VecDeque<DnsResolutionRecord>>> = OnceLock::new();
 const DNS_RECORD_CAPACITY: usize = 80;
+static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+static TRANSPORT_SLOTS: OnceLock<Mutex<BTreeMap<(String, usize), TransportSlotRecord>>> =
+    OnceLock::new();
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 #[allow(dead_code)]
 pub struct TrafficSnapshot {
     pub outbound_bytes: u64,
@@ -38,6 +41,20 @@ pub struct DnsResolutionRecord {
     pub duration_ms: u128,
 }
 
+/// 自动传输模式下某个原生 UDP 会话池 slot 当前使用的传输。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransportSlotRecord {
+    pub group: String,
+    pub slot: usize,
+    /// "native_udp" 或 "tcp_yamux"。
+    pub transport: String,
+    /// 回退后连续失败的恢复探测次数。
+    pub recovery_failures: u32,
+    /// 下一次恢复探测的时间戳；已恢复原生 UDP 时为空。
+    pub next_probe_ms: Option<u128>,
+    pub updated_ms: u128,
+}
+
 fn agent_dns_resolver() -> String {
     "agent".to_string()
 }
@@ -46,7 +63,9 @@ fn agent_dns_resolver() -> String {
 /// 若 `log_dir` 不为空，日志只会按天滚动写入该目录下的文件。
 /// 开启文件日志时，返回的 guard 必须在程序整个生命周期内保持存活。
 pub fn init_tracing(log_dir: Option<&str>, log_file: &str, log_level: &str) -> Option<WorkerGuard> {
-    let filter = EnvFilter::new(log_level);
+    // 过滤器包一层 reload，配置热重载时可以原地替换日志级别。
+    let (filter, filter_handle) = reload::Layer::new(EnvFilter::new(log_level));
+    let _ = LOG_FILTER.set(filter_handle);
 
     if let Some(log_dir) = log_dir {
         // 文件日志使用 non_blocking writer，guard 必须存活以 flush 后台缓冲。
@@ -77,6 +96,14 @@ pub fn init_tracing(log_dir: Option<&str>, log_file: &str, log_level: &str) -> O
     }
 }
 
+/// 替换 `init_tracing` 安装的日志级别。嵌入 UI 时日志由宿主初始化，这里返回 false。
+pub fn set_log_level(log_level: &str) -> bool {
+    let Some(handle) = LOG_FILTER.get() else {
+        return false;
+    };
+    handle.reload(EnvFilter::new(log_level)).is_ok()
+}
+
 /// 以 INFO 级别记录一条流量统计日志。
 /// 原 TUI 版本通过结构化 channel 渲染这些数据；无界面版本直接写日志，数据仍可观测。
 pub fn emit_traffic<S1: Into<String>, S2: Into<String>>(
@@ -151,6 +178,24 @@ pub fn dns_resolution_records() -> Vec<DnsResolutionRecord> {
         .unwrap_or_default()
 }
 
+/// 记录一个 slot 的最新传输状态，同一分组同一 slot 只保留最后一条。
+pub fn emit_transport_slot(record: TransportSlotRecord) {
+    let slots = TRANSPORT_SLOTS.get_or_init(|| Mutex::new(BTreeMap::new()));
+    let Ok(mut slots) = slots.lock() else {
+        return;
+    };
+    slots.insert((record.group.clone(), record.slot), record);
+}
+
+#[allow(dead_code)]
+pub fn transport_slot_records() -> Vec<TransportSlotRecord> {
+    TRANSPORT_SLOTS
+        .get_or_init(|| Mutex::new(BTreeMap::new()))
+        .lock()
+        .map(|slots| slots.values().cloned().collect())
+        .unwrap_or_default()
+}
+
 pub fn current_time_millis() -> u128 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -0,0 +1,95 @@
+//! Linux 透明代理入口。
+//!
+//! 路由器/网关上用 TUN 捕获流量要为每个包走一遍用户态 TCP/IP 协议栈。透明代理
+//! 模式改由内核完成转发：nftables 把经本机转发的 TCP REDIRECT 到监听端口，
+//! 连接的原始目标用 `SO_ORIGINAL_DST` 取回；UDP 由 TPROXY 原样送到监听 socket，
+//! 原始目标随 `IP_RECVORIGDSTADDR` 控制消息一起返回。还原出的目标与 HTTP/SOCKS
+//! 入口一样交给 `OutboundRouter`：直连、拒绝，或对应 proxy 分组的传输会话管理器。
+//!
+//! nftables 规则与 TPROXY 策略路由由 `--install-transparent-rules` 安装，
+//! 安装过的条目记录在状态文件中，`--remove-transparent-rules` 按记录删除。
+
+mod rules;
+mod tcp;
+mod udp;
+
+use crate::config::TransparentConfig;
+use crate::error::Result;
+use crate::routing::OutboundRouter;
+use common::{DEFAULT_TCP_LISTEN_BACKLOG, bind_tcp_listener_with_backlog, spawn_guarded};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::{TcpListener, UdpSocket};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+
+pub use rules::{install_transparent_rules, remove_transparent_rules};
+
+pub(crate) struct TransparentProxy {
+    listen_addr: SocketAddr,
+    tcp: TcpListener,
+    // 未启用 UDP 接管时为 None。
+    udp: Option<Arc<UdpSocket>>,
+    router: Arc<OutboundRouter>,
+}
+
+impl TransparentProxy {
+    /// 绑定 TCP 监听与 TPROXY UDP socket；后者需要 CAP_NET_ADMIN。
+    pub(crate) async fn bind(
+        config: &TransparentConfig,
+        router: Arc<OutboundRouter>,
+    ) -> Result<Self> {
+        let listen_addr = rules::parse_listen_addr(&config.listen_addr)?;
+        let tcp = bind_tcp_listener_with_backlog(listen_addr, DEFAULT_TCP_LISTEN_BACKLOG)?;
+        let udp = if config.udp {
+            Some(Arc::new(udp::bind_tproxy_socket(listen_addr)?))
+        } else {
+            None
+        };
+        Ok(Self {
+            listen_addr,
+            tcp,
+            udp,
+            router,
+        })
+    }
+
+    pub(crate) async fn run(self, shutdown: CancellationToken) {
+        info!(
+            "透明代理入口正在监听 {}（TCP REDIRECT{}）",
+            self.listen_addr,
+            if self.udp.is_some() {
+                " + UDP TPROXY"
+            } else {
+                ""
+            }
+        );
+        if let Some(udp) = self.udp.clone() {
+            spawn_guarded(
+                "desktop transparent udp",
+                udp::run(udp, self.router.clone(), shutdown.clone()),
+            );
+        }
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                accepted = self.tcp.accept() => {
+                    let (stream, client) = match accepted {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            debug!("透明代理 TCP 接受连接失败：{e}");
+                            continue;
+                        }
+                    };
+                    let router = self.router.clone();
+                    spawn_guarded("desktop transparent tcp connection", async move {
+                        if let Err(e) = tcp::handle(stream, client, router).await {
+                            debug!("透明代理 TCP 连接 {client} 结束：{e}");
+                        }
+                    });
+                }
+            }
+        }
+        info!("透明代理入口已停止");
+    }
+}
@@ -0,0 +1,492 @@
+//! 透明代理的 nftables 规则与 TPROXY 策略路由。
+//!
+//! 规则集放在独立的 `inet ppaass_transparent` 表里，整体安装、整体删除，不触碰
+//! 系统已有的 nftables 配置。TPROXY 需要把带 fwmark 的数据包路由到本机，这部分由
+//! `ip rule`/`ip route` 完成。安装成功的每一项都写进状态文件，删除时只按记录回滚；
+//! 再次安装前也会先清理上次遗留的条目，与 TUN 路由状态文件的做法一致。
+
+use crate::config::TransparentConfig;
+use crate::error::{AgentError, Result};
+use common::CommonError;
+use common::routing::RuleSetEntry;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Write as _;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+const NFT_TABLE: &str = "ppaass_transparent";
+const RULES_STATE_VERSION: u8 = 1;
+const RULES_STATE_FILE_NAME: &str = "transparent-rules.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum PolicyRouteKind {
+    /// `ip rule add fwmark MARK lookup TABLE`
+    Rule,
+    /// `ip route add local default dev lo table TABLE`
+    LocalRoute,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PolicyRouteRecord {
+    kind: PolicyRouteKind,
+    ipv6: bool,
+    fwmark: u32,
+    table: u32,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TransparentRuleState {
+    version: u8,
+    pid: u32,
+    created_unix_secs: u64,
+    #[serde(default)]
+    nft_table: Option<String>,
+    #[serde(default)]
+    policy_routes: Vec<PolicyRouteRecord>,
+}
+
+/// 安装透明代理需要的 nftables 规则与策略路由，需要 root 或 CAP_NET_ADMIN。
+pub fn install_transparent_rules(config: &TransparentConfig) -> Result<()> {
+    let listen_addr = parse_listen_addr(&config.listen_addr)?;
+    let script = nft_ruleset(config, listen_addr)?;
+    let path = rules_state_file_path(config.rules_state_file.as_deref());
+    if !cleanup_recorded(&path) {
+        return Err(command_error(format!(
+            "上次遗留的透明代理规则未能全部清理，请检查状态文件 {}",
+            path.display()
+        )));
+    }
+
+    let mut state = TransparentRuleState {
+        version: RULES_STATE_VERSION,
+        pid: std::process::id(),
+        created_unix_secs: now_unix_secs(),
+        ..Default::default()
+    };
+    apply_nft_script(&script)?;
+    state.nft_table = Some(NFT_TABLE.to_string());
+    persist_state(&path, &state);
+    info!("已安装 nftables 表 inet {NFT_TABLE}");
+
+    if config.udp {
+        for ipv6 in udp_families(listen_addr) {
+            for kind in [PolicyRouteKind::Rule, PolicyRouteKind::LocalRoute] {
+                let record = PolicyRouteRecord {
+                    kind,
+                    ipv6,
+                    fwmark: config.fwmark,
+                    table: config.route_table,
+                };
+                run_ip(&policy_route_args(&record, "add"))?;
+                state.policy_routes.push(record);
+                persist_state(&path, &state);
+            }
+        }
+        info!(
+            "已安装 TPROXY 策略路由：fwmark={:#x} table={}",
+            config.fwmark, config.route_table
+        );
+    }
+    info!("透明代理规则已安装，状态文件：{}", path.display());
+    Ok(())
+}
+
+/// 按状态文件删除 `install_transparent_rules` 安装过的规则与策略路由。
+pub fn remove_transparent_rules(config: &TransparentConfig) -> Result<()> {
+    let path = rules_state_file_path(config.rules_state_file.as_deref());
+    if !path.exists() {
+        info!("未找到透明代理规则状态文件 {}，无需删除", path.display());
+        return Ok(());
+    }
+    if cleanup_recorded(&path) {
+        info!("透明代理规则已删除");
+        Ok(())
+    } else {
+        Err(command_error(format!(
+            "部分透明代理规则删除失败，已保留状态文件 {} 以便重试",
+            path.display()
+        )))
+    }
+}
+
+pub(super) fn parse_listen_addr(listen_addr: &str) -> Result<SocketAddr> {
+    listen_addr.trim().parse().map_err(|e| {
+        invalid(format!(
+            "transparent.listen_addr 不是合法的 IP:端口：{listen_addr}（{e}）"
+        ))
+    })
+}
+
+/// 生成完整的 nftables 脚本；开头先声明再删除同名表，重复安装不会叠加规则。
+fn nft_ruleset(config: &TransparentConfig, listen_addr: SocketAddr) -> Result<String> {
+    let mut bypass_v4 = Vec::new();
+    let mut bypass_v6 = Vec::new();
+    for entry in &config.bypass {
+        match RuleSetEntry::parse(entry) {
+            Some(RuleSetEntry::Cidr(IpAddr::V4(ip), prefix_len)) => {
+                bypass_v4.push(format!("{ip}/{prefix_len}"))
+            }
+            Some(RuleSetEntry::Cidr(IpAddr::V6(ip), prefix_len)) => {
+                bypass_v6.push(format!("{ip}/{prefix_len}"))
+            }
+            _ => {
+                return Err(invalid(format!(
+                    "transparent.bypass 中的地址不是合法 IP/CIDR：{entry}"
+                )));
+            }
+        }
+    }
+    for interface in &config.interfaces {
+        if interface.is_empty()
+            || !interface
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '@'))
+        {
+            return Err(invalid(format!(
+                "transparent.interfaces 中的网卡名不合法：{interface:?}"
+            )));
+        }
+    }
+
+    let port = listen_addr.port();
+    let dual_stack = listen_addr.is_ipv6();
+    let mut script = String::new();
+    let _ = writeln!(script, "table inet {NFT_TABLE}");
+    let _ = writeln!(script, "delete table inet {NFT_TABLE}");
+    let _ = writeln!(script, "table inet {NFT_TABLE} {{");
+    write_set(&mut script, "bypass_v4", "ipv4_addr", &bypass_v4);
+    write_set(&mut script, "bypass_v6", "ipv6_addr", &bypass_v6);
+
+    let mut common_guards = String::new();
+    if !config.interfaces.is_empty() {
+        let names = config
+            .interfaces
+            .iter()
+            .map(|name| format!("\"{name}\""))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let _ = writeln!(common_guards, "\t\tiifname != {{ {names} }} return");
+    }
+    // 发往本机的流量（包括 agent 自身监听的端口）不接管。
+    common_guards.push_str("\t\tfib daddr type local return\n");
+    common_guards.push_str("\t\tip daddr @bypass_v4 return\n");
+    common_guards.push_str("\t\tip6 daddr @bypass_v6 return\n");
+
+    let _ = writeln!(script, "\tchain prerouting_nat {{");
+    let _ = writeln!(
+        script,
+        "\t\ttype nat hook prerouting priority dstnat; policy accept;"
+    );
+    script.push_str(&common_guards);
+    if dual_stack {
+        let _ = writeln!(script, "\t\tmeta l4proto tcp redirect to :{port}");
+    } else {
+        let _ = writeln!(
+            script,
+            "\t\tmeta nfproto ipv4 meta l4proto tcp redirect to :{port}"
+        );
+    }
+    let _ = writeln!(script, "\t}}");
+
+    if config.udp {
+        let _ = writeln!(script, "\tchain prerouting_mangle {{");
+        let _ = writeln!(
+            script,
+            "\t\ttype filter hook prerouting priority mangle; policy accept;"
+        );
+        script.push_str(&common_guards);
+        for ipv6 in udp_families(listen_addr) {
+            let (nfproto, family) = if ipv6 {
+                ("ipv6", "ip6")
+            } else {
+                ("ipv4", "ip")
+            };
+            let _ = writeln!(
+                script,
+                "\t\tmeta nfproto {nfproto} meta l4proto udp tproxy {family} to :{port} meta mark set {:#x} accept",
+                config.fwmark
+            );
+        }
+        let _ = writeln!(script, "\t}}");
+    }
+    let _ = writeln!(script, "}}");
+    Ok(script)
+}
+
+fn write_set(script: &mut String, name: &str, kind: &str, elements: &[String]) {
+    let _ = writeln!(script, "\tset {name} {{");
+    let _ = writeln!(script, "\t\ttype {kind}; flags interval;");
+    if !elements.is_empty() {
+        let _ = writeln!(script, "\t\telements = {{ {} }}", elements.join(", "));
+    }
+    let _ = writeln!(script, "\t}}");
+}
+
+/// UDP TPROXY 覆盖的地址族：`false` 为 IPv4，`true` 为 IPv6；只有双栈监听才接管 IPv6。
+fn udp_families(listen_addr: SocketAddr) -> Vec<bool> {
+    if listen_addr.is_ipv6() {
+        vec![false, true]
+    } else {
+        vec![false]
+    }
+}
+
+fn policy_route_args(record: &PolicyRouteRecord, action: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    if record.ipv6 {
+        args.push("-6".to_string());
+    }
+    match record.kind {
+        PolicyRouteKind::Rule => {
+            args.extend(["rule".to_string(), action.to_string()]);
+            args.extend(["fwmark".to_string(), format!("{:#x}", record.fwmark)]);
+        }
+        PolicyRouteKind::LocalRoute => {
+            args.extend(["route".to_string(), action.to_string()]);
+            args.extend(["local".to_string(), "default".to_string()]);
+            args.extend(["dev".to_string(), "lo".to_string()]);
+        }
+    }
+    args.extend(["table".to_string(), record.table.to_string()]);
+    args
+}
+
+/// 回滚状态文件记录的条目；全部成功（或没有记录）时删除状态文件并返回 true。
+fn cleanup_recorded(path: &Path) -> bool {
+    let state = match fs::read_to_string(path) {
+        Ok(content) => match serde_json::from_str::<TransparentRuleState>(&content) {
+            Ok(state) => state,
+            Err(e) => {
+                warn!(
+                    "透明代理规则状态文件 {} 解析失败，将移除该文件：{e}",
+                    path.display()
+                );
+                remove_file_if_exists(path);
+                return true;
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return true,
+        Err(e) => {
+            warn!("读取透明代理规则状态文件 {} 失败：{e}", path.display());
+            return false;
+        }
+    };
+
+    let mut cleanup_ok = true;
+    for record in state.policy_routes.iter().rev() {
+        if let Err(e) = run_ip(&policy_route_args(record, "del")) {
+            warn!("删除透明代理策略路由失败：{e}");
+            cleanup_ok = false;
+        }
+    }
+    if let Some(table) = &state.nft_table {
+        let result = Command::new("nft")
+            .args(["delete", "table", "inet", table])
+            .output();
+        match result {
+            Ok(output) if output.status.success() => {
+                debug!("已删除 nftables 表 inet {table}");
+            }
+            // 表已经不存在时 nft 报 ENOENT，视为已清理。
+            Ok(output) if command_output_message(&output).contains("No such file") => {}
+            Ok(output) => {
+                warn!(
+                    "删除 nftables 表 inet {table} 失败：{}",
+                    command_output_message(&output)
+                );
+                cleanup_ok = false;
+            }
+            Err(e) => {
+                warn!("执行 nft 失败：{e}");
+                cleanup_ok = false;
+            }
+        }
+    }
+
+    if cleanup_ok {
+        remove_file_if_exists(path);
+    }
+    cleanup_ok
+}
+
+fn apply_nft_script(script: &str) -> Result<()> {
+    let mut child = Command::new("nft")
+        .args(["-f", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| command_error(format!("执行 nft 失败：{e}")))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(script.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(command_error(format!(
+            "nft 加载透明代理规则失败：{}",
+            command_output_message(&output)
+        )))
+    }
+}
+
+fn run_ip(args: &[String]) -> Result<()> {
+    let output = Command::new("ip")
+        .args(args)
+        .output()
+        .map_err(|e| command_error(format!("执行 ip 失败：{e}")))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(command_error(format!(
+            "ip {} 失败：{}",
+            args.join(" "),
+            command_output_message(&output)
+        )))
+    }
+}
+
+fn persist_state(path: &Path, state: &TransparentRuleState) {
+    let result = (|| -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let data = serde_json::to_vec_pretty(state).map_err(std::io::Error::other)?;
+        let tmp_path = path.with_extension(format!("json.tmp.{}", std::process::id()));
+        fs::write(&tmp_path, data)?;
+        fs::rename(tmp_path, path)
+    })();
+    if let Err(e) = result {
+        warn!("写入透明代理规则状态文件 {} 失败：{e}", path.display());
+    }
+}
+
+fn rules_state_file_path(configured_file: Option<&str>) -> PathBuf {
+    let configured_file = configured_file
+        .map(str::trim)
+        .filter(|file| !file.is_empty())
+        .unwrap_or(RULES_STATE_FILE_NAME);
+    let path = PathBuf::from(configured_file);
+    if path.is_absolute() {
+        return path;
+    }
+    std::env::current_dir()
+        .unwrap_or_else(|_| PathBuf::from("."))
+        .join(path)
+}
+
+fn remove_file_if_exists(path: &Path) {
+    match fs::remove_file(path) {
+        Ok(()) => debug!("已删除透明代理规则状态文件：{}", path.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => warn!("删除透明代理规则状态文件 {} 失败：{e}", path.display()),
+    }
+}
+
+fn command_output_message(output: &std::process::Output) -> String {
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    match (stdout.is_empty(), stderr.is_empty()) {
+        (true, true) => format!("退出状态 {}", output.status),
+        (false, true) => stdout,
+        (true, false) => stderr,
+        (false, false) => format!("{stderr}; {stdout}"),
+    }
+}
+
+fn now_unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn invalid(message: String) -> AgentError {
+    AgentError::Config(CommonError::Config(message))
+}
+
+fn command_error(message: String) -> AgentError {
+    AgentError::Io(std::io::Error::other(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(listen_addr: &str, udp: bool, interfaces: &[&str]) -> TransparentConfig {
+        toml::from_str::<TransparentConfig>(&format!(
+            "listen_addr = {listen_addr:?}\nudp = {udp}\ninterfaces = {interfaces:?}\n"
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn ipv4_listener_redirects_tcp_and_tproxies_udp() {
+        let config = config("0.0.0.0:12345", true, &["br-lan"]);
+        let listen_addr = parse_listen_addr(&config.listen_addr).unwrap();
+        let script = nft_ruleset(&config, listen_addr).unwrap();
+
+        assert!(script.starts_with("table inet ppaass_transparent\ndelete table inet"));
+        assert!(script.contains("iifname != { \"br-lan\" } return"));
+        assert!(script.contains("elements = { 0.0.0.0/8, 10.0.0.0/8,"));
+        assert!(script.contains("meta nfproto ipv4 meta l4proto tcp redirect to :12345"));
+        assert!(script.contains(
+            "meta nfproto ipv4 meta l4proto udp tproxy ip to :12345 meta mark set 0x1a4 accept"
+        ));
+        assert!(!script.contains("tproxy ip6"));
+    }
+
+    #[test]
+    fn dual_stack_listener_covers_ipv6_and_tcp_only_skips_mangle() {
+        let dual = config("[::]:7000", true, &[]);
+        let script = nft_ruleset(&dual, parse_listen_addr(&dual.listen_addr).unwrap()).unwrap();
+        assert!(!script.contains("iifname"));
+        assert!(script.contains("\t\tmeta l4proto tcp redirect to :7000"));
+        assert!(script.contains("tproxy ip6 to :7000"));
+
+        let tcp_only = config("0.0.0.0:7000", false, &[]);
+        let script =
+            nft_ruleset(&tcp_only, parse_listen_addr(&tcp_only.listen_addr).unwrap()).unwrap();
+        assert!(!script.contains("prerouting_mangle"));
+    }
+
+    #[test]
+    fn policy_route_commands_match_record() {
+        let record = PolicyRouteRecord {
+            kind: PolicyRouteKind::LocalRoute,
+            ipv6: true,
+            fwmark: 0x1a4,
+            table: 420,
+        };
+        assert_eq!(
+            policy_route_args(&record, "add").join(" "),
+            "-6 route add local default dev lo table 420"
+        );
+        let record = PolicyRouteRecord {
+            kind: PolicyRouteKind::Rule,
+            ipv6: false,
+            ..record
+        };
+        assert_eq!(
+            policy_route_args(&record, "del").join(" "),
+            "rule del fwmark 0x1a4 table 420"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_bypass_and_interface_names() {
+        let mut bad = config("0.0.0.0:12345", true, &[]);
+        bad.bypass = vec!["example.com".to_string()];
+        assert!(nft_ruleset(&bad, "0.0.0.0:12345".parse().unwrap()).is_err());
+
+        let bad = config("0.0.0.0:12345", true, &["lan\" accept"]);
+        assert!(nft_ruleset(&bad, "0.0.0.0:12345".parse().unwrap()).is_err());
+        assert!(parse_listen_addr("12345").is_err());
+    }
+}
@@ -0,0 +1,249 @@
+//! REDIRECT 过来的 TCP 连接。
+
+use crate::connections::register_connection;
+use crate::error::{AgentError, Result};
+use crate::routing::{OutboundRouter, Route, address_to_string};
+use crate::tcp_relay::{TcpRelayOptions, relay_tcp_bidirectional};
+use crate::telemetry;
+use crate::tun_handler::network::socket_addr_to_address;
+use protocol::TransportProtocol;
+use socket2::SockRef;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tracing::{debug, info, warn};
+
+pub(super) async fn handle(
+    stream: TcpStream,
+    client: SocketAddr,
+    router: Arc<OutboundRouter>,
+) -> Result<()> {
+    let local = stream.local_addr()?;
+    let target = original_dst(&stream, local)?;
+    forward(stream, client, local, target, router).await
+}
+
+/// 按分流结果把连接转发到 REDIRECT 前的目标 `target`；`local` 是监听地址。
+async fn forward(
+    mut stream: TcpStream,
+    client: SocketAddr,
+    local: SocketAddr,
+    target: SocketAddr,
+    router: Arc<OutboundRouter>,
+) -> Result<()> {
+    // 没有经过 REDIRECT 的连接原始目标就是本地监听地址，转发出去会连回自己。
+    if target == SocketAddr::new(local.ip().to_canonical(), local.port()) {
+        warn!("透明代理收到未经 REDIRECT 的直接连接 {client}，已关闭");
+        return Ok(());
+    }
+    if let Err(err) = stream.set_nodelay(true) {
+        debug!("设置透明代理入口 TCP_NODELAY 失败，继续使用默认行为：{err}");
+    }
+
+    let address = socket_addr_to_address(target);
+    let target_label = target.to_string();
+    let decision = router.decide(&address, TransportProtocol::Tcp, None, None);
+    match decision.route {
+        Route::Direct => {
+            debug!("透明代理 TCP {client} -> 直连 {target_label}");
+            let mut target_stream = TcpStream::connect(address_to_string(&address))
+                .await
+                .map_err(|e| AgentError::Connection(format!("直连 {target_label} 失败: {e}")))?;
+            if let Err(err) = target_stream.set_nodelay(true) {
+                debug!("透明代理直连目标 TCP_NODELAY 设置失败，继续使用默认行为：{err}");
+            }
+            let connection = register_connection(
+                "TRANSPARENT TCP",
+                client,
+                &target_label,
+                TransportProtocol::Tcp,
+                &decision,
+            );
+            let stats = relay_tcp_bidirectional(
+                &mut stream,
+                &mut target_stream,
+                TcpRelayOptions::standard(&target_label).with_connection(&connection),
+            )
+            .await?;
+            telemetry::emit_traffic(
+                "TRANSPARENT TCP (direct)",
+                target_label,
+                stats.client_to_remote,
+                stats.remote_to_client,
+            );
+        }
+        Route::Block => {
+            info!("透明代理 TCP 目标 {target_label} 命中 block 规则，关闭连接");
+        }
+        Route::Proxy(group) => {
+            debug!(
+                "透明代理 TCP {client} -> 代理 {} -> {target_label}",
+                group.name()
+            );
+            let connected = group
+                .tcp_sessions()
+                .connect_to_target(address, TransportProtocol::Tcp)
+                .await?;
+            let mut proxy_io = connected.into_async_io();
+            let connection = register_connection(
+                "TRANSPARENT TCP",
+                client,
+                &target_label,
+                TransportProtocol::Tcp,
+                &decision,
+            );
+            let stats = relay_tcp_bidirectional(
+                &mut stream,
+                &mut proxy_io,
+                TcpRelayOptions::standard(&target_label).with_connection(&connection),
+            )
+            .await?;
+            telemetry::emit_traffic(
+                "TRANSPARENT TCP",
+                target_label,
+                stats.client_to_remote,
+                stats.remote_to_client,
+            );
+        }
+    }
+    Ok(())
+}
+
+/// 读取 conntrack 记录的 REDIRECT 前目标；双栈监听上的 IPv4 连接按 IPv4 查询。
+fn original_dst(stream: &TcpStream, local: SocketAddr) -> Result<SocketAddr> {
+    let socket = SockRef::from(stream);
+    let original = if local.ip().to_canonical().is_ipv4() {
+        socket.original_dst_v4()?
+    } else {
+        socket.original_dst_v6()?
+    };
+    original
+        .as_socket()
+        .map(|addr| SocketAddr::new(addr.ip().to_canonical(), addr.port()))
+        .ok_or_else(|| AgentError::Connection("无法解析 SO_ORIGINAL_DST 地址".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AgentConfig;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn router(proxy_addr: SocketAddr, routing: &str) -> Arc<OutboundRouter> {
+        let config: AgentConfig = toml::from_str(&format!(
+            "proxy_addrs = [\"{proxy_addr}\"]\nusername = \"user1\"\nprivate_key_path = \"keys/user1.pem\"\nconnect_timeout_secs = 1\n\n[routing]\nfinal = \"{routing}\"\n"
+        ))
+        .unwrap();
+        Arc::new(OutboundRouter::new(Arc::new(config)).unwrap())
+    }
+
+    /// 模拟 REDIRECT 后的入口：返回客户端一侧与 agent 接受的一侧。
+    async fn accepted_pair() -> (TcpStream, TcpStream, SocketAddr, SocketAddr) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let local = listener.local_addr().unwrap();
+        let client = TcpStream::connect(local).await.unwrap();
+        let (accepted, client_addr) = listener.accept().await.unwrap();
+        (client, accepted, client_addr, local)
+    }
+
+    async fn spawn_echo() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let (mut reader, mut writer) = stream.split();
+            let _ = tokio::io::copy(&mut reader, &mut writer).await;
+        });
+        addr
+    }
+
+    async fn read_to_end(mut client: TcpStream) -> Vec<u8> {
+        let mut received = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), client.read_to_end(&mut received))
+            .await
+            .unwrap()
+            .unwrap();
+        received
+    }
+
+    #[tokio::test]
+    async fn direct_connections_to_the_listener_are_closed() {
+        let unused_proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let router = router(unused_proxy.local_addr().unwrap(), "direct");
+        let (client, accepted, client_addr, local) = accepted_pair().await;
+
+        // 原始目标就是监听地址本身，说明连接没有经过 REDIRECT。
+        forward(accepted, client_addr, local, local, router)
+            .await
+            .unwrap();
+        assert!(read_to_end(client).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn direct_route_relays_to_the_original_destination() {
+        let unused_proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let router = router(unused_proxy.local_addr().unwrap(), "direct");
+        let target = spawn_echo().await;
+        let (mut client, accepted, client_addr, local) = accepted_pair().await;
+
+        let relay = tokio::spawn(forward(accepted, client_addr, local, target, router));
+        client.write_all(b"ping").await.unwrap();
+        client.shutdown().await.unwrap();
+        assert_eq!(read_to_end(client).await, b"ping");
+        relay.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn block_route_closes_without_connecting() {
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let router = router(target.local_addr().unwrap(), "block");
+        let (client, accepted, client_addr, local) = accepted_pair().await;
+
+        forward(
+            accepted,
+            client_addr,
+            local,
+            target.local_addr().unwrap(),
+            router,
+        )
+        .await
+        .unwrap();
+        assert!(read_to_end(client).await.is_empty());
+        // proxy 与目标用的是同一个监听，拒绝时两者都不应收到连接。
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), target.accept())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn proxy_route_dials_the_group_instead_of_the_target() {
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let router = router(proxy.local_addr().unwrap(), "proxy");
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (_client, accepted, client_addr, local) = accepted_pair().await;
+
+        let relay = tokio::spawn(forward(
+            accepted,
+            client_addr,
+            local,
+            target.local_addr().unwrap(),
+            router,
+        ));
+        let (proxy_side, _) = tokio::time::timeout(Duration::from_secs(5), proxy.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        // 这里没有真正的 proxy，关闭后认证失败，连接随之结束。
+        drop(proxy_side);
+        assert!(relay.await.unwrap().is_err());
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), target.accept())
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,395 @@
+//! TPROXY 送达的 UDP 数据报。
+//!
+//! 所有被接管的 UDP 都落在同一个监听 socket 上，原始目标从控制消息取得，
+//! 按 (客户端, 原始目标) 近似会话化。每个会话用一个绑定在原始目标地址上的
+//! 透明 socket 把回复送回客户端；该 socket 连接到客户端后，同一会话的后续
+//! 数据报会被 TPROXY 优先投递给它，因此会话任务也要从它读取上行数据。
+
+use crate::connections::register_connection;
+use crate::error::Result;
+use crate::routing::{OutboundRouter, Route};
+use crate::telemetry;
+use crate::tun_handler::network::socket_addr_to_address;
+use common::spawn_guarded;
+use nix::sys::socket::{
+    ControlMessageOwned, MsgFlags, SockaddrIn, SockaddrIn6, SockaddrLike, SockaddrStorage, recvmsg,
+    setsockopt, sockopt,
+};
+use parking_lot::Mutex;
+use protocol::TransportProtocol;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashMap;
+use std::io::{self, IoSliceMut};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::os::fd::{AsRawFd, RawFd};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, Interest};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, trace};
+
+const UDP_FLOW_IDLE: Duration = Duration::from_secs(60);
+const UDP_FLOW_QUEUE: usize = 256;
+
+type FlowKey = (SocketAddr, SocketAddr);
+type FlowMap = Arc<Mutex<HashMap<FlowKey, mpsc::Sender<Vec<u8>>>>>;
+
+/// 绑定 TPROXY 监听 socket，并开启原始目标地址控制消息。
+pub(super) fn bind_tproxy_socket(listen_addr: SocketAddr) -> Result<UdpSocket> {
+    let socket = Socket::new(
+        Domain::for_address(listen_addr),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )?;
+    socket.set_reuse_address(true)?;
+    set_transparent(&socket, listen_addr)?;
+    // 双栈 socket 上的 IPv4 数据报仍通过 IP_ORIGDSTADDR 返回原始目标。
+    setsockopt(&socket, sockopt::Ipv4OrigDstAddr, &true).map_err(io::Error::from)?;
+    if listen_addr.is_ipv6() {
+        socket.set_only_v6(false)?;
+        setsockopt(&socket, sockopt::Ipv6OrigDstAddr, &true).map_err(io::Error::from)?;
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&listen_addr.into())?;
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+pub(super) async fn run(
+    socket: Arc<UdpSocket>,
+    router: Arc<OutboundRouter>,
+    shutdown: CancellationToken,
+) {
+    let flows: FlowMap = Arc::new(Mutex::new(HashMap::new()));
+    let mut buf = vec![0u8; 65535];
+    loop {
+        let (n, client, target) = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            received = recv_with_orig_dst(&socket, &mut buf) => match received {
+                Ok(received) => received,
+                Err(e) => {
+                    debug!("透明代理 UDP 接收失败：{e}");
+                    continue;
+                }
+            },
+        };
+        let key = (client, target);
+        let Some(rx) = dispatch(&flows, key, buf[..n].to_vec()) else {
+            continue;
+        };
+        let router = router.clone();
+        let flows = flows.clone();
+        let shutdown = shutdown.clone();
+        spawn_guarded("desktop transparent udp flow", async move {
+            if let Err(e) = relay_flow(client, target, rx, router, shutdown).await {
+                debug!("透明代理 UDP 会话 {client} -> {target} 结束：{e}");
+            }
+            let mut flows = flows.lock();
+            if flows.get(&key).is_some_and(|tx| tx.is_closed()) {
+                flows.remove(&key);
+            }
+        });
+    }
+    info!("透明代理 UDP 入口已停止");
+}
+
+/// 把数据报交给 (客户端, 原始目标) 对应的会话。还没有存活的会话时新建队列并返回
+/// 接收端，由调用方启动会话任务。
+fn dispatch(flows: &FlowMap, key: FlowKey, payload: Vec<u8>) -> Option<mpsc::Receiver<Vec<u8>>> {
+    let mut flows = flows.lock();
+    if let Some(tx) = flows.get(&key).filter(|tx| !tx.is_closed()) {
+        if tx.try_send(payload).is_err() {
+            let (client, target) = key;
+            trace!("透明代理 UDP 会话 {client} -> {target} 队列已满，丢弃数据报");
+        }
+        return None;
+    }
+    let (tx, rx) = mpsc::channel(UDP_FLOW_QUEUE);
+    let _ = tx.try_send(payload);
+    flows.insert(key, tx);
+    Some(rx)
+}
+
+async fn relay_flow(
+    client: SocketAddr,
+    target: SocketAddr,
+    mut rx: mpsc::Receiver<Vec<u8>>,
+    router: Arc<OutboundRouter>,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let address = socket_addr_to_address(target);
+    let target_label = target.to_string();
+    let decision = router.decide(&address, TransportProtocol::Udp, None, None);
+    let (mut upstream, traffic_label) = match decision.route {
+        Route::Block => {
+            info!("透明代理 UDP 目标 {target_label} 命中 block 规则，丢弃会话");
+            // 会话空闲前持续吞掉数据报，避免每个包都重新建会话、重复打日志。
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => return Ok(()),
+                    received = tokio::time::timeout(UDP_FLOW_IDLE, rx.recv()) => {
+                        if !matches!(received, Ok(Some(_))) {
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+        }
+        Route::Direct => {
+            debug!("透明代理 UDP {client} -> 直连 {target_label}");
+            let bind_addr = if target.is_ipv4() {
+                SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+            } else {
+                SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))
+            };
+            let socket = UdpSocket::bind(bind_addr).await?;
+            socket.connect(target).await?;
+            (Upstream::Direct(socket), "TRANSPARENT UDP (direct)")
+        }
+        Route::Proxy(group) => {
+            debug!(
+                "透明代理 UDP {client} -> 代理 {} -> {target_label}",
+                group.name()
+            );
+            let connected = group
+                .udp_sessions()
+                .connect_to_target(address, TransportProtocol::Udp)
+                .await?;
+            let (reader, writer) = tokio::io::split(connected.into_async_io());
+            (
+                Upstream::Proxy(Box::new(reader), Box::new(writer)),
+                "TRANSPARENT UDP",
+            )
+        }
+    };
+    let reply = bind_reply_socket(target, client)?;
+    let connection = register_connection(
+        "TRANSPARENT UDP",
+        client,
+        &target_label,
+        TransportProtocol::Udp,
+        &decision,
+    );
+
+    let mut outbound = 0u64;
+    let mut inbound = 0u64;
+    let result = async {
+        let mut client_buf = vec![0u8; 65535];
+        let mut upstream_buf = vec![0u8; 65535];
+        let idle = tokio::time::sleep(UDP_FLOW_IDLE);
+        tokio::pin!(idle);
+        loop {
+            let payload = tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = connection.cancel_token().cancelled() => break,
+                _ = &mut idle => break,
+                queued = rx.recv() => match queued {
+                    Some(payload) => payload,
+                    None => break,
+                },
+                received = reply.recv(&mut client_buf) => client_buf[..received?].to_vec(),
+                received = upstream.recv(&mut upstream_buf) => {
+                    let Some(n) = received? else { break };
+                    reply.send(&upstream_buf[..n]).await?;
+                    inbound += n as u64;
+                    connection.add_inbound(n);
+                    idle.as_mut().reset(Instant::now() + UDP_FLOW_IDLE);
+                    continue;
+                }
+            };
+            upstream.send(&payload).await?;
+            outbound += payload.len() as u64;
+            connection.add_outbound(payload.len());
+            idle.as_mut().reset(Instant::now() + UDP_FLOW_IDLE);
+        }
+        Ok::<_, io::Error>(())
+    }
+    .await;
+
+    telemetry::emit_traffic(traffic_label, target_label, outbound, inbound);
+    Ok(result?)
+}
+
+/// 会话的上游：直连目标的 UDP socket，或 proxy 分组上的 UDP 语义 stream。
+enum Upstream {
+    Direct(UdpSocket),
+    Proxy(
+        Box<dyn AsyncRead + Send + Unpin>,
+        Box<dyn AsyncWrite + Send + Unpin>,
+    ),
+}
+
+impl Upstream {
+    /// 读取一个数据报；proxy stream 关闭时返回 `None`。
+    async fn recv(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>> {
+        match self {
+            Self::Direct(socket) => socket.recv(buf).await.map(Some),
+            Self::Proxy(reader, _) => match reader.read(buf).await? {
+                0 => Ok(None),
+                n => Ok(Some(n)),
+            },
+        }
+    }
+
+    async fn send(&mut self, payload: &[u8]) -> io::Result<()> {
+        match self {
+            Self::Direct(socket) => socket.send(payload).await.map(|_| ()),
+            Self::Proxy(_, writer) => {
+                writer.write_all(payload).await?;
+                writer.flush().await
+            }
+        }
+    }
+}
+
+/// 以原始目标为源地址、连接到客户端的回复 socket。
+fn bind_reply_socket(target: SocketAddr, client: SocketAddr) -> Result<UdpSocket> {
+    let socket = Socket::new(
+        Domain::for_address(target),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )?;
+    socket.set_reuse_address(true)?;
+    set_transparent(&socket, target)?;
+    socket.bind(&target.into())?;
+    socket.connect(&client.into())?;
+    socket.set_nonblocking(true)?;
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+fn set_transparent(socket: &Socket, addr: SocketAddr) -> io::Result<()> {
+    socket.set_ip_transparent_v4(true)?;
+    if addr.is_ipv6() {
+        socket.set_ip_transparent_v6(true)?;
+    }
+    Ok(())
+}
+
+async fn recv_with_orig_dst(
+    socket: &UdpSocket,
+    buf: &mut [u8],
+) -> io::Result<(usize, SocketAddr, SocketAddr)> {
+    loop {
+        socket.readable().await?;
+        match socket.try_io(Interest::READABLE, || recv_once(socket.as_raw_fd(), buf)) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            result => return result,
+        }
+    }
+}
+
+fn recv_once(fd: RawFd, buf: &mut [u8]) -> io::Result<(usize, SocketAddr, SocketAddr)> {
+    let mut cmsg = nix::cmsg_space!(nix::libc::sockaddr_in6);
+    let mut iov = [IoSliceMut::new(buf)];
+    let msg = recvmsg::<SockaddrStorage>(fd, &mut iov, Some(&mut cmsg), MsgFlags::empty())
+        .map_err(io::Error::from)?;
+    let client = msg
+        .address
+        .as_ref()
+        .and_then(storage_to_socket_addr)
+        .ok_or_else(|| io::Error::other("数据报缺少来源地址"))?;
+    let mut original = None;
+    for cmsg in msg.cmsgs().map_err(io::Error::from)? {
+        match cmsg {
+            ControlMessageOwned::Ipv4OrigDstAddr(addr) => {
+                original = Some(SocketAddr::V4(SockaddrIn::from(addr).into()));
+            }
+            ControlMessageOwned::Ipv6OrigDstAddr(addr) => {
+                original = Some(SocketAddr::V6(SockaddrIn6::from(addr).into()));
+            }
+            _ => {}
+        }
+    }
+    let target = original.ok_or_else(|| io::Error::other("数据报缺少原始目标地址"))?;
+    Ok((msg.bytes, canonical(client), canonical(target)))
+}
+
+fn storage_to_socket_addr(addr: &SockaddrStorage) -> Option<SocketAddr> {
+    match addr.family()? {
+        nix::sys::socket::AddressFamily::Inet => addr
+            .as_sockaddr_in()
+            .map(|addr| SocketAddr::V4((*addr).into())),
+        nix::sys::socket::AddressFamily::Inet6 => addr
+            .as_sockaddr_in6()
+            .map(|addr| SocketAddr::V6((*addr).into())),
+        _ => None,
+    }
+}
+
+fn canonical(addr: SocketAddr) -> SocketAddr {
+    SocketAddr::new(addr.ip().to_canonical(), addr.port())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(addr: &str) -> SocketAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn datagrams_share_a_flow_per_client_and_target() {
+        let flows: FlowMap = Arc::new(Mutex::new(HashMap::new()));
+        let client = addr("192.168.1.20:40000");
+        let dns = (client, addr("1.1.1.1:53"));
+
+        let mut first = dispatch(&flows, dns, b"a".to_vec()).unwrap();
+        assert!(dispatch(&flows, dns, b"b".to_vec()).is_none());
+        assert_eq!(first.try_recv().unwrap(), b"a");
+        assert_eq!(first.try_recv().unwrap(), b"b");
+
+        // 同一客户端端口发往另一个目标、或另一个客户端发往同一目标，都是独立会话。
+        let mut other_target =
+            dispatch(&flows, (client, addr("8.8.8.8:53")), b"c".to_vec()).unwrap();
+        let mut other_client =
+            dispatch(&flows, (addr("192.168.1.21:40000"), dns.1), b"d".to_vec()).unwrap();
+        assert_eq!(other_target.try_recv().unwrap(), b"c");
+        assert_eq!(other_client.try_recv().unwrap(), b"d");
+        assert!(first.try_recv().is_err());
+        assert_eq!(flows.lock().len(), 3);
+    }
+
+    #[test]
+    fn ended_flow_is_replaced_by_a_new_one() {
+        let flows: FlowMap = Arc::new(Mutex::new(HashMap::new()));
+        let key = (addr("192.168.1.20:40000"), addr("1.1.1.1:53"));
+
+        drop(dispatch(&flows, key, b"a".to_vec()).unwrap());
+        let mut next = dispatch(&flows, key, b"b".to_vec()).unwrap();
+        assert_eq!(next.try_recv().unwrap(), b"b");
+        assert_eq!(flows.lock().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn original_destination_is_read_from_control_message() {
+        let listener = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        // 未经 TPROXY 的数据报，IP_ORIGDSTADDR 就是监听地址本身。
+        setsockopt(&listener, sockopt::Ipv4OrigDstAddr, &true).unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        sender
+            .send_to(b"query", listener.local_addr().unwrap())
+            .await
+            .unwrap();
+
+        let mut buf = [0u8; 64];
+        let (n, client, target) = recv_with_orig_dst(&listener, &mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"query");
+        assert_eq!(client, sender.local_addr().unwrap());
+        assert_eq!(target, listener.local_addr().unwrap());
+    }
+
+    #[tokio::test]
+    async fn datagrams_without_original_destination_are_rejected() {
+        let listener = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        sender
+            .send_to(b"query", listener.local_addr().unwrap())
+            .await
+            .unwrap();
+
+        let mut buf = [0u8; 64];
+        assert!(recv_with_orig_dst(&listener, &mut buf).await.is_err());
+    }
+}
@@ -3,18 +3,22 @@
 //! 当 TUN 模式启用时，agent 会打开一个 TUN 设备，并使用
 //! [`netstack-smoltcp`](https://crates.io/crates/netstack-smoltcp) 在其上构建
 //! 用户空间 TCP/IP 协议栈。协议栈接受的 TCP/UDP 流会按配置选择
-//! agent 本地直连，或通过 [`YamuxSessionManager`] 转发到 proxy。
-//! `direct_access` 规则与 TUN UDP 的 `proxy_udp` 开关共同决定具体路径。
+//! agent 本地直连、拒绝，或通过对应 proxy 分组的 [`YamuxSessionManager`] 转发到 proxy。
+//! 分流规则与 TUN UDP 的 `proxy_udp` 开关共同决定具体路径。
+//!
+//! [`YamuxSessionManager`]: crate::yamux_session::YamuxSessionManager
 
 mod device;
 mod direct_domain_cache;
 mod dns;
-mod dns_proxy;
+pub(crate) mod dns_proxy;
+mod fake_ip;
 #[cfg(target_os = "macos")]
 #[allow(dead_code)]
 pub(crate) mod helper_service;
 mod netstack;
-mod network;
+pub(crate) mod network;
+mod process;
 mod proxy_routing;
 mod route;
 mod tasks;
@@ -23,35 +27,36 @@ mod udp;
 mod udp_relay;
 
 use crate::config::TunConfig;
-use crate::direct_access::DirectAccessChecker;
 use crate::error::{AgentError, Result};
 use crate::privilege::ensure_tun_privileges_or_relaunch;
+use crate::routing::OutboundRouter;
 #[cfg(target_os = "macos")]
 use crate::tun_helper_client::{
     HelperTunLease,
     refresh_macos_scoped_default_bypass as refresh_macos_scoped_default_bypass_via_helper,
     start_tun as start_tun_via_helper,
 };
-use crate::yamux_session::YamuxSessionManager;
 use common::{
     TransportMode, install_known_smoltcp_panic_hook, panic_payload_message, spawn_guarded,
 };
-#[cfg(windows)]
+#[cfg(any(windows, target_os = "linux"))]
 use device::tun_ipv4_peer;
 use device::{CreatedTunDevice, create_tun_device};
 use direct_domain_cache::DirectDomainCache;
 use dns::DnsGuard;
+use fake_ip::FakeIpPool;
 use futures::FutureExt;
 use netstack::{spawn_netstack_supervisor, wait_tun_task};
 use netstack_smoltcp::StackBuilder;
 use network::{TunNetworks, parse_cidr_v4, parse_cidr_v6};
+use process::ProcessResolver;
 use proxy_routing::{bind_interface_is_usable, configure_proxy_routing, install_route_guard};
 use route::{
     RouteGuard, cleanup_stale_routes, detect_default_route_interface, detect_proxy_route,
     refresh_macos_scoped_default_bypass as refresh_macos_scoped_default_bypass_local,
     resolve_proxy_ips,
 };
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::panic::AssertUnwindSafe;
 #[cfg(windows)]
 use std::path::{Path, PathBuf};
@@ -69,21 +74,41 @@ const DIRECT_EGRESS_REFRESH_COOLDOWN: Duration = Duration::from_secs(2);
 
 #[derive(Clone)]
 struct TunForwardContext {
-    // TCP/UDP 两类 proxy Yamux session 管理器分开，避免 UDP 高并发挤占 TCP session。
-    tcp_sessions: Arc<YamuxSessionManager>,
-    udp_sessions: Arc<YamuxSessionManager>,
-    // TUN TCP/UDP 都会复用同一套直连规则。
-    direct_checker: Arc<DirectAccessChecker>,
-    // DNS proxy 会记录域名解析结果，TCP/UDP 后续可用 IP -> 域名映射命中直连规则。
+    // 分流规则与各 proxy 分组；每个分组的 TCP/UDP 两类 Yamux session 管理器分开，
+    // 避免 UDP 高并发挤占 TCP session。
+    router: Arc<OutboundRouter>,
+    // DNS proxy 会记录域名解析结果，TCP/UDP 后续可用 IP -> 域名映射命中域名规则。
     direct_domain_cache: Arc<DirectDomainCache>,
+    // 启用 fake-IP 时，DNS proxy 从该地址池作答，TCP/UDP 据此把假地址还原成域名。
+    fake_ip: Option<Arc<FakeIpPool>>,
+    // true 时从 TCP 首包和 QUIC Initial 包嗅探域名，补足 DNS 缓存缺失的映射。
+    sniff: bool,
     tun_networks: TunNetworks,
     // true 时，系统 DNS 请求会被映射成 proxy 端 DNS 虚拟目标。
     proxy_dns: bool,
     // true 保持普通 UDP 原有路由语义；false 时除代理 DNS 与 QUIC 外均从 agent 直连。
-    // UDP/443 QUIC 由 quic_policy 与 direct_access 独立决定。
+    // UDP/443 QUIC 由 quic_policy 与分流规则独立决定。
     proxy_udp: bool,
     // 直连路径的物理出口绑定信息，可在失败后刷新。
     direct_egress: Arc<TunDirectEgress>,
+    // 按源地址反查发起进程，供进程规则与流量日志使用。
+    process_resolver: Arc<ProcessResolver>,
+    // true 时即使没有进程规则也反查发起进程。
+    log_process: bool,
+}
+
+impl TunForwardContext {
+    /// 当前规则需要或配置要求记录时，反查 TUN 流的发起进程。
+    async fn source_process(
+        &self,
+        network: protocol::TransportProtocol,
+        source: SocketAddr,
+    ) -> Option<Arc<common::SourceProcess>> {
+        if !self.log_process && !self.router.policy().has_process_rules() {
+            return None;
+        }
+        self.process_resolver.lookup(network, source).await
+    }
 }
 
 struct TunDirectEgress {
@@ -144,8 +169,7 @@ impl TunDirectEgress {
     async fn refresh_after_direct_failure(
         &self,
         target_ip: IpAddr,
-        tcp_sessions: &YamuxSessionManager,
-        udp_sessions: &YamuxSessionManager,
+        router: &OutboundRouter,
         tun_networks: TunNetworks,
     ) -> Option<common::BindInterface> {
         // 直连失败后刷新物理出口，但用冷却时间避免大量连接同时触发路由探测。
@@ -159,12 +183,7 @@ impl TunDirectEgress {
         }
 
         let refreshed = self
-            .refresh_after_direct_failure_locked(
-                target_ip,
-                tcp_sessions,
-                udp_sessions,
-                tun_networks,
-            )
+            .refresh_after_direct_failure_locked(target_ip, router, tun_networks)
             .await;
         self.mark_refreshed(target_ip);
         refreshed
@@ -173,12 +192,11 @@ impl TunDirectEgress {
     async fn refresh_after_direct_failure_locked(
         &self,
         target_ip: IpAddr,
-        tcp_sessions: &YamuxSessionManager,
-        udp_sessions: &YamuxSessionManager,
+        router: &OutboundRouter,
         tun_networks: TunNetworks,
     ) -> Option<common::BindInterface> {
         // helper 管理的 macOS 路由可能在待机/切网后需要先刷新。
-        // 优先重新探测 proxy 出口，这样可以同步刷新两类 proxy session manager；
+        // 优先重新探测 proxy 出口，这样可以同步刷新所有分组的 proxy session manager；
         // 若探测结果属于 TUN、地址族不匹配或没有可用接口，再按目标地址族取系统默认接口。
         self.refresh_macos_scoped_default_bypass();
         let Some(route) = detect_proxy_route(self.proxy_addrs.as_slice()).await else {
@@ -207,10 +225,7 @@ impl TunDirectEgress {
         // proxy 出口刷新与 direct 目标的地址族选择分开：
         // 即使当前 direct 目标是 IPv4、proxy 走 IPv6（或反之），
         // 后续 proxy session 也应该立即拿到新出口。
-        tcp_sessions.set_proxy_bind_ip(Some(route.local_ip));
-        tcp_sessions.set_proxy_bind_interface(Some(bind_interface.clone()));
-        udp_sessions.set_proxy_bind_ip(Some(route.local_ip));
-        udp_sessions.set_proxy_bind_interface(Some(bind_interface.clone()));
+        router.set_proxy_bind(Some(route.local_ip), Some(bind_interface.clone()));
 
         if route.local_ip.is_ipv6() != target_ip.is_ipv6() {
             info!(
@@ -302,14 +317,12 @@ impl TunDirectEgress {
 }
 
 /// 公开入口：构建 TUN 设备，连接到 netstack，运行转发循环直到 `shutdown` 触发。
-#[instrument(skip(tcp_sessions, udp_sessions, direct_access_checker, shutdown))]
+#[instrument(skip(router, shutdown))]
 pub async fn run_tun_mode(
     config: TunConfig,
     transport_mode: TransportMode,
     proxy_addrs: Vec<String>,
-    tcp_sessions: Arc<YamuxSessionManager>,
-    udp_sessions: Arc<YamuxSessionManager>,
-    direct_access_checker: Arc<DirectAccessChecker>,
+    router: Arc<OutboundRouter>,
     shutdown: CancellationToken,
 ) -> Result<()> {
     let native_udp = transport_mode.uses_native_udp_for(protocol::TransportProtocol::Udp);
@@ -325,7 +338,7 @@ pub async fn run_tun_mode(
     info!(
         "TUN 普通 UDP（不含代理 DNS/UDP443）转发：{}",
         if proxy_udp {
-            "保持原有 proxy/direct_access 路由"
+            "按分流规则路由"
         } else {
             "agent 端直连目标"
         }
@@ -334,7 +347,7 @@ pub async fn run_tun_mode(
     info!("TUN UDP/443 QUIC 策略：{}", quic_policy.description_zh());
     if !quic_policy.should_block_udp443() {
         info!(
-            "TUN UDP/443 已允许：直连规则命中时直连，否则通过 proxy 转发（UDP 传输={}）",
+            "TUN UDP/443 已允许：按分流规则直连、阻断或通过 proxy 转发（UDP 传输={}）",
             if native_udp {
                 "原生加密 UDP"
             } else {
@@ -347,17 +360,18 @@ pub async fn run_tun_mode(
     let (ipv4, ipv4_prefix) = parse_cidr_v4(&config.ipv4)?;
     let ipv6_config = config.ipv6.as_deref().map(parse_cidr_v6).transpose()?;
     let tun_networks = TunNetworks::new(ipv4, ipv4_prefix, ipv6_config);
+    let fake_ip = create_fake_ip_pool(&config, ipv4, tun_networks)?;
+
+    // 策略路由按 SO_MARK 放行 agent 自身的 proxy 连接，标记须在任何 proxy 连接建立前设置。
+    #[cfg(target_os = "linux")]
+    if config.linux_policy_routing {
+        router.set_proxy_fwmark(Some(config.linux_fwmark));
+    }
 
     // 在劫持默认路由前配置 proxy 连接绕行，否则 agent 到 proxy 也会进 TUN。
     // 这个顺序非常关键：先固定控制连接出口，再安装 TUN/split-default 路由。
-    let proxy_bind_interface = configure_proxy_routing(
-        &config,
-        &proxy_addrs,
-        &tcp_sessions,
-        &udp_sessions,
-        &shutdown,
-    )
-    .await;
+    let proxy_bind_interface =
+        configure_proxy_routing(&config, &proxy_addrs, &router, &shutdown).await;
     if shutdown.is_cancelled() {
         info!("TUN 模式启动过程中收到关闭请求，跳过 TUN 设备创建");
         return Ok(());
@@ -391,14 +405,16 @@ pub async fn run_tun_mode(
         helper_managed_network.then(|| config.macos_helper_socket.clone()),
     ));
     let forward_context = TunForwardContext {
-        tcp_sessions: tcp_sessions.clone(),
-        udp_sessions: udp_sessions.clone(),
-        direct_checker: direct_access_checker.clone(),
+        router: router.clone(),
         direct_domain_cache: Arc::new(DirectDomainCache::new(Duration::from_secs(300))),
+        fake_ip,
+        sniff: config.sniff,
         tun_networks,
         proxy_dns,
         proxy_udp,
         direct_egress,
+        process_resolver: Arc::new(ProcessResolver::new()),
+        log_process: config.log_process,
     };
     let netstack_task = spawn_netstack_supervisor(
         device.clone(),
@@ -410,13 +426,20 @@ pub async fn run_tun_mode(
     let route_guard = if helper_managed_network {
         None
     } else {
-        install_route_guard(&config, ipv4, ipv4_prefix, tun_if_index, &proxy_addrs)
+        install_route_guard(
+            &config,
+            ipv4,
+            ipv4_prefix,
+            tun_if_index,
+            &tun_name,
+            &proxy_addrs,
+        )
     };
-    #[cfg(windows)]
+    #[cfg(any(windows, target_os = "linux"))]
     let dns_guard = if helper_managed_network {
         None
     } else {
-        install_windows_dns_guard(
+        install_system_dns_guard(
             proxy_dns,
             proxy_bind_interface.as_ref(),
             tun_if_index,
@@ -425,7 +448,7 @@ pub async fn run_tun_mode(
             config.dns_state_file.as_deref(),
         )
     };
-    #[cfg(not(windows))]
+    #[cfg(not(any(windows, target_os = "linux")))]
     if !helper_managed_network {
         cleanup_stale_dns(config.dns_state_file.as_deref());
     }
@@ -434,14 +457,13 @@ pub async fn run_tun_mode(
     info!("收到 TUN 模式关闭请求");
 
     // 先恢复系统网络状态，再等待内部任务退出。否则任一任务卡住都会延迟路由恢复。
-    tcp_sessions.set_proxy_bind_ip(None);
-    tcp_sessions.set_proxy_bind_interface(None);
-    udp_sessions.set_proxy_bind_ip(None);
-    udp_sessions.set_proxy_bind_interface(None);
+    router.set_proxy_bind(None, None);
+    router.set_proxy_fwmark(None);
     // Windows DNS Client 会按接口发送查询，仅安装 DNS 服务器的 /32 TUN
     // 路由无法可靠捕获这类流量。先恢复接口 DNS，再撤销 TUN 路由，避免
-    // 退出窗口内系统查询仍指向已经不可达的虚拟 DNS 地址。
-    #[cfg(windows)]
+    // 退出窗口内系统查询仍指向已经不可达的虚拟 DNS 地址。Linux 同理先恢复
+    // resolv.conf / systemd-resolved 链路配置。
+    #[cfg(any(windows, target_os = "linux"))]
     drop(dns_guard);
     drop(route_guard);
     #[cfg(target_os = "macos")]
@@ -455,8 +477,37 @@ pub async fn run_tun_mode(
     Ok(())
 }
 
-#[cfg(windows)]
-fn install_windows_dns_guard(
+fn create_fake_ip_pool(
+    config: &TunConfig,
+    tun_ipv4: std::net::Ipv4Addr,
+    tun_networks: TunNetworks,
+) -> Result<Option<Arc<FakeIpPool>>> {
+    let Some(range) = config.fake_ip_range.as_deref() else {
+        return Ok(None);
+    };
+    if !config.proxy_dns {
+        warn!("TUN fake_ip_range 需要启用 proxy_dns，已忽略 fake-IP 配置");
+        return Ok(None);
+    }
+    let (network, prefix) = parse_cidr_v4(range)?;
+    let pool = FakeIpPool::new(network, prefix).ok_or_else(|| {
+        AgentError::Connection(format!(
+            "fake-IP 地址池 {range} 无效：前缀必须在 /8 到 /30 之间"
+        ))
+    })?;
+    if pool.contains(IpAddr::V4(tun_ipv4)) || tun_networks.contains_ip(IpAddr::V4(network)) {
+        return Err(AgentError::Connection(format!(
+            "fake-IP 地址池 {range} 与 TUN 网段重叠"
+        )));
+    }
+    info!("TUN fake-IP 已启用：地址池 {range}，走 proxy 的域名由 proxy 端解析");
+    Ok(Some(Arc::new(pool)))
+}
+
+/// Windows 切换 TUN 接口 DNS，Linux 通过 systemd-resolved 或 resolv.conf 接管；
+/// 两者都把系统 DNS 指向 TUN 网段里的虚拟 peer 地址。
+#[cfg(any(windows, target_os = "linux"))]
+fn install_system_dns_guard(
     proxy_dns: bool,
     proxy_bind_interface: Option<&common::BindInterface>,
     tun_if_index: u32,
@@ -469,7 +520,7 @@ fn install_windows_dns_guard(
         cleanup_stale_dns(dns_state_file);
         if proxy_dns {
             warn!(
-                "TUN proxy_dns 已启用，但 {} 无可用虚拟 peer 地址；跳过 Windows 系统 DNS 接管",
+                "TUN proxy_dns 已启用，但 {} 无可用虚拟 peer 地址；跳过系统 DNS 接管",
                 format_args!("{tun_ipv4}/{tun_ipv4_prefix}")
             );
         }
@@ -477,9 +528,7 @@ fn install_windows_dns_guard(
     };
 
     if proxy_dns {
-        info!(
-            "Windows TUN proxy_dns 使用虚拟 DNS 地址：{tun_dns} (TUN={tun_ipv4}/{tun_ipv4_prefix})"
-        );
+        info!("TUN proxy_dns 使用虚拟 DNS 地址：{tun_dns} (TUN={tun_ipv4}/{tun_ipv4_prefix})");
     }
     DnsGuard::install(
         proxy_dns,
@@ -491,7 +540,7 @@ fn install_windows_dns_guard(
 }
 
 fn cleanup_stale_dns(dns_state_file: Option<&str>) {
-    // Windows 正常生命周期由 install_windows_dns_guard 持有 guard；本函数用于
+    // Windows/Linux 正常生命周期由 install_system_dns_guard 持有 guard；本函数用于
     // proxy_dns 关闭、无可用虚拟 peer，以及其他平台清理异常退出遗留的状态。
     debug!("检查并恢复旧版本或异常退出遗留的 DNS 状态");
     let _ = DnsGuard::install(
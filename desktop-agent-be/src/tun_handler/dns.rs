@@ -1,13 +1,15 @@
-#[cfg(not(any(target_os = "macos", windows)))]
+#[cfg(not(any(target_os = "macos", target_os = "linux", windows)))]
 use common::BindInterface;
 #[cfg(not(any(target_os = "macos", windows)))]
 use std::fs;
 use std::net::IpAddr;
-#[cfg(not(any(target_os = "macos", windows)))]
+#[cfg(not(any(target_os = "macos", target_os = "linux", windows)))]
 use std::net::Ipv4Addr;
-#[cfg(not(any(target_os = "macos", windows)))]
+#[cfg(not(any(target_os = "macos", target_os = "linux", windows)))]
 use tracing::debug;
 
+#[cfg(target_os = "linux")]
+mod linux;
 #[cfg(target_os = "macos")]
 mod macos;
 mod state;
@@ -16,15 +18,17 @@ mod tests;
 #[cfg(windows)]
 mod windows;
 
+#[cfg(target_os = "linux")]
+pub(super) use linux::DnsGuard;
 #[cfg(target_os = "macos")]
 pub(super) use macos::DnsGuard;
 #[cfg(windows)]
 pub(super) use windows::DnsGuard;
 
-#[cfg(not(any(target_os = "macos", windows)))]
+#[cfg(not(any(target_os = "macos", target_os = "linux", windows)))]
 pub(super) struct DnsGuard;
 
-#[cfg(not(any(target_os = "macos", windows)))]
+#[cfg(not(any(target_os = "macos", target_os = "linux", windows)))]
 impl DnsGuard {
     pub(super) fn install(
         proxy_dns: bool,
@@ -102,7 +106,12 @@ pub(super) fn flush_system_dns_cache() {
     macos::flush_dns_cache();
 }
 
-#[cfg(not(target_os = "macos"))]
+#[cfg(target_os = "linux")]
+pub(super) fn flush_system_dns_cache() {
+    linux::flush_dns_cache();
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
 pub(super) fn flush_system_dns_cache() {}
 
 fn parse_dns_server_ips(output: &str) -> Vec<IpAddr> {
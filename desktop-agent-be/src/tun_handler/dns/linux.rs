@@ -0,0 +1,309 @@
+use super::state::DnsLease;
+use common::BindInterface;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{debug, info, warn};
+
+const RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
+const RESOLVED_RUNTIME_DIR: &str = "/run/systemd/resolve";
+/// 写入 resolv.conf 的首行；恢复时只回滚仍带这个标记的文件，避免覆盖他人后来的修改。
+pub(super) const RESOLV_CONF_MARKER: &str = "# ppaass desktop-agent TUN proxy_dns，退出时恢复";
+const AF_INET: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) enum PreviousResolvConf {
+    Missing,
+    Symlink(String),
+    Content(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum DnsRecord {
+    /// systemd-resolved 上 TUN 链路的 DNS 与 `~.` 路由域；恢复时对该链路 RevertLink。
+    Resolved { interface_index: u32 },
+    /// 直接改写的 resolv.conf。
+    ResolvConf {
+        path: String,
+        previous: PreviousResolvConf,
+    },
+}
+
+pub(crate) struct DnsGuard {
+    record: DnsRecord,
+    lease: DnsLease<DnsRecord>,
+}
+
+impl DnsGuard {
+    pub(crate) fn install(
+        proxy_dns: bool,
+        _bind_interface: Option<&BindInterface>,
+        tun_interface_index: u32,
+        tun_dns: Ipv4Addr,
+        dns_state_file: Option<&str>,
+    ) -> Option<Self> {
+        let mut lease = DnsLease::new(dns_state_file);
+        lease.cleanup_stale_records(restore_dns_record);
+
+        if !proxy_dns {
+            return None;
+        }
+
+        if resolved_is_running() {
+            if tun_interface_index == 0 {
+                warn!("TUN 接口 index 无效，无法通过 systemd-resolved 设置链路 DNS");
+            } else {
+                let record = DnsRecord::Resolved {
+                    interface_index: tun_interface_index,
+                };
+                lease.record_active(record.clone());
+                match resolved_set_link_dns(tun_interface_index, tun_dns) {
+                    Ok(()) => {
+                        flush_dns_cache();
+                        info!(
+                            "TUN proxy_dns 已通过 systemd-resolved 接管系统 DNS：接口={tun_interface_index} DNS={tun_dns}"
+                        );
+                        return Some(Self { record, lease });
+                    }
+                    Err(e) => {
+                        warn!(
+                            "通过 systemd-resolved 设置 TUN 链路 DNS 失败，改为修改 resolv.conf：{e}"
+                        );
+                        if restore_dns_record(&record) {
+                            lease.remove_record(&record, same_dns_target);
+                        }
+                    }
+                }
+            }
+        }
+
+        let path = Path::new(RESOLV_CONF_PATH);
+        let previous = match read_previous_resolv_conf(path) {
+            Ok(previous) => previous,
+            Err(e) => {
+                warn!("读取 {} 失败，跳过系统 DNS 临时切换：{e}", path.display());
+                return None;
+            }
+        };
+        let current = fs::read_to_string(path).unwrap_or_default();
+        let record = DnsRecord::ResolvConf {
+            path: RESOLV_CONF_PATH.to_string(),
+            previous,
+        };
+        lease.record_active(record.clone());
+        if let Err(e) = replace_file(path, render_resolv_conf(&current, tun_dns).as_bytes()) {
+            warn!("改写 {} 失败：{e}", path.display());
+            if restore_dns_record(&record) {
+                lease.remove_record(&record, same_dns_target);
+            }
+            return None;
+        }
+
+        info!(
+            "TUN proxy_dns 已接管系统 DNS：{} -> nameserver {tun_dns}",
+            path.display()
+        );
+        Some(Self { record, lease })
+    }
+}
+
+impl Drop for DnsGuard {
+    fn drop(&mut self) {
+        if restore_dns_record(&self.record) {
+            self.lease.remove_record(&self.record, same_dns_target);
+        } else {
+            warn!(
+                "保留 TUN DNS 状态文件以便下次启动重试：{}",
+                self.lease.path.display()
+            );
+        }
+    }
+}
+
+fn same_dns_target(left: &DnsRecord, right: &DnsRecord) -> bool {
+    match (left, right) {
+        (
+            DnsRecord::Resolved {
+                interface_index: left,
+            },
+            DnsRecord::Resolved {
+                interface_index: right,
+            },
+        ) => left == right,
+        (DnsRecord::ResolvConf { path: left, .. }, DnsRecord::ResolvConf { path: right, .. }) => {
+            left == right
+        }
+        _ => false,
+    }
+}
+
+fn restore_dns_record(record: &DnsRecord) -> bool {
+    match record {
+        DnsRecord::Resolved { interface_index } => {
+            match resolved_call("RevertLink", "i", &[interface_index.to_string()]) {
+                Ok(()) => {
+                    flush_dns_cache();
+                    info!("已恢复 systemd-resolved 接口 {interface_index} 的 DNS 配置");
+                    true
+                }
+                // TUN 设备删除后链路随之消失，resolved 上的链路配置也一并失效。
+                Err(e) if e.to_string().contains("not known") => {
+                    debug!("systemd-resolved 接口 {interface_index} 已不存在，无需恢复");
+                    true
+                }
+                Err(e) => {
+                    warn!("恢复 systemd-resolved 接口 {interface_index} 的 DNS 配置失败：{e}");
+                    false
+                }
+            }
+        }
+        DnsRecord::ResolvConf { path, previous } => {
+            match restore_resolv_conf(Path::new(path), previous) {
+                Ok(()) => {
+                    info!("已恢复 {path}");
+                    true
+                }
+                Err(e) => {
+                    warn!("恢复 {path} 失败：{e}");
+                    false
+                }
+            }
+        }
+    }
+}
+
+fn resolved_is_running() -> bool {
+    Path::new(RESOLVED_RUNTIME_DIR).is_dir()
+}
+
+fn resolved_set_link_dns(interface_index: u32, tun_dns: Ipv4Addr) -> std::io::Result<()> {
+    let index = interface_index.to_string();
+    let mut dns_args = vec![index.clone(), "1".to_string(), AF_INET.to_string()];
+    dns_args.push(tun_dns.octets().len().to_string());
+    dns_args.extend(tun_dns.octets().iter().map(u8::to_string));
+    resolved_call("SetLinkDNS", "ia(iay)", &dns_args)?;
+    // "." 加上 routing-only 标记即 resolvectl 里的 "~."：所有域名都优先走这条链路。
+    resolved_call(
+        "SetLinkDomains",
+        "ia(sb)",
+        &[
+            index.clone(),
+            "1".to_string(),
+            ".".to_string(),
+            "true".to_string(),
+        ],
+    )?;
+    resolved_call("SetLinkDefaultRoute", "ib", &[index, "true".to_string()])
+}
+
+pub(super) fn resolved_call_args(method: &str, signature: &str, args: &[String]) -> Vec<String> {
+    let mut call_args = vec![
+        "call".to_string(),
+        "org.freedesktop.resolve1".to_string(),
+        "/org/freedesktop/resolve1".to_string(),
+        "org.freedesktop.resolve1.Manager".to_string(),
+        method.to_string(),
+    ];
+    if !signature.is_empty() {
+        call_args.push(signature.to_string());
+        call_args.extend(args.iter().cloned());
+    }
+    call_args
+}
+
+fn resolved_call(method: &str, signature: &str, args: &[String]) -> std::io::Result<()> {
+    debug!("调用 systemd-resolved {method}");
+    let output = Command::new("busctl")
+        .args(resolved_call_args(method, signature, args))
+        .output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    Err(std::io::Error::other(if stderr.is_empty() {
+        format!("busctl {method} 退出状态 {}", output.status)
+    } else {
+        stderr
+    }))
+}
+
+pub(super) fn flush_dns_cache() {
+    if !resolved_is_running() {
+        return;
+    }
+    if let Err(e) = resolved_call("FlushCaches", "", &[]) {
+        debug!("刷新 systemd-resolved DNS 缓存失败：{e}");
+    }
+}
+
+/// 只保留原文件的 search/domain/options，nameserver 全部换成 TUN DNS。
+pub(super) fn render_resolv_conf(previous: &str, tun_dns: Ipv4Addr) -> String {
+    let mut content = format!("{RESOLV_CONF_MARKER}\nnameserver {tun_dns}\n");
+    for line in previous.lines().map(str::trim) {
+        if ["search", "domain", "options"]
+            .iter()
+            .any(|keyword| line.split_whitespace().next() == Some(keyword))
+        {
+            content.push_str(line);
+            content.push('\n');
+        }
+    }
+    content
+}
+
+pub(super) fn read_previous_resolv_conf(path: &Path) -> std::io::Result<PreviousResolvConf> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => Ok(PreviousResolvConf::Symlink(
+            fs::read_link(path)?.to_string_lossy().into_owned(),
+        )),
+        Ok(_) => Ok(PreviousResolvConf::Content(fs::read_to_string(path)?)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(PreviousResolvConf::Missing),
+        Err(e) => Err(e),
+    }
+}
+
+pub(super) fn restore_resolv_conf(
+    path: &Path,
+    previous: &PreviousResolvConf,
+) -> std::io::Result<()> {
+    let is_ours = match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => false,
+        Ok(_) => fs::read_to_string(path)?.starts_with(RESOLV_CONF_MARKER),
+        Err(e) if e.kind() == ErrorKind::NotFound => false,
+        Err(e) => return Err(e),
+    };
+    if !is_ours {
+        warn!(
+            "{} 已不是 agent 写入的内容，可能已被其他程序修改；不再恢复",
+            path.display()
+        );
+        return Ok(());
+    }
+
+    match previous {
+        PreviousResolvConf::Missing => fs::remove_file(path),
+        PreviousResolvConf::Content(content) => replace_file(path, content.as_bytes()),
+        PreviousResolvConf::Symlink(target) => {
+            let tmp_path = tmp_path_for(path);
+            let _ = fs::remove_file(&tmp_path);
+            std::os::unix::fs::symlink(target, &tmp_path)?;
+            fs::rename(tmp_path, path)
+        }
+    }
+}
+
+/// 先写临时文件再 rename：原路径是符号链接时替换的是链接本身，不会改到链接目标。
+fn replace_file(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let tmp_path = tmp_path_for(path);
+    fs::write(&tmp_path, content)?;
+    fs::rename(tmp_path, path)
+}
+
+fn tmp_path_for(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".ppaass.{}", std::process::id()));
+    path.with_file_name(name)
+}
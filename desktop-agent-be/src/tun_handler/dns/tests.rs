@@ -40,3 +40,70 @@ nameserver 1.1.1.1
         );
     }
 }
+
+#[cfg(target_os = "linux")]
+#[test]
+fn linux_resolv_conf_keeps_search_options_and_restores_symlink() {
+    use linux::*;
+    use std::net::Ipv4Addr;
+
+    let rendered = render_resolv_conf(
+        "nameserver 127.0.0.53\nsearch lan corp.example\noptions edns0 trust-ad\n",
+        Ipv4Addr::new(10, 10, 10, 2),
+    );
+    assert_eq!(
+        rendered,
+        format!(
+            "{RESOLV_CONF_MARKER}\nnameserver 10.10.10.2\nsearch lan corp.example\noptions edns0 trust-ad\n"
+        )
+    );
+
+    let dir = std::env::temp_dir().join(format!("ppaass-resolv-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("resolv.conf");
+    let _ = std::fs::remove_file(&path);
+    std::os::unix::fs::symlink("../run/stub-resolv.conf", &path).unwrap();
+
+    let previous = read_previous_resolv_conf(&path).unwrap();
+    assert_eq!(
+        previous,
+        PreviousResolvConf::Symlink("../run/stub-resolv.conf".to_string())
+    );
+    std::fs::remove_file(&path).unwrap();
+    std::fs::write(&path, &rendered).unwrap();
+    restore_resolv_conf(&path, &previous).unwrap();
+    assert_eq!(
+        std::fs::read_link(&path).unwrap(),
+        std::path::PathBuf::from("../run/stub-resolv.conf")
+    );
+
+    // 文件已被其他程序改写时不再回滚。
+    std::fs::remove_file(&path).unwrap();
+    std::fs::write(&path, "nameserver 1.1.1.1\n").unwrap();
+    restore_resolv_conf(&path, &PreviousResolvConf::Missing).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "nameserver 1.1.1.1\n"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn linux_resolved_call_args_use_manager_interface() {
+    let args = linux::resolved_call_args(
+        "SetLinkDomains",
+        "ia(sb)",
+        &[
+            "7".to_string(),
+            "1".to_string(),
+            ".".to_string(),
+            "true".to_string(),
+        ],
+    );
+    assert_eq!(
+        args.join(" "),
+        "call org.freedesktop.resolve1 /org/freedesktop/resolve1 \
+         org.freedesktop.resolve1.Manager SetLinkDomains ia(sb) 7 1 . true"
+    );
+}
@@ -2,11 +2,17 @@
 //!
 //! 当 TUN 捕获到 UDP/53 且启用 proxy_dns 时，DNS 请求会走这里：
 //! agent 通过 UDP Yamux session manager 连接 proxy 的 `Address::ProxyDns` 虚拟目标，让 proxy 端使用
-//! 它所在网络的 DNS 上游解析。同时本模块记录响应中的域名/IP 映射，供 direct_access
+//! 它所在网络的 DNS 上游解析。同时本模块记录响应中的域名/IP 映射，供分流规则
 //! 在后续 TCP/UDP IP 连接上还原域名规则。
+//!
+//! 启用 fake-IP 时，分流结果不是 direct 的域名的 A/AAAA 查询直接在本地作答：
+//! A 记录返回 [`FakeIpPool`] 分配的假地址，AAAA 返回空应答促使应用改用 IPv4。
+//! 分流到 direct 的域名仍交给上游解析，直连路径拿到的始终是真实地址。
 
 use super::direct_domain_cache::DirectDomainCache;
+use super::fake_ip::FakeIpPool;
 use super::udp::UdpWriter;
+use crate::routing::OutboundRouter;
 use crate::telemetry::{self, DnsResolutionRecord};
 use crate::yamux_session::YamuxSessionManager;
 use common::spawn_guarded;
@@ -15,7 +21,7 @@ use protocol::{Address, TransportProtocol};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::io;
-use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
@@ -28,18 +34,27 @@ mod parser;
 #[cfg(test)]
 mod tests;
 
-use parser::{parse_dns_query, parse_dns_response};
+pub(crate) use parser::parse_dns_response;
+use parser::{dns_question_end, parse_dns_query};
 
 const DNS_PENDING_TTL: Duration = Duration::from_secs(10);
 const DNS_REQUEST_CHANNEL_SIZE: usize = 1024;
 const DNS_PROXY_CONNECTION_IDLE: Duration = Duration::from_secs(15);
 const DNS_RESPONSE_CACHE_MAX_ENTRIES: usize = 4096;
 const DNS_RESPONSE_CACHE_MAX_TTL: Duration = Duration::from_secs(300);
+// 假地址映射可能被 LRU 淘汰，TTL 取 1 秒让应用尽快重新查询。
+const FAKE_IP_DNS_TTL: u32 = 1;
 
 pub(super) struct DnsProxy {
     tx: mpsc::Sender<DnsProxyRequest>,
 }
 
+/// fake-IP 模式下本地作答所需的地址池与分流策略。
+pub(super) struct FakeIpDns {
+    pub(super) pool: Arc<FakeIpPool>,
+    pub(super) router: Arc<OutboundRouter>,
+}
+
 #[derive(Clone)]
 struct DnsProxyRequest {
     client: SocketAddr,
@@ -58,10 +73,10 @@ struct PendingDnsRequest {
     expires_at: Instant,
 }
 
-struct DnsResponseSummary {
-    status: String,
-    answers: Vec<String>,
-    min_ttl: Option<u32>,
+pub(crate) struct DnsResponseSummary {
+    pub(crate) status: String,
+    pub(crate) answers: Vec<String>,
+    pub(crate) min_ttl: Option<u32>,
 }
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
@@ -75,13 +90,19 @@ struct CachedDnsResponse {
     expires_at: Instant,
 }
 
+/// 按查询名与记录类型缓存完整 DNS 响应，TUN DNS proxy 与本地 DNS 监听共用。
 #[derive(Default)]
-struct DnsResponseCache {
+pub(crate) struct DnsResponseCache {
     entries: HashMap<DnsCacheKey, CachedDnsResponse>,
 }
 
 impl DnsResponseCache {
-    fn get(&mut self, query: &str, record_type: &str, request_id: u16) -> Option<Vec<u8>> {
+    pub(crate) fn get(
+        &mut self,
+        query: &str,
+        record_type: &str,
+        request_id: u16,
+    ) -> Option<Vec<u8>> {
         self.cleanup_expired();
         let key = dns_cache_key(query, record_type);
         let entry = self.entries.get(&key)?;
@@ -95,7 +116,7 @@ impl DnsResponseCache {
         Some(packet)
     }
 
-    fn insert(
+    pub(crate) fn insert(
         &mut self,
         query: &str,
         record_type: &str,
@@ -159,12 +180,20 @@ impl DnsProxy {
         sessions: Arc<YamuxSessionManager>,
         netstack_tx: UdpWriter,
         direct_domain_cache: Arc<DirectDomainCache>,
+        fake_ip: Option<FakeIpDns>,
         shutdown: CancellationToken,
     ) -> Arc<Self> {
         let (tx, rx) = mpsc::channel(DNS_REQUEST_CHANNEL_SIZE);
         spawn_guarded(
             "desktop tun dns proxy",
-            run_dns_proxy(sessions, netstack_tx, direct_domain_cache, rx, shutdown),
+            run_dns_proxy(
+                sessions,
+                netstack_tx,
+                direct_domain_cache,
+                fake_ip,
+                rx,
+                shutdown,
+            ),
         );
         Arc::new(Self { tx })
     }
@@ -186,6 +215,7 @@ async fn run_dns_proxy(
     sessions: Arc<YamuxSessionManager>,
     netstack_tx: UdpWriter,
     direct_domain_cache: Arc<DirectDomainCache>,
+    fake_ip: Option<FakeIpDns>,
     mut rx: mpsc::Receiver<DnsProxyRequest>,
     shutdown: CancellationToken,
 ) {
@@ -210,9 +240,10 @@ async fn run_dns_proxy(
             }
         };
 
-        if try_send_cached_dns_response(
+        if try_send_local_dns_response(
             &netstack_tx,
             direct_domain_cache.as_ref(),
+            fake_ip.as_ref(),
             &mut response_cache,
             &first_request,
         )
@@ -251,9 +282,10 @@ async fn run_dns_proxy(
 
         loop {
             if let Some(request) = retry_request.take() {
-                if try_send_cached_dns_response(
+                if try_send_local_dns_response(
                     &netstack_tx,
                     direct_domain_cache.as_ref(),
+                    fake_ip.as_ref(),
                     &mut response_cache,
                     &request,
                 )
@@ -292,9 +324,10 @@ async fn run_dns_proxy(
                         let _ = writer.shutdown().await;
                         return;
                     };
-                    if try_send_cached_dns_response(
+                    if try_send_local_dns_response(
                         &netstack_tx,
                         direct_domain_cache.as_ref(),
+                        fake_ip.as_ref(),
                         &mut response_cache,
                         &request,
                     ).await {
@@ -353,6 +386,100 @@ async fn connect_dns_stream(
     Ok(connected.into_async_io())
 }
 
+/// 先尝试 fake-IP 作答，再查响应缓存；本地已作答时返回 true。
+async fn try_send_local_dns_response(
+    netstack_tx: &UdpWriter,
+    direct_domain_cache: &DirectDomainCache,
+    fake_ip: Option<&FakeIpDns>,
+    response_cache: &mut DnsResponseCache,
+    request: &DnsProxyRequest,
+) -> bool {
+    if let Some(fake_ip) = fake_ip
+        && try_send_fake_ip_response(netstack_tx, fake_ip, request).await
+    {
+        return true;
+    }
+    try_send_cached_dns_response(netstack_tx, direct_domain_cache, response_cache, request).await
+}
+
+async fn try_send_fake_ip_response(
+    netstack_tx: &UdpWriter,
+    fake_ip: &FakeIpDns,
+    request: &DnsProxyRequest,
+) -> bool {
+    let Some((query, record_type)) = parse_dns_query(&request.packet) else {
+        return false;
+    };
+    if record_type != "A" && record_type != "AAAA" {
+        return false;
+    }
+    // 可能分流到 direct 的域名需要真实地址，交给上游解析。端口、进程等条件要到建连时才知道，
+    // 只要有可能命中 direct 规则就不发假地址。
+    if fake_ip.router.policy().may_route_direct(&query) {
+        return false;
+    }
+    let answer = (record_type == "A").then(|| IpAddr::V4(fake_ip.pool.ip_for_domain(&query)));
+    let Some(response) = local_dns_response(&request.packet, answer.as_slice(), FAKE_IP_DNS_TTL)
+    else {
+        debug!("TUN UDP DNS fake-IP 应答构造失败：{query}");
+        return false;
+    };
+
+    telemetry::emit_dns_resolution(DnsResolutionRecord {
+        timestamp_ms: telemetry::current_time_millis(),
+        resolver: "agent-fake-ip".to_string(),
+        client: request.client.to_string(),
+        upstream: request.target.to_string(),
+        query,
+        record_type,
+        status: "NOERROR".to_string(),
+        answers: answer.iter().map(ToString::to_string).collect(),
+        duration_ms: 0,
+    });
+
+    let mut writer = netstack_tx.lock().await;
+    if let Err(e) = writer
+        .send((response, request.target, request.client))
+        .await
+    {
+        debug!("TUN UDP DNS fake-IP 回复写回失败：{e}");
+    }
+    true
+}
+
+/// 按查询包在本地构造 NOERROR 应答：保留 ID 与问题段，`answers` 为空时返回空应答。
+/// IPv4 写成 A 记录，IPv6 写成 AAAA 记录，调用方负责按查询类型筛选。
+pub(crate) fn local_dns_response(query: &[u8], answers: &[IpAddr], ttl: u32) -> Option<Vec<u8>> {
+    let question_end = dns_question_end(query)?;
+    let mut response = query[..question_end].to_vec();
+    // QR=1，保留 OPCODE 与 RD，置 RA=1，RCODE=NOERROR。
+    response[2] = 0x80 | (query[2] & 0x79);
+    response[3] = 0x80;
+    response[4..6].copy_from_slice(&1u16.to_be_bytes());
+    response[6..8].copy_from_slice(&u16::try_from(answers.len()).ok()?.to_be_bytes());
+    // 丢弃 EDNS 等附加段。
+    response[8..12].fill(0);
+    for answer in answers {
+        // 名称压缩指针指向偏移 12 的问题段域名。
+        response.extend_from_slice(&[0xc0, 0x0c]);
+        match answer {
+            IpAddr::V4(ip) => {
+                response.extend_from_slice(&[0x00, 0x01, 0x00, 0x01]);
+                response.extend_from_slice(&ttl.to_be_bytes());
+                response.extend_from_slice(&4u16.to_be_bytes());
+                response.extend_from_slice(&ip.octets());
+            }
+            IpAddr::V6(ip) => {
+                response.extend_from_slice(&[0x00, 0x1c, 0x00, 0x01]);
+                response.extend_from_slice(&ttl.to_be_bytes());
+                response.extend_from_slice(&16u16.to_be_bytes());
+                response.extend_from_slice(&ip.octets());
+            }
+        }
+    }
+    Some(response)
+}
+
 async fn try_send_cached_dns_response(
     netstack_tx: &UdpWriter,
     direct_domain_cache: &DirectDomainCache,
@@ -534,12 +661,12 @@ fn allocate_dns_id(pending: &HashMap<u16, PendingDnsRequest>, next_id: &mut u16)
     None
 }
 
-fn dns_id(packet: &[u8]) -> Option<u16> {
+pub(crate) fn dns_id(packet: &[u8]) -> Option<u16> {
     let bytes = packet.get(..2)?;
     Some(u16::from_be_bytes([bytes[0], bytes[1]]))
 }
 
-fn write_dns_id(packet: &mut [u8], id: u16) {
+pub(crate) fn write_dns_id(packet: &mut [u8], id: u16) {
     let bytes = id.to_be_bytes();
     packet[0] = bytes[0];
     packet[1] = bytes[1];
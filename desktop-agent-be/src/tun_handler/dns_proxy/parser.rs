@@ -5,7 +5,15 @@ pub(super) fn parse_dns_query(packet: &[u8]) -> Option<(String, String)> {
     Some((query.query, query.record_type))
 }
 
-pub(super) fn parse_dns_response(packet: &[u8]) -> Option<DnsResponseSummary> {
+/// 返回唯一问题段结束的位置，用于在本地直接构造应答。
+pub(super) fn dns_question_end(packet: &[u8]) -> Option<usize> {
+    let mut offset = 12;
+    parse_dns_name(packet, &mut offset)?;
+    let end = offset.checked_add(4)?;
+    (end <= packet.len()).then_some(end)
+}
+
+pub(crate) fn parse_dns_response(packet: &[u8]) -> Option<DnsResponseSummary> {
     if packet.len() < 12 {
         return None;
     }
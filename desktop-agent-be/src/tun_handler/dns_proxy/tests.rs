@@ -97,3 +97,37 @@ fn dns_response_cache_rewrites_transaction_id_on_hit() {
     assert_eq!(dns_id(&cached), Some(0xabcd));
     assert_eq!(&cached[2..], &response[2..]);
 }
+
+#[test]
+fn local_response_answers_query_with_given_addresses() {
+    let mut query = vec![
+        0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x07, b'e', b'x',
+        b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00, 0x00, 0x01, 0x00, 0x01,
+    ];
+    // EDNS(0) OPT 记录不会出现在应答里。
+    query.extend_from_slice(&[
+        0x00, 0x00, 0x29, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ]);
+
+    let answers = [IpAddr::V4(Ipv4Addr::new(198, 18, 0, 1))];
+    let response = local_dns_response(&query, &answers, FAKE_IP_DNS_TTL).unwrap();
+    assert_eq!(dns_id(&response), Some(0x1234));
+    let parsed = parse_dns_response(&response).unwrap();
+    assert_eq!(parsed.status, "NOERROR");
+    assert_eq!(parsed.answers, vec!["198.18.0.1"]);
+    assert_eq!(parsed.min_ttl, Some(FAKE_IP_DNS_TTL));
+    assert_eq!(&response[8..12], &[0, 0, 0, 0]);
+
+    let empty = local_dns_response(&query, &[], FAKE_IP_DNS_TTL).unwrap();
+    let parsed = parse_dns_response(&empty).unwrap();
+    assert_eq!(parsed.status, "NOERROR");
+    assert!(parsed.answers.is_empty());
+
+    let answers = [
+        IpAddr::V6("2001:db8::1".parse().unwrap()),
+        IpAddr::V6("2001:db8::2".parse().unwrap()),
+    ];
+    let response = local_dns_response(&query, &answers, 60).unwrap();
+    let parsed = parse_dns_response(&response).unwrap();
+    assert_eq!(parsed.answers, vec!["2001:db8::1", "2001:db8::2"]);
+}
@@ -0,0 +1,172 @@
+//! TUN fake-IP 地址池。
+//!
+//! 启用后 DNS proxy 不再把走 proxy 的域名交给上游解析，而是从保留网段（如
+//! 198.18.0.0/15）中分配一个假地址作答，并在这里记录假地址与域名的双向映射。
+//! 应用随后连向假地址时，TCP/UDP 入口据此把目标还原成 `Address::Domain`，
+//! 由 proxy 端完成真实解析。地址池写满后按最近最少使用淘汰旧映射。
+
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr};
+
+/// 单个地址池最多保留的映射数，避免 /8 这类大网段让映射表无限增长。
+const FAKE_IP_MAX_ENTRIES: u32 = 65536;
+/// 允许的最短前缀。更短的网段会吞掉大量真实地址，/0 还会让掩码移位溢出。
+const FAKE_IP_MIN_PREFIX: u8 = 8;
+/// 允许的最长前缀：至少需要网络地址、一个可分配地址和广播地址。
+const FAKE_IP_MAX_PREFIX: u8 = 30;
+
+pub(super) struct FakeIpPool {
+    network: u32,
+    prefix: u8,
+    // 可分配的主机偏移为 1..=capacity，跳过网络地址。
+    capacity: u32,
+    table: Mutex<FakeIpTable>,
+}
+
+#[derive(Default)]
+struct FakeIpTable {
+    domain_to_offset: HashMap<String, u32>,
+    // 偏移 -> (域名, 最近一次使用的序号)。
+    offset_to_domain: HashMap<u32, (String, u64)>,
+    // 使用序号 -> 偏移，最小的序号即最久未使用的映射。
+    lru: BTreeMap<u64, u32>,
+    next_offset: u32,
+    tick: u64,
+}
+
+impl FakeIpPool {
+    pub(super) fn new(network: Ipv4Addr, prefix: u8) -> Option<Self> {
+        if !(FAKE_IP_MIN_PREFIX..=FAKE_IP_MAX_PREFIX).contains(&prefix) {
+            return None;
+        }
+        let mask = u32::MAX << (32 - prefix);
+        let hosts = !mask - 1;
+        Some(Self {
+            network: u32::from(network) & mask,
+            prefix,
+            capacity: hosts.min(FAKE_IP_MAX_ENTRIES),
+            table: Mutex::new(FakeIpTable::default()),
+        })
+    }
+
+    pub(super) fn contains(&self, ip: IpAddr) -> bool {
+        let IpAddr::V4(ip) = ip else {
+            return false;
+        };
+        let mask = u32::MAX << (32 - self.prefix);
+        u32::from(ip) & mask == self.network
+    }
+
+    /// 返回域名对应的假地址，没有映射时分配一个新地址。
+    pub(super) fn ip_for_domain(&self, domain: &str) -> Ipv4Addr {
+        let domain = domain.trim().trim_end_matches('.').to_ascii_lowercase();
+        let mut table = self.table.lock();
+        let offset = match table.domain_to_offset.get(&domain) {
+            Some(&offset) => offset,
+            None => table.allocate(domain, self.capacity),
+        };
+        table.touch(offset);
+        Ipv4Addr::from(self.network + offset)
+    }
+
+    /// 查询假地址对应的域名；映射已被淘汰或地址从未分配时返回 `None`。
+    pub(super) fn domain_for_ip(&self, ip: IpAddr) -> Option<String> {
+        if !self.contains(ip) {
+            return None;
+        }
+        let IpAddr::V4(ip) = ip else {
+            return None;
+        };
+        let offset = u32::from(ip) - self.network;
+        let mut table = self.table.lock();
+        let domain = table.offset_to_domain.get(&offset)?.0.clone();
+        table.touch(offset);
+        Some(domain)
+    }
+}
+
+impl FakeIpTable {
+    fn allocate(&mut self, domain: String, capacity: u32) -> u32 {
+        let offset = if (self.offset_to_domain.len() as u32) < capacity {
+            // 映射只会在池满后被淘汰，未满时顺序分配即可。
+            self.next_offset += 1;
+            self.next_offset
+        } else {
+            let (_, offset) = self
+                .lru
+                .pop_first()
+                .expect("full fake-ip table has lru entries");
+            if let Some((evicted, _)) = self.offset_to_domain.remove(&offset) {
+                self.domain_to_offset.remove(&evicted);
+            }
+            offset
+        };
+        self.domain_to_offset.insert(domain.clone(), offset);
+        self.offset_to_domain.insert(offset, (domain, 0));
+        offset
+    }
+
+    fn touch(&mut self, offset: u32) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some((_, last_used)) = self.offset_to_domain.get_mut(&offset) {
+            self.lru.remove(last_used);
+            *last_used = tick;
+            self.lru.insert(tick, offset);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(cidr: &str) -> FakeIpPool {
+        let (network, prefix) = cidr.split_once('/').unwrap();
+        FakeIpPool::new(network.parse().unwrap(), prefix.parse().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn maps_domains_to_stable_addresses_in_both_directions() {
+        let pool = pool("198.18.0.0/15");
+        let ip = pool.ip_for_domain("WWW.Example.com.");
+
+        assert_eq!(ip, Ipv4Addr::new(198, 18, 0, 1));
+        assert_eq!(pool.ip_for_domain("www.example.com"), ip);
+        assert_ne!(pool.ip_for_domain("example.org"), ip);
+        assert_eq!(
+            pool.domain_for_ip(IpAddr::V4(ip)).as_deref(),
+            Some("www.example.com")
+        );
+        assert!(pool.contains("198.19.255.254".parse().unwrap()));
+        assert!(!pool.contains("198.20.0.1".parse().unwrap()));
+        assert_eq!(pool.domain_for_ip("198.18.0.99".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn evicts_least_recently_used_mapping_when_full() {
+        // /30 只有两个可分配地址。
+        let pool = pool("198.18.0.0/30");
+        let a = pool.ip_for_domain("a.test");
+        let b = pool.ip_for_domain("b.test");
+        // 访问 a 后，b 成为最久未使用的映射。
+        assert_eq!(pool.domain_for_ip(IpAddr::V4(a)).as_deref(), Some("a.test"));
+
+        let c = pool.ip_for_domain("c.test");
+        assert_eq!(c, b);
+        assert_eq!(pool.domain_for_ip(IpAddr::V4(b)).as_deref(), Some("c.test"));
+        assert_eq!(pool.ip_for_domain("a.test"), a);
+        assert!(FakeIpPool::new(Ipv4Addr::new(198, 18, 0, 0), 31).is_none());
+    }
+
+    #[test]
+    fn rejects_ranges_that_would_cover_real_addresses() {
+        assert!(FakeIpPool::new(Ipv4Addr::UNSPECIFIED, 0).is_none());
+        assert!(FakeIpPool::new(Ipv4Addr::new(198, 0, 0, 0), 7).is_none());
+
+        let pool = pool("10.0.0.0/8");
+        assert!(pool.contains("10.255.255.254".parse().unwrap()));
+        assert!(!pool.contains("11.0.0.1".parse().unwrap()));
+    }
+}
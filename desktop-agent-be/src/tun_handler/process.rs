@@ -0,0 +1,259 @@
+//! TUN 流量的发起进程反查。
+//!
+//! netstack 只交出连接的源/目标地址。本机程序发出的连接在内核里仍是一个普通
+//! socket：Linux 上按 TUN 流的源地址在 `/proc/net/{tcp,tcp6,udp,udp6}` 中找到
+//! socket inode，再扫描 `/proc/*/fd` 找到持有它的进程并读取 `/proc/<pid>/exe`。
+//! 扫描所有进程的 fd 代价较高，结果（包括没找到）按 (协议, 源地址) 短暂缓存，
+//! 同一条 UDP 流持续有包时缓存持续有效。其他平台暂不支持，始终返回 `None`。
+
+use common::SourceProcess;
+use parking_lot::Mutex;
+use protocol::TransportProtocol;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::trace;
+
+/// 缓存项在最后一次命中后保留的时间。
+const PROCESS_CACHE_IDLE: Duration = Duration::from_secs(5);
+/// 缓存项上限，超过时先淘汰过期项。
+const PROCESS_CACHE_CAPACITY: usize = 4096;
+
+// (是否 UDP, 源地址)；TransportProtocol 没有实现 Hash。
+type CacheKey = (bool, SocketAddr);
+
+struct CachedProcess {
+    process: Option<Arc<SourceProcess>>,
+    last_used: Instant,
+}
+
+pub(super) struct ProcessResolver {
+    cache: Mutex<HashMap<CacheKey, CachedProcess>>,
+}
+
+impl ProcessResolver {
+    pub(super) fn new() -> Self {
+        Self {
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 查询打开 `source` 这个本地端点的进程；找不到或平台不支持时返回 `None`。
+    pub(super) async fn lookup(
+        &self,
+        network: TransportProtocol,
+        source: SocketAddr,
+    ) -> Option<Arc<SourceProcess>> {
+        let key = (matches!(network, TransportProtocol::Udp), source);
+        let now = Instant::now();
+        if let Some(cached) = self.cache.lock().get_mut(&key)
+            && now.duration_since(cached.last_used) < PROCESS_CACHE_IDLE
+        {
+            cached.last_used = now;
+            return cached.process.clone();
+        }
+
+        let process = find_process(network, source).await.map(Arc::new);
+        trace!("TUN 源地址 {source} ({network:?}) 的发起进程：{process:?}");
+        let mut cache = self.cache.lock();
+        if cache.len() >= PROCESS_CACHE_CAPACITY {
+            cache.retain(|_, cached| now.duration_since(cached.last_used) < PROCESS_CACHE_IDLE);
+            if cache.len() >= PROCESS_CACHE_CAPACITY {
+                cache.clear();
+            }
+        }
+        cache.insert(
+            key,
+            CachedProcess {
+                process: process.clone(),
+                last_used: now,
+            },
+        );
+        process
+    }
+}
+
+#[cfg(target_os = "linux")]
+async fn find_process(network: TransportProtocol, source: SocketAddr) -> Option<SourceProcess> {
+    // 读取 /proc 是阻塞文件 IO，进程多时要遍历上万个 fd 链接。
+    tokio::task::spawn_blocking(move || linux::find_process(network, source))
+        .await
+        .ok()
+        .flatten()
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn find_process(_network: TransportProtocol, _source: SocketAddr) -> Option<SourceProcess> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::*;
+    use std::fs;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::path::Path;
+
+    pub(super) fn find_process(
+        network: TransportProtocol,
+        source: SocketAddr,
+    ) -> Option<SourceProcess> {
+        let tables: &[&str] = match network {
+            TransportProtocol::Tcp => &["/proc/net/tcp", "/proc/net/tcp6"],
+            TransportProtocol::Udp => &["/proc/net/udp", "/proc/net/udp6"],
+        };
+        let inode = tables.iter().find_map(|table| {
+            let content = fs::read_to_string(table).ok()?;
+            find_socket_inode(&content, network, source)
+        })?;
+        let pid = find_socket_owner(inode)?;
+        Some(read_process(pid))
+    }
+
+    /// 在 `/proc/net/*` 表中找本地端点为 `source` 的 socket inode。
+    ///
+    /// 未 connect 的 UDP socket 本地地址是通配地址，找不到精确匹配时只比较端口；TCP 连接的
+    /// 本地地址总是具体地址，同端口的通配条目是监听 socket，不是发起连接的进程。
+    pub(super) fn find_socket_inode(
+        table: &str,
+        network: TransportProtocol,
+        source: SocketAddr,
+    ) -> Option<u64> {
+        let source_ip = source.ip().to_canonical();
+        let mut wildcard = None;
+        for (local, inode) in table.lines().skip(1).filter_map(parse_proc_net_line) {
+            if local.port() != source.port() || inode == 0 {
+                continue;
+            }
+            let local_ip = local.ip().to_canonical();
+            if local_ip == source_ip {
+                return Some(inode);
+            }
+            if network == TransportProtocol::Udp && local_ip.is_unspecified() {
+                wildcard.get_or_insert(inode);
+            }
+        }
+        wildcard
+    }
+
+    /// 解析一行 `/proc/net/{tcp,udp}[6]`，返回本地地址与 socket inode。
+    pub(super) fn parse_proc_net_line(line: &str) -> Option<(SocketAddr, u64)> {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let local = parse_proc_net_addr(fields.get(1)?)?;
+        let inode = fields.get(9)?.parse().ok()?;
+        Some((local, inode))
+    }
+
+    /// 地址按内核字节序以 32 位字打印：`0100007F:0035` 在小端机器上是 127.0.0.1:53。
+    fn parse_proc_net_addr(field: &str) -> Option<SocketAddr> {
+        let (ip, port) = field.split_once(':')?;
+        let port = u16::from_str_radix(port, 16).ok()?;
+        let mut octets = Vec::with_capacity(16);
+        for word in ip.as_bytes().chunks(8) {
+            let word = u32::from_str_radix(std::str::from_utf8(word).ok()?, 16).ok()?;
+            octets.extend_from_slice(&word.to_ne_bytes());
+        }
+        let ip = match octets.len() {
+            4 => IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(octets).ok()?)),
+            16 => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(octets).ok()?)),
+            _ => return None,
+        };
+        Some(SocketAddr::new(ip, port))
+    }
+
+    fn find_socket_owner(inode: u64) -> Option<u32> {
+        let target = format!("socket:[{inode}]");
+        fs::read_dir("/proc").ok()?.flatten().find_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse::<u32>().ok()?;
+            let fds = fs::read_dir(entry.path().join("fd")).ok()?;
+            fds.flatten()
+                .any(|fd| fs::read_link(fd.path()).is_ok_and(|link| link.as_os_str() == &*target))
+                .then_some(pid)
+        })
+    }
+
+    fn read_process(pid: u32) -> SourceProcess {
+        let proc_dir = Path::new("/proc").join(pid.to_string());
+        let path = fs::read_link(proc_dir.join("exe"))
+            .map(|path| {
+                let path = path.to_string_lossy();
+                path.strip_suffix(" (deleted)").unwrap_or(&path).to_string()
+            })
+            .unwrap_or_default();
+        // comm 最长 15 字节，优先用可执行文件名。
+        let name = Path::new(&path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .or_else(|| {
+                fs::read_to_string(proc_dir.join("comm"))
+                    .ok()
+                    .map(|comm| comm.trim().to_string())
+            })
+            .unwrap_or_default();
+        SourceProcess { pid, name, path }
+    }
+}
+
+#[cfg(all(test, target_os = "linux", target_endian = "little"))]
+mod tests {
+    use super::linux::{find_socket_inode, parse_proc_net_line};
+    use protocol::TransportProtocol;
+    use std::net::SocketAddr;
+
+    const PROC_NET_TABLE: &str = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100000A:D431 08080808:01BB 01 00000000:00000000 00:00000000 00000000  1000        0 52811 1 0000000000000000 20 4 30 10 -1
+   1: 00000000:D432 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 52812 1 0000000000000000 100 0 0 10 0
+";
+
+    #[test]
+    fn parses_proc_net_lines_in_kernel_byte_order() {
+        let line = "   0: 0100007F:0035 00000000:0000 0A 00000000:00000000 00:00000000 00000000   102        0 18421 1";
+        assert_eq!(
+            parse_proc_net_line(line),
+            Some(("127.0.0.1:53".parse().unwrap(), 18421))
+        );
+        let line6 = "   1: 0000000000000000FFFF00000100000A:D431 00000000000000000000000000000000:0000 01 00000000:00000000 00:00000000 00000000  1000        0 52813 1";
+        assert_eq!(
+            parse_proc_net_line(line6),
+            Some(("[::ffff:10.0.0.1]:54321".parse().unwrap(), 52813))
+        );
+        assert_eq!(
+            parse_proc_net_line(PROC_NET_TABLE.lines().next().unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn finds_udp_socket_by_exact_source_then_wildcard_port() {
+        let source = |addr: &str| addr.parse::<SocketAddr>().unwrap();
+        let udp = TransportProtocol::Udp;
+        assert_eq!(
+            find_socket_inode(PROC_NET_TABLE, udp, source("10.0.0.1:54321")),
+            Some(52811)
+        );
+        assert_eq!(
+            find_socket_inode(PROC_NET_TABLE, udp, source("10.0.0.1:54322")),
+            Some(52812)
+        );
+        assert_eq!(
+            find_socket_inode(PROC_NET_TABLE, udp, source("10.0.0.1:54323")),
+            None
+        );
+    }
+
+    #[test]
+    fn tcp_lookup_ignores_wildcard_listeners_on_the_same_port() {
+        let source = |addr: &str| addr.parse::<SocketAddr>().unwrap();
+        let tcp = TransportProtocol::Tcp;
+        assert_eq!(
+            find_socket_inode(PROC_NET_TABLE, tcp, source("10.0.0.1:54321")),
+            Some(52811)
+        );
+        // 0.0.0.0:54322 是监听 socket，不能把连接归到监听进程名下。
+        assert_eq!(
+            find_socket_inode(PROC_NET_TABLE, tcp, source("10.0.0.1:54322")),
+            None
+        );
+    }
+}
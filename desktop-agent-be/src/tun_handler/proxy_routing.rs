@@ -1,7 +1,7 @@
 //! TUN 模式下 agent->proxy 控制连接的旁路准备。
 //!
 //! TUN 一旦接管默认路由，agent 自己连接 proxy 的 TCP 连接也可能被送回 TUN。
-//! 因此在安装 TUN 路由前，需要先探测当前物理出口 IP/接口，并写入 Yamux session manager，
+//! 因此在安装 TUN 路由前，需要先探测当前物理出口 IP/接口，并写入所有 proxy 分组的 Yamux session manager，
 //! 后续新建 proxy 连接都会绑定到这个物理出口。
 
 use super::device::tun_ipv4_peer;
@@ -11,8 +11,7 @@ use super::*;
 pub(super) async fn configure_proxy_routing(
     config: &TunConfig,
     proxy_addrs: &[String],
-    tcp_sessions: &YamuxSessionManager,
-    udp_sessions: &YamuxSessionManager,
+    router: &OutboundRouter,
     shutdown: &CancellationToken,
 ) -> Option<common::BindInterface> {
     // 通过 OS 路由决策探测物理出口 IP/接口，用于后续 proxy 连接 bind。
@@ -67,19 +66,13 @@ pub(super) async fn configure_proxy_routing(
             attempts,
             started.elapsed()
         );
-        tcp_sessions.set_proxy_bind_ip(Some(route.local_ip));
-        tcp_sessions.set_proxy_bind_interface(route.bind_interface.clone());
-        udp_sessions.set_proxy_bind_ip(Some(route.local_ip));
-        udp_sessions.set_proxy_bind_interface(route.bind_interface);
+        router.set_proxy_bind(Some(route.local_ip), route.bind_interface);
     } else {
         warn!(
             "无法检测物理出口 IP — 代理连接可能会回环进入 TUN。\
              请确保启动 TUN 模式前代理服务器可达。"
         );
-        tcp_sessions.set_proxy_bind_ip(None);
-        tcp_sessions.set_proxy_bind_interface(None);
-        udp_sessions.set_proxy_bind_ip(None);
-        udp_sessions.set_proxy_bind_interface(None);
+        router.set_proxy_bind(None, None);
     }
 
     debug!(
@@ -141,12 +134,19 @@ pub(super) fn install_route_guard(
     tun_ipv4: std::net::Ipv4Addr,
     tun_ipv4_prefix: u8,
     tun_if_index: u32,
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))] tun_name: &str,
     proxy_addrs: &[String],
 ) -> Option<RouteGuard> {
     // 解析 proxy IP 后安装旁路和 split-default 路由；失败时继续运行但不接管全局路由。
     // route guard 的 Drop 会负责恢复路由状态。
     let proxy_ips = resolve_proxy_ips(proxy_addrs);
     let dns_capture_target = tun_ipv4_peer(tun_ipv4, tun_ipv4_prefix).unwrap_or(tun_ipv4);
+    #[cfg(target_os = "linux")]
+    let policy_routing = config.linux_policy_routing.then(|| route::PolicyRouting {
+        fwmark: config.linux_fwmark,
+        table: config.linux_route_table,
+        tun_name: tun_name.to_string(),
+    });
     match RouteGuard::install(
         tun_if_index,
         tun_ipv4,
@@ -155,6 +155,8 @@ pub(super) fn install_route_guard(
         config.route_state_file.as_deref(),
         &proxy_ips,
         config.proxy_dns,
+        #[cfg(target_os = "linux")]
+        policy_routing.as_ref(),
     ) {
         Ok(guard) => Some(guard),
         Err(e) => {
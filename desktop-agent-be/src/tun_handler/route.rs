@@ -19,6 +19,8 @@ mod dns_capture;
 mod guard;
 #[cfg(target_os = "macos")]
 mod macos_dns;
+#[cfg(target_os = "linux")]
+mod policy;
 mod probe;
 mod state;
 #[cfg(test)]
@@ -29,6 +31,8 @@ const ROUTE_STATE_FILE_NAME: &str = "tun-routes.json";
 #[cfg(target_os = "macos")]
 const PF_DNS_ANCHOR: &str = "com.apple/ppaass-ai-tun-dns";
 
+#[cfg(target_os = "linux")]
+use cleanup::run_route_cleanup_command;
 use cleanup::{cleanup_existing_tun_split_routes, delete_recorded_route};
 #[cfg(all(test, target_os = "macos"))]
 use cleanup::{macos_route_delete_command, should_delete_recorded_route};
@@ -43,6 +47,12 @@ pub(super) use guard::RouteGuard;
 use macos_dns::macos_pf_dns_rules;
 #[cfg(target_os = "macos")]
 use macos_dns::{MacosPfDnsGuard, command_output_message, macos_default_dns_interfaces};
+#[cfg(target_os = "linux")]
+pub(super) use policy::PolicyRouting;
+#[cfg(all(test, target_os = "linux"))]
+use policy::{PolicyRuleKind, policy_rule_args};
+#[cfg(target_os = "linux")]
+use policy::{PolicyRuleRecord, delete_policy_rule, install_policy_rule, policy_rule_records};
 #[cfg(target_os = "macos")]
 use probe::interface_name_for_index;
 #[cfg(all(test, target_os = "macos"))]
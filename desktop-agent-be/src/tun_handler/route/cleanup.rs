@@ -352,7 +352,7 @@ fn delete_route_with_platform_tool(_record: &RouteRecord) -> bool {
 }
 
 #[cfg(any(target_os = "linux", target_os = "macos", windows))]
-fn run_route_cleanup_command(mut command: Command) -> bool {
+pub(super) fn run_route_cleanup_command(mut command: Command) -> bool {
     debug!("运行路由清理命令：{:?}", command);
     command
         .stdin(Stdio::null())
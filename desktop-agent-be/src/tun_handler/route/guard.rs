@@ -55,6 +55,8 @@ pub(crate) struct RouteGuard {
 impl RouteGuard {
     /// 先安装代理 /32 与本地网络旁路路由，再安装指向 TUN 的 split-default 路由。
     /// 顺序很重要：旁路路由必须先于默认重定向存在，否则内核无法到达代理和局域网。
+    /// Linux 传入 `policy_routing` 时改装 fwmark 策略路由，失败才回退到上述路由。
+    #[cfg_attr(target_os = "linux", allow(clippy::too_many_arguments))]
     pub(crate) fn install(
         tun_if_index: u32,
         tun_ipv4: Ipv4Addr,
@@ -63,6 +65,7 @@ impl RouteGuard {
         route_state_file: Option<&str>,
         proxy_ips: &[IpAddr],
         capture_system_dns: bool,
+        #[cfg(target_os = "linux")] policy_routing: Option<&PolicyRouting>,
     ) -> Result<Self> {
         let mut mgr = RouteManager::new()
             .map_err(|e| AgentError::Connection(format!("RouteManager 初始化失败：{e}")))?;
@@ -89,7 +92,13 @@ impl RouteGuard {
         #[cfg(target_os = "macos")]
         let mut pf_dns_guard = None;
 
-        for ip in proxy_ips {
+        #[cfg(target_os = "linux")]
+        let policy_installed = policy_routing
+            .is_some_and(|policy| install_policy_routing(policy, tun_ipv6_cidr, &mut lease));
+        #[cfg(not(target_os = "linux"))]
+        let policy_installed = false;
+        // 策略路由下 proxy 连接靠 fwmark 走 main 表，不需要按 IP 安装旁路。
+        for ip in proxy_ips.iter().filter(|_| !policy_installed) {
             // 给每个 proxy IP 安装最具体的主机路由，使 agent 到 proxy 绕过 TUN。
             let route = match ip {
                 IpAddr::V4(v4) => {
@@ -177,23 +186,26 @@ impl RouteGuard {
         // direct_access 只能处理已经进入 TUN netstack 的连接；mDNS/SSDP/投屏/互联
         // 这类局域网流量更依赖物理接口和组播语义。先安装更具体的本地网络旁路，
         // 再安装 split-default，可让这些流量继续走原 Wi-Fi/以太网接口。
-        install_local_network_bypass_routes(
-            &mut mgr,
-            default_v4_gw,
-            default_v4_if,
-            &mut installed,
-            &mut lease,
-        );
+        // 策略路由的 suppress_prefixlength 规则已让 main 表里的局域网路由优先，两者都不再需要。
+        if !policy_installed {
+            install_local_network_bypass_routes(
+                &mut mgr,
+                default_v4_gw,
+                default_v4_if,
+                &mut installed,
+                &mut lease,
+            );
 
-        // split-default 将公网流量分成两半导入 TUN，同时让更具体的旁路路由优先。
-        install_ipv4_split_routes(&mut mgr, tun_if_index, tun_ipv4, &mut installed, &mut lease);
-        install_ipv6_split_routes(
-            &mut mgr,
-            tun_if_index,
-            tun_ipv6_cidr,
-            &mut installed,
-            &mut lease,
-        );
+            // split-default 将公网流量分成两半导入 TUN，同时让更具体的旁路路由优先。
+            install_ipv4_split_routes(&mut mgr, tun_if_index, tun_ipv4, &mut installed, &mut lease);
+            install_ipv6_split_routes(
+                &mut mgr,
+                tun_if_index,
+                tun_ipv6_cidr,
+                &mut installed,
+                &mut lease,
+            );
+        }
 
         Ok(Self {
             mgr,
@@ -212,9 +224,9 @@ impl Drop for RouteGuard {
 
         info!(
             "正在恢复路由表：删除 {} 条已安装的路由",
-            self.lease.state.routes.len()
+            self.lease.state.entry_count()
         );
-        let mut cleanup_ok = true;
+        let mut cleanup_ok = self.lease.state.delete_policy_rules();
         for record in self.lease.state.routes.iter().rev() {
             if !delete_recorded_route(&mut self.mgr, record) {
                 cleanup_ok = false;
@@ -232,6 +244,52 @@ impl Drop for RouteGuard {
     }
 }
 
+/// 安装 fwmark 策略路由；任一条目失败时撤销已装条目并返回 false。
+#[cfg(target_os = "linux")]
+fn install_policy_routing(
+    policy: &PolicyRouting,
+    tun_ipv6_cidr: Option<&str>,
+    lease: &mut RouteLease,
+) -> bool {
+    let ipv6 = tun_ipv6_cidr.is_some_and(|cidr| parse_cidr_v6(cidr).is_ok());
+    let families: &[bool] = if ipv6 { &[false, true] } else { &[false] };
+    for &family in families {
+        for record in policy_rule_records(policy, family) {
+            if let Err(e) = install_or_replace_policy_rule(&record) {
+                warn!(
+                    "安装 TUN 策略路由条目 {:?} 失败，回退到 split-default 路由：{e}",
+                    record.kind
+                );
+                lease.rollback_policy_rules();
+                return false;
+            }
+            // 只记录本次确实装上的条目，回滚和退出清理不会删除别人的规则。
+            lease.record_policy_rule(record);
+        }
+    }
+    info!(
+        "已安装 TUN fwmark 策略路由：fwmark={:#x} table={} 设备={}",
+        policy.fwmark, policy.table, policy.tun_name
+    );
+    true
+}
+
+/// 安装一条策略路由条目。同一优先级的条目已存在时多半是上次异常退出的残留：
+/// 先显式删除再重新安装，之后由本次运行接管。
+#[cfg(target_os = "linux")]
+fn install_or_replace_policy_rule(record: &PolicyRuleRecord) -> std::io::Result<()> {
+    match install_policy_rule(record) {
+        Err(e) if route_add_error_is_already_exists(&e.to_string()) => {
+            debug!("TUN 策略路由条目已存在，删除后重新安装：{:?}", record.kind);
+            if !delete_policy_rule(record) {
+                return Err(std::io::Error::other("删除已存在的同名条目失败"));
+            }
+            install_policy_rule(record)
+        }
+        result => result,
+    }
+}
+
 fn install_local_network_bypass_routes(
     mgr: &mut RouteManager,
     default_v4_gw: Option<IpAddr>,
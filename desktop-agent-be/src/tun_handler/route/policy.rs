@@ -0,0 +1,133 @@
+//! Linux fwmark 策略路由。
+//!
+//! split-default 与 proxy /32 旁路路由依赖安装时解析出的 proxy IP，proxy 换 IP 或
+//! 其他 VPN 也装了 split 路由时就会失效。策略路由改为按标记分流：agent 自身的
+//! proxy 连接带 `SO_MARK`，查 main 表走物理出口；其余流量先查忽略默认路由的 main 表，
+//! 让局域网和 DNS 捕获等具体路由继续生效，最后落到只有一条 TUN 默认路由的专用表。
+
+use super::*;
+
+/// 三条 `ip rule` 依次使用的优先级，小于系统默认的 main 规则（32766）。
+const POLICY_RULE_PRIORITY_BASE: u32 = 9000;
+
+/// Linux 策略路由参数。
+#[derive(Debug, Clone)]
+pub(crate) struct PolicyRouting {
+    pub(crate) fwmark: u32,
+    pub(crate) table: u32,
+    pub(crate) tun_name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) enum PolicyRuleKind {
+    /// 专用表里指向 TUN 设备的默认路由。
+    TunTableDefault { table: u32, tun_name: String },
+    /// 带 agent 标记的流量查 main 表。
+    MarkedToMain { fwmark: u32 },
+    /// 查 main 表但忽略其中的默认路由。
+    MainWithoutDefault,
+    /// 其余流量查 TUN 专用表。
+    UnmarkedToTunTable { table: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct PolicyRuleRecord {
+    pub(super) kind: PolicyRuleKind,
+    pub(super) ipv6: bool,
+}
+
+/// 按安装顺序列出某个地址族需要的条目：先建好 TUN 表，再从高优先级到低优先级加规则。
+pub(super) fn policy_rule_records(policy: &PolicyRouting, ipv6: bool) -> Vec<PolicyRuleRecord> {
+    [
+        PolicyRuleKind::TunTableDefault {
+            table: policy.table,
+            tun_name: policy.tun_name.clone(),
+        },
+        PolicyRuleKind::MarkedToMain {
+            fwmark: policy.fwmark,
+        },
+        PolicyRuleKind::MainWithoutDefault,
+        PolicyRuleKind::UnmarkedToTunTable {
+            table: policy.table,
+        },
+    ]
+    .into_iter()
+    .map(|kind| PolicyRuleRecord { kind, ipv6 })
+    .collect()
+}
+
+/// `ip` 命令参数；`action` 为 add/del/show。
+pub(super) fn policy_rule_args(record: &PolicyRuleRecord, action: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    if record.ipv6 {
+        args.push("-6".to_string());
+    }
+    let priority = |offset: u32| (POLICY_RULE_PRIORITY_BASE + offset).to_string();
+    match &record.kind {
+        PolicyRuleKind::TunTableDefault { table, tun_name } => {
+            args.extend(["route", action, "default"].map(str::to_string));
+            if action != "show" {
+                args.extend(["dev".to_string(), tun_name.clone()]);
+            }
+            args.extend(["table".to_string(), table.to_string()]);
+        }
+        PolicyRuleKind::MarkedToMain { fwmark } => {
+            args.extend(["rule", action, "priority"].map(str::to_string));
+            args.push(priority(0));
+            if action != "show" {
+                args.extend(["fwmark".to_string(), format!("{fwmark:#x}")]);
+                args.extend(["lookup", "main"].map(str::to_string));
+            }
+        }
+        PolicyRuleKind::MainWithoutDefault => {
+            args.extend(["rule", action, "priority"].map(str::to_string));
+            args.push(priority(1));
+            if action != "show" {
+                args.extend(["lookup", "main", "suppress_prefixlength", "0"].map(str::to_string));
+            }
+        }
+        PolicyRuleKind::UnmarkedToTunTable { table } => {
+            args.extend(["rule", action, "priority"].map(str::to_string));
+            args.push(priority(2));
+            if action != "show" {
+                args.extend(["lookup".to_string(), table.to_string()]);
+            }
+        }
+    }
+    args
+}
+
+pub(super) fn install_policy_rule(record: &PolicyRuleRecord) -> std::io::Result<()> {
+    let output = Command::new("ip")
+        .args(policy_rule_args(record, "add"))
+        .output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    Err(std::io::Error::other(if stderr.is_empty() {
+        format!("ip 退出状态 {}", output.status)
+    } else {
+        stderr
+    }))
+}
+
+/// 删除一条记录；条目本来就不存在时同样视为成功。
+pub(super) fn delete_policy_rule(record: &PolicyRuleRecord) -> bool {
+    let mut command = Command::new("ip");
+    command.args(policy_rule_args(record, "del"));
+    if run_route_cleanup_command(command) {
+        debug!("已删除 TUN 策略路由条目：{:?}", record.kind);
+        return true;
+    }
+    match Command::new("ip")
+        .args(policy_rule_args(record, "show"))
+        .output()
+    {
+        Ok(output) if output.status.success() && output.stdout.trim_ascii().is_empty() => true,
+        Ok(_) | Err(_) => {
+            warn!("删除 TUN 策略路由条目失败：{:?}", record.kind);
+            false
+        }
+    }
+}
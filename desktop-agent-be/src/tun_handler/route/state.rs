@@ -42,6 +42,10 @@ pub(super) struct RouteState {
     pub(super) pid: u32,
     pub(super) created_unix_secs: u64,
     pub(super) routes: Vec<RouteRecord>,
+    /// Linux 策略路由模式安装的 `ip rule`/专用表路由，按安装顺序记录。
+    #[cfg(target_os = "linux")]
+    #[serde(default)]
+    pub(super) policy_rules: Vec<PolicyRuleRecord>,
 }
 
 pub(super) struct RouteLease {
@@ -59,6 +63,8 @@ impl RouteLease {
                 pid: std::process::id(),
                 created_unix_secs: now_unix_secs(),
                 routes: Vec::new(),
+                #[cfg(target_os = "linux")]
+                policy_rules: Vec::new(),
             },
             persist_failed: false,
         }
@@ -84,7 +90,7 @@ impl RouteLease {
             }
         };
 
-        if state.routes.is_empty() {
+        if state.is_empty() {
             remove_file_if_exists(&self.path);
             return;
         }
@@ -92,10 +98,10 @@ impl RouteLease {
         info!(
             "发现上次 TUN 模式遗留的路由状态文件：{}，准备清理 {} 条路由",
             self.path.display(),
-            state.routes.len()
+            state.entry_count()
         );
 
-        let mut cleanup_ok = true;
+        let mut cleanup_ok = state.delete_policy_rules();
         for record in state.routes.iter().rev() {
             if !delete_recorded_route(mgr, record) {
                 cleanup_ok = false;
@@ -137,6 +143,26 @@ impl RouteLease {
         fs::rename(tmp_path, &self.path)
     }
 
+    #[cfg(target_os = "linux")]
+    pub(super) fn record_policy_rule(&mut self, record: PolicyRuleRecord) {
+        self.state.policy_rules.push(record);
+        if let Err(e) = self.persist() {
+            self.persist_failed = true;
+            warn!("写入 TUN 路由状态文件 {} 失败：{e}", self.path.display());
+        }
+    }
+
+    /// 撤销已安装的策略路由条目（用于安装中途失败的回滚）。
+    #[cfg(target_os = "linux")]
+    pub(super) fn rollback_policy_rules(&mut self) {
+        self.state.delete_policy_rules();
+        self.state.policy_rules.clear();
+        if let Err(e) = self.persist() {
+            self.persist_failed = true;
+            warn!("写入 TUN 路由状态文件 {} 失败：{e}", self.path.display());
+        }
+    }
+
     pub(super) fn clear(&mut self) {
         if self.persist_failed {
             debug!(
@@ -146,6 +172,35 @@ impl RouteLease {
         }
         remove_file_if_exists(&self.path);
         self.state.routes.clear();
+        #[cfg(target_os = "linux")]
+        self.state.policy_rules.clear();
+    }
+}
+
+impl RouteState {
+    fn is_empty(&self) -> bool {
+        self.entry_count() == 0
+    }
+
+    pub(super) fn entry_count(&self) -> usize {
+        #[cfg(target_os = "linux")]
+        return self.routes.len() + self.policy_rules.len();
+        #[cfg(not(target_os = "linux"))]
+        self.routes.len()
+    }
+
+    /// 逆序删除策略路由条目；非 Linux 平台没有这类条目。
+    pub(super) fn delete_policy_rules(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        return self
+            .policy_rules
+            .iter()
+            .rev()
+            .filter(|record| !delete_policy_rule(record))
+            .count()
+            == 0;
+        #[cfg(not(target_os = "linux"))]
+        true
     }
 }
 
@@ -315,6 +315,70 @@ fn macos_pf_dns_rules_prefer_scutil_scope_over_default_interface() {
     assert!(!rules.contains("pass out quick on en0"));
 }
 
+#[cfg(target_os = "linux")]
+#[test]
+fn linux_policy_records_build_tun_table_before_rules() {
+    let policy = PolicyRouting {
+        fwmark: 0x1a5,
+        table: 421,
+        tun_name: "ppaass0".to_string(),
+    };
+
+    let records = policy_rule_records(&policy, true);
+
+    assert!(records.iter().all(|record| record.ipv6));
+    assert_eq!(
+        records
+            .iter()
+            .map(|record| record.kind.clone())
+            .collect::<Vec<_>>(),
+        vec![
+            PolicyRuleKind::TunTableDefault {
+                table: 421,
+                tun_name: "ppaass0".to_string(),
+            },
+            PolicyRuleKind::MarkedToMain { fwmark: 0x1a5 },
+            PolicyRuleKind::MainWithoutDefault,
+            PolicyRuleKind::UnmarkedToTunTable { table: 421 },
+        ]
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn linux_policy_rule_args_delete_by_priority_and_table() {
+    let policy = PolicyRouting {
+        fwmark: 0x1a5,
+        table: 421,
+        tun_name: "ppaass0".to_string(),
+    };
+    let records = policy_rule_records(&policy, false);
+    let args = |record: &PolicyRuleRecord, action: &str| policy_rule_args(record, action).join(" ");
+
+    assert_eq!(
+        args(&records[0], "add"),
+        "route add default dev ppaass0 table 421"
+    );
+    assert_eq!(
+        args(&records[1], "add"),
+        "rule add priority 9000 fwmark 0x1a5 lookup main"
+    );
+    assert_eq!(
+        args(&records[2], "add"),
+        "rule add priority 9001 lookup main suppress_prefixlength 0"
+    );
+    assert_eq!(
+        args(&records[3], "add"),
+        "rule add priority 9002 lookup 421"
+    );
+    assert_eq!(args(&records[1], "show"), "rule show priority 9000");
+    assert_eq!(args(&records[0], "show"), "route show default table 421");
+    assert_eq!(
+        policy_rule_args(&policy_rule_records(&policy, true)[3], "del").join(" "),
+        "-6 rule del priority 9002 lookup 421"
+    );
+}
+
 #[cfg(target_os = "macos")]
 fn command_args(command: &Command) -> Vec<String> {
     command
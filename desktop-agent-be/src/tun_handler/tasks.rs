@@ -4,13 +4,16 @@
 //! `handle_tun_tcp`、`handle_tun_udp`、DNS proxy 或共享 UDP relay。
 
 use super::TunForwardContext;
-use super::dns_proxy::DnsProxy;
+use super::dns_proxy::{DnsProxy, FakeIpDns};
 use super::network::{address_for_tun_target, is_tun_local_udp_target, reject_tun_target};
 use super::tcp::handle_tun_tcp;
 use super::udp::handle_tun_udp;
 use super::udp_relay::UdpRelay;
-use common::{QuicPolicy, QuicUdpStats, dns::is_dns_query_packet, spawn_guarded};
+use common::routing::DEFAULT_PROXY_GROUP;
+use common::sniff::sniff_quic_sni;
+use common::{Outbound, QuicPolicy, QuicUdpStats, dns::is_dns_query_packet, spawn_guarded};
 use futures::{SinkExt, StreamExt};
+use protocol::{Address, TransportProtocol};
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
@@ -27,7 +30,8 @@ type UdpSessions = Arc<dashmap::DashMap<UdpSessionKey, UdpSessionTx>>;
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum UdpRoute {
     Direct,
-    Proxy,
+    // 值为 OutboundRouter 中 proxy 分组的下标。
+    Proxy(usize),
     Block,
 }
 
@@ -202,21 +206,24 @@ pub(super) fn spawn_udp_sessions(
         // DNS 请求单独走 DnsProxy：它会维护 DNS ID 映射并记录域名解析缓存。
         let dns_proxy = context.proxy_dns.then(|| {
             DnsProxy::spawn(
-                context.udp_sessions.clone(),
+                context.router.default_group().udp_sessions().clone(),
                 udp_tx.clone(),
                 context.direct_domain_cache.clone(),
+                context.fake_ip.clone().map(|pool| FakeIpDns {
+                    pool,
+                    router: context.router.clone(),
+                }),
                 shutdown.clone(),
             )
         });
         // proxy_udp 只控制普通 UDP。quic_policy=allow 时，即使普通 UDP
-        // 被配置为直连，仍需要 relay 承载未命中 direct_access 的 UDP/443。
-        let udp_relay = should_start_udp_relay(context.proxy_udp, quic_policy).then(|| {
-            UdpRelay::spawn(
-                context.udp_sessions.clone(),
-                udp_tx.clone(),
-                shutdown.clone(),
-            )
-        });
+        // 被配置为直连，仍需要 relay 承载按分流规则走 proxy 的 UDP/443。
+        // fake-IP 目标只能经 proxy 转发，因此启用 fake-IP 时同样需要 relay。
+        // 每个 proxy 分组各自一个 relay，首次有流量分到该分组时再启动。
+        let relay_enabled =
+            should_start_udp_relay(context.proxy_udp || context.fake_ip.is_some(), quic_policy);
+        let mut udp_relays: Vec<Option<Arc<UdpRelay>>> =
+            context.router.groups().iter().map(|_| None).collect();
         let quic_stats = Arc::new(QuicUdpStats::default());
         spawn_quic_udp_stats_logger(quic_stats.clone(), shutdown.clone());
 
@@ -262,6 +269,21 @@ pub(super) fn spawn_udp_sessions(
                         continue;
                     }
 
+                    // fake-IP 目标还原成域名，交给 proxy 端解析。
+                    let fake_address = match context.fake_ip.as_deref() {
+                        Some(pool) if pool.contains(target_addr.ip()) => {
+                            let Some(domain) = pool.domain_for_ip(target_addr.ip()) else {
+                                debug!("TUN UDP fake-IP 映射已失效，丢弃一个 UDP 包 -> {}", target_addr);
+                                continue;
+                            };
+                            Some(Address::Domain {
+                                host: domain,
+                                port: target_addr.port(),
+                            })
+                        }
+                        _ => None,
+                    };
+
                     let key = (source_addr, target_addr);
                     // 已存在的 direct 会话优先复用，避免域名缓存过期后把同一 UDP 流切到 proxy。
                     if let Some(tx) = sessions.get(&key).map(|t| t.clone()) {
@@ -274,48 +296,98 @@ pub(super) fn spawn_udp_sessions(
                         continue;
                     }
 
-                    // 先独立计算 direct_access 结论。proxy_udp=false 只强制普通 UDP
+                    // 发起进程按源地址缓存；同一条流持续有包时只在首包扫描一次 /proc。
+                    let process = context
+                        .source_process(TransportProtocol::Udp, source_addr)
+                        .await;
+
+                    // 先独立计算分流结论。proxy_udp=false 只强制普通 UDP
                     // 直连，不能把本应经 proxy 的浏览器 QUIC 一并改成直连。
-                    let mut direct_access_match = context.direct_checker.is_direct(&address);
-                    let proxy_address = address.clone();
-                    if !direct_access_match
-                        && should_consult_udp_domain_cache(context.proxy_udp, target_addr.port())
-                    {
-                        // 会参与 proxy/direct 分流的 UDP 才查询 DNS 记录的域名缓存。
-                        // 非直连代理目标始终保留原始 IP，避免 proxy 端重新 DNS 到
-                        // 不同 CDN 边缘节点后出现播放抖动。
-                        if context.direct_checker.has_domain_direct_rules()
-                            && context
-                                .direct_domain_cache
-                                .matching_domain_for_ip(target_addr.ip(), |domain| {
-                                context.direct_checker.is_direct_domain(domain)
+                    let policy = context.router.policy();
+                    let (proxy_address, outbound, proxy_udp) = match fake_address {
+                        Some(fake_address) => {
+                            let outbound = match policy.route_with_process(
+                                &fake_address,
+                                TransportProtocol::Udp,
+                                None,
+                                process.as_deref(),
+                            ) {
+                                Outbound::Direct => {
+                                    // 走 relay 的 UDP 没有会话表，每个包都会重新分流，不能用 warn 刷屏。
+                                    debug!(
+                                        "TUN UDP fake-IP 目标 {} 命中直连规则，但假地址无法直连，改走默认分组",
+                                        target_addr
+                                    );
+                                    Outbound::Proxy(DEFAULT_PROXY_GROUP)
+                                }
+                                outbound => outbound,
+                            };
+                            // 假地址无法直连，proxy_udp=false 的强制直连同样不适用。
+                            (fake_address, outbound, true)
+                        }
+                        None => {
+                            // QUIC Initial 里嗅探到的 SNI 记入域名缓存：同一流后续的短包头
+                            // 数据包不再携带 SNI，要靠缓存继续命中同一条域名规则。
+                            if context.sniff
+                                && target_addr.port() == 443
+                                && let Some(domain) = sniff_quic_sni(&data)
+                            {
+                                debug!("TUN UDP QUIC 嗅探到 SNI：{} ({})", target_addr, domain);
+                                context
+                                    .direct_domain_cache
+                                    .record_resolution(&domain, &[target_addr.ip().to_string()]);
+                            }
+                            // 会参与分流的 UDP 才查询 DNS 记录的域名缓存。代理目标始终保留
+                            // 原始 IP，避免 proxy 端重新 DNS 到不同 CDN 边缘节点后出现播放抖动。
+                            let cached_domain = (policy.has_domain_rules()
+                                && should_consult_udp_domain_cache(context.proxy_udp, target_addr.port()))
+                            .then(|| {
+                                context
+                                    .direct_domain_cache
+                                    .matching_domain_for_ip(target_addr.ip(), |domain| {
+                                        policy.matches_domain_rule(domain)
+                                    })
                             })
-                            .is_some()
-                        {
-                            direct_access_match = true;
+                            .flatten();
+                            let outbound = policy.route_with_process(
+                                &address,
+                                TransportProtocol::Udp,
+                                cached_domain.as_deref(),
+                                process.as_deref(),
+                            );
+                            (address.clone(), outbound, context.proxy_udp)
                         }
-                    }
+                    };
 
                     match classify_udp_route(
                         target_addr.port(),
                         quic_policy,
-                        context.proxy_udp,
-                        direct_access_match,
+                        proxy_udp,
+                        outbound,
                     ) {
                         UdpRoute::Block => {
-                            quic_stats.record_blocked();
+                            if target_addr.port() == 443 {
+                                quic_stats.record_blocked();
+                            }
                             debug!(
-                                "TUN UDP/443 QUIC 已按显式策略 {:?} 阻断 -> {}",
+                                "TUN UDP 已按 QUIC 策略 {:?} 或分流规则阻断 -> {}",
                                 quic_policy,
                                 target_addr
                             );
                             continue;
                         }
-                        UdpRoute::Proxy => {
+                        UdpRoute::Proxy(index) => {
                             if target_addr.port() == 443 {
                                 quic_stats.record_proxied();
                             }
-                            if let Some(udp_relay) = &udp_relay {
+                            if relay_enabled {
+                                let udp_relay = udp_relays[index].get_or_insert_with(|| {
+                                    UdpRelay::spawn(
+                                        context.router.groups()[index].udp_sessions().clone(),
+                                        udp_tx.clone(),
+                                        shutdown.clone(),
+                                    )
+                                });
                                 udp_relay.send(source_addr, target_addr, proxy_address, data);
                             } else {
                                 warn!(
@@ -345,11 +417,10 @@ pub(super) fn spawn_udp_sessions(
                         force_direct: !context.proxy_udp,
                         quic_policy,
                         netstack_tx: udp_tx.clone(),
-                        tcp_sessions: context.tcp_sessions.clone(),
-                        udp_sessions: context.udp_sessions.clone(),
-                        direct_checker: context.direct_checker.clone(),
+                        router: context.router.clone(),
                         direct_domain_cache: context.direct_domain_cache.clone(),
                         direct_egress: context.direct_egress.clone(),
+                        process,
                         shutdown: shutdown.clone(),
                     };
                     spawn_guarded("desktop tun udp flow", async move {
@@ -377,20 +448,17 @@ fn classify_udp_route(
     target_port: u16,
     quic_policy: QuicPolicy,
     proxy_udp: bool,
-    direct_access_match: bool,
+    outbound: Outbound,
 ) -> UdpRoute {
-    if target_port == 443 {
-        if quic_policy.should_block_udp443() {
-            UdpRoute::Block
-        } else if direct_access_match {
-            UdpRoute::Direct
-        } else {
-            UdpRoute::Proxy
-        }
-    } else if !proxy_udp || direct_access_match {
-        UdpRoute::Direct
-    } else {
-        UdpRoute::Proxy
+    if target_port == 443 && quic_policy.should_block_udp443() {
+        return UdpRoute::Block;
+    }
+    match outbound {
+        Outbound::Block => UdpRoute::Block,
+        // 普通 UDP 在 proxy_udp=false 时强制直连；UDP/443 仍按分流规则。
+        Outbound::Proxy(_) if target_port != 443 && !proxy_udp => UdpRoute::Direct,
+        Outbound::Proxy(index) => UdpRoute::Proxy(index),
+        Outbound::Direct => UdpRoute::Direct,
     }
 }
 
@@ -432,7 +500,9 @@ mod tests {
     use super::{
         UdpRoute, classify_udp_route, should_consult_udp_domain_cache, should_start_udp_relay,
     };
-    use common::QuicPolicy;
+    use common::{Outbound, QuicPolicy};
+
+    const PROXY: Outbound = Outbound::Proxy(0);
 
     fn ipv4_packet(protocol: u8, payload: &[u8]) -> Vec<u8> {
         let total_len = 20 + payload.len();
@@ -482,19 +552,19 @@ mod tests {
     #[test]
     fn ordinary_udp_proxy_switch_preserves_old_routing_or_forces_direct() {
         assert_eq!(
-            classify_udp_route(3478, QuicPolicy::Allow, true, false),
-            UdpRoute::Proxy
+            classify_udp_route(3478, QuicPolicy::Allow, true, PROXY),
+            UdpRoute::Proxy(0)
         );
         assert_eq!(
-            classify_udp_route(3478, QuicPolicy::Allow, true, true),
+            classify_udp_route(3478, QuicPolicy::Allow, true, Outbound::Direct),
             UdpRoute::Direct
         );
         assert_eq!(
-            classify_udp_route(3478, QuicPolicy::Allow, false, false),
+            classify_udp_route(3478, QuicPolicy::Allow, false, PROXY),
             UdpRoute::Direct
         );
         assert_eq!(
-            classify_udp_route(3478, QuicPolicy::Block, false, true),
+            classify_udp_route(3478, QuicPolicy::Block, false, Outbound::Direct),
             UdpRoute::Direct
         );
     }
@@ -502,31 +572,51 @@ mod tests {
     #[test]
     fn quic_allow_routes_direct_matches_direct_and_other_targets_to_proxy() {
         assert_eq!(
-            classify_udp_route(443, QuicPolicy::Allow, false, false),
-            UdpRoute::Proxy
+            classify_udp_route(443, QuicPolicy::Allow, false, PROXY),
+            UdpRoute::Proxy(0)
         );
         assert_eq!(
-            classify_udp_route(443, QuicPolicy::Allow, false, true),
+            classify_udp_route(443, QuicPolicy::Allow, false, Outbound::Direct),
             UdpRoute::Direct
         );
         assert_eq!(
-            classify_udp_route(443, QuicPolicy::Allow, true, false),
-            UdpRoute::Proxy
+            classify_udp_route(443, QuicPolicy::Allow, true, PROXY),
+            UdpRoute::Proxy(0)
         );
     }
 
     #[test]
-    fn explicit_quic_block_overrides_udp_and_direct_access_routing() {
+    fn explicit_quic_block_overrides_udp_and_outbound_routing() {
         for proxy_udp in [false, true] {
-            for direct_access_match in [false, true] {
+            for outbound in [Outbound::Direct, PROXY, Outbound::Block] {
                 assert_eq!(
-                    classify_udp_route(443, QuicPolicy::Block, proxy_udp, direct_access_match,),
+                    classify_udp_route(443, QuicPolicy::Block, proxy_udp, outbound),
                     UdpRoute::Block
                 );
             }
         }
     }
 
+    #[test]
+    fn routing_block_and_proxy_group_are_preserved() {
+        assert_eq!(
+            classify_udp_route(3478, QuicPolicy::Allow, false, Outbound::Block),
+            UdpRoute::Block
+        );
+        assert_eq!(
+            classify_udp_route(443, QuicPolicy::Allow, true, Outbound::Block),
+            UdpRoute::Block
+        );
+        assert_eq!(
+            classify_udp_route(3478, QuicPolicy::Allow, true, Outbound::Proxy(2)),
+            UdpRoute::Proxy(2)
+        );
+        assert_eq!(
+            classify_udp_route(443, QuicPolicy::Allow, false, Outbound::Proxy(1)),
+            UdpRoute::Proxy(1)
+        );
+    }
+
     #[test]
     fn relay_and_domain_cache_stay_available_for_quic() {
         assert!(should_start_udp_relay(false, QuicPolicy::Allow));
@@ -2,29 +2,38 @@
 //!
 //! netstack 把系统 IP 包还原成 `TcpStream` 后进入这里。处理顺序是：
 //! 1. 过滤 TUN 自身网段和 proxy DNS 特例；
-//! 2. 用 IP/CIDR 和 DNS proxy 缓存判断是否直连；
-//! 3. 命中直连则连真实目标，否则从 proxy session manager 打开目标流并双向中继。
+//! 2. fake-IP 目标还原成域名后按域名分流；启用 `tun.sniff` 时先从首包嗅探
+//!    TLS SNI/HTTP Host，其余用 IP/CIDR 和 DNS proxy 缓存按分流规则选择出口；
+//! 3. 直连则连真实目标，拒绝则关闭连接，否则从对应分组的 proxy session manager
+//!    打开目标流并双向中继。
 
 use super::TunForwardContext;
 use super::network::{address_for_tun_target, reject_tun_target};
+use crate::connections::register_connection;
 use crate::error::{AgentError, Result};
+use crate::routing::{OutboundRouter, Route, RouteDecision};
 use crate::tcp_relay::{TcpRelayOptions, relay_tcp_bidirectional};
 use crate::telemetry;
 use crate::yamux_session::YamuxSessionManager;
+use common::sniff::{SniffResult, sniff_tcp_domain};
 use common::{BindInterface, bind_socket_to_interface};
-use protocol::TransportProtocol;
+use protocol::{Address, TransportProtocol};
 use socket2::{Domain, Protocol, Socket, TcpKeepalive, Type};
 use std::net::SocketAddr;
 use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpSocket, TcpStream};
-use tokio::time::timeout;
-use tracing::debug;
+use tokio::time::{Instant, timeout, timeout_at};
+use tracing::{debug, warn};
 
 /// macOS 待机恢复后 scoped route 可能短暂失效，避免直连卡到系统 TCP 超时。
 const DIRECT_TCP_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
 const TUN_TCP_PREFETCH_LIMIT: usize = 64 * 1024;
 const TUN_TCP_PREFETCH_CHUNK: usize = 16 * 1024;
+/// 首包嗅探最多等待的时间；服务端先发言的协议（SSH、SMTP 等）会在这里超时。
+const TUN_TCP_SNIFF_TIMEOUT: Duration = Duration::from_millis(300);
+/// 首包嗅探最多缓存的字节数，足够容纳带后量子密钥交换的 ClientHello。
+const TUN_TCP_SNIFF_LIMIT: usize = 16 * 1024;
 
 pub(super) async fn handle_tun_tcp(
     mut client: netstack_smoltcp::TcpStream,
@@ -32,15 +41,19 @@ pub(super) async fn handle_tun_tcp(
     target: SocketAddr,
     context: TunForwardContext,
 ) -> Result<()> {
+    // 连接刚被 netstack 接受，发起进程的 socket 此时一定还在。
+    let process = context.source_process(TransportProtocol::Tcp, source).await;
     let TunForwardContext {
-        tcp_sessions,
-        udp_sessions,
-        direct_checker,
+        router,
         direct_domain_cache,
+        fake_ip,
+        sniff,
         tun_networks,
         proxy_dns,
         proxy_udp: _,
         direct_egress,
+        process_resolver: _,
+        log_process: _,
     } = context;
 
     // 先把 TUN 目标地址转成代理协议地址，并处理 proxy DNS 特例。
@@ -49,39 +62,116 @@ pub(super) async fn handle_tun_tcp(
         // 普通目标不能落入 TUN 自身网段，避免流量在本机回环。
         reject_tun_target("TCP", source, target, tun_networks)?;
     }
+    let fake_domain = match fake_ip.as_deref() {
+        Some(pool) if !proxy_dns_request && pool.contains(target.ip()) => {
+            let Some(domain) = pool.domain_for_ip(target.ip()) else {
+                debug!("TUN TCP fake-IP 映射已失效，关闭连接 -> {}", target);
+                let _ = client.shutdown().await;
+                return Ok(());
+            };
+            Some(domain)
+        }
+        _ => None,
+    };
+    // 嗅探只用于真实 IP 目标；读到的首包字节之后原样补写给直连或 proxy 目标。
+    let (sniffed_domain, prefetched) = if sniff && !proxy_dns_request && fake_domain.is_none() {
+        sniff_tun_tcp_domain(&mut client).await?
+    } else {
+        (None, Vec::new())
+    };
     let target_label = if proxy_dns_request {
         format!("{target} -> proxy默认DNS")
+    } else if let Some(domain) = &fake_domain {
+        format!("{domain}:{} (fake-IP {target})", target.port())
+    } else if let Some(domain) = &sniffed_domain {
+        format!("{domain}:{} (嗅探 {target})", target.port())
     } else {
         target.to_string()
     };
-    // 1. IP/CIDR 命中：完全不需要嗅探，直接连原始目标。
+    let connection_target = target_label.clone();
+    let target_label = match &process {
+        Some(process) => format!("{target_label}，进程 {process}"),
+        None => target_label,
+    };
     let mut direct_target = None;
-    let proxy_address = address.clone();
+    // fake-IP 和嗅探到的目标以域名交给 proxy，由 proxy 端解析真实地址。
+    let proxy_address = match fake_domain.as_ref().or(sniffed_domain.as_ref()) {
+        Some(domain) => Address::Domain {
+            host: domain.clone(),
+            port: target.port(),
+        },
+        None => address.clone(),
+    };
     let mut proxy_reason = None;
-    if !proxy_dns_request && direct_checker.is_direct(&address) {
-        direct_target = Some(target);
-    }
-
-    // 2. 缓存中已知 IP -> 域名映射且命中域名规则：仍然使用原始 IP 直连。
-    //    这里的域名只来自 proxy DNS 缓存，不触发 agent 本机 DNS 解析，也不再
-    //    从 TCP payload 读取 TLS SNI/HTTP Host。这样 TUN 数据面不会因为首包
-    //    嗅探和补发逻辑影响视频分片下载。
-    if direct_target.is_none()
-        && !proxy_dns_request
-        && direct_checker.has_domain_direct_rules()
-        && let Some(domain) = direct_domain_cache.matching_domain_for_ip(target.ip(), |domain| {
-            direct_checker.is_direct_domain(domain)
-        })
-    {
-        debug!(
-            "TUN TCP 缓存域名规则命中：{} ({})，先使用原始 IP 直连",
-            target, domain
+    // proxy DNS 等内部目标固定走默认分组。
+    let mut proxy_group = router.default_group();
+    // proxy DNS 等内部目标不经过分流规则。
+    let mut rule = "proxy_dns".to_string();
+    if let Some(domain) = &fake_domain {
+        let decision = router.decide(
+            &proxy_address,
+            TransportProtocol::Tcp,
+            None,
+            process.as_deref(),
         );
-        direct_target = Some(target);
+        rule = decision.rule;
+        match decision.route {
+            // DNS 阶段已为可能直连的域名返回真实地址，走到这里通常是应答后分流规则又变了。
+            Route::Direct => warn!(
+                "TUN TCP fake-IP 目标 {} 命中直连规则，但假地址无法直连，改走默认分组",
+                domain
+            ),
+            Route::Block => {
+                debug!("TUN TCP 已被分流规则拒绝 -> {}", target_label);
+                let _ = client.shutdown().await;
+                return Ok(());
+            }
+            Route::Proxy(group) => proxy_group = group,
+        }
+    } else if !proxy_dns_request {
+        // 1. IP/CIDR 规则直接按原始目标匹配。
+        // 2. 已知域名且命中域名规则：直连仍然使用原始 IP 连接。域名优先取首包
+        //    嗅探结果（仅在启用 tun.sniff 时），其次取 proxy DNS 缓存，都不会触发
+        //    agent 本机 DNS 解析。
+        let policy = router.policy();
+        let cached_domain = if sniffed_domain.is_some() {
+            sniffed_domain.clone()
+        } else if policy.has_domain_rules() {
+            direct_domain_cache
+                .matching_domain_for_ip(target.ip(), |domain| policy.matches_domain_rule(domain))
+        } else {
+            None
+        };
+        let decision = router.decide(
+            &address,
+            TransportProtocol::Tcp,
+            cached_domain.as_deref(),
+            process.as_deref(),
+        );
+        rule = decision.rule;
+        match decision.route {
+            Route::Direct => {
+                if let Some(domain) = &cached_domain {
+                    debug!(
+                        "TUN TCP 域名规则命中：{} ({})，先使用原始 IP 直连",
+                        target, domain
+                    );
+                }
+                direct_target = Some(target);
+            }
+            Route::Block => {
+                debug!("TUN TCP 已被分流规则拒绝 -> {}", target_label);
+                let _ = client.shutdown().await;
+                return Ok(());
+            }
+            Route::Proxy(group) => proxy_group = group,
+        }
     }
 
     if direct_target.is_none()
         && !proxy_dns_request
+        && fake_domain.is_none()
+        && sniffed_domain.is_none()
         && let Some(domain) = direct_domain_cache.matching_domain_for_ip(target.ip(), |_| true)
     {
         debug!(
@@ -98,15 +188,28 @@ pub(super) async fn handle_tun_tcp(
             target: connect_target,
             target_str: &target_str,
             direct_egress: direct_egress.as_ref(),
-            tcp_sessions: tcp_sessions.as_ref(),
-            udp_sessions: udp_sessions.as_ref(),
+            router: router.as_ref(),
             tun_networks,
         })
         .await?;
+        if !prefetched.is_empty() {
+            target_stream.write_all(&prefetched).await?;
+        }
+        let connection = register_connection(
+            "TUN TCP",
+            source,
+            connection_target,
+            TransportProtocol::Tcp,
+            &RouteDecision {
+                route: Route::Direct,
+                rule,
+            },
+        );
+        connection.set_process(process.as_deref());
         match relay_tcp_bidirectional(
             &mut client,
             &mut target_stream,
-            TcpRelayOptions::standard(&target_str),
+            TcpRelayOptions::standard(&target_str).with_connection(&connection),
         )
         .await
         {
@@ -128,34 +231,45 @@ pub(super) async fn handle_tun_tcp(
     if proxy_dns_request {
         debug!("TUN TCP DNS -> 代理 -> {}", target_label);
     } else {
-        debug!("TUN TCP -> 代理 -> {}", target_label);
+        debug!("TUN TCP -> 代理 {} -> {}", proxy_group.name(), target_label);
     }
     let proxy_label = proxy_target_label(&target_label, proxy_reason.as_deref());
     if !proxy_dns_request {
         debug!("TUN TCP 代理目标：{}", proxy_label);
     }
-    // TUN TCP 不再抢读首包做 SNI/Host 嗅探。proxy 路径直接把原始字节流交给
-    // copy_bidirectional，中间没有“已读首段再补发”的状态，减少短连接分片卡顿点。
+    // 未启用嗅探时 prefetched 为空，proxy 建连期间才开始预读首包。
     let (connected, prefetched) = connect_proxy_stream_with_tun_prefetch(
         &mut client,
-        tcp_sessions.as_ref(),
+        proxy_group.tcp_sessions(),
         proxy_address,
         &proxy_label,
+        prefetched,
     )
     .await?;
     let mut proxy_io = connected.into_async_io();
     if !prefetched.is_empty() {
-        // 这里只做“预读后原样补写”，不解析、不嗅探、不参与直连规则。
+        // 这里只做“预读后原样补写”，嗅探阶段读到的字节也在其中。
         // TUN TCP 三次握手已经由 netstack 接住；如果等待 proxy 建连期间完全不读本地流，
         // 浏览器的 TLS/HTTP2 首包会卡在接收窗口里。先缓存少量首包，远端通道建立后
         // 立即写出，可以降低视频分片连接在建连阶段的抖动。
         proxy_io.write_all(&prefetched).await?;
         proxy_io.flush().await?;
     }
+    let connection = register_connection(
+        "TUN TCP",
+        source,
+        connection_target,
+        TransportProtocol::Tcp,
+        &RouteDecision {
+            route: Route::Proxy(proxy_group),
+            rule,
+        },
+    );
+    connection.set_process(process.as_deref());
     match relay_tcp_bidirectional(
         &mut client,
         &mut proxy_io,
-        TcpRelayOptions::tun(&proxy_label),
+        TcpRelayOptions::tun(&proxy_label).with_connection(&connection),
     )
     .await
     {
@@ -185,10 +299,11 @@ async fn connect_proxy_stream_with_tun_prefetch(
     tcp_sessions: &YamuxSessionManager,
     proxy_address: protocol::Address,
     label: &str,
+    mut prefetched: Vec<u8>,
 ) -> Result<(crate::yamux_session::YamuxTargetStream, Vec<u8>)> {
     let mut connect =
         Box::pin(tcp_sessions.connect_to_target(proxy_address, TransportProtocol::Tcp));
-    let mut prefetched = Vec::with_capacity(TUN_TCP_PREFETCH_CHUNK);
+    prefetched.reserve(TUN_TCP_PREFETCH_CHUNK);
 
     loop {
         if prefetched.len() >= TUN_TCP_PREFETCH_LIMIT {
@@ -226,6 +341,31 @@ async fn connect_proxy_stream_with_tun_prefetch(
     }
 }
 
+/// 在短超时内读取客户端首包并嗅探域名，返回嗅探结果和已经读到的字节。
+async fn sniff_tun_tcp_domain(
+    client: &mut netstack_smoltcp::TcpStream,
+) -> Result<(Option<String>, Vec<u8>)> {
+    let deadline = Instant::now() + TUN_TCP_SNIFF_TIMEOUT;
+    let mut prefetched = Vec::with_capacity(TUN_TCP_PREFETCH_CHUNK);
+    let mut buf = vec![0u8; TUN_TCP_PREFETCH_CHUNK];
+    while prefetched.len() < TUN_TCP_SNIFF_LIMIT {
+        let read = match timeout_at(deadline, client.read(&mut buf)).await {
+            Ok(read) => read?,
+            Err(_) => break,
+        };
+        if read == 0 {
+            break;
+        }
+        prefetched.extend_from_slice(&buf[..read]);
+        match sniff_tcp_domain(&prefetched) {
+            SniffResult::Domain(domain) => return Ok((Some(domain), prefetched)),
+            SniffResult::NotMatched => break,
+            SniffResult::Incomplete => {}
+        }
+    }
+    Ok((None, prefetched))
+}
+
 async fn connect_direct_tcp(
     target: SocketAddr,
     bind_interface: Option<&BindInterface>,
@@ -255,8 +395,7 @@ struct DirectTcpRefreshContext<'a> {
     target: SocketAddr,
     target_str: &'a str,
     direct_egress: &'a super::TunDirectEgress,
-    tcp_sessions: &'a YamuxSessionManager,
-    udp_sessions: &'a crate::yamux_session::YamuxSessionManager,
+    router: &'a OutboundRouter,
     tun_networks: super::network::TunNetworks,
 }
 
@@ -267,8 +406,7 @@ async fn connect_direct_tcp_with_refresh(
         target,
         target_str,
         direct_egress,
-        tcp_sessions,
-        udp_sessions,
+        router,
         tun_networks,
     } = context;
     let initial_bind_interface = direct_egress.bind_interface(target.ip());
@@ -280,7 +418,7 @@ async fn connect_direct_tcp_with_refresh(
                 target_str, initial_bind_interface, first_err
             );
             let refreshed_bind_interface = direct_egress
-                .refresh_after_direct_failure(target.ip(), tcp_sessions, udp_sessions, tun_networks)
+                .refresh_after_direct_failure(target.ip(), router, tun_networks)
                 .await;
             match connect_direct_tcp(target, refreshed_bind_interface.as_ref()).await {
                 Ok(stream) => Ok(stream),
@@ -1,23 +1,23 @@
 //! TUN UDP 会话处理。
 //!
 //! UDP 在 netstack 层没有连接生命周期，所以外层会按 source/target 近似会话化。
-//! 本模块负责单个会话的直连或代理中继；未命中直连规则的高并发普通 UDP
+//! 本模块负责单个会话的直连或代理中继；按分流规则走 proxy 的高并发普通 UDP
 //! 通常会被 `udp_relay.rs` 的共享 relay 接走。
 
 use super::network::{
     TunNetworks, address_for_tun_target, is_tun_local_udp_target, reject_tun_target,
 };
-use crate::direct_access::{DirectAccessChecker, address_to_string};
+use crate::connections::{ConnectionLease, register_connection};
 use crate::error::{AgentError, Result};
+use crate::routing::{OutboundRouter, Route, RouteDecision, address_to_string};
 use crate::telemetry;
-use crate::yamux_session::YamuxSessionManager;
 use common::{BindInterface, QuicPolicy, bind_socket_to_interface};
 use futures::SinkExt;
 use protocol::TransportProtocol;
 use socket2::{Domain, Protocol, SockAddr, Socket, Type};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::sync::Arc;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::Ordering;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::UdpSocket;
 use tokio::time::{Duration, timeout};
@@ -37,11 +37,10 @@ pub(super) struct UdpSessionContext {
     pub(super) force_direct: bool,
     pub(super) quic_policy: QuicPolicy,
     pub(super) netstack_tx: UdpWriter,
-    pub(super) tcp_sessions: Arc<YamuxSessionManager>,
-    pub(super) udp_sessions: Arc<YamuxSessionManager>,
-    pub(super) direct_checker: Arc<DirectAccessChecker>,
+    pub(super) router: Arc<OutboundRouter>,
     pub(super) direct_domain_cache: Arc<DirectDomainCache>,
     pub(super) direct_egress: Arc<super::TunDirectEgress>,
+    pub(super) process: Option<Arc<common::SourceProcess>>,
     pub(super) shutdown: CancellationToken,
 }
 
@@ -53,9 +52,9 @@ struct DirectUdpRelayContext {
     rx: tokio::sync::mpsc::Receiver<Vec<u8>>,
     netstack_tx: UdpWriter,
     direct_egress: Arc<super::TunDirectEgress>,
-    tcp_sessions: Arc<YamuxSessionManager>,
-    udp_sessions: Arc<YamuxSessionManager>,
+    router: Arc<OutboundRouter>,
     tun_networks: TunNetworks,
+    connection: ConnectionLease,
     shutdown: CancellationToken,
 }
 
@@ -72,11 +71,10 @@ pub(super) async fn handle_tun_udp(
         force_direct,
         quic_policy,
         netstack_tx,
-        tcp_sessions,
-        udp_sessions,
-        direct_checker,
+        router,
         direct_domain_cache,
         direct_egress,
+        process,
         shutdown,
     } = context;
 
@@ -101,27 +99,58 @@ pub(super) async fn handle_tun_udp(
     } else {
         target.to_string()
     };
+    let connection_target = target_label.clone();
+    let target_label = match &process {
+        Some(process) => format!("{target_label}，进程 {process}"),
+        None => target_label,
+    };
 
     let mut direct_target = (!proxy_dns_request && force_direct).then_some(target);
     let mut direct_label = target_label.clone();
     let proxy_address = address.clone();
     let mut proxy_reason = None;
+    // proxy DNS 等内部目标固定走默认分组。
+    let mut proxy_group = router.default_group();
+    // 没有经过分流规则的会话按原因标注。
+    let mut rule = if proxy_dns_request {
+        "proxy_dns"
+    } else {
+        "force_direct"
+    }
+    .to_string();
     if direct_target.is_none() && !proxy_dns_request {
         // UDP 没有 TCP 的 SNI 嗅探机会，主要依赖 IP/CIDR 和 DNS proxy 记录的域名缓存。
-        if direct_checker.is_direct(&address) {
-            direct_target = Some(target);
-        } else if direct_checker.has_domain_direct_rules()
-            && let Some(domain) = direct_domain_cache
-                .matching_domain_for_ip(target.ip(), |domain| {
-                    direct_checker.is_direct_domain(domain)
-                })
-        {
-            debug!(
-                "TUN UDP 缓存域名规则命中：{} ({})，先使用原始 IP 直连",
-                target, domain
-            );
-            direct_label = format!("{} ({})", target_label, domain);
-            direct_target = Some(target);
+        let policy = router.policy();
+        let cached_domain = if policy.has_domain_rules() {
+            direct_domain_cache
+                .matching_domain_for_ip(target.ip(), |domain| policy.matches_domain_rule(domain))
+        } else {
+            None
+        };
+        let decision = router.decide(
+            &address,
+            TransportProtocol::Udp,
+            cached_domain.as_deref(),
+            process.as_deref(),
+        );
+        rule = decision.rule;
+        match decision.route {
+            Route::Direct => {
+                if let Some(domain) = &cached_domain {
+                    debug!(
+                        "TUN UDP 缓存域名规则命中：{} ({})，先使用原始 IP 直连",
+                        target, domain
+                    );
+                    direct_label = format!("{} ({})", target_label, domain);
+                }
+                direct_target = Some(target);
+            }
+            Route::Block => {
+                debug!("TUN UDP 已被分流规则拒绝 -> {}", target_label);
+                drain_dropped_udp(rx, &shutdown).await;
+                return Ok(());
+            }
+            Route::Proxy(group) => proxy_group = group,
         }
     }
 
@@ -149,6 +178,17 @@ pub(super) async fn handle_tun_udp(
         // 直连 UDP 使用本地 UDP socket 与目标通信，回复写回 netstack。
         let target_str = address_to_string(&address);
         debug!("TUN UDP 直连 -> {}", target_str);
+        let connection = register_connection(
+            "TUN UDP",
+            client,
+            connection_target,
+            TransportProtocol::Udp,
+            &RouteDecision {
+                route: Route::Direct,
+                rule,
+            },
+        );
+        connection.set_process(process.as_deref());
         relay_direct_udp(DirectUdpRelayContext {
             client,
             original_target: target,
@@ -157,9 +197,9 @@ pub(super) async fn handle_tun_udp(
             rx,
             netstack_tx,
             direct_egress,
-            tcp_sessions,
-            udp_sessions,
+            router: router.clone(),
             tun_networks,
+            connection,
             shutdown,
         })
         .await?;
@@ -171,21 +211,29 @@ pub(super) async fn handle_tun_udp(
     if proxy_dns_request {
         debug!("TUN UDP DNS -> 代理 -> {}", target_label);
     } else {
-        debug!("TUN UDP -> 代理 -> {}", proxy_label);
+        debug!("TUN UDP -> 代理 {} -> {}", proxy_group.name(), proxy_label);
     }
-    let connected = udp_sessions
-        .as_ref()
+    let connected = proxy_group
+        .udp_sessions()
         .connect_to_target(proxy_address, TransportProtocol::Udp)
         .await?;
     let proxy_io = connected.into_async_io();
     let (mut reader, mut writer) = tokio::io::split(proxy_io);
-    let outbound_bytes = Arc::new(AtomicU64::new(0));
-    let inbound_bytes = Arc::new(AtomicU64::new(0));
+    let connection = register_connection(
+        "TUN UDP",
+        client,
+        connection_target,
+        TransportProtocol::Udp,
+        &RouteDecision {
+            route: Route::Proxy(proxy_group),
+            rule,
+        },
+    );
+    connection.set_process(process.as_deref());
 
     // 写方向：同一 UDP 会话的 payload 从 channel 进入 proxy stream。
     let write_target = target_label.clone();
-    let outbound_bytes_w = outbound_bytes.clone();
-    let write = async move {
+    let write = async {
         while let Some(data) = rx.recv().await {
             let data_len = data.len();
             trace!(
@@ -196,15 +244,14 @@ pub(super) async fn handle_tun_udp(
                 debug!("UDP 代理写入错误：{e}");
                 break;
             }
-            outbound_bytes_w.fetch_add(data_len as u64, Ordering::Relaxed);
+            connection.add_outbound(data_len);
             let _ = writer.flush().await;
         }
     };
     let netstack_tx_r = netstack_tx.clone();
     let read_target = target_label.clone();
-    let inbound_bytes_r = inbound_bytes.clone();
     // 读方向：proxy 返回的 payload 重新写回 netstack 的 UDP 发送半边。
-    let read = async move {
+    let read = async {
         let mut buf = vec![0u8; 65535];
         loop {
             match reader.read(&mut buf).await {
@@ -220,7 +267,7 @@ pub(super) async fn handle_tun_udp(
                         debug!("UDP 代理回复错误：{e}");
                         break;
                     }
-                    inbound_bytes_r.fetch_add(n as u64, Ordering::Relaxed);
+                    connection.add_inbound(n);
                 }
                 Err(e) => {
                     debug!("UDP 代理读取错误：{e}");
@@ -233,13 +280,14 @@ pub(super) async fn handle_tun_udp(
     tokio::select! {
         _ = write => {}
         _ = read => {}
+        _ = connection.cancel_token().cancelled() => {}
     }
 
     telemetry::emit_traffic(
         "TUN UDP",
         target_label,
-        outbound_bytes.load(Ordering::Relaxed),
-        inbound_bytes.load(Ordering::Relaxed),
+        connection.outbound_counter().load(Ordering::Relaxed),
+        connection.inbound_counter().load(Ordering::Relaxed),
     );
     Ok(())
 }
@@ -253,9 +301,9 @@ async fn relay_direct_udp(context: DirectUdpRelayContext) -> Result<()> {
         mut rx,
         netstack_tx,
         direct_egress,
-        tcp_sessions,
-        udp_sessions,
+        router,
         tun_networks,
+        connection,
         shutdown,
     } = context;
 
@@ -264,8 +312,7 @@ async fn relay_direct_udp(context: DirectUdpRelayContext) -> Result<()> {
         connect_target,
         &target_label,
         direct_egress.as_ref(),
-        tcp_sessions.as_ref(),
-        udp_sessions.as_ref(),
+        router.as_ref(),
         tun_networks,
     )
     .await?;
@@ -278,6 +325,7 @@ async fn relay_direct_udp(context: DirectUdpRelayContext) -> Result<()> {
     loop {
         tokio::select! {
             _ = shutdown.cancelled() => break,
+            _ = connection.cancel_token().cancelled() => break,
             _ = &mut idle => {
                 debug!(
                     "TUN UDP 直连会话空闲超过 {} 秒，关闭 -> {}",
@@ -293,6 +341,7 @@ async fn relay_direct_udp(context: DirectUdpRelayContext) -> Result<()> {
                     debug!("UDP 直连发送错误：{e}");
                     break;
                 }
+                connection.add_outbound(data_len);
                 let data_len = data_len as u64;
                 outbound_bytes += data_len;
                 telemetry::record_traffic(data_len, 0);
@@ -307,6 +356,7 @@ async fn relay_direct_udp(context: DirectUdpRelayContext) -> Result<()> {
                             debug!("UDP 直连回复错误：{e}");
                             break;
                         }
+                        connection.add_inbound(n);
                         let received_bytes = n as u64;
                         inbound_bytes += received_bytes;
                         telemetry::record_traffic(0, received_bytes);
@@ -333,8 +383,7 @@ async fn connect_direct_udp_with_refresh(
     target: SocketAddr,
     target_label: &str,
     direct_egress: &super::TunDirectEgress,
-    tcp_sessions: &YamuxSessionManager,
-    udp_sessions: &YamuxSessionManager,
+    router: &OutboundRouter,
     tun_networks: TunNetworks,
 ) -> Result<UdpSocket> {
     let initial_bind_interface = match direct_egress.bind_interface(target.ip()) {
@@ -345,7 +394,7 @@ async fn connect_direct_udp_with_refresh(
                 target_label
             );
             direct_egress
-                .refresh_after_direct_failure(target.ip(), tcp_sessions, udp_sessions, tun_networks)
+                .refresh_after_direct_failure(target.ip(), router, tun_networks)
                 .await
         }
     };
@@ -364,7 +413,7 @@ async fn connect_direct_udp_with_refresh(
                 target_label, initial_bind_interface, first_err
             );
             let refreshed_bind_interface = direct_egress
-                .refresh_after_direct_failure(target.ip(), tcp_sessions, udp_sessions, tun_networks)
+                .refresh_after_direct_failure(target.ip(), router, tun_networks)
                 .await
                 .ok_or_else(|| {
                     AgentError::Connection(format!(
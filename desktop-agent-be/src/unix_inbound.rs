@@ -0,0 +1,259 @@
+//! Unix domain socket 本地入口。
+//!
+//! 与 TCP 监听共用 SOCKS4/4a、SOCKS5 与 HTTP 处理逻辑，只是换了一种传输。访问控制依赖
+//! socket 文件权限：IP 白名单与客户端黑名单对 Unix 连接没有意义，本地凭据仍然生效。
+//! SOCKS5 UDP ASSOCIATE 的 relay 是 IP 端口，绕开了文件权限，因此在这里以 0x07 拒绝。
+
+use crate::config::UnixListenerConfig;
+use crate::error::Result;
+use crate::http_handler::UpstreamPool;
+use crate::listener_access::ListenerAccess;
+use crate::routing::OutboundRouter;
+use crate::server::{InboundPeer, handle_connection};
+use crate::unix_socket::bind_unix_socket;
+use common::spawn_guarded;
+use parking_lot::RwLock;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+use tokio::net::{UnixListener, UnixStream};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info};
+
+pub(crate) struct UnixInbound {
+    listener: UnixListener,
+    path: PathBuf,
+    // PAC 与 UDP ASSOCIATE 回复里需要告诉客户端一个 IP 入口。
+    local_addr: SocketAddr,
+    router: Arc<OutboundRouter>,
+    access: Arc<RwLock<Arc<ListenerAccess>>>,
+    pool: Arc<UpstreamPool>,
+}
+
+impl UnixInbound {
+    pub(crate) fn bind(
+        config: &UnixListenerConfig,
+        local_addr: SocketAddr,
+        router: Arc<OutboundRouter>,
+        access: Arc<RwLock<Arc<ListenerAccess>>>,
+        pool: Arc<UpstreamPool>,
+    ) -> Result<Self> {
+        let path = PathBuf::from(config.path.trim());
+        if path.as_os_str().is_empty() {
+            return Err(
+                common::CommonError::Config("[unix_listener] path 不能为空".to_string()).into(),
+            );
+        }
+        let listener = bind_unix_socket(&path, config.mode)?;
+        Ok(Self {
+            listener,
+            path,
+            local_addr,
+            router,
+            access,
+            pool,
+        })
+    }
+
+    pub(crate) async fn run(self, shutdown: CancellationToken) {
+        info!("Agent 服务器正在监听 Unix socket {}", self.path.display());
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                accepted = self.listener.accept() => match accepted {
+                    Ok((stream, _)) => self.serve(stream),
+                    Err(e) => error!("接受 Unix socket 连接失败：{}", e),
+                }
+            }
+        }
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            debug!("删除 Unix socket {} 失败：{e}", self.path.display());
+        }
+        info!("Unix socket 入口已停止");
+    }
+
+    fn serve(&self, stream: UnixStream) {
+        let pid = stream.peer_cred().ok().and_then(|cred| cred.pid());
+        let peer = InboundPeer::Unix(pid);
+        debug!("接受来自 {} 的连接", peer);
+        let local_addr = self.local_addr;
+        let router = self.router.clone();
+        let access = self.access.read().clone();
+        let pool = self.pool.clone();
+        spawn_guarded("desktop unix inbound connection", async move {
+            let mut stream = stream;
+            // UnixStream 没有 peek，读出首字节后再由 PrefixedStream 回放给协议处理器。
+            let first_byte = match stream.read_u8().await {
+                Ok(byte) => byte,
+                Err(e) => {
+                    debug!("读取 {} 的首字节失败：{}", peer, e);
+                    return;
+                }
+            };
+            let stream = PrefixedStream::new(first_byte, stream);
+            if let Err(e) =
+                handle_connection(stream, first_byte, peer, local_addr, router, access, pool).await
+            {
+                error!("处理连接时出错：{}", e);
+            }
+        });
+    }
+}
+
+/// 先回放一个已读出的字节，再透传底层流。
+struct PrefixedStream<S> {
+    prefix: Option<u8>,
+    inner: S,
+}
+
+impl<S> PrefixedStream<S> {
+    fn new(prefix: u8, inner: S) -> Self {
+        Self {
+            prefix: Some(prefix),
+            inner,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for PrefixedStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if buf.remaining() > 0
+            && let Some(byte) = self.prefix.take()
+        {
+            buf.put_slice(&[byte]);
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for PrefixedStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AgentConfig;
+    use std::os::unix::fs::PermissionsExt;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    const CONFIG: &str = r#"
+proxy_addrs = ["127.0.0.1:8080"]
+username = "user1"
+private_key_path = "keys/user1.pem"
+
+[direct_access]
+mode = "direct_all"
+
+[unix_listener]
+path = "unused"
+"#;
+
+    /// 在临时目录绑定 Unix 入口并启动，返回 socket 路径、关闭令牌与服务任务。
+    fn start_inbound(name: &str) -> (PathBuf, CancellationToken, tokio::task::JoinHandle<()>) {
+        let config: AgentConfig = toml::from_str(CONFIG).unwrap();
+        let path =
+            std::env::temp_dir().join(format!("ppaass-unix-inbound-{name}-{}", std::process::id()));
+        let unix_config = UnixListenerConfig {
+            path: path.to_string_lossy().into_owned(),
+            ..config.unix_listener.clone().unwrap()
+        };
+        let config = Arc::new(config);
+        let access = ListenerAccess::new(&config.listener_access).unwrap();
+        let inbound = UnixInbound::bind(
+            &unix_config,
+            SocketAddr::from(([127, 0, 0, 1], 1080)),
+            Arc::new(OutboundRouter::new(config).unwrap()),
+            Arc::new(RwLock::new(Arc::new(access))),
+            Arc::new(UpstreamPool::default()),
+        )
+        .unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let shutdown = CancellationToken::new();
+        let server = tokio::spawn(inbound.run(shutdown.clone()));
+        (path, shutdown, server)
+    }
+
+    #[tokio::test]
+    async fn socks5_connect_over_unix_socket_relays_direct_target() {
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target_port = target.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = target.accept().await.unwrap();
+            let mut buf = [0u8; 4];
+            stream.read_exact(&mut buf).await.unwrap();
+            stream.write_all(&buf).await.unwrap();
+        });
+
+        let (path, shutdown, server) = start_inbound("connect");
+
+        let mut client = UnixStream::connect(&path).await.unwrap();
+        client.write_all(&[5, 1, 0]).await.unwrap();
+        let mut method = [0u8; 2];
+        client.read_exact(&mut method).await.unwrap();
+        assert_eq!(method, [5, 0]);
+
+        let mut request = vec![5, 1, 0, 1, 127, 0, 0, 1];
+        request.extend_from_slice(&target_port.to_be_bytes());
+        client.write_all(&request).await.unwrap();
+        let mut reply = [0u8; 10];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply[..2], [5, 0]);
+
+        client.write_all(b"ping").await.unwrap();
+        let mut echoed = [0u8; 4];
+        client.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"ping");
+
+        shutdown.cancel();
+        server.await.unwrap();
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn udp_associate_is_rejected_over_unix_socket() {
+        let (path, shutdown, server) = start_inbound("udp");
+
+        let mut client = UnixStream::connect(&path).await.unwrap();
+        client.write_all(&[5, 1, 0]).await.unwrap();
+        let mut method = [0u8; 2];
+        client.read_exact(&mut method).await.unwrap();
+        assert_eq!(method, [5, 0]);
+
+        client
+            .write_all(&[5, 3, 0, 1, 0, 0, 0, 0, 0, 0])
+            .await
+            .unwrap();
+        let mut reply = [0u8; 2];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply, [5, 7]);
+
+        shutdown.cancel();
+        server.await.unwrap();
+    }
+}
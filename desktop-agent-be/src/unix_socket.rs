@@ -0,0 +1,115 @@
+//! 本地 Unix socket 的安全绑定。
+//!
+//! 控制 API 与 Unix 入口都靠 socket 文件权限做访问控制，因此绑定时要保证：
+//! 只清理确实已无人监听的旧 socket，不误删同名的普通文件；socket 文件从出现在
+//! 目标路径的那一刻起就已经是最终权限，没有先 bind 后 chmod 的可连接窗口。
+
+use std::fs::{self, DirBuilder, Permissions};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use tokio::net::UnixListener;
+
+/// 在 `path` 绑定 Unix socket，文件权限为 `mode`。
+///
+/// socket 先在同目录下一个仅属主可访问的临时目录中创建并设置权限，再原子地
+/// rename 到 `path`。`path` 已存在时，只有无人监听的旧 socket 会被删除；仍在
+/// 使用的 socket 或其他类型的文件一律报错。
+pub(crate) fn bind_unix_socket(path: &Path, mode: u32) -> io::Result<UnixListener> {
+    remove_stale_socket(path)?;
+    let staging = staging_dir(path)?;
+    DirBuilder::new().mode(0o700).create(&staging)?;
+    let bound = bind_in(&staging, path, mode);
+    // 临时目录只在 rename 失败时还留有 socket 文件。
+    let _ = fs::remove_dir_all(&staging);
+    let listener = bound?;
+    listener.set_nonblocking(true)?;
+    UnixListener::from_std(listener)
+}
+
+fn bind_in(staging: &Path, path: &Path, mode: u32) -> io::Result<std::os::unix::net::UnixListener> {
+    let staged = staging.join("socket");
+    let listener = std::os::unix::net::UnixListener::bind(&staged)?;
+    fs::set_permissions(&staged, Permissions::from_mode(mode))?;
+    fs::rename(&staged, path)?;
+    Ok(listener)
+}
+
+/// 上次异常退出留下的 socket 文件会让 bind 失败；只删除连不上的 socket。
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} 已存在且不是 Unix socket，拒绝覆盖", path.display()),
+        ));
+    }
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("{} 已有进程在监听", path.display()),
+        ));
+    }
+    fs::remove_file(path)
+}
+
+fn staging_dir(path: &Path) -> io::Result<PathBuf> {
+    let name = path.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unix socket 路径 {} 缺少文件名", path.display()),
+        )
+    })?;
+    let mut staging = std::ffi::OsString::from(".");
+    staging.push(name);
+    staging.push(format!(".bind-{}", std::process::id()));
+    Ok(path.with_file_name(staging))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ppaass-unix-socket-{name}-{}", std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn binds_with_final_mode_and_replaces_only_stale_sockets() {
+        let path = temp_path("stale");
+        let _ = fs::remove_file(&path);
+
+        let listener = bind_unix_socket(&path, 0o600).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // 仍在监听的 socket 不能被第二个实例抢走。
+        assert_eq!(
+            bind_unix_socket(&path, 0o600).unwrap_err().kind(),
+            io::ErrorKind::AddrInUse
+        );
+
+        // 监听者退出后留下的 socket 文件可以复用。
+        drop(listener);
+        assert!(path.exists());
+        let _listener = bind_unix_socket(&path, 0o660).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+        assert!(!staging_dir(&path).unwrap().exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_to_replace_regular_files() {
+        let path = temp_path("regular");
+        fs::write(&path, b"keep me").unwrap();
+
+        let err = bind_unix_socket(&path, 0o600).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&path).unwrap(), b"keep me");
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -5,6 +5,7 @@
 //! TCP 上的 Yamux 连接池。
 
 use super::proxy_connection::new_yamux_connection;
+use super::selector::ProxySelector;
 use super::target_stream::YamuxTargetStream;
 use crate::config::AgentConfig;
 use crate::error::{AgentError, Result};
@@ -12,16 +13,19 @@ use common::{
     BindInterface, UdpClientConnection, YAMUX_SESSION_STREAM_CAPACITY_EXHAUSTED_MESSAGE,
     YAMUX_TARGET_CONNECT_RESPONSE_TIMEOUT_MESSAGE, YamuxClientConnection,
 };
+use parking_lot::RwLock;
 use protocol::{Address, TransportProtocol};
 use std::net::IpAddr;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Notify};
 use tracing::{debug, instrument, warn};
 
 const MAX_CONCURRENT_SESSION_CONNECTS: usize = 20;
 
 mod connect;
+mod health;
+mod recovery;
 mod yamux;
 
 #[derive(Clone)]
@@ -37,11 +41,15 @@ struct UdpSessionHandle {
 }
 
 pub struct YamuxSessionManager {
-    config: Arc<AgentConfig>,
+    // 热重载时整体替换；新建连接读取最新快照，已建立的连接不受影响。
+    config: RwLock<Arc<AgentConfig>>,
+    // 同一分组的 TCP/UDP manager 共享，记录各 proxy 地址的 RTT 与可用状态。
+    selector: RwLock<Arc<ProxySelector>>,
     manager_name: &'static str,
     yamux_transport: TransportProtocol,
     proxy_bind_ip: Arc<std::sync::RwLock<Option<IpAddr>>>,
     proxy_bind_interface: Arc<std::sync::RwLock<Option<BindInterface>>>,
+    proxy_fwmark: Arc<std::sync::RwLock<Option<u32>>>,
     yamux_sessions: Arc<Mutex<Vec<YamuxSessionHandle>>>,
     // 每个 slot 拥有独立原生 UDP socket/会话密钥/序号空间。slot 级锁使首次
     // 并发建连可以平行进行，不会被一把全局锁串行化。
@@ -54,35 +62,53 @@ pub struct YamuxSessionManager {
     // 自动模式按原生 UDP pool slot 独立记录回退状态。一个 session 超时不会
     // 让其他仍可用的 UDP session 一并切到 TCP。
     auto_udp_fallback_to_yamux: Vec<AtomicBool>,
+    // slot 回退时唤醒后台恢复探测任务。
+    auto_udp_recovery_notify: Notify,
 }
 
 impl YamuxSessionManager {
-    pub fn new(config: Arc<AgentConfig>) -> Self {
-        Self::new_for_transport(config, TransportProtocol::Tcp, "tcp_direct_connections")
+    pub(crate) fn new(config: Arc<AgentConfig>, selector: Arc<ProxySelector>) -> Self {
+        Self::new_for_transport(
+            config,
+            selector,
+            TransportProtocol::Tcp,
+            "tcp_direct_connections",
+        )
     }
 
-    pub fn new_udp(config: Arc<AgentConfig>) -> Self {
-        Self::new_for_transport(config, TransportProtocol::Udp, "udp_yamux_sessions")
+    pub(crate) fn new_udp(config: Arc<AgentConfig>, selector: Arc<ProxySelector>) -> Self {
+        Self::new_for_transport(
+            config,
+            selector,
+            TransportProtocol::Udp,
+            "udp_yamux_sessions",
+        )
     }
 
     fn new_for_transport(
         config: Arc<AgentConfig>,
+        selector: Arc<ProxySelector>,
         yamux_transport: TransportProtocol,
         manager_name: &'static str,
     ) -> Self {
         // TCP manager 始终走 direct framed TCP，不需要占用 UDP socket/内存。
         // 只有 UDP manager 保留可配置的原生 UDP 会话池。
-        let udp_pool_size = if config.transport_mode.uses_native_udp_for(yamux_transport) {
+        let udp_pool_size = if config
+            .effective_transport_mode()
+            .uses_native_udp_for(yamux_transport)
+        {
             config.effective_udp_session_pool_size()
         } else {
             0
         };
         Self {
-            config,
+            config: RwLock::new(config),
+            selector: RwLock::new(selector),
             manager_name,
             yamux_transport,
             proxy_bind_ip: Arc::new(std::sync::RwLock::new(None)),
             proxy_bind_interface: Arc::new(std::sync::RwLock::new(None)),
+            proxy_fwmark: Arc::new(std::sync::RwLock::new(None)),
             yamux_sessions: Arc::new(Mutex::new(Vec::new())),
             udp_sessions: (0..udp_pool_size).map(|_| Mutex::new(None)).collect(),
             yamux_refill_lock: Arc::new(Mutex::new(())),
@@ -93,9 +119,39 @@ impl YamuxSessionManager {
             auto_udp_fallback_to_yamux: (0..udp_pool_size)
                 .map(|_| AtomicBool::new(false))
                 .collect(),
+            auto_udp_recovery_notify: Notify::new(),
         }
     }
 
+    pub(crate) fn config(&self) -> Arc<AgentConfig> {
+        self.config.read().clone()
+    }
+
+    pub(crate) fn selector(&self) -> Arc<ProxySelector> {
+        self.selector.read().clone()
+    }
+
+    /// 热重载：替换配置与地址选择器。proxy 端点或认证身份变化时清空会话池，
+    /// 让后续流量用新配置重新建连；已经打开的目标流继续使用原来的会话。
+    pub(crate) async fn reload(&self, config: Arc<AgentConfig>, selector: Arc<ProxySelector>) {
+        let endpoint_changed = !same_proxy_endpoint(&self.config(), &config);
+        *self.config.write() = config;
+        *self.selector.write() = selector;
+        if !endpoint_changed {
+            return;
+        }
+        let dropped_yamux = std::mem::take(&mut *self.yamux_sessions.lock().await).len();
+        let mut dropped_udp = 0;
+        for (slot, session) in self.udp_sessions.iter().enumerate() {
+            dropped_udp += usize::from(session.lock().await.take().is_some());
+            self.auto_udp_fallback_to_yamux[slot].store(false, Ordering::Release);
+        }
+        debug!(
+            manager = self.manager_name,
+            dropped_yamux, dropped_udp, "proxy 端点已变化，会话池已清空"
+        );
+    }
+
     pub fn set_proxy_bind_ip(&self, ip: Option<IpAddr>) {
         if let Ok(mut guard) = self.proxy_bind_ip.write() {
             *guard = ip;
@@ -108,6 +164,12 @@ impl YamuxSessionManager {
         }
     }
 
+    pub fn set_proxy_fwmark(&self, fwmark: Option<u32>) {
+        if let Ok(mut guard) = self.proxy_fwmark.write() {
+            *guard = fwmark;
+        }
+    }
+
     fn get_proxy_bind_ip(&self) -> Option<IpAddr> {
         let guard = self.proxy_bind_ip.read().ok()?;
         *guard
@@ -118,6 +180,11 @@ impl YamuxSessionManager {
         guard.clone()
     }
 
+    fn get_proxy_fwmark(&self) -> Option<u32> {
+        let guard = self.proxy_fwmark.read().ok()?;
+        *guard
+    }
+
     fn next_udp_session_slot(&self) -> usize {
         // 只有 UDP manager 会进入此路径，AgentConfig 已把 pool size 夹到至少 1。
         debug_assert_eq!(self.yamux_transport, TransportProtocol::Udp);
@@ -126,7 +193,17 @@ impl YamuxSessionManager {
     }
 }
 
-fn is_yamux_target_connect_error(message: &str) -> bool {
+fn same_proxy_endpoint(current: &AgentConfig, next: &AgentConfig) -> bool {
+    current.proxy_addrs == next.proxy_addrs
+        && current.username == next.username
+        && current.private_key_path == next.private_key_path
+        && current.compression_mode == next.compression_mode
+        && current.connect_timeout_secs == next.connect_timeout_secs
+        && current.pre_proxy == next.pre_proxy
+        && current.yamux == next.yamux
+}
+
+pub(super) fn is_yamux_target_connect_error(message: &str) -> bool {
     message.starts_with("连接失败:")
         || message == YAMUX_TARGET_CONNECT_RESPONSE_TIMEOUT_MESSAGE
         || message == "连接目标响应超时"
@@ -169,8 +246,9 @@ private_key_path = "keys/user1.pem"
     fn only_udp_manager_allocates_native_udp_pool() {
         let config: AgentConfig = toml::from_str(MINIMAL_AGENT_CONFIG).unwrap();
         let config = Arc::new(config);
-        let tcp_manager = YamuxSessionManager::new(config.clone());
-        let udp_manager = YamuxSessionManager::new_udp(config);
+        let selector = Arc::new(ProxySelector::new(&config));
+        let tcp_manager = YamuxSessionManager::new(config.clone(), selector.clone());
+        let udp_manager = YamuxSessionManager::new_udp(config, selector);
 
         assert!(tcp_manager.udp_sessions.is_empty());
         assert_eq!(udp_manager.udp_sessions.len(), 4);
@@ -185,7 +263,8 @@ private_key_path = "keys/user1.pem"
         let config: AgentConfig =
             toml::from_str(&(MINIMAL_AGENT_CONFIG.to_owned() + "transport_mode = \"tcp\"\n"))
                 .unwrap();
-        let manager = YamuxSessionManager::new_udp(Arc::new(config));
+        let selector = Arc::new(ProxySelector::new(&config));
+        let manager = YamuxSessionManager::new_udp(Arc::new(config), selector);
 
         assert!(manager.udp_sessions.is_empty());
     }
@@ -195,7 +274,8 @@ private_key_path = "keys/user1.pem"
         let config: AgentConfig =
             toml::from_str(&(MINIMAL_AGENT_CONFIG.to_owned() + "transport_mode = \"auto\"\n"))
                 .unwrap();
-        let manager = YamuxSessionManager::new_udp(Arc::new(config));
+        let selector = Arc::new(ProxySelector::new(&config));
+        let manager = YamuxSessionManager::new_udp(Arc::new(config), selector);
 
         assert_eq!(manager.auto_udp_fallback_to_yamux.len(), 4);
         manager.auto_udp_fallback_to_yamux[1].store(true, Ordering::Release);
@@ -203,4 +283,21 @@ private_key_path = "keys/user1.pem"
         assert!(manager.auto_udp_fallback_to_yamux[1].load(Ordering::Acquire));
         assert!(!manager.auto_udp_fallback_to_yamux[2].load(Ordering::Acquire));
     }
+
+    #[test]
+    fn endpoint_comparison_tracks_pre_proxy_and_yamux_changes() {
+        let parse = |extra: &str| -> AgentConfig {
+            toml::from_str(&(MINIMAL_AGENT_CONFIG.to_owned() + extra)).unwrap()
+        };
+        let base = parse("");
+        assert!(same_proxy_endpoint(&base, &parse("")));
+        assert!(!same_proxy_endpoint(
+            &base,
+            &parse("pre_proxy = \"socks5://127.0.0.1:1080\"\n")
+        ));
+        assert!(!same_proxy_endpoint(
+            &base,
+            &parse("[yamux.udp]\nsessions = 2\n")
+        ));
+    }
 }
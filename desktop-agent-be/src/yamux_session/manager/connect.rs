@@ -1,6 +1,7 @@
 use super::*;
 use crate::yamux_session::proxy_connection::new_direct_tcp_target_stream;
 use common::TransportMode;
+use common::routing::address_to_string;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum ProxyStreamRoute {
@@ -40,15 +41,28 @@ impl YamuxSessionManager {
         // TCP 数据始终使用原有的 direct framed TCP 路径，transport_mode
         // 只决定 UDP 数据是否改用原生加密 UDP。先校验 manager 类型，避免误调用
         // 绕过 TCP/UDP 语义隔离。
-        match proxy_stream_route(self.config.transport_mode, self.yamux_transport, transport) {
+        match proxy_stream_route(
+            self.config().effective_transport_mode(),
+            self.yamux_transport,
+            transport,
+        ) {
             ProxyStreamRoute::DirectTcp => {
-                let (stream, stream_id) = new_direct_tcp_target_stream(
-                    &self.config,
-                    self.get_proxy_bind_ip(),
-                    self.get_proxy_bind_interface(),
-                    address,
-                )
-                .await?;
+                // 每条 TCP 目标连接独立拨号，目标建连失败时换一个 proxy 重试。
+                let label = format!("TCP 目标 {}", address_to_string(&address));
+                let config = self.config();
+                let (stream, stream_id) = self
+                    .selector()
+                    .connect_with_failover(Some(&address), &label, |proxy_addr| {
+                        new_direct_tcp_target_stream(
+                            &config,
+                            proxy_addr,
+                            self.get_proxy_bind_ip(),
+                            self.get_proxy_bind_interface(),
+                            self.get_proxy_fwmark(),
+                            address.clone(),
+                        )
+                    })
+                    .await?;
                 Ok(YamuxTargetStream::new_direct(stream, stream_id))
             }
             ProxyStreamRoute::NativeUdp => self.open_udp_target_stream(address, transport).await,
@@ -64,10 +78,11 @@ impl YamuxSessionManager {
                     Ok(stream) => Ok(stream),
                     Err(err) if is_native_udp_timeout(&err) => {
                         self.auto_udp_fallback_to_yamux[slot_index].store(true, Ordering::Release);
+                        self.auto_udp_recovery_notify.notify_one();
                         warn!(
                             manager = self.manager_name,
                             slot = slot_index,
-                            "自动 UDP 模式检测到原生加密 UDP session 超时，仅将该 session slot 的后续流量切换到 TCP/Yamux：{err}"
+                            "自动 UDP 模式检测到原生加密 UDP session 超时，仅将该 session slot 的后续流量切换到 TCP/Yamux，后台将探测恢复：{err}"
                         );
                         self.open_target_stream(address, transport).await
                     }
@@ -107,7 +122,10 @@ impl YamuxSessionManager {
         for attempt in 0..2 {
             let handle = {
                 let mut current = self.udp_sessions[slot_index].lock().await;
-                if self.config.transport_mode.automatically_falls_back_to_tcp()
+                if self
+                    .config()
+                    .effective_transport_mode()
+                    .automatically_falls_back_to_tcp()
                     && current
                         .as_ref()
                         .is_some_and(|handle| handle.connection.timed_out())
@@ -121,14 +139,23 @@ impl YamuxSessionManager {
                     .as_ref()
                     .is_none_or(|handle| handle.connection.is_closed())
                 {
-                    let adapter = crate::yamux_session::proxy_connection::AgentClientConfig::new(
-                        &self.config,
-                        self.get_proxy_bind_ip(),
-                        self.get_proxy_bind_interface(),
-                    );
-                    let connection = UdpClientConnection::connect(&adapter)
-                        .await
-                        .map_err(AgentError::Io)?;
+                    let config = self.config();
+                    let connection = self
+                        .selector()
+                        .connect_with_failover(None, "原生 UDP 会话", |proxy_addr| async {
+                            let adapter =
+                                crate::yamux_session::proxy_connection::AgentClientConfig::new(
+                                    &config,
+                                    proxy_addr,
+                                    self.get_proxy_bind_ip(),
+                                    self.get_proxy_bind_interface(),
+                                )
+                                .with_fwmark(self.get_proxy_fwmark());
+                            UdpClientConnection::connect(&adapter)
+                                .await
+                                .map_err(AgentError::Io)
+                        })
+                        .await?;
                     let connection_id = self.udp_next_session_id.fetch_add(1, Ordering::AcqRel);
                     debug!(
                         manager = self.manager_name,
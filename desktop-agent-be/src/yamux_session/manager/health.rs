@@ -0,0 +1,89 @@
+//! proxy 健康探测。
+//!
+//! 每个周期对分组内每个 proxy 地址做一次完整的 TCP 连接 + 认证握手，握手耗时
+//! 作为 RTT 样本交给 [`ProxySelector`]；失败则把该地址暂时标记为不可用。
+
+use super::*;
+use crate::yamux_session::proxy_connection::AgentClientConfig;
+use common::{AuthenticatedConnection, spawn_guarded};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+/// 单次探测的超时；connect_timeout_secs 面向业务连接，通常太长。
+const PROXY_HEALTH_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+/// 探测未启用时重新检查选择器的间隔，热重载可能切换策略或地址列表。
+const PROXY_HEALTH_IDLE_RECHECK: Duration = Duration::from_secs(30);
+
+impl YamuxSessionManager {
+    pub(crate) fn spawn_proxy_health_checks(
+        self: &Arc<Self>,
+        group: &str,
+        shutdown: CancellationToken,
+    ) {
+        if let Some(period) = self.selector().health_check_interval() {
+            info!(
+                "proxy 分组 {} 启用健康探测：策略={:?} 周期={}s",
+                group,
+                self.config().proxy_selection.strategy,
+                period.as_secs()
+            );
+        }
+        let manager = self.clone();
+        spawn_guarded("desktop proxy health check", async move {
+            loop {
+                // 每轮重新取选择器，热重载替换后探测结果记到新的选择器上。
+                let selector = manager.selector();
+                let period = match selector.health_check_interval() {
+                    Some(period) => {
+                        let probes = selector
+                            .addrs()
+                            .map(|addr| manager.probe_proxy(&selector, addr));
+                        tokio::select! {
+                            _ = shutdown.cancelled() => break,
+                            _ = futures::future::join_all(probes) => {}
+                        }
+                        period
+                    }
+                    None => PROXY_HEALTH_IDLE_RECHECK,
+                };
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = tokio::time::sleep(period) => {}
+                }
+            }
+        });
+    }
+
+    async fn probe_proxy(&self, selector: &ProxySelector, addr: &str) {
+        let config = self.config();
+        let adapter = AgentClientConfig::new(
+            &config,
+            addr.to_string(),
+            self.get_proxy_bind_ip(),
+            self.get_proxy_bind_interface(),
+        )
+        .with_fwmark(self.get_proxy_fwmark());
+        let started = Instant::now();
+        match tokio::time::timeout(
+            PROXY_HEALTH_PROBE_TIMEOUT,
+            AuthenticatedConnection::connect(&adapter),
+        )
+        .await
+        {
+            Ok(Ok(_connection)) => {
+                let rtt = started.elapsed();
+                debug!("proxy 健康探测成功：{} rtt={}ms", addr, rtt.as_millis());
+                selector.record_rtt(addr, rtt);
+            }
+            Ok(Err(err)) => {
+                debug!("proxy 健康探测失败：{} {}", addr, err);
+                selector.record_failure(addr);
+            }
+            Err(_) => {
+                debug!("proxy 健康探测超时：{}", addr);
+                selector.record_failure(addr);
+            }
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! 自动传输模式的原生 UDP 恢复探测。
+//!
+//! slot 因原生 UDP 超时回退到 TCP/Yamux 后，后台任务按指数退避为该 slot 重新
+//! 建立一条原生 UDP 会话（完整 Auth），再连续发送 Ping 并等待 Pong。全部成功后
+//! 用这条新会话替换 slot 中已超时的旧会话，并把 slot 切回原生加密 UDP。
+
+use super::*;
+use crate::telemetry::{self, TransportSlotRecord};
+use crate::yamux_session::proxy_connection::AgentClientConfig;
+use common::spawn_guarded;
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+const AUTO_UDP_RECOVERY_INITIAL_BACKOFF: Duration = Duration::from_secs(10);
+const AUTO_UDP_RECOVERY_MAX_BACKOFF: Duration = Duration::from_secs(300);
+/// 一次探测要求连续成功的 Ping 数，避免网络刚恢复一瞬间就切回又再次回退。
+const AUTO_UDP_RECOVERY_PINGS: usize = 3;
+const AUTO_UDP_RECOVERY_PING_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// 单个已回退 slot 的探测计划。
+struct RecoverySchedule {
+    backoff: Duration,
+    next_probe: Instant,
+    failures: u32,
+}
+
+impl RecoverySchedule {
+    fn new(now: Instant) -> Self {
+        Self {
+            backoff: AUTO_UDP_RECOVERY_INITIAL_BACKOFF,
+            next_probe: now + AUTO_UDP_RECOVERY_INITIAL_BACKOFF,
+            failures: 0,
+        }
+    }
+
+    fn record_failure(&mut self, now: Instant) {
+        self.failures += 1;
+        self.backoff = (self.backoff * 2).min(AUTO_UDP_RECOVERY_MAX_BACKOFF);
+        self.next_probe = now + self.backoff;
+    }
+}
+
+impl YamuxSessionManager {
+    pub(crate) fn spawn_auto_udp_recovery(
+        self: &Arc<Self>,
+        group: &str,
+        shutdown: CancellationToken,
+    ) {
+        if self.auto_udp_fallback_to_yamux.is_empty()
+            || !self
+                .config()
+                .effective_transport_mode()
+                .automatically_falls_back_to_tcp()
+        {
+            return;
+        }
+        let manager = self.clone();
+        let group = group.to_string();
+        spawn_guarded("desktop auto udp recovery", async move {
+            manager.run_auto_udp_recovery(&group, shutdown).await;
+        });
+    }
+
+    async fn run_auto_udp_recovery(&self, group: &str, shutdown: CancellationToken) {
+        let mut schedules = (0..self.auto_udp_fallback_to_yamux.len())
+            .map(|_| None)
+            .collect::<Vec<Option<RecoverySchedule>>>();
+        loop {
+            let now = Instant::now();
+            for (slot, schedule) in schedules.iter_mut().enumerate() {
+                if schedule.is_none()
+                    && self.auto_udp_fallback_to_yamux[slot].load(Ordering::Acquire)
+                {
+                    let new_schedule = RecoverySchedule::new(now);
+                    self.emit_transport_slot(group, slot, Some(&new_schedule));
+                    *schedule = Some(new_schedule);
+                }
+            }
+
+            let next_probe = schedules
+                .iter()
+                .flatten()
+                .map(|schedule| schedule.next_probe)
+                .min();
+            let wait = async move {
+                match next_probe {
+                    Some(at) => tokio::time::sleep_until(at).await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = self.auto_udp_recovery_notify.notified() => continue,
+                _ = wait => {}
+            }
+
+            for (slot, entry) in schedules.iter_mut().enumerate() {
+                let Some(schedule) = entry.as_mut() else {
+                    continue;
+                };
+                if !self.auto_udp_fallback_to_yamux[slot].load(Ordering::Acquire) {
+                    // 热重载更换 proxy 端点时会清空会话池并复位回退标记。
+                    *entry = None;
+                    self.emit_transport_slot(group, slot, None);
+                    continue;
+                }
+                if schedule.next_probe > Instant::now() {
+                    continue;
+                }
+                match self.probe_native_udp().await {
+                    Ok((connection, rtt)) => {
+                        let failures = schedule.failures;
+                        self.restore_native_udp_slot(slot, connection).await;
+                        *entry = None;
+                        info!(
+                            manager = self.manager_name,
+                            group,
+                            slot,
+                            rtt_ms = rtt.as_millis() as u64,
+                            failures,
+                            "原生 UDP 恢复探测成功，session slot 已切回原生加密 UDP"
+                        );
+                        self.emit_transport_slot(group, slot, None);
+                    }
+                    Err(err) => {
+                        schedule.record_failure(Instant::now());
+                        info!(
+                            manager = self.manager_name,
+                            group,
+                            slot,
+                            failures = schedule.failures,
+                            "原生 UDP 恢复探测失败，{}s 后重试，slot 继续使用 TCP/Yamux：{err}",
+                            schedule.backoff.as_secs()
+                        );
+                        self.emit_transport_slot(group, slot, Some(schedule));
+                    }
+                }
+            }
+        }
+    }
+
+    async fn probe_native_udp(&self) -> Result<(UdpClientConnection, Duration)> {
+        // 不经过 connect_with_failover：UDP 不通不代表该 proxy 的 TCP 也不可用，
+        // 恢复探测不能影响选择器的可用状态。
+        let proxy_addr = self
+            .selector()
+            .candidates(None)
+            .into_iter()
+            .next()
+            .ok_or_else(|| AgentError::Connection("没有可用的 proxy 地址".to_string()))?;
+        let config = self.config();
+        let adapter = AgentClientConfig::new(
+            &config,
+            proxy_addr,
+            self.get_proxy_bind_ip(),
+            self.get_proxy_bind_interface(),
+        )
+        .with_fwmark(self.get_proxy_fwmark());
+        let connection = UdpClientConnection::connect(&adapter)
+            .await
+            .map_err(AgentError::Io)?;
+        let mut rtt = Duration::ZERO;
+        for _ in 0..AUTO_UDP_RECOVERY_PINGS {
+            rtt = connection
+                .ping(AUTO_UDP_RECOVERY_PING_TIMEOUT)
+                .await
+                .map_err(AgentError::Io)?;
+        }
+        Ok((connection, rtt))
+    }
+
+    async fn restore_native_udp_slot(&self, slot: usize, connection: UdpClientConnection) {
+        // 先替换掉已超时的旧会话，再清除回退标记，避免新流量又读到超时状态。
+        let connection_id = self.udp_next_session_id.fetch_add(1, Ordering::AcqRel);
+        *self.udp_sessions[slot].lock().await = Some(UdpSessionHandle {
+            id: connection_id,
+            connection,
+        });
+        self.auto_udp_fallback_to_yamux[slot].store(false, Ordering::Release);
+    }
+
+    fn emit_transport_slot(&self, group: &str, slot: usize, schedule: Option<&RecoverySchedule>) {
+        let now_ms = telemetry::current_time_millis();
+        telemetry::emit_transport_slot(TransportSlotRecord {
+            group: group.to_string(),
+            slot,
+            transport: if schedule.is_some() {
+                "tcp_yamux"
+            } else {
+                "native_udp"
+            }
+            .to_string(),
+            recovery_failures: schedule.map_or(0, |schedule| schedule.failures),
+            next_probe_ms: schedule.map(|schedule| {
+                now_ms
+                    + schedule
+                        .next_probe
+                        .saturating_duration_since(Instant::now())
+                        .as_millis()
+            }),
+            updated_ms: now_ms,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recovery_backoff_doubles_up_to_the_cap() {
+        let now = Instant::now();
+        let mut schedule = RecoverySchedule::new(now);
+        assert_eq!(schedule.next_probe, now + AUTO_UDP_RECOVERY_INITIAL_BACKOFF);
+
+        let delays = (0..6)
+            .map(|_| {
+                schedule.record_failure(now);
+                schedule.next_probe - now
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [20, 40, 80, 160, 300, 300].map(Duration::from_secs));
+        assert_eq!(schedule.failures, 6);
+    }
+}
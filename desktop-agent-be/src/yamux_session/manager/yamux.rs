@@ -106,15 +106,27 @@ impl YamuxSessionManager {
         let semaphore = Arc::new(tokio::sync::Semaphore::new(MAX_CONCURRENT_SESSION_CONNECTS));
         let mut set = tokio::task::JoinSet::new();
         for _ in 0..to_create {
-            let config = self.config.clone();
+            let config = self.config();
+            let selector = self.selector();
             let semaphore = semaphore.clone();
             let bind_ip = self.get_proxy_bind_ip();
             let bind_interface = self.get_proxy_bind_interface();
+            let fwmark = self.get_proxy_fwmark();
             let transport = self.yamux_transport;
             let session_id = self.yamux_next_session_id.fetch_add(1, Ordering::AcqRel);
             set.spawn(async move {
                 let _permit = semaphore.acquire().await.ok();
-                new_yamux_connection(&config, bind_ip, bind_interface, transport)
+                selector
+                    .connect_with_failover(None, "Yamux session", |proxy_addr| {
+                        new_yamux_connection(
+                            &config,
+                            proxy_addr,
+                            bind_ip,
+                            bind_interface.clone(),
+                            fwmark,
+                            transport,
+                        )
+                    })
                     .await
                     .map(|connection| YamuxSessionHandle {
                         id: session_id,
@@ -256,7 +268,7 @@ impl YamuxSessionManager {
 
     pub(super) fn yamux_target_size(&self) -> usize {
         match self.yamux_transport {
-            TransportProtocol::Udp => self.config.yamux.udp_session_count(),
+            TransportProtocol::Udp => self.config().yamux.udp_session_count(),
             TransportProtocol::Tcp => 0,
         }
     }
@@ -1,6 +1,8 @@
 mod manager;
 mod proxy_connection;
+mod selector;
 mod target_stream;
 
 pub use manager::YamuxSessionManager;
+pub(crate) use selector::ProxySelector;
 pub use target_stream::YamuxTargetStream;
@@ -5,9 +5,10 @@ use std::{fs::read_to_string, net::IpAddr, net::SocketAddr, time::Duration};
 use crate::config::AgentConfig;
 use crate::error::{AgentError, Result};
 use common::{
-    AuthenticatedConnection, BindInterface, ClientConnectionConfig, YamuxClientConnection,
+    AuthenticatedConnection, BindInterface, ClientConnectionConfig, PreProxy, YamuxClientConnection,
 };
 use protocol::{Address, CompressionMode, TransportProtocol};
+use socket2::Socket;
 use tracing::instrument;
 
 // 桌面端 agent 到 proxy 的 TCP 缓冲。
@@ -16,33 +17,40 @@ const DESKTOP_PROXY_SOCKET_BUFFER_SIZE: usize = 1024 * 1024;
 #[derive(Debug)]
 pub(super) struct AgentClientConfig<'a> {
     config: &'a AgentConfig,
+    // 由分组的 ProxySelector 选出的本次连接地址。
+    proxy_addr: String,
     bind_ip: Option<IpAddr>,
     bind_interface: Option<BindInterface>,
+    // Linux TUN 策略路由模式下 proxy 连接的 SO_MARK。
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    fwmark: Option<u32>,
 }
 
 impl<'a> AgentClientConfig<'a> {
     pub(super) fn new(
         config: &'a AgentConfig,
+        proxy_addr: String,
         bind_ip: Option<IpAddr>,
         bind_interface: Option<BindInterface>,
     ) -> Self {
         Self {
             config,
+            proxy_addr,
             bind_ip,
             bind_interface,
+            fwmark: None,
         }
     }
+
+    pub(super) fn with_fwmark(mut self, fwmark: Option<u32>) -> Self {
+        self.fwmark = fwmark;
+        self
+    }
 }
 
 impl<'a> ClientConnectionConfig for AgentClientConfig<'a> {
     fn remote_addr(&self) -> String {
-        use rand::prelude::*;
-        let mut rng = rand::rng();
-        self.config
-            .proxy_addrs
-            .choose(&mut rng)
-            .cloned()
-            .unwrap_or_default()
+        self.proxy_addr.clone()
     }
 
     fn username(&self) -> String {
@@ -72,16 +80,33 @@ impl<'a> ClientConnectionConfig for AgentClientConfig<'a> {
     fn tcp_socket_buffer_size(&self) -> Option<usize> {
         Some(DESKTOP_PROXY_SOCKET_BUFFER_SIZE)
     }
+
+    fn pre_proxy(&self) -> Option<PreProxy> {
+        self.config.pre_proxy.clone()
+    }
+
+    fn protect_socket(&self, socket: &Socket, _dst: SocketAddr) -> std::io::Result<()> {
+        #[cfg(target_os = "linux")]
+        if let Some(fwmark) = self.fwmark {
+            socket.set_mark(fwmark)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = socket;
+        Ok(())
+    }
 }
 
 #[instrument(skip(config))]
 pub(super) async fn new_yamux_connection(
     config: &AgentConfig,
+    proxy_addr: String,
     bind_ip: Option<IpAddr>,
     bind_interface: Option<BindInterface>,
+    fwmark: Option<u32>,
     transport: TransportProtocol,
 ) -> Result<YamuxClientConnection> {
-    let config_adapter = AgentClientConfig::new(config, bind_ip, bind_interface);
+    let config_adapter =
+        AgentClientConfig::new(config, proxy_addr, bind_ip, bind_interface).with_fwmark(fwmark);
     let yamux_settings = config.yamux.udp_settings();
     YamuxClientConnection::connect_for(&config_adapter, transport, yamux_settings)
         .await
@@ -91,11 +116,14 @@ pub(super) async fn new_yamux_connection(
 #[instrument(skip(config))]
 pub(super) async fn new_direct_tcp_target_stream(
     config: &AgentConfig,
+    proxy_addr: String,
     bind_ip: Option<IpAddr>,
     bind_interface: Option<BindInterface>,
+    fwmark: Option<u32>,
     address: Address,
 ) -> Result<(common::ClientStream, String)> {
-    let config_adapter = AgentClientConfig::new(config, bind_ip, bind_interface);
+    let config_adapter =
+        AgentClientConfig::new(config, proxy_addr, bind_ip, bind_interface).with_fwmark(fwmark);
     let connection = AuthenticatedConnection::connect(&config_adapter)
         .await
         .map_err(|e| AgentError::Connection(e.to_string()))?;
@@ -121,8 +149,9 @@ compression_mode = "gzip"
     #[test]
     fn connection_config_adapter_forwards_compression_mode() {
         let config: AgentConfig = toml::from_str(MINIMAL_AGENT_CONFIG).unwrap();
-        let adapter = AgentClientConfig::new(&config, None, None);
+        let adapter = AgentClientConfig::new(&config, "127.0.0.1:8080".to_string(), None, None);
 
         assert_eq!(adapter.compression_mode(), CompressionMode::Gzip);
+        assert_eq!(adapter.remote_addr(), "127.0.0.1:8080");
     }
 }
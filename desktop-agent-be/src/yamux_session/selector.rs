@@ -0,0 +1,354 @@
+//! 分组内 proxy 地址选择。
+//!
+//! 每个 proxy 分组共享一个 [`ProxySelector`]：它记录各地址的平滑 RTT 与可用状态，
+//! 并按配置的策略给出本次连接的候选顺序。建连失败的地址会暂时排到最后，
+//! 直到健康探测或一次成功连接把它恢复。
+
+use crate::config::{AgentConfig, ProxySelectionStrategy};
+use crate::error::{AgentError, Result};
+use parking_lot::Mutex;
+use protocol::Address;
+use rand::seq::SliceRandom;
+use std::cmp::Reverse;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// 一次目标连接最多尝试的 proxy 数：首选失败后只换一个地址重试，避免目标本身
+/// 不可达时把等待时间放大到所有地址。
+const MAX_PROXY_CONNECT_ATTEMPTS: usize = 2;
+/// 建连失败后地址被视为不可用的时长；健康探测成功会提前恢复。
+const PROXY_DOWN_HOLD: Duration = Duration::from_secs(30);
+
+pub(crate) struct ProxySelector {
+    strategy: ProxySelectionStrategy,
+    health_check_interval: Option<Duration>,
+    servers: Vec<ProxyServer>,
+    next_index: AtomicUsize,
+}
+
+struct ProxyServer {
+    addr: String,
+    health: Mutex<ProxyHealth>,
+}
+
+#[derive(Default)]
+struct ProxyHealth {
+    // 只由健康探测的认证握手耗时更新；业务连接包含目标建连时间，不计入。
+    srtt: Option<Duration>,
+    consecutive_failures: u32,
+    down_until: Option<Instant>,
+}
+
+impl ProxyHealth {
+    fn is_available(&self, now: Instant) -> bool {
+        self.down_until.is_none_or(|until| until <= now)
+    }
+}
+
+impl ProxySelector {
+    pub(crate) fn new(config: &AgentConfig) -> Self {
+        Self {
+            strategy: config.proxy_selection.strategy,
+            health_check_interval: health_check_interval(config),
+            servers: config
+                .proxy_addrs
+                .iter()
+                .map(|addr| ProxyServer {
+                    addr: addr.clone(),
+                    health: Mutex::new(ProxyHealth::default()),
+                })
+                .collect(),
+            next_index: AtomicUsize::new(0),
+        }
+    }
+
+    /// 地址列表与选择参数都与 `config` 一致时返回 true，热重载据此沿用已有的 RTT 状态。
+    pub(crate) fn matches(&self, config: &AgentConfig) -> bool {
+        self.strategy == config.proxy_selection.strategy
+            && self.health_check_interval == health_check_interval(config)
+            && self
+                .addrs()
+                .eq(config.proxy_addrs.iter().map(String::as_str))
+    }
+
+    /// 需要后台健康探测时返回探测周期。
+    pub(super) fn health_check_interval(&self) -> Option<Duration> {
+        self.health_check_interval
+    }
+
+    pub(super) fn addrs(&self) -> impl Iterator<Item = &str> {
+        self.servers.iter().map(|server| server.addr.as_str())
+    }
+
+    /// 按策略返回本次连接的 proxy 地址顺序，暂时不可用的地址排在最后兜底。
+    ///
+    /// `target` 只用于 consistent_hash；共享会话池建连时没有具体目标，按 fastest 排序。
+    pub(super) fn candidates(&self, target: Option<&Address>) -> Vec<String> {
+        let mut order = (0..self.servers.len()).collect::<Vec<_>>();
+        match (self.strategy, target) {
+            (ProxySelectionStrategy::Random, _) => order.shuffle(&mut rand::rng()),
+            (ProxySelectionStrategy::RoundRobin, _) if !order.is_empty() => {
+                let start = self.next_index.fetch_add(1, Ordering::Relaxed) % order.len();
+                order.rotate_left(start);
+            }
+            (ProxySelectionStrategy::ConsistentHash, Some(target)) => {
+                // rendezvous hash：地址增减时只有落在该地址上的目标会迁移。
+                let key = target_hash_key(target);
+                order.sort_by_key(|&index| {
+                    Reverse(rendezvous_weight(&key, &self.servers[index].addr))
+                });
+            }
+            (ProxySelectionStrategy::Fastest | ProxySelectionStrategy::ConsistentHash, _) => {
+                order.sort_by_key(|&index| {
+                    self.servers[index]
+                        .health
+                        .lock()
+                        .srtt
+                        .unwrap_or(Duration::MAX)
+                });
+            }
+            (ProxySelectionStrategy::RoundRobin | ProxySelectionStrategy::Failover, _) => {}
+        }
+        let now = Instant::now();
+        // 稳定排序，可用地址之间保留策略给出的顺序。
+        order.sort_by_key(|&index| !self.servers[index].health.lock().is_available(now));
+        order
+            .into_iter()
+            .map(|index| self.servers[index].addr.clone())
+            .collect()
+    }
+
+    /// 按候选顺序建连，失败时换下一个 proxy 重试，全部失败才返回最后一个错误。
+    pub(super) async fn connect_with_failover<T, F, Fut>(
+        &self,
+        target: Option<&Address>,
+        label: &str,
+        mut connect: F,
+    ) -> Result<T>
+    where
+        F: FnMut(String) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut candidates = self.candidates(target);
+        candidates.truncate(MAX_PROXY_CONNECT_ATTEMPTS);
+        let attempts = candidates.len();
+        let mut last_error = None;
+        for (attempt, addr) in candidates.into_iter().enumerate() {
+            match connect(addr.clone()).await {
+                Ok(value) => {
+                    self.record_success(&addr);
+                    return Ok(value);
+                }
+                Err(err) => {
+                    // proxy 已响应但目标不可达时不算 proxy 故障，只换地址重试。
+                    if !is_target_connect_failure(&err) {
+                        self.record_failure(&addr);
+                    }
+                    if attempt + 1 < attempts {
+                        warn!(
+                            "{} 经 proxy {} 建连失败，尝试其它地址：{}",
+                            label, addr, err
+                        );
+                    }
+                    last_error = Some(err);
+                }
+            }
+        }
+        Err(last_error
+            .unwrap_or_else(|| AgentError::Connection("没有可用的 proxy 地址".to_string())))
+    }
+
+    /// 记录一次健康探测成功，按 RFC 6298 的 7/8 权重平滑 RTT。
+    pub(super) fn record_rtt(&self, addr: &str, rtt: Duration) {
+        let Some(server) = self.server(addr) else {
+            return;
+        };
+        let mut health = server.health.lock();
+        health.srtt = Some(match health.srtt {
+            Some(srtt) => (srtt * 7 + rtt) / 8,
+            None => rtt,
+        });
+        Self::mark_up(addr, &mut health);
+    }
+
+    fn record_success(&self, addr: &str) {
+        if let Some(server) = self.server(addr) {
+            Self::mark_up(addr, &mut server.health.lock());
+        }
+    }
+
+    pub(super) fn record_failure(&self, addr: &str) {
+        // 只有一个地址时没有可以切换的候选，不必记录状态。
+        if self.servers.len() < 2 {
+            return;
+        }
+        let Some(server) = self.server(addr) else {
+            return;
+        };
+        let mut health = server.health.lock();
+        let now = Instant::now();
+        if health.is_available(now) {
+            warn!(
+                "proxy {} 暂时标记为不可用（连续失败 {} 次）",
+                addr,
+                health.consecutive_failures + 1
+            );
+        }
+        health.consecutive_failures += 1;
+        health.down_until = Some(now + PROXY_DOWN_HOLD);
+    }
+
+    fn mark_up(addr: &str, health: &mut ProxyHealth) {
+        if health.consecutive_failures > 0 {
+            info!(
+                "proxy {} 已恢复可用（此前连续失败 {} 次）",
+                addr, health.consecutive_failures
+            );
+        }
+        health.consecutive_failures = 0;
+        health.down_until = None;
+    }
+
+    fn server(&self, addr: &str) -> Option<&ProxyServer> {
+        self.servers.iter().find(|server| server.addr == addr)
+    }
+}
+
+fn health_check_interval(config: &AgentConfig) -> Option<Duration> {
+    let selection = &config.proxy_selection;
+    (selection.strategy != ProxySelectionStrategy::Random
+        && selection.health_check_interval_secs > 0
+        && config.proxy_addrs.len() > 1)
+        .then(|| Duration::from_secs(selection.health_check_interval_secs))
+}
+
+fn is_target_connect_failure(error: &AgentError) -> bool {
+    matches!(error, AgentError::Connection(message) if super::manager::is_yamux_target_connect_error(message))
+}
+
+fn target_hash_key(target: &Address) -> String {
+    // 只按主机哈希，同一站点的不同端口落在同一 proxy。
+    match target {
+        Address::Domain { host, .. } => host.to_ascii_lowercase(),
+        Address::Ipv4 { addr, .. } => Ipv4Addr::from(*addr).to_string(),
+        Address::Ipv6 { addr, .. } => Ipv6Addr::from(*addr).to_string(),
+        other => common::routing::address_to_string(other),
+    }
+}
+
+fn rendezvous_weight(key: &str, addr: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    addr.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selector(strategy: &str) -> ProxySelector {
+        let config: AgentConfig = toml::from_str(&format!(
+            r#"
+proxy_addrs = ["10.0.0.1:8080", "10.0.0.2:8080", "10.0.0.3:8080"]
+username = "user1"
+private_key_path = "keys/user1.pem"
+
+[proxy_selection]
+strategy = "{strategy}"
+"#
+        ))
+        .unwrap();
+        ProxySelector::new(&config)
+    }
+
+    fn domain(host: &str) -> Address {
+        Address::Domain {
+            host: host.to_string(),
+            port: 443,
+        }
+    }
+
+    #[test]
+    fn fastest_prefers_lowest_smoothed_rtt_and_demotes_failed_servers() {
+        let selector = selector("fastest");
+        selector.record_rtt("10.0.0.1:8080", Duration::from_millis(80));
+        selector.record_rtt("10.0.0.2:8080", Duration::from_millis(20));
+        selector.record_rtt("10.0.0.3:8080", Duration::from_millis(50));
+        assert_eq!(selector.candidates(None)[0], "10.0.0.2:8080");
+        assert!(selector.health_check_interval().is_some());
+
+        // 一次 200ms 的慢探测只按 1/8 权重拉高平滑 RTT。
+        selector.record_rtt("10.0.0.2:8080", Duration::from_millis(200));
+        assert_eq!(selector.candidates(None)[0], "10.0.0.2:8080");
+
+        selector.record_failure("10.0.0.2:8080");
+        assert_eq!(
+            selector.candidates(None),
+            vec!["10.0.0.3:8080", "10.0.0.1:8080", "10.0.0.2:8080"]
+        );
+        selector.record_rtt("10.0.0.2:8080", Duration::from_millis(20));
+        assert_eq!(selector.candidates(None)[0], "10.0.0.2:8080");
+    }
+
+    #[test]
+    fn round_robin_failover_and_consistent_hash_orders() {
+        let selector = selector("round_robin");
+        let firsts = (0..4)
+            .map(|_| selector.candidates(None)[0].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            firsts,
+            vec![
+                "10.0.0.1:8080",
+                "10.0.0.2:8080",
+                "10.0.0.3:8080",
+                "10.0.0.1:8080"
+            ]
+        );
+
+        let selector = self::selector("failover");
+        selector.record_failure("10.0.0.1:8080");
+        assert_eq!(selector.candidates(None)[0], "10.0.0.2:8080");
+
+        let selector = self::selector("consistent_hash");
+        let first = selector.candidates(Some(&domain("video.example.com")))[0].clone();
+        let mut other_port = domain("VIDEO.example.com");
+        if let Address::Domain { port, .. } = &mut other_port {
+            *port = 80;
+        }
+        assert_eq!(selector.candidates(Some(&other_port))[0], first);
+        selector.record_failure(&first);
+        assert_ne!(
+            selector.candidates(Some(&domain("video.example.com")))[0],
+            first
+        );
+    }
+
+    #[tokio::test]
+    async fn connect_with_failover_retries_next_proxy() {
+        let selector = selector("failover");
+        let mut tried = Vec::new();
+        let result = selector
+            .connect_with_failover(None, "test", |addr| {
+                tried.push(addr.clone());
+                async move {
+                    if addr == "10.0.0.1:8080" {
+                        Err(AgentError::Connection("proxy 不可达".to_string()))
+                    } else {
+                        Ok(addr)
+                    }
+                }
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(result, "10.0.0.2:8080");
+        assert_eq!(tried, vec!["10.0.0.1:8080", "10.0.0.2:8080"]);
+        assert_eq!(selector.candidates(None)[0], "10.0.0.2:8080");
+        assert!(self::selector("random").health_check_interval().is_none());
+    }
+}
@@ -8,6 +8,7 @@ use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
 
+use desktop_agent_be::reload::ConfigReloader;
 use tokio_util::sync::CancellationToken;
 
 use crate::config::{locate_config_path, make_absolute_path, summarize_config};
@@ -227,16 +228,10 @@ fn spawn_embedded_agent(
     last_error: Arc<Mutex<Option<String>>>,
 ) -> Result<EmbeddedAgent, String> {
     let agent_base_dir = agent_base_dir(&config_path);
-    let mut config = desktop_agent_be::config::AgentConfig::load(&config_path)
-        .map_err(|err| format!("加载 Agent 配置失败：{err}"))?;
-    normalize_agent_config_paths(&mut config, &agent_base_dir);
-    config.log_dir = None;
-    #[cfg(target_os = "macos")]
-    {
-        config.tun.macos_helper_fallback_to_privilege = false;
-    }
+    let config = load_embedded_agent_config(&config_path, &agent_base_dir)?;
     let shutdown = CancellationToken::new();
     let shutdown_for_thread = shutdown.clone();
+    let (reloader, reloads) = ConfigReloader::channel();
     let thread_logs = logs.clone();
     let thread_error = last_error.clone();
     let stack_size = config.async_runtime_stack_size_mb * 1024 * 1024;
@@ -264,8 +259,11 @@ fn spawn_embedded_agent(
 
             match builder.build() {
                 Ok(runtime) => {
-                    let result =
-                        runtime.block_on(desktop_agent_be::run_agent(config, shutdown_for_thread));
+                    let result = runtime.block_on(desktop_agent_be::run_agent(
+                        config,
+                        shutdown_for_thread,
+                        reloads,
+                    ));
                     if let Err(err) = result {
                         let message = format!("内嵌 Agent 异常停止：{err}");
                         if let Ok(mut last_error) = thread_error.lock() {
@@ -288,9 +286,54 @@ fn spawn_embedded_agent(
     Ok(EmbeddedAgent {
         shutdown,
         join: Some(join),
+        reloader,
     })
 }
 
+/// 把磁盘上的最新配置热重载进运行中的内嵌 Agent；未运行时什么也不做。
+pub(crate) fn reload_embedded_agent(runtime: &AgentRuntime) -> Result<(), String> {
+    let reloader = match runtime
+        .agent
+        .lock()
+        .map_err(|_| "进程状态锁已损坏".to_string())?
+        .as_ref()
+    {
+        Some(agent) if !agent.join.as_ref().is_some_and(JoinHandle::is_finished) => {
+            agent.reloader.clone()
+        }
+        _ => return Ok(()),
+    };
+    let Some(config_path) = runtime
+        .config_path
+        .lock()
+        .map_err(|_| "配置路径状态锁已损坏".to_string())?
+        .clone()
+    else {
+        return Ok(());
+    };
+    let config = load_embedded_agent_config(&config_path, &agent_base_dir(&config_path))?;
+    // 结果和需要重启的字段由 Agent 自己写入日志。
+    reloader
+        .blocking_reload(config)
+        .map(|_| ())
+        .map_err(|err| format!("Agent 配置热重载失败：{err}"))
+}
+
+fn load_embedded_agent_config(
+    config_path: &Path,
+    agent_base_dir: &Path,
+) -> Result<desktop_agent_be::config::AgentConfig, String> {
+    let mut config = desktop_agent_be::config::AgentConfig::load(config_path)
+        .map_err(|err| format!("加载 Agent 配置失败：{err}"))?;
+    normalize_agent_config_paths(&mut config, agent_base_dir);
+    config.log_dir = None;
+    #[cfg(target_os = "macos")]
+    {
+        config.tun.macos_helper_fallback_to_privilege = false;
+    }
+    Ok(config)
+}
+
 fn normalize_agent_config_paths(
     config: &mut desktop_agent_be::config::AgentConfig,
     base_dir: &Path,
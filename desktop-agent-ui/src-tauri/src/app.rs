@@ -3,7 +3,8 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use crate::agent::{
-    apply_ui_log_level, get_agent_state_inner, start_agent_command, stop_agent_inner_command,
+    apply_ui_log_level, get_agent_state_inner, reload_embedded_agent, start_agent_command,
+    stop_agent_inner_command,
 };
 use crate::config::{
     install_bundled_agent_assets, load_config_from_path, load_default_config, locate_config_path,
@@ -20,7 +21,11 @@ use crate::models::ServiceRequest;
 use crate::models::{AgentState, ConnectivityReport, LoadedAgentConfig, NetworkTrafficSnapshot};
 use crate::process_util::run_blocking;
 use crate::runtime::AgentRuntime;
-use crate::telemetry::{get_dns_resolution_records_inner, get_network_traffic_snapshot_inner};
+use crate::telemetry::{
+    close_connection_inner, get_connections_inner, get_dns_resolution_records_inner,
+    get_inbound_clients_inner, get_network_traffic_snapshot_inner,
+    set_inbound_client_blocked_inner,
+};
 use crate::tray::restore_main_window;
 #[cfg(any(windows, target_os = "macos"))]
 use crate::tray::{
@@ -122,6 +127,34 @@ async fn get_dns_resolution_records(
     run_blocking("读取 DNS 解析记录", get_dns_resolution_records_inner).await
 }
 
+#[tauri::command]
+async fn get_inbound_clients() -> Result<serde_json::Value, String> {
+    run_blocking("读取客户端列表", get_inbound_clients_inner).await
+}
+
+#[tauri::command]
+async fn set_inbound_client_blocked(
+    ip: String,
+    blocked: bool,
+) -> Result<serde_json::Value, String> {
+    run_blocking("更新客户端黑名单", move || {
+        set_inbound_client_blocked_inner(ip, blocked)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_connections() -> Result<Vec<desktop_agent_be::connections::ConnectionRecord>, String> {
+    run_blocking("读取连接列表", get_connections_inner).await
+}
+
+#[tauri::command]
+async fn close_connection(
+    id: u64,
+) -> Result<Vec<desktop_agent_be::connections::ConnectionRecord>, String> {
+    run_blocking("关闭连接", move || close_connection_inner(id)).await
+}
+
 fn load_agent_config_inner(
     runtime: &AgentRuntime,
     path: Option<String>,
@@ -156,6 +189,9 @@ fn save_agent_config_inner(
 
     apply_ui_log_level(runtime, &loaded.summary.log_level);
     remember_ui_config_path(runtime, &loaded.path)?;
+    if let Err(err) = reload_embedded_agent(runtime) {
+        runtime.logs.push(err);
+    }
     #[cfg(windows)]
     let _ = send_service_request(&ServiceRequest::SetLogLevel {
         log_level: loaded.summary.log_level.clone(),
@@ -248,7 +284,11 @@ pub(crate) fn run() {
             stop_agent,
             run_connectivity_tests,
             get_network_traffic_snapshot,
-            get_dns_resolution_records
+            get_dns_resolution_records,
+            get_inbound_clients,
+            set_inbound_client_blocked,
+            get_connections,
+            close_connection
         ])
         .run(tauri::generate_context!())
         .expect("error while running PPAASS Desktop Agent UI");
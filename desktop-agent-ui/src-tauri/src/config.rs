@@ -241,6 +241,14 @@ pub(crate) fn summarize_config(raw: &str) -> Result<AgentConfigSummary, String>
     })
 }
 
+/// 本地监听配置了 `[listener_access]` 凭据时返回 curl `--proxy-user` 使用的 "user:pass"。
+pub(crate) fn listener_proxy_user(raw: &str) -> Option<String> {
+    let value = toml::from_str::<Value>(raw).ok()?;
+    let username = str_at(&value, &["listener_access", "username"])?;
+    let password = str_at(&value, &["listener_access", "password"])?;
+    Some(format!("{username}:{password}"))
+}
+
 pub(crate) fn locate_config_path() -> Option<PathBuf> {
     let file_names = [
         "agent.toml",
@@ -6,7 +6,7 @@ use tokio::net::TcpStream;
 use tokio::runtime::Builder;
 use tokio::time::timeout;
 
-use crate::config::{locate_config_path, summarize_config};
+use crate::config::{listener_proxy_user, locate_config_path, summarize_config};
 use crate::models::{ConnectivityCheck, ConnectivityReport};
 use crate::network::{
     connect_addr, failed_connectivity_check, probe_tun_ready, proxy_url, run_curl_check,
@@ -25,6 +25,7 @@ pub(crate) fn run_connectivity_tests_blocking(
     };
     let raw = fs::read_to_string(&config_path).map_err(|err| format!("读取配置失败：{err}"))?;
     let summary = summarize_config(&raw)?;
+    let proxy_user = listener_proxy_user(&raw);
     let listen_addr = summary.listen_addr.clone();
     let tun_enabled = summary.tun_enabled;
     let tun_name = summary.tun_name.clone();
@@ -60,8 +61,16 @@ pub(crate) fn run_connectivity_tests_blocking(
             let protocol = (*protocol).to_string();
             let url = url.to_string();
             let proxy = proxy.clone();
+            let proxy_user = proxy_user.clone();
             result_jobs.push(thread::spawn(move || {
-                run_curl_check(&target, &protocol, &url, Some(proxy.as_str()), &proxy)
+                run_curl_check(
+                    &target,
+                    &protocol,
+                    &url,
+                    Some(proxy.as_str()),
+                    proxy_user.as_deref(),
+                    &proxy,
+                )
             }));
         }
     }
@@ -82,7 +91,7 @@ pub(crate) fn run_connectivity_tests_blocking(
             // and make otherwise healthy TCP checks fail. Diagnostics should observe the path,
             // not create an artificial cold-start burst on it.
             for &(target, url, _) in &targets {
-                tun_results.push(run_curl_check(target, "TUN", url, None, &tun_route));
+                tun_results.push(run_curl_check(target, "TUN", url, None, None, &tun_route));
             }
             for &(target, _, quic_host) in &targets {
                 tun_results.push(run_quic_check(
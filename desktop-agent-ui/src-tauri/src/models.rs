@@ -106,6 +106,10 @@ pub(crate) enum ServiceRequest {
     State,
     Traffic,
     DnsRecords,
+    InboundClients,
+    SetInboundClientBlocked { ip: String, blocked: bool },
+    Connections,
+    CloseConnection { id: u64 },
     SetLogLevel { log_level: String },
 }
 
@@ -116,5 +120,7 @@ pub(crate) struct ServiceResponse {
     pub(crate) state: Option<AgentState>,
     pub(crate) traffic: Option<NetworkTrafficSnapshot>,
     pub(crate) dns_records: Option<Vec<desktop_agent_be::telemetry::DnsResolutionRecord>>,
+    pub(crate) inbound_clients: Option<serde_json::Value>,
+    pub(crate) connections: Option<Vec<desktop_agent_be::connections::ConnectionRecord>>,
     pub(crate) error: Option<String>,
 }
@@ -55,6 +55,7 @@ pub(crate) fn run_curl_check(
     protocol: &str,
     url: &str,
     proxy_url: Option<&str>,
+    proxy_user: Option<&str>,
     route_label: &str,
 ) -> ConnectivityCheck {
     let start = Instant::now();
@@ -79,6 +80,9 @@ pub(crate) fn run_curl_check(
     ];
     if let Some(proxy_url) = proxy_url {
         args.extend(["--proxy".to_string(), proxy_url.to_string()]);
+        if let Some(proxy_user) = proxy_user {
+            args.extend(["--proxy-user".to_string(), proxy_user.to_string()]);
+        }
     } else {
         args.extend(["--noproxy".to_string(), "*".to_string()]);
     }
@@ -2,6 +2,7 @@ use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
 
+use desktop_agent_be::reload::ConfigReloader;
 use tokio_util::sync::CancellationToken;
 
 use crate::logging::UiLogBuffer;
@@ -17,6 +18,7 @@ pub(crate) struct AgentRuntime {
 pub(crate) struct EmbeddedAgent {
     pub(crate) shutdown: CancellationToken,
     pub(crate) join: Option<JoinHandle<()>>,
+    pub(crate) reloader: ConfigReloader,
 }
 
 impl AgentRuntime {
@@ -36,6 +36,102 @@ pub(crate) fn get_dns_resolution_records_inner(
     Ok(desktop_agent_be::telemetry::dns_resolution_records())
 }
 
+pub(crate) fn get_inbound_clients_inner() -> Result<serde_json::Value, String> {
+    #[cfg(windows)]
+    {
+        service_inbound_clients(&ServiceRequest::InboundClients)
+    }
+
+    #[cfg(not(windows))]
+    inbound_clients_snapshot()
+}
+
+pub(crate) fn set_inbound_client_blocked_inner(
+    ip: String,
+    blocked: bool,
+) -> Result<serde_json::Value, String> {
+    #[cfg(windows)]
+    {
+        service_inbound_clients(&ServiceRequest::SetInboundClientBlocked { ip, blocked })
+    }
+
+    #[cfg(not(windows))]
+    {
+        set_inbound_client_blocked(&ip, blocked)?;
+        inbound_clients_snapshot()
+    }
+}
+
+#[cfg(windows)]
+fn service_inbound_clients(request: &ServiceRequest) -> Result<serde_json::Value, String> {
+    let response = send_service_request(request)?;
+    if response.ok {
+        return Ok(response.inbound_clients.unwrap_or_default());
+    }
+    Err(response
+        .error
+        .unwrap_or_else(|| "Agent 服务客户端列表请求失败".to_string()))
+}
+
+pub(crate) fn get_connections_inner(
+) -> Result<Vec<desktop_agent_be::connections::ConnectionRecord>, String> {
+    #[cfg(windows)]
+    {
+        service_connections(&ServiceRequest::Connections)
+    }
+
+    #[cfg(not(windows))]
+    Ok(desktop_agent_be::connections::active_connections())
+}
+
+/// 关闭一条活动连接并返回最新的连接列表；连接已经结束时直接返回列表。
+pub(crate) fn close_connection_inner(
+    id: u64,
+) -> Result<Vec<desktop_agent_be::connections::ConnectionRecord>, String> {
+    #[cfg(windows)]
+    {
+        service_connections(&ServiceRequest::CloseConnection { id })
+    }
+
+    #[cfg(not(windows))]
+    {
+        desktop_agent_be::connections::close_connection(id);
+        Ok(desktop_agent_be::connections::active_connections())
+    }
+}
+
+#[cfg(windows)]
+fn service_connections(
+    request: &ServiceRequest,
+) -> Result<Vec<desktop_agent_be::connections::ConnectionRecord>, String> {
+    let response = send_service_request(request)?;
+    if response.ok {
+        return Ok(response.connections.unwrap_or_default());
+    }
+    Err(response
+        .error
+        .unwrap_or_else(|| "Agent 服务连接列表请求失败".to_string()))
+}
+
+/// 本地 HTTP/SOCKS5 入口的活动客户端、最近客户端与黑名单。
+pub(crate) fn inbound_clients_snapshot() -> Result<serde_json::Value, String> {
+    serde_json::from_str(&desktop_agent_be::inbound_clients::inbound_clients_json())
+        .map_err(|err| format!("解析客户端列表失败：{err}"))
+}
+
+pub(crate) fn set_inbound_client_blocked(ip: &str, blocked: bool) -> Result<(), String> {
+    let updated = if blocked {
+        desktop_agent_be::inbound_clients::block_inbound_client(ip)
+    } else {
+        desktop_agent_be::inbound_clients::unblock_inbound_client(ip)
+    };
+    if updated {
+        Ok(())
+    } else {
+        Err(format!("无效的客户端 IP：{ip}"))
+    }
+}
+
 pub(crate) fn agent_traffic_snapshot() -> NetworkTrafficSnapshot {
     let traffic = desktop_agent_be::telemetry::traffic_snapshot();
 
@@ -30,7 +30,9 @@ use crate::agent::{agent_state, start_agent_inner, stop_embedded_agent};
 use crate::logging::UiLogBuffer;
 use crate::models::{AgentState, ServiceRequest, ServiceResponse};
 use crate::runtime::AgentRuntime;
-use crate::telemetry::agent_traffic_snapshot;
+use crate::telemetry::{
+    agent_traffic_snapshot, inbound_clients_snapshot, set_inbound_client_blocked,
+};
 
 pub(crate) const SERVICE_ARG: &str = "--ppaass-agent-service";
 pub(crate) const INSTALL_SERVICE_ARG: &str = "--ppaass-install-service";
@@ -465,6 +467,8 @@ async fn handle_service_request(runtime: &AgentRuntime, stream: &mut TcpStream)
             state: None,
             traffic: Some(agent_traffic_snapshot()),
             dns_records: None,
+            inbound_clients: None,
+            connections: None,
             error: None,
         },
         ServiceRequest::DnsRecords => ServiceResponse {
@@ -472,8 +476,22 @@ async fn handle_service_request(runtime: &AgentRuntime, stream: &mut TcpStream)
             state: None,
             traffic: None,
             dns_records: Some(desktop_agent_be::telemetry::dns_resolution_records()),
+            inbound_clients: None,
+            connections: None,
             error: None,
         },
+        ServiceRequest::InboundClients => service_inbound_clients(),
+        ServiceRequest::SetInboundClientBlocked { ip, blocked } => {
+            match set_inbound_client_blocked(&ip, blocked) {
+                Ok(()) => service_inbound_clients(),
+                Err(err) => service_error(err),
+            }
+        }
+        ServiceRequest::Connections => service_connections(),
+        ServiceRequest::CloseConnection { id } => {
+            desktop_agent_be::connections::close_connection(id);
+            service_connections()
+        }
         ServiceRequest::SetLogLevel { log_level } => match runtime.logs.set_log_level(&log_level) {
             Ok(()) => match agent_state(runtime) {
                 Ok(state) => service_state_ok(state),
@@ -490,6 +508,35 @@ fn service_state_ok(state: AgentState) -> ServiceResponse {
         state: Some(state),
         traffic: None,
         dns_records: None,
+        inbound_clients: None,
+        connections: None,
+        error: None,
+    }
+}
+
+fn service_inbound_clients() -> ServiceResponse {
+    match inbound_clients_snapshot() {
+        Ok(clients) => ServiceResponse {
+            ok: true,
+            state: None,
+            traffic: None,
+            dns_records: None,
+            inbound_clients: Some(clients),
+            connections: None,
+            error: None,
+        },
+        Err(err) => service_error(err),
+    }
+}
+
+fn service_connections() -> ServiceResponse {
+    ServiceResponse {
+        ok: true,
+        state: None,
+        traffic: None,
+        dns_records: None,
+        inbound_clients: None,
+        connections: Some(desktop_agent_be::connections::active_connections()),
         error: None,
     }
 }
@@ -500,6 +547,8 @@ fn service_error(error: String) -> ServiceResponse {
         state: None,
         traffic: None,
         dns_records: None,
+        inbound_clients: None,
+        connections: None,
         error: Some(error),
     }
 }
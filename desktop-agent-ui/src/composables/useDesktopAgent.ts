@@ -14,11 +14,13 @@ import {
 } from "../formatters";
 import { emptyTrafficBuckets, ensureTrafficBaseline, ensureTrafficHourlyStore, saveTrafficHourlyStore } from "../trafficStorage";
 import type {
+  ActiveConnection,
   AgentConfigSummary,
   AgentState,
   ConnectivityReport,
   DirectRuleGroup,
   DnsResolutionRecord,
+  InboundClients,
   LoadedAgentConfig,
   NetworkTrafficSnapshot,
   TabKey,
@@ -26,6 +28,23 @@ import type {
   ToastKind
 } from "../types";
 
+// Fields that only take effect on restart; everything else hot-reloads into a running agent on save.
+const RESTART_ONLY_FIELDS = new Set<keyof AgentConfigSummary>([
+  "listen_addr",
+  "transport_mode",
+  "udp_session_pool_size",
+  "runtime_threads",
+  "log_dir",
+  "log_file",
+  "tun_enabled",
+  "tun_name",
+  "tun_ipv4",
+  "tun_mtu",
+  "tun_proxy_udp",
+  "tun_proxy_dns",
+  "tun_quic_policy"
+]);
+
 export function useDesktopAgent() {
   const state = reactive({
     activeTab: "overview" as TabKey,
@@ -56,7 +75,9 @@ export function useDesktopAgent() {
       day_download_bytes: 0,
       day_upload_bytes: 0
     },
-    dnsRecords: [] as DnsResolutionRecord[]
+    dnsRecords: [] as DnsResolutionRecord[],
+    inboundClients: { active: [], recent: [], blocked: [] } as InboundClients,
+    connections: [] as ActiveConnection[]
   });
 
   const summary = computed(() => state.config?.summary ?? summarizeRaw(fallbackRawConfig));
@@ -110,6 +131,8 @@ export function useDesktopAgent() {
   let agentRefreshInFlight = false;
   let configRefreshInFlight = false;
   let dnsRefreshInFlight = false;
+  let inboundClientsRefreshInFlight = false;
+  let connectionsRefreshInFlight = false;
   let unlistenConfigUpdated: UnlistenFn | undefined;
   let unlistenTrayError: UnlistenFn | undefined;
   let unlistenAgentStateUpdated: UnlistenFn | undefined;
@@ -225,13 +248,16 @@ export function useDesktopAgent() {
   }
 
   async function saveConfig() {
-    if (!state.config || !ensureConfigEditable()) {
+    if (!state.config) {
       return;
     }
     try {
       state.busy = true;
       await persistConfig();
-      showToast("success", `已保存到 ${shortPath(state.config.path)}`);
+      showToast(
+        "success",
+        running.value ? "已保存并热重载到运行中的代理" : `已保存到 ${shortPath(state.config.path)}`
+      );
     } catch (error) {
       showToast("error", getErrorMessage(error));
     } finally {
@@ -346,7 +372,7 @@ export function useDesktopAgent() {
   }
 
   function setField(field: keyof AgentConfigSummary, value: unknown) {
-    if (!state.config || !ensureConfigEditable(false)) {
+    if (!state.config || (RESTART_ONLY_FIELDS.has(field) && !ensureConfigEditable(false))) {
       return;
     }
     const coerced = coerceField(field, value);
@@ -360,7 +386,7 @@ export function useDesktopAgent() {
   }
 
   function setRawConfig(raw: string) {
-    if (!state.config || !ensureConfigEditable(false)) {
+    if (!state.config) {
       return;
     }
     state.config.raw = raw;
@@ -373,7 +399,7 @@ export function useDesktopAgent() {
   }
 
   function addDirectRules(rules: string[]) {
-    if (!state.config || !ensureConfigEditable()) {
+    if (!state.config) {
       return;
     }
     updateDirectRules(normalizeRules([...state.config.summary.direct_rules, ...rules]));
@@ -381,7 +407,7 @@ export function useDesktopAgent() {
     showToast("success", "规则已更新");
   }
 
-  async function addDirectRulesAndRestart(rules: string[]) {
+  async function addDirectRulesAndApply(rules: string[]) {
     if (!state.config) {
       return;
     }
@@ -396,37 +422,11 @@ export function useDesktopAgent() {
       return;
     }
 
-    const wasRunning = state.agent.running;
     try {
       state.busy = true;
-      updateDirectRules(nextRules, true);
+      updateDirectRules(nextRules);
       await persistConfig();
-
-      if (!wasRunning) {
-        showToast("success", "直连规则已添加并保存");
-        return;
-      }
-
-      state.agent = await invokeOrFallback<AgentState>(
-        "stop_agent",
-        {},
-        () => ({ ...fallbackAgentState(), running: false, pid: null, config_path: state.config?.path })
-      );
-      if (state.agent.running) {
-        throw new Error("直连规则已保存，但 Agent 停止失败");
-      }
-
-      state.agent = await invokeOrFallback<AgentState>(
-        "start_agent",
-        { configPath: state.config.path },
-        () => ({ ...fallbackAgentState(), running: true, managed: true, pid: 4242, config_path: state.config?.path })
-      );
-      await delay(1800);
-      await refreshAgentState();
-      if (!state.agent.running) {
-        throw new Error(latestAgentLog() ?? "直连规则已保存，但 Agent 重启失败");
-      }
-      showToast("success", "直连规则已添加，Agent 已重启");
+      showToast("success", state.agent.running ? "直连规则已添加并热重载" : "直连规则已添加并保存");
     } catch (error) {
       await refreshAgentState();
       showToast("error", getErrorMessage(error));
@@ -436,13 +436,11 @@ export function useDesktopAgent() {
   }
 
   function addDraftRules() {
-    if (ensureConfigEditable()) {
-      addDirectRules(parseRuleInput(state.ruleDraft));
-    }
+    addDirectRules(parseRuleInput(state.ruleDraft));
   }
 
   function removeDirectRule(index: number) {
-    if (!state.config || !Number.isInteger(index) || !ensureConfigEditable()) {
+    if (!state.config || !Number.isInteger(index)) {
       return;
     }
     const next = normalizeRules(state.config.summary.direct_rules).filter((_, current) => current !== index);
@@ -591,6 +589,10 @@ export function useDesktopAgent() {
     }
     if (!state.busy) {
       await refreshDnsRecords();
+      if (state.activeTab === "forwarding") {
+        await refreshInboundClients();
+        await refreshConnections();
+      }
     }
     if (pollingActive) {
       dnsTimer = window.setTimeout(() => void pollDnsRecords(), 2500);
@@ -614,6 +616,56 @@ export function useDesktopAgent() {
     }
   }
 
+  async function refreshInboundClients() {
+    if (inboundClientsRefreshInFlight) {
+      return;
+    }
+    inboundClientsRefreshInFlight = true;
+    try {
+      state.inboundClients = await invokeOrFallback<InboundClients>("get_inbound_clients", {}, () => state.inboundClients);
+    } catch {
+      // Keep the last visible client list if the runtime status read fails.
+    } finally {
+      inboundClientsRefreshInFlight = false;
+    }
+  }
+
+  async function refreshConnections() {
+    if (connectionsRefreshInFlight) {
+      return;
+    }
+    connectionsRefreshInFlight = true;
+    try {
+      state.connections = await invokeOrFallback<ActiveConnection[]>("get_connections", {}, () => state.connections);
+    } catch {
+      // Keep the last visible connection list if the runtime status read fails.
+    } finally {
+      connectionsRefreshInFlight = false;
+    }
+  }
+
+  async function closeConnection(id: number) {
+    try {
+      state.connections = await invokeOrFallback<ActiveConnection[]>("close_connection", { id }, () => state.connections);
+      showToast("success", "已关闭连接");
+    } catch (error) {
+      showToast("error", getErrorMessage(error));
+    }
+  }
+
+  async function setInboundClientBlocked(ip: string, blocked: boolean) {
+    try {
+      state.inboundClients = await invokeOrFallback<InboundClients>(
+        "set_inbound_client_blocked",
+        { ip, blocked },
+        () => state.inboundClients
+      );
+      showToast("success", blocked ? `已拉黑 ${ip} 并断开其连接` : `已解除 ${ip} 的拉黑`);
+    } catch (error) {
+      showToast("error", getErrorMessage(error));
+    }
+  }
+
   async function persistConfig() {
     if (!state.config) {
       return;
@@ -662,8 +714,8 @@ export function useDesktopAgent() {
     state.traffic.day_upload_bytes = store.buckets.reduce((total, bucket) => total + bucket.upload_bytes, 0);
   }
 
-  function updateDirectRules(rules: string[], allowWhileRunning = false) {
-    if (!state.config || (!allowWhileRunning && !ensureConfigEditable(false))) {
+  function updateDirectRules(rules: string[]) {
+    if (!state.config) {
       return;
     }
     const directRules = normalizeRules(rules);
@@ -684,7 +736,7 @@ export function useDesktopAgent() {
       return true;
     }
     if (notify) {
-      showToast("error", "代理运行中，停止后再修改配置");
+      showToast("error", "代理运行中，该项需停止后再修改");
     }
     return false;
   }
@@ -704,7 +756,7 @@ export function useDesktopAgent() {
   return {
     activeForwardingLabel,
     addDirectRules,
-    addDirectRulesAndRestart,
+    addDirectRulesAndApply,
     addDraftRules,
     configLocked,
     diagnosticsPassed,
@@ -726,6 +778,8 @@ export function useDesktopAgent() {
     sanitizeIntegerInput,
     saveConfig,
     setField,
+    setInboundClientBlocked,
+    closeConnection,
     setRawConfig,
     startAgent,
     state,
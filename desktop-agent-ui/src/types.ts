@@ -97,6 +97,35 @@ export type DnsResolutionRecord = {
   duration_ms: number;
 };
 
+export type InboundClient = {
+  ip: string;
+  connections: number;
+  total_connections?: number;
+  last_seen_ms?: number;
+  peers: string[];
+  blocked: boolean;
+};
+
+export type ActiveConnection = {
+  id: number;
+  inbound: string;
+  source: string;
+  target: string;
+  transport: string;
+  route: string;
+  rule: string;
+  process?: string | null;
+  outbound_bytes: number;
+  inbound_bytes: number;
+  started_ms: number;
+};
+
+export type InboundClients = {
+  active: InboundClient[];
+  recent: InboundClient[];
+  blocked: string[];
+};
+
 export type TrafficBaseline = {
   date: string;
   received: number;
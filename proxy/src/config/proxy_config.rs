@@ -65,11 +65,42 @@ pub struct ProxyConfig {
     #[serde(default)]
     pub outbound_interface: Option<String>,
 
+    /// 出站源地址池。用户通过 users.toml 的 `egress_pool` 选择地址池，
+    /// 池内按 `selection` 轮询或按目标哈希挑选源地址；配置地址池的连接不再使用 `outbound_interface`。
+    #[serde(default)]
+    pub egress_pools: Vec<EgressPoolConfig>,
+
+    /// 未指定 `egress_pool` 的用户以及 proxy 自身的 DNS 查询使用的地址池；为空时沿用 `outbound_interface`。
+    #[serde(default)]
+    pub default_egress_pool: Option<String>,
+
     /// proxy 端处理 DNS 请求时使用的上游 DNS。
+    /// 支持 `ip[:port]` 明文上游、`https://host/dns-query`（DoH）和 `tls://host[:port]`（DoT）。
     /// 为空时读取系统默认 DNS。
     #[serde(default)]
     pub dns_upstream_addr: Option<String>,
 
+    /// 追加的上游 DNS 列表，排在 `dns_upstream_addr` 之后，格式相同。
+    /// 两者都为空时使用系统 DNS 配置中的全部 nameserver。
+    #[serde(default)]
+    pub dns_upstream_addrs: Vec<String>,
+
+    /// 多个上游 DNS 的使用方式：failover 按顺序故障转移，race 同时查询取最先成功的应答。
+    #[serde(default)]
+    pub dns_upstream_strategy: DnsUpstreamStrategy,
+
+    /// proxy 内置 DNS 缓存的最大条目数（按查询名 + 类型计）；0 表示关闭缓存。
+    #[serde(default = "default_dns_cache_size")]
+    pub dns_cache_size: usize,
+
+    /// 正向应答在缓存中的最长保留时间（秒），实际取记录 TTL 与该值的较小者。
+    #[serde(default = "default_dns_cache_max_ttl_secs")]
+    pub dns_cache_max_ttl_secs: u64,
+
+    /// 否定应答（NXDOMAIN/无记录）在缓存中的最长保留时间（秒）。
+    #[serde(default = "default_dns_negative_cache_ttl_secs")]
+    pub dns_negative_cache_ttl_secs: u64,
+
     /// 上游代理连接超时时间（秒）
     #[serde(default = "default_connect_timeout_secs")]
     pub connect_timeout_secs: u64,
@@ -120,6 +151,40 @@ pub struct ProxyConfig {
     pub udp_session_max_flows: usize,
 }
 
+/// 一组可供出站连接绑定的本机源地址。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EgressPoolConfig {
+    pub name: String,
+
+    /// 本机源 IP（如 `203.0.113.10`、`2001:db8::10`）或网卡名（如 `eth1`）。
+    pub sources: Vec<String>,
+
+    #[serde(default)]
+    pub selection: EgressPoolSelection,
+}
+
+/// 地址池内选择源地址的方式。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EgressPoolSelection {
+    /// 每条新连接轮换到下一个源地址，分散负载。
+    #[default]
+    RoundRobin,
+    /// 按目标 `host:port` 哈希固定源地址，同一目标始终从同一出口访问。
+    TargetHash,
+}
+
+/// 多上游 DNS 的查询策略。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DnsUpstreamStrategy {
+    /// 按配置顺序逐个尝试，前一个超时或失败时才查询下一个。
+    #[default]
+    Failover,
+    /// 同时向所有上游发出查询，采用最先返回的有效应答。
+    Race,
+}
+
 fn default_log_level() -> String {
     "info".to_string()
 }
@@ -184,6 +249,18 @@ fn default_udp_session_max_flows() -> usize {
     256
 }
 
+fn default_dns_cache_size() -> usize {
+    4096
+}
+
+fn default_dns_cache_max_ttl_secs() -> u64 {
+    3600
+}
+
+fn default_dns_negative_cache_ttl_secs() -> u64 {
+    30
+}
+
 fn default_async_runtime_stack_size_mb() -> usize {
     2
 }
@@ -267,4 +344,65 @@ udp_relay_max_flows = 23
 
         assert_eq!(config.udp_relay_max_flows, 23);
     }
+
+    #[test]
+    fn egress_pools_are_parsed() {
+        let config: ProxyConfig = toml::from_str(
+            r#"
+listen_addr = "127.0.0.1:0"
+default_egress_pool = "shared"
+
+[[egress_pools]]
+name = "shared"
+sources = ["203.0.113.10", "203.0.113.11"]
+
+[[egress_pools]]
+name = "tenant-a"
+sources = ["eth1", "2001:db8::10"]
+selection = "target_hash"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.default_egress_pool.as_deref(), Some("shared"));
+        assert_eq!(config.egress_pools.len(), 2);
+        assert_eq!(
+            config.egress_pools[0].selection,
+            EgressPoolSelection::RoundRobin
+        );
+        assert_eq!(
+            config.egress_pools[1].selection,
+            EgressPoolSelection::TargetHash
+        );
+        assert_eq!(config.egress_pools[1].sources, ["eth1", "2001:db8::10"]);
+    }
+
+    #[test]
+    fn dns_resolver_defaults_and_upstream_list() {
+        let config: ProxyConfig = toml::from_str(
+            r#"
+listen_addr = "127.0.0.1:0"
+"#,
+        )
+        .unwrap();
+        assert!(config.dns_upstream_addrs.is_empty());
+        assert_eq!(config.dns_upstream_strategy, DnsUpstreamStrategy::Failover);
+        assert_eq!(config.dns_cache_size, 4096);
+        assert_eq!(config.dns_cache_max_ttl_secs, 3600);
+        assert_eq!(config.dns_negative_cache_ttl_secs, 30);
+
+        let config: ProxyConfig = toml::from_str(
+            r#"
+listen_addr = "127.0.0.1:0"
+dns_upstream_addr = "8.8.8.8"
+dns_upstream_addrs = ["1.1.1.1:53", "tls://dns.quad9.net"]
+dns_upstream_strategy = "race"
+dns_cache_size = 0
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.dns_upstream_addrs.len(), 2);
+        assert_eq!(config.dns_upstream_strategy, DnsUpstreamStrategy::Race);
+        assert_eq!(config.dns_cache_size, 0);
+    }
 }
@@ -18,6 +18,11 @@ pub struct UserConfig {
         deserialize_with = "deserialize_expires_at"
     )]
     pub expires_at: Option<String>,
+
+    /// 该用户出站连接使用的地址池名，对应 proxy.toml 的 `egress_pools`；
+    /// 不配置时使用 `default_egress_pool`。
+    #[serde(default)]
+    pub egress_pool: Option<String>,
 }
 
 impl UserConfig {
@@ -83,6 +88,7 @@ mod tests {
             username: "user1".to_string(),
             public_key_pem: "public-key".to_string(),
             expires_at: expires_at.map(str::to_string),
+            egress_pool: None,
         }
     }
 
@@ -32,6 +32,11 @@ impl ServerConnection {
             return self.handle_upstream_connect(connect_request).await;
         }
 
+        // ProxyDns 由 proxy 内置的缓存解析器应答，不再直接建立到 DNS 上游的 TCP/UDP 连接。
+        if matches!(connect_request.address, Address::ProxyDns { .. }) {
+            return self.handle_proxy_dns_connect(connect_request).await;
+        }
+
         // 普通 Domain/IPv4/IPv6 目标到这里才会被转换成 Tokio 可连接的 host:port。
         let target_addr = self.target_addr_for_request(&connect_request.address)?;
         match connect_request.transport {
@@ -41,8 +46,52 @@ impl ServerConnection {
     }
 
     fn target_addr_for_request(&self, address: &Address) -> Result<String> {
-        // ProxyDns 是特殊地址类型，需要在 proxy 端决定真正的 DNS 上游。
-        target_addr_for_address(&self.proxy_config, address)
+        target_addr_for_address(address)
+    }
+
+    /// 当前用户配置的出站地址池；未配置时由出站状态回退到默认地址池。
+    pub(super) fn egress_pool(&self) -> Option<&str> {
+        self.user_config
+            .as_ref()
+            .and_then(|user| user.egress_pool.as_deref())
+    }
+
+    async fn handle_proxy_dns_connect(&mut self, connect_request: ConnectRequest) -> Result<()> {
+        let Address::ProxyDns { port } = connect_request.address else {
+            unreachable!("只有 ProxyDns 请求会进入内置 DNS 应答");
+        };
+        debug!(
+            "ProxyDns 由内置 DNS 解析器应答（{:?}，端口 {}）",
+            connect_request.transport, port
+        );
+        match connect_request.transport {
+            TransportProtocol::Tcp => {
+                // duplex 的另一端按 DNS-over-TCP 格式逐条应答，relay 把它当作普通目标流。
+                let mut bridge = crate::dns::spawn_tcp_bridge(self.egress_state.clone(), port);
+                self.send_connect_success(connect_request.request_id.clone(), "Connected")
+                    .await?;
+                self.relay(connect_request.request_id, &mut bridge).await
+            }
+            TransportProtocol::Udp => {
+                // guard 活到 relay 结束，drop 时停止应答任务。
+                let (socket, _bridge_guard) =
+                    match crate::dns::spawn_udp_bridge(self.egress_state.clone(), port).await {
+                        Ok(bridge) => bridge,
+                        Err(e) => {
+                            warn!("建立加密 DNS UDP 桥失败：{}", e);
+                            return self
+                                .send_connect_error(
+                                    connect_request.request_id,
+                                    format!("Failed to connect UDP: {}", e),
+                                )
+                                .await;
+                        }
+                    };
+                self.send_connect_success(connect_request.request_id.clone(), "Connected")
+                    .await?;
+                self.relay_udp(connect_request.request_id, socket).await
+            }
+        }
     }
 
     async fn handle_upstream_connect(&mut self, connect_request: ConnectRequest) -> Result<()> {
@@ -95,8 +144,12 @@ impl ServerConnection {
         // 通过启动时共享的出站状态连接目标，避免每次请求重新读取路由表。
         // 超时只包 connect 阶段；连接建立后的空闲控制交给 relay 层。
         let connect_timeout = Duration::from_secs(self.proxy_config.connect_timeout_secs);
-        match tokio::time::timeout(connect_timeout, self.egress_state.connect_tcp(target_addr))
-            .await
+        match tokio::time::timeout(
+            connect_timeout,
+            self.egress_state
+                .connect_tcp(target_addr, self.egress_pool()),
+        )
+        .await
         {
             Ok(Ok(mut target_stream)) => {
                 debug!(
@@ -149,7 +202,11 @@ impl ServerConnection {
 
         // UDP 也复用同一份出站状态，保持 TCP/UDP 的出口选择一致。
         // tokio 的 UDP connect 只是固定默认对端，后续 send/recv 不需要每包携带地址。
-        match self.egress_state.connect_udp(target_addr).await {
+        match self
+            .egress_state
+            .connect_udp(target_addr, self.egress_pool())
+            .await
+        {
             Ok(socket) => {
                 debug!(
                     "已连接到目标（UDP）：{}，出站设备={}",
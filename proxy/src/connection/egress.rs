@@ -6,16 +6,22 @@
 
 mod auto;
 mod bind;
+mod happy_eyeballs;
+mod pool;
 mod route_guard;
 mod source;
 mod stream;
 
+use crate::config::EgressPoolConfig;
+use crate::dns::DnsResolver;
 use auto::AutoInterfaceSelector;
 use bind::bind_socket_to_interface;
+use pool::EgressPool;
 use route_guard::TargetRouteGuard;
 use socket2::{Domain, Protocol, SockAddr, SockRef, Socket, Type};
 use source::{BoundSource, interface_bind_addrs};
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::io;
 use std::net::SocketAddr;
 use std::time::{Duration, Instant};
@@ -34,6 +40,23 @@ const PROXY_EGRESS_TCP_ADDR_RETRY_MAX_DELAY: Duration = Duration::from_millis(25
 pub struct EgressState {
     // None 表示完全交给系统默认路由；Some 表示需要做接口/源地址绑定。
     interface: Option<InterfaceSelection>,
+    // 内置缓存解析器：应答 ProxyDns 查询，并解析目标域名；上游连接同样走本出站状态。
+    dns: DnsResolver,
+    // 按名称索引的出站源地址池；选中地址池的连接不再使用 interface。
+    pools: HashMap<String, EgressPool>,
+    // 调用方未指定地址池时使用的池名。
+    default_pool: Option<String>,
+}
+
+/// 单条连接的出站绑定方式，在解析目标地址之前确定。
+#[derive(Clone, Copy)]
+enum EgressBinding<'a> {
+    // 系统默认路由，不绑定设备和源地址。
+    Default,
+    // outbound_interface 指定的设备或 auto 选择的设备。
+    Interface,
+    // 地址池及本次连接选中的首选成员下标。
+    Pool(&'a EgressPool, usize),
 }
 
 enum InterfaceSelection {
@@ -54,29 +77,209 @@ impl EgressState {
             None => None,
         };
 
-        Ok(Self { interface })
+        Ok(Self {
+            interface,
+            dns: DnsResolver::system_default(),
+            pools: HashMap::new(),
+            default_pool: None,
+        })
+    }
+
+    /// 挂载出站源地址池；池名重复或默认池不存在时返回错误。
+    pub(crate) fn with_egress_pools(
+        mut self,
+        pools: &[EgressPoolConfig],
+        default_pool: Option<&str>,
+    ) -> io::Result<Self> {
+        for config in pools {
+            let pool = EgressPool::from_config(config)?;
+            let name = pool.name().to_string();
+            if self.pools.insert(name.clone(), pool).is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("出站地址池名称重复：{name}"),
+                ));
+            }
+        }
+        let default_pool = default_pool.map(str::trim).filter(|name| !name.is_empty());
+        if let Some(name) = default_pool
+            && !self.has_egress_pool(name)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("默认出站地址池不存在：{name}"),
+            ));
+        }
+        self.default_pool = default_pool.map(str::to_string);
+        Ok(self)
+    }
+
+    pub(crate) fn has_egress_pool(&self, name: &str) -> bool {
+        self.pools.contains_key(name)
+    }
+
+    /// 替换默认的系统 DNS 解析器。
+    pub(crate) fn with_dns_resolver(mut self, resolver: DnsResolver) -> Self {
+        self.dns = resolver;
+        self
+    }
+
+    pub(crate) fn dns_resolver(&self) -> &DnsResolver {
+        &self.dns
+    }
+
+    /// 通过内置解析器应答一条 wire-format DNS 查询；`port` 为 ProxyDns 请求的 DNS 端口。
+    pub(crate) async fn exchange_dns(&self, query: &[u8], port: u16) -> io::Result<Vec<u8>> {
+        self.dns.exchange(self, query, port).await
+    }
+
+    /// 连接目标；`egress_pool` 为用户配置的地址池名，None 时使用默认地址池或出站设备配置。
+    pub async fn connect_tcp(
+        &self,
+        target_addr: &str,
+        egress_pool: Option<&str>,
+    ) -> io::Result<EgressTcpStream> {
+        let binding = self.binding(egress_pool, target_addr)?;
+        let addrs = self.resolve_target(target_addr).await?;
+        self.connect_tcp_addrs(&addrs, binding).await
+    }
+
+    pub async fn connect_udp(
+        &self,
+        target_addr: &str,
+        egress_pool: Option<&str>,
+    ) -> io::Result<UdpSocket> {
+        let binding = self.binding(egress_pool, target_addr)?;
+        let addrs = self.resolve_target(target_addr).await?;
+        self.connect_udp_addrs(&addrs, binding).await
+    }
+
+    /// 连接上游 DNS 服务器本身时使用系统解析，避免内置解析器递归解析自己的上游。
+    pub(crate) async fn connect_tcp_via_system_dns(
+        &self,
+        target_addr: &str,
+    ) -> io::Result<EgressTcpStream> {
+        let binding = self.binding(None, target_addr)?;
+        let addrs = tokio::net::lookup_host(target_addr)
+            .await?
+            .collect::<Vec<_>>();
+        self.connect_tcp_addrs(&addrs, binding).await
+    }
+
+    pub(crate) async fn connect_udp_via_system_dns(
+        &self,
+        target_addr: &str,
+    ) -> io::Result<UdpSocket> {
+        let binding = self.binding(None, target_addr)?;
+        let addrs = tokio::net::lookup_host(target_addr)
+            .await?
+            .collect::<Vec<_>>();
+        self.connect_udp_addrs(&addrs, binding).await
     }
 
-    pub async fn connect_tcp(&self, target_addr: &str) -> io::Result<EgressTcpStream> {
+    fn binding(
+        &self,
+        egress_pool: Option<&str>,
+        target_addr: &str,
+    ) -> io::Result<EgressBinding<'_>> {
+        let Some(name) = egress_pool.or(self.default_pool.as_deref()) else {
+            return Ok(if self.interface.is_some() {
+                EgressBinding::Interface
+            } else {
+                EgressBinding::Default
+            });
+        };
+        let pool = self.pools.get(name).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("出站地址池不存在：{name}"))
+        })?;
+        Ok(EgressBinding::Pool(pool, pool.start_index(target_addr)))
+    }
+
+    async fn connect_tcp_addrs(
+        &self,
+        addrs: &[SocketAddr],
+        binding: EgressBinding<'_>,
+    ) -> io::Result<EgressTcpStream> {
+        if addrs.is_empty() {
+            return Err(no_target_addr_error(addrs));
+        }
+        // 两条路径都按 Happy Eyeballs 交错 IPv6/IPv4 并错峰连接，坏掉的一族地址不会拖住整个请求。
+        let addrs = happy_eyeballs::interleave_families(addrs);
+
         // 未指定出站设备时走系统默认路由，不做额外绑定。
-        if self.interface.is_none() {
-            let stream = connect_tcp_default_with_retry(target_addr).await?;
+        if matches!(binding, EgressBinding::Default) {
+            let stream = happy_eyeballs::race(
+                addrs,
+                happy_eyeballs::CONNECTION_ATTEMPT_DELAY,
+                connect_tcp_default_with_retry,
+            )
+            .await?;
             tune_egress_tcp_stream(&stream, "默认出站 TCP 连接");
             return Ok(EgressTcpStream::new(stream, None));
         }
 
-        // 指定设备或 auto 模式需要按目标地址族选择可用源地址后再连接。
-        connect_tcp_with_interface(target_addr, self).await
+        // 指定设备、auto 模式或地址池需要按目标地址族选择可用源地址后再连接。
+        connect_tcp_with_interface(&addrs, self, binding).await
     }
 
-    pub async fn connect_udp(&self, target_addr: &str) -> io::Result<UdpSocket> {
+    async fn connect_udp_addrs(
+        &self,
+        addrs: &[SocketAddr],
+        binding: EgressBinding<'_>,
+    ) -> io::Result<UdpSocket> {
         // UDP 默认路径只绑定通配地址，由操作系统选择出口。
-        if self.interface.is_none() {
-            return connect_udp_default(target_addr).await;
+        if matches!(binding, EgressBinding::Default) {
+            return connect_udp_default(addrs).await;
+        }
+
+        // 指定设备、auto 模式或地址池复用同一套源地址选择逻辑。
+        connect_udp_with_interface(addrs, self, binding).await
+    }
+
+    /// 把 `host:port` 解析成候选地址。多标签域名交给内置解析器并利用其缓存；
+    /// 解析器没有结果时回退到系统解析：上游不可用，或域名只在 hosts 文件里存在。
+    async fn resolve_target(&self, target_addr: &str) -> io::Result<Vec<SocketAddr>> {
+        if let Ok(addr) = target_addr.parse::<SocketAddr>() {
+            return Ok(vec![addr]);
+        }
+        let (host, port) = target_addr
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("目标地址无效：{target_addr}"),
+                )
+            })?;
+        if self.dns.handles_host(host) {
+            match self.dns.lookup_ip(self, host).await {
+                Ok(ips) => {
+                    return Ok(ips
+                        .into_iter()
+                        .map(|ip| SocketAddr::new(ip, port))
+                        .collect());
+                }
+                Err(err) => {
+                    tracing::debug!("内置 DNS 解析 {host} 失败，回退到系统解析：{err}");
+                }
+            }
         }
+        Ok(tokio::net::lookup_host((host, port)).await?.collect())
+    }
 
-        // 指定设备或 auto 模式复用同一套出站设备选择逻辑。
-        connect_udp_with_interface(target_addr, self).await
+    /// 为目标地址确定绑定的出站设备及其候选源地址。
+    fn bind_for_dst<'a>(
+        &'a self,
+        binding: EgressBinding<'a>,
+        dst: SocketAddr,
+    ) -> io::Result<(Cow<'a, str>, Vec<BoundSource>)> {
+        if let EgressBinding::Pool(pool, start) = binding {
+            return pool.bind_for_dst(start, dst);
+        }
+        // 先确定要绑定的出站设备，再从该设备上挑选与目标地址族匹配的本地源地址。
+        let interface = self.interface_for_dst(dst)?;
+        let sources = interface_bind_addrs(&interface, dst)?;
+        Ok((interface, sources))
     }
 
     fn interface_for_dst(&self, dst: SocketAddr) -> io::Result<Cow<'_, str>> {
@@ -92,81 +295,56 @@ impl EgressState {
 }
 
 async fn connect_tcp_with_interface(
-    target_addr: &str,
+    addrs: &[SocketAddr],
     egress_state: &EgressState,
+    binding: EgressBinding<'_>,
 ) -> io::Result<EgressTcpStream> {
-    if egress_state.interface.is_none() {
-        let stream = connect_tcp_default_with_retry(target_addr).await?;
-        tune_egress_tcp_stream(&stream, "默认出站 TCP 连接");
-        return Ok(EgressTcpStream::new(stream, None));
-    }
-
     let mut last_error = None;
-    let mut resolved = false;
-    for dst in tokio::net::lookup_host(target_addr).await? {
-        resolved = true;
-        // 一个域名可能解析出多个 IPv4/IPv6 地址；逐个尝试能提升可达性。
-        // 对每个解析出的目标地址，先确定要绑定的出站设备。
-        let interface = match egress_state.interface_for_dst(dst) {
-            Ok(interface) => interface,
-            Err(err) => {
-                last_error = Some(err);
-                continue;
-            }
-        };
-        // 再从该设备上挑选与目标地址族匹配的本地源地址。
-        let sources = match interface_bind_addrs(&interface, dst) {
-            Ok(sources) => sources,
+    let mut candidates = Vec::new();
+    for &dst in addrs {
+        // 一个域名可能解析出多个 IPv4/IPv6 地址；每个地址和源地址组合都是一个竞速候选。
+        let (interface, sources) = match egress_state.bind_for_dst(binding, dst) {
+            Ok(bound) => bound,
             Err(err) => {
                 last_error = Some(err);
                 continue;
             }
         };
 
-        for source in sources {
-            // 尝试按候选源地址连接，失败则继续尝试下一个候选。
-            match connect_tcp_addr(dst, &interface, source).await {
-                Ok(stream) => return Ok(stream),
-                Err(err) => {
-                    last_error = Some(connect_context_error(&interface, source.addr, dst, err));
-                }
-            }
-        }
+        candidates.extend(
+            sources
+                .into_iter()
+                .map(|source| (dst, interface.clone(), source)),
+        );
+    }
+    if candidates.is_empty() {
+        return Err(last_error.unwrap_or_else(|| no_target_addr_error(addrs)));
     }
 
-    Err(last_error.unwrap_or_else(|| {
-        if resolved {
-            io::Error::other("所有目标地址连接失败")
-        } else {
-            io::Error::new(io::ErrorKind::NotFound, "未解析到目标地址")
-        }
-    }))
+    // 候选之间错峰竞速，同一地址的多个源地址也按顺序参与，失败时立即启动下一个。
+    happy_eyeballs::race(
+        candidates,
+        happy_eyeballs::CONNECTION_ATTEMPT_DELAY,
+        |(dst, interface, source)| async move {
+            connect_tcp_addr(dst, &interface, source)
+                .await
+                .map_err(|err| connect_context_error(&interface, source.addr, dst, err))
+        },
+    )
+    .await
 }
 
 async fn connect_udp_with_interface(
-    target_addr: &str,
+    addrs: &[SocketAddr],
     egress_state: &EgressState,
+    binding: EgressBinding<'_>,
 ) -> io::Result<UdpSocket> {
-    if egress_state.interface.is_none() {
-        return connect_udp_default(target_addr).await;
-    }
-
     let mut last_error = None;
-    let mut resolved = false;
-    for dst in tokio::net::lookup_host(target_addr).await? {
-        resolved = true;
+    for &dst in addrs {
         // UDP 也遍历所有解析结果；只有成功 bind + connect 的 socket 才会返回给 relay。
-        // UDP 与 TCP 使用相同的出口设备选择，确保两种协议路径一致。
-        let interface = match egress_state.interface_for_dst(dst) {
-            Ok(interface) => interface,
-            Err(err) => {
-                last_error = Some(err);
-                continue;
-            }
-        };
-        // 只使用目标地址族兼容的源地址，避免 IPv4/IPv6 混绑。
-        let sources = match interface_bind_addrs(&interface, dst) {
-            Ok(sources) => sources,
+        // UDP 与 TCP 使用相同的出口设备选择，只使用目标地址族兼容的源地址。
+        let (interface, sources) = match egress_state.bind_for_dst(binding, dst) {
+            Ok(bound) => bound,
             Err(err) => {
                 last_error = Some(err);
                 continue;
@@ -184,21 +362,17 @@ async fn connect_udp_with_interface(
         }
     }
 
-    Err(last_error.unwrap_or_else(|| {
-        if resolved {
-            io::Error::other("所有目标地址连接失败")
-        } else {
-            io::Error::new(io::ErrorKind::NotFound, "未解析到目标地址")
-        }
-    }))
+    Err(last_error.unwrap_or_else(|| no_target_addr_error(addrs)))
 }
 
-async fn connect_tcp_default_with_retry(target_addr: &str) -> io::Result<TcpStream> {
+/// 连接单个候选地址。本地临时端口耗尽（EADDRNOTAVAIL）时退避重试，
+/// 其它错误立即返回，由 Happy Eyeballs 启动下一个候选。
+async fn connect_tcp_default_with_retry(dst: SocketAddr) -> io::Result<TcpStream> {
     let started = Instant::now();
     let mut delay = PROXY_EGRESS_TCP_ADDR_RETRY_INITIAL_DELAY;
 
     loop {
-        match TcpStream::connect(target_addr).await {
+        match TcpStream::connect(dst).await {
             Ok(stream) => return Ok(stream),
             Err(err)
                 if is_transient_addr_not_available(&err)
@@ -212,6 +386,14 @@ async fn connect_tcp_default_with_retry(target_addr: &str) -> io::Result<TcpStre
     }
 }
 
+fn no_target_addr_error(addrs: &[SocketAddr]) -> io::Error {
+    if addrs.is_empty() {
+        io::Error::new(io::ErrorKind::NotFound, "未解析到目标地址")
+    } else {
+        io::Error::other("所有目标地址连接失败")
+    }
+}
+
 fn is_transient_addr_not_available(err: &io::Error) -> bool {
     err.kind() == io::ErrorKind::AddrNotAvailable || err.raw_os_error() == Some(49)
 }
@@ -274,11 +456,9 @@ async fn connect_udp_addr(
     Ok(socket)
 }
 
-async fn connect_udp_default(target_addr: &str) -> io::Result<UdpSocket> {
+async fn connect_udp_default(addrs: &[SocketAddr]) -> io::Result<UdpSocket> {
     let mut last_error = None;
-    let mut resolved = false;
-    for dst in tokio::net::lookup_host(target_addr).await? {
-        resolved = true;
+    for &dst in addrs {
         // 默认 UDP 路径仍按目标地址族选择通配绑定地址。
         let bind_addr = if dst.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
         match UdpSocket::bind(bind_addr).await {
@@ -293,13 +473,7 @@ async fn connect_udp_default(target_addr: &str) -> io::Result<UdpSocket> {
         }
     }
 
-    Err(last_error.unwrap_or_else(|| {
-        if resolved {
-            io::Error::other("所有目标地址连接失败")
-        } else {
-            io::Error::new(io::ErrorKind::NotFound, "未解析到目标地址")
-        }
-    }))
+    Err(last_error.unwrap_or_else(|| no_target_addr_error(addrs)))
 }
 
 fn tune_egress_udp_socket(socket: &UdpSocket, context: &str) {
@@ -326,3 +500,148 @@ fn connect_context_error(
         format!("出站设备 {interface} 使用源地址 {source} 连接 {dst} 失败：{err}"),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    async fn listener_and_refused_addr() -> (TcpListener, SocketAddr) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let refused = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        (listener, refused)
+    }
+
+    #[tokio::test]
+    async fn default_connect_skips_refused_address() {
+        let (listener, refused) = listener_and_refused_addr().await;
+        let reachable = listener.local_addr().unwrap();
+        let egress = EgressState::new(None).unwrap();
+
+        let _stream = egress
+            .connect_tcp_addrs(&[refused, reachable], EgressBinding::Default)
+            .await
+            .unwrap();
+        listener.accept().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn empty_address_list_is_not_found() {
+        let egress = EgressState::new(None).unwrap();
+        let Err(err) = egress.connect_tcp_addrs(&[], EgressBinding::Default).await else {
+            panic!("empty address list should fail");
+        };
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn named_interface_connect_races_candidates() {
+        // 源地址选择会跳过回环地址，这里借用本机第一块有 IPv4 地址的非回环网卡。
+        let Some(interface) = if_addrs::get_if_addrs()
+            .unwrap()
+            .into_iter()
+            .find(|interface| !interface.is_loopback() && interface.ip().is_ipv4())
+        else {
+            return;
+        };
+        let listener = TcpListener::bind((interface.ip(), 0)).await.unwrap();
+        let reachable = listener.local_addr().unwrap();
+        let refused = TcpListener::bind((interface.ip(), 0))
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let egress = EgressState::new(Some(&interface.name)).unwrap();
+
+        match egress
+            .connect_tcp_addrs(&[refused, reachable], EgressBinding::Interface)
+            .await
+        {
+            Ok(_stream) => {
+                listener.accept().await.unwrap();
+            }
+            // SO_BINDTODEVICE 需要 CAP_NET_RAW，无权限的环境里跳过。
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {}
+            Err(err) => panic!("{err}"),
+        }
+    }
+
+    fn pool_config(name: &str, sources: &[&str]) -> EgressPoolConfig {
+        EgressPoolConfig {
+            name: name.to_string(),
+            sources: sources.iter().map(ToString::to_string).collect(),
+            selection: crate::config::EgressPoolSelection::RoundRobin,
+        }
+    }
+
+    #[test]
+    fn egress_pools_are_validated() {
+        let pools = [
+            pool_config("a", &["192.0.2.1"]),
+            pool_config("a", &["eth0"]),
+        ];
+        assert!(
+            EgressState::new(None)
+                .unwrap()
+                .with_egress_pools(&pools, None)
+                .is_err()
+        );
+        assert!(
+            EgressState::new(None)
+                .unwrap()
+                .with_egress_pools(&pools[..1], Some("missing"))
+                .is_err()
+        );
+
+        let egress = EgressState::new(None)
+            .unwrap()
+            .with_egress_pools(&pools[..1], Some("a"))
+            .unwrap();
+        assert!(egress.has_egress_pool("a"));
+        assert!(matches!(
+            egress.binding(None, "example.com:443"),
+            Ok(EgressBinding::Pool(_, _))
+        ));
+        assert_eq!(
+            egress
+                .binding(Some("b"), "example.com:443")
+                .err()
+                .unwrap()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[tokio::test]
+    async fn pool_binds_configured_source_address() {
+        // 同 named_interface_connect_races_candidates：借用非回环网卡上的地址作为池内源地址。
+        let Some(interface) = if_addrs::get_if_addrs()
+            .unwrap()
+            .into_iter()
+            .find(|interface| !interface.is_loopback() && interface.ip().is_ipv4())
+        else {
+            return;
+        };
+        let source_ip = interface.ip();
+        let listener = TcpListener::bind((source_ip, 0)).await.unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        let egress = EgressState::new(None)
+            .unwrap()
+            .with_egress_pools(&[pool_config("tenant", &[&source_ip.to_string()])], None)
+            .unwrap();
+
+        match egress.connect_tcp(&target, Some("tenant")).await {
+            Ok(_stream) => {
+                let (_, peer) = listener.accept().await.unwrap();
+                assert_eq!(peer.ip(), source_ip);
+            }
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {}
+            Err(err) => panic!("{err}"),
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! RFC 8305 Happy Eyeballs v2：交错地址族并错峰发起 TCP 连接，取最先成功的 socket。
+//!
+//! 目标同时有 A/AAAA 记录但其中一族不可达（例如 AAAA 指向黑洞）时，顺序连接要等到
+//! 失败地址的 connect 超时才会尝试下一个；错峰竞速把这段停顿压到一个尝试间隔以内。
+
+use futures::StreamExt;
+use futures::stream::FuturesUnordered;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// RFC 8305 推荐的连接尝试间隔。
+pub(super) const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// 按 RFC 8305 第 4 节交错地址族：以第一个地址的地址族开头，两族交替排列，族内保持解析顺序。
+pub(super) fn interleave_families(addrs: &[SocketAddr]) -> Vec<SocketAddr> {
+    let Some(first) = addrs.first() else {
+        return Vec::new();
+    };
+    let (preferred, other): (Vec<_>, Vec<_>) = addrs
+        .iter()
+        .copied()
+        .partition(|addr| addr.is_ipv4() == first.is_ipv4());
+
+    let mut preferred = preferred.into_iter();
+    let mut other = other.into_iter();
+    let mut interleaved = Vec::with_capacity(addrs.len());
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => return interleaved,
+            (a, b) => interleaved.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+/// 依次启动候选连接：每隔 `attempt_delay`，或上一个尝试失败时立即启动下一个。
+///
+/// 返回最先成功的结果，其余仍在进行的尝试随 future 一起被取消；全部失败时返回最后一个错误。
+pub(super) async fn race<C, T, F, Fut>(
+    candidates: Vec<C>,
+    attempt_delay: Duration,
+    mut connect: F,
+) -> io::Result<T>
+where
+    F: FnMut(C) -> Fut,
+    Fut: Future<Output = io::Result<T>>,
+{
+    let mut pending = candidates.into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_error = None;
+
+    loop {
+        if attempts.is_empty() {
+            match pending.next() {
+                Some(candidate) => attempts.push(connect(candidate)),
+                None => break,
+            }
+        }
+
+        let has_pending = pending.len() > 0;
+        tokio::select! {
+            Some(result) = attempts.next() => match result {
+                Ok(connected) => return Ok(connected),
+                Err(err) => {
+                    last_error = Some(err);
+                    if let Some(candidate) = pending.next() {
+                        attempts.push(connect(candidate));
+                    }
+                }
+            },
+            _ = tokio::time::sleep(attempt_delay), if has_pending => {
+                if let Some(candidate) = pending.next() {
+                    attempts.push(connect(candidate));
+                }
+            }
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| io::Error::other("所有目标地址连接失败")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use std::time::Instant;
+    use tokio::net::{TcpListener, TcpStream};
+
+    fn v4(last: u8) -> SocketAddr {
+        SocketAddr::from((Ipv4Addr::new(192, 0, 2, last), 443))
+    }
+
+    fn v6(last: u16) -> SocketAddr {
+        SocketAddr::from((Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, last), 443))
+    }
+
+    #[test]
+    fn interleaves_families_starting_with_first_address() {
+        assert_eq!(
+            interleave_families(&[v6(1), v6(2), v6(3), v4(1), v4(2)]),
+            vec![v6(1), v4(1), v6(2), v4(2), v6(3)]
+        );
+        assert_eq!(
+            interleave_families(&[v4(1), v4(2), v6(1)]),
+            vec![v4(1), v6(1), v4(2)]
+        );
+        assert!(interleave_families(&[]).is_empty());
+    }
+
+    /// 本地已关闭的端口，连接会被立即拒绝。
+    async fn refused_addr() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    #[tokio::test]
+    async fn stalled_attempt_is_overtaken_after_attempt_delay() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let reachable = listener.local_addr().unwrap();
+        // 第一个候选模拟黑洞地址：connect 永远不返回。
+        let candidates = vec![None, Some(reachable)];
+
+        let started = Instant::now();
+        let stream = race(
+            candidates,
+            CONNECTION_ATTEMPT_DELAY,
+            |candidate| async move {
+                match candidate {
+                    Some(addr) => TcpStream::connect(addr).await,
+                    None => std::future::pending().await,
+                }
+            },
+        )
+        .await
+        .unwrap();
+
+        let elapsed = started.elapsed();
+        assert_eq!(stream.peer_addr().unwrap(), reachable);
+        assert!(elapsed >= CONNECTION_ATTEMPT_DELAY, "{elapsed:?}");
+        assert!(elapsed < Duration::from_secs(5), "{elapsed:?}");
+    }
+
+    #[tokio::test]
+    async fn failed_attempt_starts_next_without_waiting() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let reachable = listener.local_addr().unwrap();
+        let candidates = vec![refused_addr().await, reachable];
+
+        let started = Instant::now();
+        let stream = race(candidates, Duration::from_secs(10), TcpStream::connect)
+            .await
+            .unwrap();
+
+        assert_eq!(stream.peer_addr().unwrap(), reachable);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn all_failures_return_last_error() {
+        let candidates = vec![refused_addr().await, refused_addr().await];
+        let err = race(candidates, CONNECTION_ATTEMPT_DELAY, TcpStream::connect)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+    }
+}
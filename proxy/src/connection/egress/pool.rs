@@ -0,0 +1,172 @@
+use super::source::{BoundSource, address_bind_source, interface_bind_addrs};
+use crate::config::{EgressPoolConfig, EgressPoolSelection};
+use std::borrow::Cow;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// 配置中的一个出站源地址池。
+pub(super) struct EgressPool {
+    name: String,
+    members: Vec<PoolMember>,
+    selection: EgressPoolSelection,
+    // 轮询游标；每条新连接前进一格。
+    next: AtomicUsize,
+}
+
+enum PoolMember {
+    // 本机源地址；连接时绑定该地址及其所属网卡。
+    Address(IpAddr),
+    // 网卡名；连接时按目标地址族从该网卡挑选源地址。
+    Interface(String),
+}
+
+impl EgressPool {
+    pub(super) fn from_config(config: &EgressPoolConfig) -> io::Result<Self> {
+        let name = config.name.trim();
+        if name.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "出站地址池名称不能为空",
+            ));
+        }
+        let members = config
+            .sources
+            .iter()
+            .map(|source| source.trim())
+            .filter(|source| !source.is_empty())
+            .map(|source| match source.parse::<IpAddr>() {
+                Ok(ip) => PoolMember::Address(ip),
+                Err(_) => PoolMember::Interface(source.to_string()),
+            })
+            .collect::<Vec<_>>();
+        if members.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("出站地址池 {name} 没有配置源地址"),
+            ));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            members,
+            selection: config.selection,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    pub(super) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 为一条新连接选出首选成员的下标；同一连接的所有候选目标地址共用这个起点。
+    pub(super) fn start_index(&self, target_addr: &str) -> usize {
+        let start = match self.selection {
+            EgressPoolSelection::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed),
+            EgressPoolSelection::TargetHash => {
+                let mut hasher = DefaultHasher::new();
+                target_addr.to_ascii_lowercase().hash(&mut hasher);
+                hasher.finish() as usize
+            }
+        };
+        start % self.members.len()
+    }
+
+    /// 从 `start` 开始找第一个能连接 `dst` 的成员，返回要绑定的网卡和候选源地址。
+    ///
+    /// 首选成员的地址族与目标不匹配（例如 IPv4 源地址连 IPv6 目标）时顺延到下一个成员。
+    pub(super) fn bind_for_dst(
+        &self,
+        start: usize,
+        dst: SocketAddr,
+    ) -> io::Result<(Cow<'_, str>, Vec<BoundSource>)> {
+        let mut last_error = None;
+        for offset in 0..self.members.len() {
+            let member = &self.members[(start + offset) % self.members.len()];
+            let bound = match member {
+                PoolMember::Address(ip) if ip.is_ipv4() != dst.is_ipv4() => continue,
+                PoolMember::Address(ip) => address_bind_source(*ip)
+                    .map(|(interface, source)| (Cow::Owned(interface), vec![source])),
+                PoolMember::Interface(interface) => interface_bind_addrs(interface, dst)
+                    .map(|sources| (Cow::Borrowed(interface.as_str()), sources)),
+            };
+            match bound {
+                Ok(bound) => return Ok(bound),
+                Err(err) => last_error = Some(err),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                format!("出站地址池 {} 没有匹配 {dst} 地址族的源地址", self.name),
+            )
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(sources: &[&str], selection: EgressPoolSelection) -> EgressPool {
+        EgressPool::from_config(&EgressPoolConfig {
+            name: "test".to_string(),
+            sources: sources.iter().map(ToString::to_string).collect(),
+            selection,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn rejects_empty_pool() {
+        let config = EgressPoolConfig {
+            name: "empty".to_string(),
+            sources: vec![" ".to_string()],
+            selection: EgressPoolSelection::RoundRobin,
+        };
+        assert!(EgressPool::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn round_robin_rotates_members() {
+        let pool = pool(
+            &["192.0.2.1", "192.0.2.2", "192.0.2.3"],
+            EgressPoolSelection::RoundRobin,
+        );
+        let starts = (0..4)
+            .map(|_| pool.start_index("example.com:443"))
+            .collect::<Vec<_>>();
+        assert_eq!(starts, [0, 1, 2, 0]);
+    }
+
+    #[test]
+    fn target_hash_is_stable_per_target() {
+        let pool = pool(
+            &["192.0.2.1", "192.0.2.2", "192.0.2.3"],
+            EgressPoolSelection::TargetHash,
+        );
+        let first = pool.start_index("Example.com:443");
+        assert_eq!(pool.start_index("example.com:443"), first);
+        assert_eq!(pool.start_index("example.com:443"), first);
+    }
+
+    #[test]
+    fn skips_members_of_other_address_family() {
+        let pool = pool(
+            &["2001:db8::1", "127.0.0.1"],
+            EgressPoolSelection::RoundRobin,
+        );
+        let dst = SocketAddr::from(([127, 0, 0, 1], 80));
+        let (interface, sources) = pool.bind_for_dst(0, dst).unwrap();
+        assert!(!interface.is_empty());
+        assert_eq!(sources[0].addr.ip(), dst.ip());
+
+        let v6_dst = SocketAddr::from(([0xfd00, 0, 0, 0, 0, 0, 0, 1], 80));
+        let Err(err) = pool.bind_for_dst(0, v6_dst) else {
+            panic!("pool has no local IPv6 source");
+        };
+        assert_eq!(err.kind(), io::ErrorKind::AddrNotAvailable);
+    }
+}
@@ -78,6 +78,47 @@ fn refresh_if_addrs_locked(cache: &mut InterfaceAddrCache) -> io::Result<Vec<Int
     Ok(cache.interfaces.clone())
 }
 
+/// 查找持有本机地址 `ip` 的网卡，返回网卡名和以该地址为源的绑定参数。
+///
+/// 地址池按 IP 配置源地址时同样绑定到所属网卡，保持与按网卡名配置时一致的出口行为。
+pub(super) fn address_bind_source(ip: IpAddr) -> io::Result<(String, BoundSource)> {
+    match address_bind_source_from_snapshot(ip, &cached_if_addrs()?) {
+        Ok(bound) => Ok(bound),
+        Err(err) if should_refresh_if_addrs(&err) => {
+            address_bind_source_from_snapshot(ip, &refresh_if_addrs()?)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn address_bind_source_from_snapshot(
+    ip: IpAddr,
+    interfaces: &[Interface],
+) -> io::Result<(String, BoundSource)> {
+    let iface = interfaces
+        .iter()
+        .find(|iface| iface.ip() == ip)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                format!("本机没有网卡持有源地址 {ip}"),
+            )
+        })?;
+    let addr = match ip {
+        IpAddr::V4(_) => SocketAddr::new(ip, 0),
+        IpAddr::V6(ip) => {
+            SocketAddr::V6(SocketAddrV6::new(ip, 0, 0, ipv6_scope_id(ip, iface.index)))
+        }
+    };
+    Ok((
+        iface.name.clone(),
+        BoundSource {
+            addr,
+            interface_index: iface.index,
+        },
+    ))
+}
+
 fn interface_bind_addrs_from_snapshot(
     interface: &str,
     dst: SocketAddr,
@@ -17,7 +17,7 @@ mod udp_relay_flow;
 mod upstream;
 
 pub use agent_io::AgentIo;
-pub use egress::EgressState;
+pub use egress::{EgressState, EgressTcpStream};
 pub use response_sink::BytesToProxyResponseSink;
 pub(crate) use target::target_addr_for_address;
 pub(crate) use udp_relay_flow::{
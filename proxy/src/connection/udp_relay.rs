@@ -35,6 +35,7 @@ impl ServerConnection {
         let mut flow_set = UdpRelayFlowSet::new(
             self.proxy_config.as_ref(),
             self.egress_state.clone(),
+            self.egress_pool().map(Arc::from),
             UdpRelayFlowChannels {
                 response_tx: response_tx.clone(),
                 flow_done_tx: flow_done_tx.clone(),
@@ -47,6 +47,8 @@ pub(super) struct UdpRelayFlowOptions {
 #[derive(Clone)]
 pub(super) struct UdpRelayFlowContext {
     egress_state: Arc<EgressState>,
+    // 所属用户的出站地址池，每个 flow 建立目标 socket 时使用。
+    egress_pool: Option<Arc<str>>,
     channels: UdpRelayFlowChannels,
     relay_label: &'static str,
     flow_task_name: &'static str,
@@ -83,6 +85,7 @@ impl UdpRelayFlowSet {
     pub(crate) fn new(
         proxy_config: &ProxyConfig,
         egress_state: Arc<EgressState>,
+        egress_pool: Option<Arc<str>>,
         channels: UdpRelayFlowChannels,
         relay_label: &'static str,
         flow_task_name: &'static str,
@@ -97,6 +100,7 @@ impl UdpRelayFlowSet {
             },
             context: UdpRelayFlowContext {
                 egress_state,
+                egress_pool,
                 channels,
                 relay_label,
                 flow_task_name,
@@ -206,7 +210,7 @@ async fn spawn_udp_relay_flow(
     let target_addr = relay_target_addr(&address)?;
     let socket = context
         .egress_state
-        .connect_udp(&target_addr)
+        .connect_udp(&target_addr, context.egress_pool.as_deref())
         .await
         .map_err(|e| ProxyError::Connection(format!("Failed to connect UDP relay target: {e}")))?;
     let (tx, mut rx) = tokio::sync::mpsc::channel::<QueuedUdpRelayData>(options.channel_size);
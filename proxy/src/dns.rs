@@ -0,0 +1,236 @@
+//! proxy 内置 DNS 解析器。
+//!
+//! `Address::ProxyDns` 查询和普通 `Address::Domain` 目标的地址解析都经过这里的
+//! `DnsResolver`：正向/否定应答按 TTL 缓存，多个上游按 failover 或 race 策略查询。
+//! 上游可以是明文 `ip[:port]`、`https://host/dns-query`（DoH）或 `tls://host:853`（DoT）；
+//! DoH/DoT 连接在请求之间复用。所有上游连接都通过 `EgressState` 建立，
+//! 因此同样遵守 `outbound_interface` 出站设备配置。
+
+mod bridge;
+mod cache;
+mod doh;
+mod dot;
+mod plain;
+mod resolver;
+mod system;
+mod tls;
+
+pub(crate) use bridge::{spawn_tcp_bridge, spawn_udp_bridge};
+pub(crate) use resolver::{DnsResolver, spawn_stats_logger};
+
+use crate::connection::EgressState;
+use crate::error::{ProxyError, Result};
+use doh::DohClient;
+use dot::DotClient;
+use hickory_proto::op::{Message, ResponseCode};
+use hyper::Uri;
+use plain::PlainClient;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+// 单个上游一次交换的上限，包含必要时的建连和 TLS 握手；超时后 failover 到下一个上游。
+const DNS_UPSTREAM_EXCHANGE_TIMEOUT: Duration = Duration::from_secs(3);
+// DNS 报文最大长度；TCP/DoT 的 2 字节长度前缀也限制在这个范围内。
+const MAX_DNS_MESSAGE_SIZE: usize = 65_535;
+const DEFAULT_DNS_PORT: u16 = 53;
+
+/// 单个 DNS 上游。
+pub(crate) enum DnsUpstream {
+    Plain(PlainClient),
+    Https(DohClient),
+    Tls(DotClient),
+}
+
+impl DnsUpstream {
+    /// 解析一条上游配置：`ip[:port]`、`https://…` 或 `tls://…`。
+    ///
+    /// 明文 `http://` DoH 只在单元测试中接受，用于本机替身服务器。
+    pub(crate) fn parse(value: &str) -> Result<Self> {
+        let value = value.trim();
+        let lower = value.to_ascii_lowercase();
+        if lower.starts_with("https://") || (cfg!(test) && lower.starts_with("http://")) {
+            Ok(Self::Https(DohClient::parse(value)?))
+        } else if lower.starts_with("http://") {
+            Err(ProxyError::Configuration(format!(
+                "上游 DNS 不支持明文 http:// DoH：{value}，请改用 https://"
+            )))
+        } else if lower.starts_with("tls://") {
+            Ok(Self::Tls(DotClient::parse(value)?))
+        } else if lower.contains("://") {
+            Err(ProxyError::Configuration(format!(
+                "上游 DNS 不支持的协议：{value}，可用 ip[:port]、https://…/dns-query 或 tls://host[:port]"
+            )))
+        } else if value.is_empty() {
+            Err(ProxyError::Configuration(
+                "上游 DNS 地址不能为空".to_string(),
+            ))
+        } else {
+            Ok(Self::Plain(PlainClient::new(value)))
+        }
+    }
+
+    /// 把一条 wire-format DNS 查询发给上游，返回 wire-format 响应。
+    ///
+    /// `port` 是查询请求的 DNS 端口，只用于没有写端口的明文上游；DoH/DoT 端口由 URL 决定。
+    pub(crate) async fn exchange(
+        &self,
+        egress: &EgressState,
+        query: &[u8],
+        port: u16,
+    ) -> io::Result<Vec<u8>> {
+        if query.len() < 12 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "DNS 查询报文长度不足",
+            ));
+        }
+        let exchange = async {
+            match self {
+                Self::Plain(client) => client.exchange(egress, query, port).await,
+                Self::Https(client) => client.exchange(egress, query).await,
+                Self::Tls(client) => client.exchange(egress, query).await,
+            }
+        };
+        tokio::time::timeout(DNS_UPSTREAM_EXCHANGE_TIMEOUT, exchange)
+            .await
+            .map_err(|_| {
+                io::Error::new(io::ErrorKind::TimedOut, format!("上游 DNS {self} 响应超时"))
+            })?
+    }
+}
+
+impl fmt::Display for DnsUpstream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Plain(client) => write!(f, "{client}"),
+            Self::Https(client) => write!(f, "DoH {client}"),
+            Self::Tls(client) => write!(f, "DoT {client}"),
+        }
+    }
+}
+
+/// 加密 DNS 上游的主机和端口。`host` 不带 IPv6 方括号，可直接用于 TLS 证书校验。
+struct UpstreamEndpoint {
+    host: String,
+    port: u16,
+}
+
+impl UpstreamEndpoint {
+    fn from_uri(uri: &Uri, default_port: u16, raw: &str) -> Result<Self> {
+        let host = uri
+            .host()
+            .map(|host| host.trim_start_matches('[').trim_end_matches(']'))
+            .filter(|host| !host.is_empty())
+            .ok_or_else(|| {
+                ProxyError::Configuration(format!("dns_upstream_addr 缺少主机名：{raw}"))
+            })?;
+        Ok(Self {
+            host: host.to_string(),
+            port: uri.port_u16().unwrap_or(default_port),
+        })
+    }
+
+    /// `host:port` 形式，IPv6 自动加方括号；既用于出站连接，也用于 HTTP Host。
+    fn authority(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+}
+
+/// 读取一条 2 字节长度前缀的 DNS 报文（RFC 1035 TCP 格式）；对端干净关闭时返回 `None`。
+async fn read_length_prefixed<S>(stream: &mut S) -> io::Result<Option<Vec<u8>>>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut len = [0_u8; 2];
+    match stream.read_exact(&mut len).await {
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let mut message = vec![0_u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut message).await?;
+    Ok(Some(message))
+}
+
+/// 写出一条 2 字节长度前缀的 DNS 报文。
+async fn write_length_prefixed<S>(stream: &mut S, message: &[u8]) -> io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    let len = u16::try_from(message.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "DNS 报文超过 65535 字节"))?;
+    let mut frame = Vec::with_capacity(message.len() + 2);
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(message);
+    stream.write_all(&frame).await?;
+    stream.flush().await
+}
+
+/// 上游失败时给 agent 回 SERVFAIL，让系统解析器立即换下一个查询而不是等待超时。
+fn servfail_response(query: &[u8]) -> Option<Vec<u8>> {
+    let request = Message::from_vec(query).ok()?;
+    let mut response = Message::error_msg(
+        request.metadata.id,
+        request.metadata.op_code,
+        ResponseCode::ServFail,
+    );
+    response.metadata.recursion_desired = request.metadata.recursion_desired;
+    response.metadata.recursion_available = true;
+    response.add_queries(request.queries);
+    response.to_vec().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hickory_proto::op::{MessageType, OpCode, Query};
+    use hickory_proto::rr::{Name, RecordType};
+    use std::str::FromStr;
+
+    pub(super) fn sample_query(id: u16) -> Vec<u8> {
+        let mut message = Message::new(id, MessageType::Query, OpCode::Query);
+        message.metadata.recursion_desired = true;
+        message.add_query(Query::query(
+            Name::from_str("example.com.").unwrap(),
+            RecordType::A,
+        ));
+        message.to_vec().unwrap()
+    }
+
+    #[test]
+    fn parses_plain_doh_and_dot_upstreams() {
+        // 明文上游保留配置原文，没写端口时按每次查询请求的端口补齐。
+        let plain = DnsUpstream::parse("8.8.8.8").unwrap();
+        assert_eq!(plain.to_string(), "8.8.8.8");
+        let plain = DnsUpstream::parse("8.8.8.8:5353").unwrap();
+        assert_eq!(plain.to_string(), "8.8.8.8:5353");
+
+        let doh = DnsUpstream::parse("https://dns.google/dns-query").unwrap();
+        assert_eq!(doh.to_string(), "DoH https://dns.google:443/dns-query");
+
+        let dot = DnsUpstream::parse("tls://1.1.1.1").unwrap();
+        assert_eq!(dot.to_string(), "DoT tls://1.1.1.1:853");
+    }
+
+    #[test]
+    fn rejects_unknown_scheme_and_empty_upstream() {
+        assert!(DnsUpstream::parse("quic://dns.example:853").is_err());
+        assert!(DnsUpstream::parse("  ").is_err());
+    }
+
+    #[test]
+    fn servfail_keeps_id_and_question() {
+        let response = servfail_response(&sample_query(0x1234)).unwrap();
+        let message = Message::from_vec(&response).unwrap();
+        assert_eq!(message.metadata.id, 0x1234);
+        assert_eq!(message.metadata.message_type, MessageType::Response);
+        assert_eq!(message.metadata.response_code, ResponseCode::ServFail);
+        assert_eq!(message.queries.len(), 1);
+    }
+}
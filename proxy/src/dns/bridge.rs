@@ -0,0 +1,144 @@
+//! 把内置 DNS 解析器接到现有 relay 上。
+//!
+//! TCP/UDP relay 只认识 `AsyncRead + AsyncWrite` 和已 connect 的 `UdpSocket`，这里分别用
+//! 内存 duplex 和一对回环 UDP socket 伪装成“目标连接”，让 relay 代码保持不变。
+
+use super::{MAX_DNS_MESSAGE_SIZE, read_length_prefixed, servfail_response, write_length_prefixed};
+use crate::connection::EgressState;
+use common::spawn_guarded;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use tokio::io::DuplexStream;
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+/// 为 TCP ProxyDns 返回一端 duplex；另一端由后台任务按 DNS-over-TCP 格式逐条应答。
+///
+/// `port` 是 `Address::ProxyDns` 请求的 DNS 端口。
+pub(crate) fn spawn_tcp_bridge(egress: Arc<EgressState>, port: u16) -> DuplexStream {
+    let (relay_side, mut dns_side) = tokio::io::duplex(MAX_DNS_MESSAGE_SIZE + 2);
+    spawn_guarded("proxy dns tcp bridge", async move {
+        loop {
+            let query = match read_length_prefixed(&mut dns_side).await {
+                Ok(Some(query)) => query,
+                Ok(None) => break,
+                Err(err) => {
+                    debug!("ProxyDns TCP 桥读取查询失败：{err}");
+                    break;
+                }
+            };
+            let Some(response) = answer(&egress, &query, port).await else {
+                continue;
+            };
+            if let Err(err) = write_length_prefixed(&mut dns_side, &response).await {
+                debug!("ProxyDns TCP 桥写回响应失败：{err}");
+                break;
+            }
+        }
+    });
+    relay_side
+}
+
+/// 为 UDP ProxyDns 返回一个已 connect 到本机应答 socket 的 `UdpSocket`。
+///
+/// UDP 没有关闭信号，应答端无法自行感知 relay 结束；调用方持有返回的 guard，
+/// relay 结束 drop 时停止应答任务。
+pub(crate) async fn spawn_udp_bridge(
+    egress: Arc<EgressState>,
+    port: u16,
+) -> io::Result<(UdpSocket, UdpBridgeGuard)> {
+    let loopback = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+    let relay_side = UdpSocket::bind(loopback).await?;
+    let dns_side = UdpSocket::bind(loopback).await?;
+    relay_side.connect(dns_side.local_addr()?).await?;
+    dns_side.connect(relay_side.local_addr()?).await?;
+    let dns_side = Arc::new(dns_side);
+
+    let task = spawn_guarded("proxy dns udp bridge", async move {
+        let mut buf = vec![0_u8; MAX_DNS_MESSAGE_SIZE];
+        loop {
+            let size = match dns_side.recv(&mut buf).await {
+                Ok(size) => size,
+                Err(err) => {
+                    debug!("ProxyDns UDP 桥读取查询失败：{err}");
+                    break;
+                }
+            };
+            // 同一 socket 上的 A/AAAA 等查询并发发出，h2 上游可以在一条连接上多路复用。
+            let query = buf[..size].to_vec();
+            let egress = egress.clone();
+            let dns_side = dns_side.clone();
+            spawn_guarded("proxy dns udp query", async move {
+                if let Some(response) = answer(&egress, &query, port).await {
+                    let _ = dns_side.send(&response).await;
+                }
+            });
+        }
+    });
+    Ok((relay_side, UdpBridgeGuard(task)))
+}
+
+pub(crate) struct UdpBridgeGuard(JoinHandle<()>);
+
+impl Drop for UdpBridgeGuard {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+async fn answer(egress: &EgressState, query: &[u8], port: u16) -> Option<Vec<u8>> {
+    match egress.exchange_dns(query, port).await {
+        Ok(response) => Some(response),
+        Err(err) => {
+            warn!("DNS 上游查询失败，回复 SERVFAIL：{err}");
+            servfail_response(query)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::DnsResolver;
+    use crate::dns::doh::tests::spawn_stand_in_doh_server;
+    use crate::dns::tests::sample_query;
+
+    fn egress_with_upstream(url: &str) -> Arc<EgressState> {
+        let config = toml::from_str(&format!(
+            "listen_addr = \"127.0.0.1:0\"\ndns_upstream_addrs = [\"{url}\"]\n"
+        ))
+        .unwrap();
+        let resolver = DnsResolver::from_config(&config).unwrap();
+        Arc::new(EgressState::new(None).unwrap().with_dns_resolver(resolver))
+    }
+
+    #[tokio::test]
+    async fn udp_bridge_answers_through_resolver() {
+        let (url, _) = spawn_stand_in_doh_server().await;
+        let egress = egress_with_upstream(&url);
+        let (socket, _guard) = spawn_udp_bridge(egress, 53).await.unwrap();
+
+        let query = sample_query(0x5151);
+        socket.send(&query).await.unwrap();
+        let mut buf = vec![0_u8; MAX_DNS_MESSAGE_SIZE];
+        let size = socket.recv(&mut buf).await.unwrap();
+        assert_eq!(&buf[..2], &query[..2]);
+        assert_ne!(buf[2] & 0x80, 0);
+        assert_eq!(size, query.len());
+    }
+
+    #[tokio::test]
+    async fn tcp_bridge_answers_length_prefixed_queries() {
+        let (url, _) = spawn_stand_in_doh_server().await;
+        let egress = egress_with_upstream(&url);
+        let mut stream = spawn_tcp_bridge(egress, 53);
+
+        let query = sample_query(0x6161);
+        write_length_prefixed(&mut stream, &query).await.unwrap();
+        let response = read_length_prefixed(&mut stream).await.unwrap().unwrap();
+        assert_eq!(&response[..2], &query[..2]);
+        assert_ne!(response[2] & 0x80, 0);
+    }
+}
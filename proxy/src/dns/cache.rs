@@ -0,0 +1,303 @@
+use hickory_proto::op::{Message, MessageType, OpCode, ResponseCode};
+use hickory_proto::rr::RData;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// 缓存键：规范化后的查询名、类型、类别、是否请求 DNSSEC 记录，以及查询的 DNS 端口。
+///
+/// 不同端口上可能是不同的 DNS 服务（如 5353 上的本地 dnsmasq），应答分开缓存。
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(super) struct CacheKey {
+    name: String,
+    query_type: u16,
+    query_class: u16,
+    dnssec_ok: bool,
+    port: u16,
+}
+
+impl CacheKey {
+    /// 只有单问题的标准查询可以缓存；其它报文直接透传给上游。
+    pub(super) fn for_query(query: &Message, port: u16) -> Option<Self> {
+        if query.metadata.message_type != MessageType::Query
+            || query.metadata.op_code != OpCode::Query
+            || query.queries.len() != 1
+        {
+            return None;
+        }
+        let question = query.queries.first()?;
+        Some(Self {
+            name: question.name.to_lowercase().to_ascii(),
+            query_type: question.query_type.into(),
+            query_class: question.query_class.into(),
+            dnssec_ok: query
+                .edns
+                .as_ref()
+                .is_some_and(|edns| edns.flags().dnssec_ok),
+            port,
+        })
+    }
+}
+
+struct CacheEntry {
+    response: Message,
+    inserted: Instant,
+    expires: Instant,
+}
+
+/// 命中率统计快照，由周期日志输出。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct DnsCacheStatsSnapshot {
+    pub(crate) hits: u64,
+    pub(crate) misses: u64,
+    pub(crate) entries: usize,
+}
+
+/// 按 TTL 缓存完整 DNS 应答的有界缓存。
+///
+/// 条目满时先清理过期项，仍然满则淘汰最早过期的条目；命中时按已过去的时间
+/// 递减记录 TTL，保证下游看到的剩余有效期不会超过上游给出的值。
+pub(super) struct DnsCache {
+    capacity: usize,
+    max_ttl: Duration,
+    negative_ttl: Duration,
+    entries: Mutex<HashMap<CacheKey, CacheEntry>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl DnsCache {
+    pub(super) fn new(capacity: usize, max_ttl: Duration, negative_ttl: Duration) -> Self {
+        Self {
+            capacity,
+            max_ttl,
+            negative_ttl,
+            entries: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub(super) fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// 查找缓存；命中时返回已改写 ID 和剩余 TTL 的应答。
+    pub(super) fn get(&self, key: &CacheKey, id: u16) -> Option<Message> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let now = Instant::now();
+        let cached = {
+            let mut entries = self.entries.lock();
+            match entries.get(key) {
+                Some(entry) if entry.expires > now => {
+                    Some((entry.response.clone(), now.duration_since(entry.inserted)))
+                }
+                Some(_) => {
+                    entries.remove(key);
+                    None
+                }
+                None => None,
+            }
+        };
+        let Some((mut response, elapsed)) = cached else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        self.hits.fetch_add(1, Ordering::Relaxed);
+
+        let elapsed = u32::try_from(elapsed.as_secs()).unwrap_or(u32::MAX);
+        response.metadata.id = id;
+        for record in response
+            .answers
+            .iter_mut()
+            .chain(response.authorities.iter_mut())
+            .chain(response.additionals.iter_mut())
+        {
+            record.ttl = record.ttl.saturating_sub(elapsed);
+        }
+        Some(response)
+    }
+
+    pub(super) fn insert(&self, key: CacheKey, response: &Message) {
+        if !self.is_enabled() {
+            return;
+        }
+        let Some(ttl) = self.cache_ttl(response) else {
+            return;
+        };
+        let now = Instant::now();
+        let mut entries = self.entries.lock();
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            entries.retain(|_, entry| entry.expires > now);
+            if entries.len() >= self.capacity
+                && let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.expires)
+                    .map(|(key, _)| key.clone())
+            {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            key,
+            CacheEntry {
+                response: response.clone(),
+                inserted: now,
+                expires: now + ttl,
+            },
+        );
+    }
+
+    pub(super) fn snapshot_and_reset(&self) -> DnsCacheStatsSnapshot {
+        DnsCacheStatsSnapshot {
+            hits: self.hits.swap(0, Ordering::Relaxed),
+            misses: self.misses.swap(0, Ordering::Relaxed),
+            entries: self.entries.lock().len(),
+        }
+    }
+
+    /// 计算应答可缓存的时长；SERVFAIL、截断等临时性结果不缓存。
+    fn cache_ttl(&self, response: &Message) -> Option<Duration> {
+        if response.metadata.truncation {
+            return None;
+        }
+        let ttl = match response.metadata.response_code {
+            ResponseCode::NoError if !response.answers.is_empty() => {
+                let min_ttl = response.answers.iter().map(|record| record.ttl).min()?;
+                Duration::from_secs(u64::from(min_ttl)).min(self.max_ttl)
+            }
+            // RFC 2308：否定应答的 TTL 取 SOA 记录 TTL 与 MINIMUM 的较小者。
+            ResponseCode::NoError | ResponseCode::NXDomain => response
+                .authorities
+                .iter()
+                .find_map(|record| match &record.data {
+                    RData::SOA(soa) => Some(record.ttl.min(soa.minimum)),
+                    _ => None,
+                })
+                .map(|ttl| Duration::from_secs(u64::from(ttl)).min(self.negative_ttl))
+                .unwrap_or(self.negative_ttl),
+            _ => return None,
+        };
+        (!ttl.is_zero()).then_some(ttl)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hickory_proto::op::Query;
+    use hickory_proto::rr::rdata::A;
+    use hickory_proto::rr::{Name, Record, RecordType};
+    use std::net::Ipv4Addr;
+    use std::str::FromStr;
+
+    fn query(name: &str) -> Message {
+        let mut message = Message::new(7, MessageType::Query, OpCode::Query);
+        message.add_query(Query::query(Name::from_str(name).unwrap(), RecordType::A));
+        message
+    }
+
+    fn answer(name: &str, ttl: u32) -> Message {
+        let request = query(name);
+        let mut response = Message::response(request.metadata.id, OpCode::Query);
+        response.add_queries(request.queries);
+        response.add_answer(Record::from_rdata(
+            Name::from_str(name).unwrap(),
+            ttl,
+            RData::A(A(Ipv4Addr::new(192, 0, 2, 1))),
+        ));
+        response
+    }
+
+    fn cache(capacity: usize) -> DnsCache {
+        DnsCache::new(capacity, Duration::from_secs(3600), Duration::from_secs(30))
+    }
+
+    #[test]
+    fn key_ignores_name_case() {
+        assert_eq!(
+            CacheKey::for_query(&query("Example.COM."), 53),
+            CacheKey::for_query(&query("example.com."), 53)
+        );
+    }
+
+    #[test]
+    fn hit_rewrites_id_and_counts_stats() {
+        let cache = cache(16);
+        let key = CacheKey::for_query(&query("example.com."), 53).unwrap();
+        assert!(cache.get(&key, 1).is_none());
+
+        cache.insert(key.clone(), &answer("example.com.", 300));
+        let hit = cache.get(&key, 0x4321).unwrap();
+        assert_eq!(hit.metadata.id, 0x4321);
+        assert_eq!(hit.answers.len(), 1);
+        assert!(hit.answers[0].ttl <= 300);
+
+        let stats = cache.snapshot_and_reset();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+        assert_eq!(cache.snapshot_and_reset().hits, 0);
+    }
+
+    #[test]
+    fn negative_answers_use_negative_ttl_and_servfail_is_not_cached() {
+        let cache = cache(16);
+        let request = query("missing.example.");
+        let key = CacheKey::for_query(&request, 53).unwrap();
+
+        let mut servfail = Message::error_msg(7, OpCode::Query, ResponseCode::ServFail);
+        servfail.add_queries(request.queries.clone());
+        assert_eq!(cache.cache_ttl(&servfail), None);
+
+        let mut nxdomain = Message::error_msg(7, OpCode::Query, ResponseCode::NXDomain);
+        nxdomain.add_queries(request.queries);
+        assert_eq!(cache.cache_ttl(&nxdomain), Some(Duration::from_secs(30)));
+        cache.insert(key.clone(), &nxdomain);
+        assert_eq!(
+            cache.get(&key, 9).unwrap().metadata.response_code,
+            ResponseCode::NXDomain
+        );
+    }
+
+    #[test]
+    fn ttl_is_capped_and_zero_ttl_is_not_cached() {
+        let cache = DnsCache::new(16, Duration::from_secs(60), Duration::from_secs(30));
+        assert_eq!(
+            cache.cache_ttl(&answer("example.com.", 86_400)),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(cache.cache_ttl(&answer("example.com.", 0)), None);
+    }
+
+    #[test]
+    fn full_cache_evicts_earliest_expiry() {
+        let cache = cache(2);
+        for (name, ttl) in [("a.example.", 10), ("b.example.", 300), ("c.example.", 300)] {
+            cache.insert(
+                CacheKey::for_query(&query(name), 53).unwrap(),
+                &answer(name, ttl),
+            );
+        }
+        assert!(
+            cache
+                .get(&CacheKey::for_query(&query("a.example."), 53).unwrap(), 1)
+                .is_none()
+        );
+        assert!(
+            cache
+                .get(&CacheKey::for_query(&query("c.example."), 53).unwrap(), 1)
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn zero_capacity_disables_cache() {
+        let cache = cache(0);
+        let key = CacheKey::for_query(&query("example.com."), 53).unwrap();
+        cache.insert(key.clone(), &answer("example.com.", 300));
+        assert!(cache.get(&key, 1).is_none());
+        assert_eq!(cache.snapshot_and_reset(), DnsCacheStatsSnapshot::default());
+    }
+}
@@ -0,0 +1,316 @@
+use super::{MAX_DNS_MESSAGE_SIZE, UpstreamEndpoint, tls};
+use crate::connection::EgressState;
+use crate::error::{ProxyError, Result};
+use bytes::Bytes;
+use common::spawn_guarded;
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::client::conn::{http1, http2};
+use hyper::header::{ACCEPT, CONTENT_TYPE, HOST};
+use hyper::{Method, Request, StatusCode, Uri};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use rustls::ClientConfig;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::Mutex;
+use tracing::debug;
+
+const DNS_MESSAGE_CONTENT_TYPE: &str = "application/dns-message";
+
+trait DohIo: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T> DohIo for T where T: AsyncRead + AsyncWrite + Send + Unpin {}
+
+/// RFC 8484 DoH 客户端：POST `application/dns-message`，连接在请求之间复用。
+///
+/// TLS 握手协商出 h2 时所有查询在同一条连接上多路复用；否则退回 HTTP/1.1，
+/// 同一时刻只有一个查询占用连接。明文 `http://` 只在单元测试中用于本机替身服务器。
+pub(crate) struct DohClient {
+    endpoint: UpstreamEndpoint,
+    path: String,
+    // None 表示明文 http://，仅测试构建可用。
+    tls: Option<Arc<ClientConfig>>,
+    sender: Mutex<Option<DohSender>>,
+}
+
+enum DohSender {
+    Http1(http1::SendRequest<Full<Bytes>>),
+    Http2(http2::SendRequest<Full<Bytes>>),
+}
+
+impl DohSender {
+    fn is_closed(&self) -> bool {
+        match self {
+            Self::Http1(sender) => sender.is_closed(),
+            Self::Http2(sender) => sender.is_closed(),
+        }
+    }
+}
+
+impl DohClient {
+    pub(super) fn parse(value: &str) -> Result<Self> {
+        let uri = value.parse::<Uri>().map_err(|e| {
+            ProxyError::Configuration(format!("dns_upstream_addr DoH 地址无效：{value}：{e}"))
+        })?;
+        let secure = match uri.scheme_str() {
+            Some(scheme) if scheme.eq_ignore_ascii_case("https") => true,
+            Some(scheme) if cfg!(test) && scheme.eq_ignore_ascii_case("http") => false,
+            _ => {
+                return Err(ProxyError::Configuration(format!(
+                    "dns_upstream_addr DoH 地址必须以 https:// 开头：{value}"
+                )));
+            }
+        };
+        let endpoint = UpstreamEndpoint::from_uri(&uri, if secure { 443 } else { 80 }, value)?;
+        // 只写主机时使用 RFC 8484 推荐的 /dns-query 路径。
+        let path = match uri.path_and_query().map(|path| path.as_str()) {
+            None | Some("") | Some("/") => "/dns-query".to_string(),
+            Some(path) => path.to_string(),
+        };
+        let tls =
+            if secure {
+                Some(tls::client_config(&[b"h2", b"http/1.1"]).map_err(|e| {
+                    ProxyError::Configuration(format!("初始化 DoH TLS 配置失败：{e}"))
+                })?)
+            } else {
+                None
+            };
+
+        Ok(Self {
+            endpoint,
+            path,
+            tls,
+            sender: Mutex::new(None),
+        })
+    }
+
+    pub(super) async fn exchange(&self, egress: &EgressState, query: &[u8]) -> io::Result<Vec<u8>> {
+        // RFC 8484 建议把 DNS ID 置 0 以提高 HTTP 缓存命中；响应回来后恢复 agent 的原始 ID。
+        let id = [query[0], query[1]];
+        let mut body = query.to_vec();
+        body[..2].fill(0);
+        let body = Bytes::from(body);
+
+        let mut response = match self.send(egress, body.clone(), true).await {
+            Ok(response) => response,
+            Err(err) => {
+                // 复用的连接可能已被服务端空闲关闭，换一条新连接重试一次。
+                debug!("DoH 请求失败，重建连接后重试：上游={self}，错误={err}");
+                self.send(egress, body, false).await?
+            }
+        };
+        if response.len() < 12 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "DoH 响应不是有效的 DNS 报文",
+            ));
+        }
+        response[..2].copy_from_slice(&id);
+        Ok(response)
+    }
+
+    async fn send(&self, egress: &EgressState, body: Bytes, reuse: bool) -> io::Result<Vec<u8>> {
+        let mut slot = self.sender.lock().await;
+        if !reuse || slot.as_ref().is_none_or(DohSender::is_closed) {
+            *slot = Some(self.connect(egress).await?);
+        }
+
+        // h2 连接可并发使用：克隆 sender 后立即释放锁。
+        if let Some(DohSender::Http2(sender)) = slot.as_ref() {
+            let mut sender = sender.clone();
+            drop(slot);
+            sender.ready().await.map_err(io::Error::other)?;
+            let response = sender
+                .send_request(self.request(body, true)?)
+                .await
+                .map_err(io::Error::other)?;
+            return read_response(response).await;
+        }
+
+        // HTTP/1.1 连接一次只能承载一个请求，持锁直到响应读完。
+        let Some(DohSender::Http1(sender)) = slot.as_mut() else {
+            return Err(io::Error::other("DoH 连接状态异常"));
+        };
+        sender.ready().await.map_err(io::Error::other)?;
+        let response = sender
+            .send_request(self.request(body, false)?)
+            .await
+            .map_err(io::Error::other)?;
+        read_response(response).await
+    }
+
+    async fn connect(&self, egress: &EgressState) -> io::Result<DohSender> {
+        let stream = egress
+            .connect_tcp_via_system_dns(&self.endpoint.authority())
+            .await?;
+        let (io, use_h2): (Box<dyn DohIo>, bool) = match &self.tls {
+            Some(config) => {
+                let stream = tls::connect(config.clone(), &self.endpoint.host, stream).await?;
+                let use_h2 = stream.get_ref().1.alpn_protocol() == Some(b"h2");
+                (Box::new(stream), use_h2)
+            }
+            None => (Box::new(stream), false),
+        };
+        let io = TokioIo::new(io);
+
+        let upstream = self.to_string();
+        if use_h2 {
+            let (sender, connection) = http2::handshake(TokioExecutor::new(), io)
+                .await
+                .map_err(io::Error::other)?;
+            spawn_guarded("proxy doh h2 connection", async move {
+                if let Err(err) = connection.await {
+                    debug!("DoH h2 连接结束：上游={upstream}，错误={err}");
+                }
+            });
+            debug!("DoH 已建立 h2 连接：{self}");
+            Ok(DohSender::Http2(sender))
+        } else {
+            let (sender, connection) = http1::handshake(io).await.map_err(io::Error::other)?;
+            spawn_guarded("proxy doh http1 connection", async move {
+                if let Err(err) = connection.await {
+                    debug!("DoH HTTP/1.1 连接结束：上游={upstream}，错误={err}");
+                }
+            });
+            debug!("DoH 已建立 HTTP/1.1 连接：{self}");
+            Ok(DohSender::Http1(sender))
+        }
+    }
+
+    fn request(&self, body: Bytes, absolute_uri: bool) -> io::Result<Request<Full<Bytes>>> {
+        // h2 从绝对 URI 生成 :scheme/:authority 伪头；HTTP/1.1 使用 origin-form 加 Host 头。
+        let authority = self.endpoint.authority();
+        let uri = if absolute_uri {
+            format!("{}://{authority}{}", self.scheme(), self.path)
+        } else {
+            self.path.clone()
+        };
+        let mut builder = Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .header(CONTENT_TYPE, DNS_MESSAGE_CONTENT_TYPE)
+            .header(ACCEPT, DNS_MESSAGE_CONTENT_TYPE);
+        if !absolute_uri {
+            builder = builder.header(HOST, authority);
+        }
+        builder.body(Full::new(body)).map_err(io::Error::other)
+    }
+
+    fn scheme(&self) -> &'static str {
+        if self.tls.is_some() { "https" } else { "http" }
+    }
+}
+
+impl fmt::Display for DohClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}://{}{}",
+            self.scheme(),
+            self.endpoint.authority(),
+            self.path
+        )
+    }
+}
+
+async fn read_response(response: hyper::Response<hyper::body::Incoming>) -> io::Result<Vec<u8>> {
+    let status = response.status();
+    if status != StatusCode::OK {
+        return Err(io::Error::other(format!("DoH 上游返回 HTTP {status}")));
+    }
+    let body = Limited::new(response.into_body(), MAX_DNS_MESSAGE_SIZE)
+        .collect()
+        .await
+        .map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("读取 DoH 响应失败：{e}"),
+            )
+        })?;
+    Ok(body.to_bytes().to_vec())
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::dns::tests::sample_query;
+    use hyper::body::Incoming;
+    use hyper::server::conn::http1 as server_http1;
+    use hyper::service::service_fn;
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::net::TcpListener;
+
+    /// 本机 DoH 替身：记录建立的 TCP 连接数，把查询改成响应后原样返回。
+    pub(in crate::dns) async fn spawn_stand_in_doh_server() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted = connections.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                accepted.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let service = service_fn(|request: Request<Incoming>| async move {
+                        assert_eq!(request.method(), Method::POST);
+                        assert_eq!(request.uri().path(), "/dns-query");
+                        assert_eq!(request.headers()[CONTENT_TYPE], DNS_MESSAGE_CONTENT_TYPE);
+                        let mut message = request
+                            .into_body()
+                            .collect()
+                            .await
+                            .unwrap()
+                            .to_bytes()
+                            .to_vec();
+                        // 客户端发出的 ID 必须已被置 0。
+                        assert_eq!(&message[..2], &[0, 0]);
+                        message[2] |= 0x80;
+                        Ok::<_, Infallible>(
+                            hyper::Response::builder()
+                                .header(CONTENT_TYPE, DNS_MESSAGE_CONTENT_TYPE)
+                                .body(Full::new(Bytes::from(message)))
+                                .unwrap(),
+                        )
+                    });
+                    let _ = server_http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+        (format!("http://{addr}/dns-query"), connections)
+    }
+
+    #[test]
+    fn parse_defaults_path_and_port() {
+        let client = DohClient::parse("https://dns.example").unwrap();
+        assert_eq!(client.to_string(), "https://dns.example:443/dns-query");
+
+        let client = DohClient::parse("https://[2001:db8::1]:8443/resolve?x=1").unwrap();
+        assert_eq!(client.endpoint.host, "2001:db8::1");
+        assert_eq!(client.endpoint.authority(), "[2001:db8::1]:8443");
+        assert_eq!(client.path, "/resolve?x=1");
+    }
+
+    #[tokio::test]
+    async fn exchange_restores_id_and_reuses_connection() {
+        let (url, connections) = spawn_stand_in_doh_server().await;
+        let client = DohClient::parse(&url).unwrap();
+        let egress = EgressState::new(None).unwrap();
+
+        for id in [0x1234_u16, 0xbeef] {
+            let query = sample_query(id);
+            let response = client.exchange(&egress, &query).await.unwrap();
+            assert_eq!(u16::from_be_bytes([response[0], response[1]]), id);
+            assert_ne!(response[2] & 0x80, 0);
+            assert_eq!(&response[2..], {
+                let mut expected = query[2..].to_vec();
+                expected[0] |= 0x80;
+                expected
+            });
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+}
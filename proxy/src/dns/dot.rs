@@ -0,0 +1,133 @@
+use super::{UpstreamEndpoint, read_length_prefixed, tls, write_length_prefixed};
+use crate::connection::{EgressState, EgressTcpStream};
+use crate::error::{ProxyError, Result};
+use hyper::Uri;
+use rustls::ClientConfig;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio_rustls::client::TlsStream;
+use tracing::debug;
+
+/// RFC 7858 DoT 客户端：在持久 TLS 连接上按 2 字节长度前缀收发 DNS 报文。
+///
+/// 连接只有一条，查询串行执行；服务端空闲关闭后在下一次查询时自动重连。
+pub(crate) struct DotClient {
+    endpoint: UpstreamEndpoint,
+    tls: Arc<ClientConfig>,
+    // TLS 状态较大，装箱后放进锁里。
+    connection: Mutex<Option<Box<TlsStream<EgressTcpStream>>>>,
+}
+
+impl DotClient {
+    pub(super) fn parse(value: &str) -> Result<Self> {
+        let uri = value.parse::<Uri>().map_err(|e| {
+            ProxyError::Configuration(format!("dns_upstream_addr DoT 地址无效：{value}：{e}"))
+        })?;
+        if uri
+            .path_and_query()
+            .is_some_and(|path| !matches!(path.as_str(), "" | "/"))
+        {
+            return Err(ProxyError::Configuration(format!(
+                "dns_upstream_addr DoT 地址不应包含路径：{value}"
+            )));
+        }
+        let endpoint = UpstreamEndpoint::from_uri(&uri, 853, value)?;
+        let tls = tls::client_config(&[b"dot"])
+            .map_err(|e| ProxyError::Configuration(format!("初始化 DoT TLS 配置失败：{e}")))?;
+
+        Ok(Self {
+            endpoint,
+            tls,
+            connection: Mutex::new(None),
+        })
+    }
+
+    pub(super) async fn exchange(&self, egress: &EgressState, query: &[u8]) -> io::Result<Vec<u8>> {
+        let mut connection = self.connection.lock().await;
+        let reused = connection.is_some();
+        if !reused {
+            *connection = Some(self.connect(egress).await?);
+        }
+
+        let stream = connection.as_mut().expect("DoT connection initialized");
+        let result = match exchange_on(stream, query).await {
+            Err(err) if reused => {
+                // 复用的连接可能已被服务端空闲关闭，换一条新连接重试一次。
+                debug!("DoT 请求失败，重建连接后重试：上游={self}，错误={err}");
+                // 重连失败时不能把已断开的连接留给下一次查询。
+                *connection = None;
+                let stream = connection.insert(self.connect(egress).await?);
+                exchange_on(stream, query).await
+            }
+            result => result,
+        };
+        if result.is_err() {
+            *connection = None;
+        }
+        result
+    }
+
+    async fn connect(&self, egress: &EgressState) -> io::Result<Box<TlsStream<EgressTcpStream>>> {
+        let stream = egress
+            .connect_tcp_via_system_dns(&self.endpoint.authority())
+            .await?;
+        let stream = tls::connect(self.tls.clone(), &self.endpoint.host, stream).await?;
+        debug!("DoT 已建立连接：{self}");
+        Ok(Box::new(stream))
+    }
+}
+
+impl fmt::Display for DotClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tls://{}", self.endpoint.authority())
+    }
+}
+
+async fn exchange_on<S>(stream: &mut S, query: &[u8]) -> io::Result<Vec<u8>>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    write_length_prefixed(stream, query).await?;
+    let response = read_length_prefixed(stream)
+        .await?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "DoT 上游关闭了连接"))?;
+    // 串行收发时响应 ID 必须与查询一致，否则说明连接上残留了旧响应。
+    if response.len() < 12 || response[..2] != query[..2] {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "DoT 响应与查询不匹配",
+        ));
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::tests::sample_query;
+
+    #[test]
+    fn parse_defaults_port_and_rejects_path() {
+        let client = DotClient::parse("tls://dns.example").unwrap();
+        assert_eq!(client.to_string(), "tls://dns.example:853");
+        assert!(DotClient::parse("tls://dns.example/dns-query").is_err());
+    }
+
+    #[tokio::test]
+    async fn exchange_uses_length_prefixed_framing() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        let query = sample_query(0x4242);
+        let server_task = tokio::spawn(async move {
+            let mut request = read_length_prefixed(&mut server).await.unwrap().unwrap();
+            request[2] |= 0x80;
+            write_length_prefixed(&mut server, &request).await.unwrap();
+        });
+
+        let response = exchange_on(&mut client, &query).await.unwrap();
+        server_task.await.unwrap();
+        assert_eq!(&response[..2], &query[..2]);
+        assert_ne!(response[2] & 0x80, 0);
+    }
+}
@@ -0,0 +1,54 @@
+use super::system::endpoint_with_port;
+use super::{MAX_DNS_MESSAGE_SIZE, read_length_prefixed, write_length_prefixed};
+use crate::connection::EgressState;
+use std::fmt;
+use std::io;
+
+/// 明文 DNS 上游：先走 UDP，应答带 TC 截断标志时按 RFC 1035 改用 TCP 重查。
+///
+/// 配置值可以不写端口，此时使用查询所请求的 DNS 端口。
+pub(crate) struct PlainClient {
+    addr: String,
+}
+
+impl PlainClient {
+    pub(super) fn new(addr: &str) -> Self {
+        Self {
+            addr: addr.trim().to_string(),
+        }
+    }
+
+    pub(super) async fn exchange(
+        &self,
+        egress: &EgressState,
+        query: &[u8],
+        port: u16,
+    ) -> io::Result<Vec<u8>> {
+        let addr = endpoint_with_port(&self.addr, port);
+        let socket = egress.connect_udp_via_system_dns(&addr).await?;
+        socket.send(query).await?;
+        let mut buf = vec![0_u8; MAX_DNS_MESSAGE_SIZE];
+        let response = loop {
+            let size = socket.recv(&mut buf).await?;
+            // 已 connect 的 socket 只收该上游的包；ID 不符的是迟到的旧应答，丢弃继续等。
+            if size >= 12 && buf[..2] == query[..2] {
+                break &buf[..size];
+            }
+        };
+        if response[2] & 0x02 == 0 {
+            return Ok(response.to_vec());
+        }
+
+        let mut stream = egress.connect_tcp_via_system_dns(&addr).await?;
+        write_length_prefixed(&mut stream, query).await?;
+        read_length_prefixed(&mut stream)
+            .await?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "DNS 上游关闭了 TCP 连接"))
+    }
+}
+
+impl fmt::Display for PlainClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.addr)
+    }
+}
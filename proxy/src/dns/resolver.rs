@@ -0,0 +1,373 @@
+use super::cache::{CacheKey, DnsCache, DnsCacheStatsSnapshot};
+use super::plain::PlainClient;
+use super::{DEFAULT_DNS_PORT, DnsUpstream, system};
+use crate::config::{DnsUpstreamStrategy, ProxyConfig};
+use crate::connection::EgressState;
+use crate::error::Result;
+use common::spawn_guarded;
+use futures::StreamExt;
+use futures::stream::FuturesUnordered;
+use hickory_proto::op::{Message, ResponseCode};
+use hickory_proto::rr::{Name, RData, RecordType};
+use std::io;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+const DNS_STATS_LOG_INTERVAL: Duration = Duration::from_secs(60);
+// 与 ProxyConfig 中 dns_cache_* 的默认值保持一致。
+const DEFAULT_DNS_CACHE_SIZE: usize = 4096;
+const DEFAULT_DNS_CACHE_MAX_TTL_SECS: u64 = 3600;
+const DEFAULT_DNS_NEGATIVE_CACHE_TTL_SECS: u64 = 30;
+
+/// proxy 共享的缓存解析器，同时服务 ProxyDns 查询和目标域名解析。
+pub(crate) struct DnsResolver {
+    // 未配置上游时，构建解析器时读取一次系统 DNS 的 nameserver 作为明文上游。
+    upstreams: Vec<DnsUpstream>,
+    system: bool,
+    strategy: DnsUpstreamStrategy,
+    cache: DnsCache,
+}
+
+impl DnsResolver {
+    pub(crate) fn from_config(config: &ProxyConfig) -> Result<Self> {
+        let upstreams = config
+            .dns_upstream_addr
+            .iter()
+            .chain(config.dns_upstream_addrs.iter())
+            .map(|addr| addr.trim())
+            .filter(|addr| !addr.is_empty())
+            .map(DnsUpstream::parse)
+            .collect::<Result<Vec<_>>>()?;
+        let system = upstreams.is_empty();
+        let upstreams = if system {
+            system_upstreams()
+        } else {
+            upstreams
+        };
+
+        Ok(Self {
+            upstreams,
+            system,
+            strategy: config.dns_upstream_strategy,
+            cache: DnsCache::new(
+                config.dns_cache_size,
+                Duration::from_secs(config.dns_cache_max_ttl_secs),
+                Duration::from_secs(config.dns_negative_cache_ttl_secs),
+            ),
+        })
+    }
+
+    /// 使用系统 DNS 和默认缓存参数的解析器，供未显式配置的出站状态使用。
+    pub(crate) fn system_default() -> Self {
+        Self {
+            upstreams: system_upstreams(),
+            system: true,
+            strategy: DnsUpstreamStrategy::default(),
+            cache: DnsCache::new(
+                DEFAULT_DNS_CACHE_SIZE,
+                Duration::from_secs(DEFAULT_DNS_CACHE_MAX_TTL_SECS),
+                Duration::from_secs(DEFAULT_DNS_NEGATIVE_CACHE_TTL_SECS),
+            ),
+        }
+    }
+
+    /// 日志用的上游描述。
+    pub(crate) fn describe(&self) -> String {
+        let upstreams = if self.system {
+            "系统 DNS".to_string()
+        } else {
+            self.upstreams
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        format!("{upstreams}（策略 {:?}）", self.strategy)
+    }
+
+    /// 应答一条 wire-format DNS 查询；可缓存的查询先查缓存，未命中再发给上游。
+    ///
+    /// `port` 是 `Address::ProxyDns` 请求的端口，明文上游没写端口时使用它。
+    pub(crate) async fn exchange(
+        &self,
+        egress: &EgressState,
+        query: &[u8],
+        port: u16,
+    ) -> io::Result<Vec<u8>> {
+        let Some((request, key)) = Message::from_vec(query)
+            .ok()
+            .and_then(|request| CacheKey::for_query(&request, port).map(|key| (request, key)))
+        else {
+            return self.query_upstreams(egress, query, port).await;
+        };
+        if let Some(hit) = self.cache.get(&key, request.metadata.id) {
+            return hit.to_vec().map_err(io::Error::other);
+        }
+
+        let response = self.query_upstreams(egress, query, port).await?;
+        if let Ok(message) = Message::from_vec(&response)
+            && message.queries == request.queries
+        {
+            self.cache.insert(key, &message);
+        }
+        Ok(response)
+    }
+
+    /// 目标域名是否交给内置解析器。单标签名和 localhost 仍走系统解析，保留 hosts 文件语义。
+    pub(crate) fn handles_host(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        host.contains('.') && host.is_ascii() && !host.ends_with(".localhost")
+    }
+
+    /// 并发查询 A/AAAA 并返回地址列表，IPv4 在前。
+    ///
+    /// 域名不存在或没有地址记录时返回 `NotFound`；其它错误表示上游不可用。
+    pub(crate) async fn lookup_ip(
+        &self,
+        egress: &EgressState,
+        host: &str,
+    ) -> io::Result<Vec<IpAddr>> {
+        let name = Name::from_ascii(format!("{}.", host.trim_end_matches('.'))).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("域名无效 {host}：{e}"))
+        })?;
+        let (v4, v6) = tokio::join!(
+            self.lookup_records(egress, &name, RecordType::A),
+            self.lookup_records(egress, &name, RecordType::AAAA)
+        );
+
+        let mut addrs = Vec::new();
+        let mut answered = false;
+        let mut last_error = None;
+        for result in [v4, v6] {
+            match result {
+                Ok(response) => {
+                    answered |= matches!(
+                        response.metadata.response_code,
+                        ResponseCode::NoError | ResponseCode::NXDomain
+                    );
+                    addrs.extend(
+                        response
+                            .answers
+                            .iter()
+                            .filter_map(|record| match &record.data {
+                                RData::A(a) => Some(IpAddr::V4(a.0)),
+                                RData::AAAA(aaaa) => Some(IpAddr::V6(aaaa.0)),
+                                _ => None,
+                            }),
+                    );
+                }
+                Err(err) => last_error = Some(err),
+            }
+        }
+
+        if !addrs.is_empty() {
+            return Ok(addrs);
+        }
+        if answered {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("域名 {host} 没有可用的地址记录"),
+            ));
+        }
+        Err(last_error.unwrap_or_else(|| io::Error::other(format!("上游 DNS 未能解析域名 {host}"))))
+    }
+
+    pub(crate) fn stats_snapshot_and_reset(&self) -> DnsCacheStatsSnapshot {
+        self.cache.snapshot_and_reset()
+    }
+
+    async fn lookup_records(
+        &self,
+        egress: &EgressState,
+        name: &Name,
+        record_type: RecordType,
+    ) -> io::Result<Message> {
+        let mut request = Message::query();
+        request.metadata.recursion_desired = true;
+        request.add_query(hickory_proto::op::Query::query(name.clone(), record_type));
+        let query = request.to_vec().map_err(io::Error::other)?;
+        let response = self.exchange(egress, &query, DEFAULT_DNS_PORT).await?;
+        Message::from_vec(&response)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    }
+
+    async fn query_upstreams(
+        &self,
+        egress: &EgressState,
+        query: &[u8],
+        port: u16,
+    ) -> io::Result<Vec<u8>> {
+        let upstreams = self.upstreams.as_slice();
+        match self.strategy {
+            DnsUpstreamStrategy::Failover => {
+                let mut last_response = None;
+                let mut last_error = None;
+                for upstream in upstreams {
+                    match upstream.exchange(egress, query, port).await {
+                        Ok(response) if !is_upstream_failure(&response) => return Ok(response),
+                        Ok(response) => last_response = Some(response),
+                        Err(err) => {
+                            debug!("上游 DNS {upstream} 查询失败，尝试下一个：{err}");
+                            last_error = Some(err);
+                        }
+                    }
+                }
+                finish(last_response, last_error)
+            }
+            DnsUpstreamStrategy::Race => {
+                let mut pending = upstreams
+                    .iter()
+                    .map(|upstream| async move {
+                        (upstream, upstream.exchange(egress, query, port).await)
+                    })
+                    .collect::<FuturesUnordered<_>>();
+                let mut last_response = None;
+                let mut last_error = None;
+                while let Some((upstream, result)) = pending.next().await {
+                    match result {
+                        Ok(response) if !is_upstream_failure(&response) => return Ok(response),
+                        Ok(response) => last_response = Some(response),
+                        Err(err) => {
+                            debug!("上游 DNS {upstream} 查询失败：{err}");
+                            last_error = Some(err);
+                        }
+                    }
+                }
+                finish(last_response, last_error)
+            }
+        }
+    }
+}
+
+/// 系统 DNS 的 nameserver 作为明文上游；读取失败时记录日志，查询将直接报错并回退到系统解析。
+fn system_upstreams() -> Vec<DnsUpstream> {
+    match system::system_dns_nameservers() {
+        Ok(nameservers) => nameservers
+            .iter()
+            .map(|nameserver| DnsUpstream::Plain(PlainClient::new(nameserver)))
+            .collect(),
+        Err(err) => {
+            warn!("读取系统 DNS 配置失败，内置 DNS 解析器没有可用上游：{err}");
+            Vec::new()
+        }
+    }
+}
+
+/// SERVFAIL/REFUSED 视为该上游暂不可用，继续等待其它上游。
+fn is_upstream_failure(response: &[u8]) -> bool {
+    let response_code = response.get(3).map_or(0, |flags| flags & 0x0f);
+    response_code == ResponseCode::ServFail.low() || response_code == ResponseCode::Refused.low()
+}
+
+fn finish(last_response: Option<Vec<u8>>, last_error: Option<io::Error>) -> io::Result<Vec<u8>> {
+    // 所有上游都失败时，优先把上游给出的 SERVFAIL/REFUSED 原样交给调用方。
+    match (last_response, last_error) {
+        (Some(response), _) => Ok(response),
+        (None, Some(err)) => Err(err),
+        (None, None) => Err(io::Error::other("没有可用的上游 DNS")),
+    }
+}
+
+/// 周期输出 DNS 缓存命中率；调用方在服务退出时 abort 返回的任务。
+pub(crate) fn spawn_stats_logger(egress: Arc<EgressState>) -> JoinHandle<()> {
+    spawn_guarded("proxy dns stats", async move {
+        let mut interval = tokio::time::interval(DNS_STATS_LOG_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let snapshot = egress.dns_resolver().stats_snapshot_and_reset();
+            let lookups = snapshot.hits + snapshot.misses;
+            if lookups > 0 {
+                info!(
+                    "DNS 缓存：查询={} 命中={} 命中率={:.1}% 条目={}",
+                    lookups,
+                    snapshot.hits,
+                    snapshot.hits as f64 * 100.0 / lookups as f64,
+                    snapshot.entries
+                );
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::doh::tests::spawn_stand_in_doh_server;
+
+    fn resolver_config(upstreams: &[&str], strategy: &str) -> ProxyConfig {
+        let upstreams = upstreams
+            .iter()
+            .map(|upstream| format!("\"{upstream}\""))
+            .collect::<Vec<_>>()
+            .join(", ");
+        toml::from_str(&format!(
+            "listen_addr = \"127.0.0.1:0\"\ndns_upstream_addrs = [{upstreams}]\ndns_upstream_strategy = \"{strategy}\"\n"
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn handles_only_multi_label_names() {
+        let resolver = DnsResolver::from_config(&resolver_config(&[], "failover")).unwrap();
+        assert!(resolver.handles_host("cdn.example.com"));
+        assert!(!resolver.handles_host("localhost"));
+        assert!(!resolver.handles_host("db"));
+        assert!(!resolver.handles_host("app.localhost"));
+    }
+
+    #[test]
+    fn detects_servfail_and_refused() {
+        assert!(is_upstream_failure(&[0, 0, 0x81, 0x82]));
+        assert!(is_upstream_failure(&[0, 0, 0x81, 0x85]));
+        assert!(!is_upstream_failure(&[0, 0, 0x81, 0x83]));
+    }
+
+    #[tokio::test]
+    async fn race_ignores_silent_upstream_and_caches_answer() {
+        let (url, _) = spawn_stand_in_doh_server().await;
+        // 一个不应答的 UDP 上游与 DoH 替身竞速，结果应来自替身。
+        let silent = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let silent_addr = silent.local_addr().unwrap().to_string();
+        let resolver =
+            DnsResolver::from_config(&resolver_config(&[&silent_addr, &url], "race")).unwrap();
+        let egress = EgressState::new(None).unwrap();
+
+        let query = crate::dns::tests::sample_query(0x0102);
+        let response = resolver.exchange(&egress, &query, 53).await.unwrap();
+        assert_eq!(&response[..2], &query[..2]);
+
+        let second = crate::dns::tests::sample_query(0x0304);
+        let cached = resolver.exchange(&egress, &second, 53).await.unwrap();
+        assert_eq!(&cached[..2], &[0x03, 0x04]);
+
+        let stats = resolver.stats_snapshot_and_reset();
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+        drop(silent);
+    }
+
+    #[tokio::test]
+    async fn plain_upstream_without_port_uses_requested_port() {
+        // 明文替身只在随机端口上应答，把查询原样加上 QR 位返回。
+        let upstream = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = upstream.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut buf = vec![0_u8; 512];
+            while let Ok((size, peer)) = upstream.recv_from(&mut buf).await {
+                buf[2] |= 0x80;
+                let _ = upstream.send_to(&buf[..size], peer).await;
+            }
+        });
+        let resolver =
+            DnsResolver::from_config(&resolver_config(&["127.0.0.1"], "failover")).unwrap();
+        let egress = EgressState::new(None).unwrap();
+
+        let query = crate::dns::tests::sample_query(0x0506);
+        let response = resolver.exchange(&egress, &query, port).await.unwrap();
+        assert_eq!(&response[..2], &query[..2]);
+        assert_ne!(response[2] & 0x80, 0);
+    }
+}
@@ -0,0 +1,42 @@
+use rustls::ClientConfig;
+use rustls::pki_types::ServerName;
+use std::io;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::TlsConnector;
+use tokio_rustls::client::TlsStream;
+
+/// 构建加密 DNS 使用的 TLS 客户端配置。
+///
+/// 根证书使用内置的 webpki-roots，避免 proxy 运行在精简容器里时依赖系统证书目录。
+pub(super) fn client_config(alpn_protocols: &[&[u8]]) -> io::Result<Arc<ClientConfig>> {
+    let roots = rustls::RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let mut config =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+    config.alpn_protocols = alpn_protocols.iter().map(|proto| proto.to_vec()).collect();
+    Ok(Arc::new(config))
+}
+
+/// 在已建立的出站 TCP 流上完成 TLS 握手，证书按 `host` 校验（域名或 IP 均可）。
+pub(super) async fn connect<S>(
+    config: Arc<ClientConfig>,
+    host: &str,
+    stream: S,
+) -> io::Result<TlsStream<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let server_name = ServerName::try_from(host.to_string()).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("加密 DNS 上游主机名无效：{host}：{e}"),
+        )
+    })?;
+    TlsConnector::from(config)
+        .connect(server_name, stream)
+        .await
+}
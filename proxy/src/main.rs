@@ -6,6 +6,7 @@
 
 mod config;
 mod connection;
+mod dns;
 mod error;
 mod native_udp;
 mod server;
@@ -7,10 +7,12 @@ use protocol::udp_transport::{
     encode_auth_ok, encode_session_secret, udp_auth_proof_digest,
 };
 use rand::Rng;
+use std::sync::Arc;
 
 pub(super) struct PreparedSession {
     pub(super) codec: UdpSessionCodec,
     pub(super) auth_ok_datagram: Vec<u8>,
+    pub(super) egress_pool: Option<Arc<str>>,
 }
 
 pub(super) async fn prepare_session(
@@ -70,6 +72,7 @@ pub(super) async fn prepare_session(
     Ok(PreparedSession {
         codec,
         auth_ok_datagram,
+        egress_pool: user.egress_pool.as_deref().map(Arc::from),
     })
 }
 
@@ -81,14 +81,32 @@ async fn run_connected_udp_channel(
     outbound_tx: mpsc::Sender<UdpSessionMessage>,
     event_tx: mpsc::UnboundedSender<ChannelEvent>,
 ) {
-    let target = match target_addr_for_address(&context.config, &address) {
-        Ok(target) => target,
-        Err(error) => {
-            send_connect_result(&event_tx, flow_id, Some(error.to_string()));
-            return;
-        }
+    // ProxyDns 由内置 DNS 解析器应答：用回环 UDP 桥代替到 DNS 上游的 socket，
+    // 下面的收发循环保持不变。
+    let mut _proxy_dns_guard = None;
+    let (target, connected) = if let Address::ProxyDns { port } = address {
+        let connected = crate::dns::spawn_udp_bridge(context.egress_state.clone(), port)
+            .await
+            .map(|(socket, guard)| {
+                _proxy_dns_guard = Some(guard);
+                socket
+            });
+        ("proxy-dns".to_string(), connected)
+    } else {
+        let target = match target_addr_for_address(&address) {
+            Ok(target) => target,
+            Err(error) => {
+                send_connect_result(&event_tx, flow_id, Some(error.to_string()));
+                return;
+            }
+        };
+        let connected = context
+            .egress_state
+            .connect_udp(&target, context.egress_pool.as_deref())
+            .await;
+        (target, connected)
     };
-    let socket = match context.egress_state.connect_udp(&target).await {
+    let socket = match connected {
         Ok(socket) => socket,
         Err(error) => {
             send_connect_result(
@@ -153,6 +171,7 @@ async fn run_udp_relay_channel(
     let mut flow_set = UdpRelayFlowSet::new(
         &context.config,
         context.egress_state.clone(),
+        context.egress_pool.clone(),
         UdpRelayFlowChannels {
             response_tx,
             flow_done_tx,
@@ -193,6 +193,7 @@ impl NativeUdpListener {
             socket: self.socket.clone(),
             config: self.config.clone(),
             egress_state: self.egress_state.clone(),
+            egress_pool: prepared.egress_pool,
             peer,
         };
         let cleanup_tx = self.cleanup_tx.clone();
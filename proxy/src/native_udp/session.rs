@@ -18,6 +18,8 @@ pub(super) struct SessionContext {
     pub(super) socket: Arc<UdpSocket>,
     pub(super) config: Arc<ProxyConfig>,
     pub(super) egress_state: Arc<EgressState>,
+    // 认证用户的出站地址池名。
+    pub(super) egress_pool: Option<Arc<str>>,
     pub(super) peer: SocketAddr,
 }
 
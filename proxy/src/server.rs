@@ -5,7 +5,8 @@
 
 use crate::config::ProxyConfig;
 use crate::connection::{EgressState, ServerConnection};
-use crate::error::Result;
+use crate::dns::DnsResolver;
+use crate::error::{ProxyError, Result};
 use crate::user_manager::UserManager;
 use common::{
     DEFAULT_TCP_LISTEN_BACKLOG, bind_tcp_listener_with_backlog, configure_proxy_tcp_stream,
@@ -50,7 +51,24 @@ impl ProxyServer {
         let user_manager = Arc::new(UserManager::new(&config.users_path)?);
 
         // 出站状态在启动时构建；auto 模式会缓存初始路由表，并在默认路由不可用时刷新。
-        let egress_state = Arc::new(EgressState::new(config.outbound_interface.as_deref())?);
+        // DNS 上游列表在这里解析，配置错误在启动阶段暴露。
+        let dns_resolver = DnsResolver::from_config(&config)?;
+        info!("proxy DNS 解析器：{}", dns_resolver.describe());
+        let egress_state = EgressState::new(config.outbound_interface.as_deref())?
+            .with_dns_resolver(dns_resolver)
+            .with_egress_pools(&config.egress_pools, config.default_egress_pool.as_deref())?;
+        // 用户引用了不存在的地址池时启动失败，避免运行中该用户的所有连接都失败。
+        for (username, pool) in user_manager.egress_pool_refs() {
+            if !egress_state.has_egress_pool(&pool) {
+                return Err(ProxyError::Configuration(format!(
+                    "用户 {username} 的 egress_pool {pool} 未在 egress_pools 中定义"
+                )));
+            }
+        }
+        if !config.egress_pools.is_empty() {
+            info!("已加载 {} 个出站地址池", config.egress_pools.len());
+        }
+        let egress_state = Arc::new(egress_state);
 
         Ok(Self {
             config,
@@ -75,6 +93,8 @@ impl ProxyServer {
             self.egress_state.clone(),
         );
         tokio::pin!(udp_listener);
+        // 周期输出 DNS 缓存命中率；服务退出时一并停止。
+        let dns_stats_logger = crate::dns::spawn_stats_logger(self.egress_state.clone());
         info!(
             "代理服务器正在监听 {}（TCP + 原生加密 UDP）",
             self.config.listen_addr
@@ -107,6 +127,7 @@ impl ProxyServer {
                     }
                 }
                 result = &mut udp_listener => {
+                    dns_stats_logger.abort();
                     return result;
                 }
                 _ = tokio::signal::ctrl_c() => {
@@ -116,6 +137,7 @@ impl ProxyServer {
             }
         }
 
+        dns_stats_logger.abort();
         Ok(())
     }
 }
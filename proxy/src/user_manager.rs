@@ -46,6 +46,16 @@ impl UserManager {
         // 认证路径只读用户配置，RwLock 让多个连接可以并发查询。
         Ok(self.users.read().users.get(username).cloned())
     }
+
+    /// 返回配置了出站地址池的 `(用户名, 地址池名)`，供启动时校验地址池引用。
+    pub fn egress_pool_refs(&self) -> Vec<(String, String)> {
+        self.users
+            .read()
+            .users
+            .values()
+            .filter_map(|user| Some((user.username.clone(), user.egress_pool.clone()?)))
+            .collect()
+    }
 }
 
 fn load_users(path: &Path) -> Result<UsersConfig> {